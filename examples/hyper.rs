@@ -1,37 +1,26 @@
 use hyper::service::{make_service_fn, service_fn};
 use hyper::{header, Body, Request, Response, Server, StatusCode};
 use std::io::Cursor;
-use tokio::io::duplex;
-use tokio_util::io::ReaderStream;
-use zipit::{archive_size, Archive, FileDateTime};
+use zipit::{archive_size, ArchiveStream, EntrySource, FileDateTime};
 
 async fn zip_archive(_req: Request<Body>) -> Result<Response<Body>, hyper::http::Error> {
-    let (filename_1, mut fd_1) = (String::from("file1.txt"), Cursor::new(b"hello\n".to_vec()));
-    let (filename_2, mut fd_2) = (String::from("file2.txt"), Cursor::new(b"world\n".to_vec()));
+    let (filename_1, fd_1) = (String::from("file1.txt"), Cursor::new(b"hello\n".to_vec()));
+    let (filename_2, fd_2) = (String::from("file2.txt"), Cursor::new(b"world\n".to_vec()));
     let archive_size = archive_size([
         (filename_1.as_ref(), fd_1.get_ref().len()),
         (filename_2.as_ref(), fd_2.get_ref().len()),
     ]);
 
-    let (w, r) = duplex(4096);
-    tokio::spawn(async move {
-        let mut archive = Archive::new(w);
-        archive
-            .append(filename_1, FileDateTime::now(), &mut fd_1)
-            .await
-            .unwrap();
-        archive
-            .append(filename_2, FileDateTime::now(), &mut fd_2)
-            .await
-            .unwrap();
-        archive.finalize().await.unwrap();
-    });
+    let stream = ArchiveStream::new([
+        EntrySource::file(filename_1, FileDateTime::now(), fd_1),
+        EntrySource::file(filename_2, FileDateTime::now(), fd_2),
+    ]);
 
     Response::builder()
         .status(StatusCode::OK)
         .header(header::CONTENT_LENGTH, archive_size)
         .header(header::CONTENT_TYPE, "application/zip")
-        .body(Body::wrap_stream(ReaderStream::new(r)))
+        .body(Body::wrap_stream(stream))
 }
 
 #[tokio::main]
@@ -0,0 +1,121 @@
+use std::fmt::Write;
+use std::io::{Error as IoError, ErrorKind};
+
+use reqwest::{Client, RequestBuilder, Response, Url};
+
+use crate::base64;
+use crate::url::io_error;
+use crate::ChunkedUpload;
+
+// The maximum number of blocks of a blob.
+const MAX_BLOCKS: usize = 50_000;
+// The version of the Blob service REST API, required to authorize requests with a bearer token.
+const VERSION: &str = "2021-08-06";
+
+/// An upload of an Azure Storage [block blob](https://learn.microsoft.com/en-us/rest/api/storageservices/understanding-block-blobs--append-blobs--and-page-blobs)
+/// receiving an archive through a `ChunkedWriter`, each part being staged as a block (Put Block),
+/// the blob being committed with the list of its blocks (Put Block List) with a `Content-Type` of `application/zip`
+/// once the writer is shut down.
+///
+/// The requests are authorized with a SAS token included in the blob's URL, or with an OAuth 2.0 bearer token,
+/// supplied by the caller. A blob has up to 50,000 blocks of up to 4000 MiB, e.g. 390 GiB with the default part size.
+///
+/// ## Example
+///
+/// ```no_run
+/// use zipit::{Archive, AzureUpload, ChunkedWriter, FileDateTime};
+/// use tokio::io::AsyncWriteExt;
+///
+/// # async fn upload(sas: &str) -> Result<(), std::io::Error> {
+/// let client = reqwest::Client::new();
+/// let url = format!("https://account.blob.core.windows.net/container/files.zip?{sas}");
+/// let upload = AzureUpload::with_sas(&client, &url)?;
+/// let mut archive = Archive::new(ChunkedWriter::new(upload));
+/// archive.append("file.txt".to_owned(), FileDateTime::Zero, &mut b"hello\n".as_ref()).await?;
+/// archive.finalize().await?.shutdown().await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct AzureUpload {
+    client: Client,
+    url: Url,
+    token: Option<String>,
+    blocks: Vec<String>,
+}
+
+impl AzureUpload {
+    /// Upload the blob at `url`, authorized by the SAS token of its query string.
+    ///
+    /// # Error
+    ///
+    /// This function will return an `std::io::Error` of kind `InvalidInput` if `url` isn't a valid URL.
+    pub fn with_sas(client: &Client, url: &str) -> Result<Self, IoError> {
+        Self::new(client, url, None)
+    }
+
+    /// Upload the blob at `url`, authorized by the bearer token `token` (e.g. of Microsoft Entra ID), see `with_sas`.
+    pub fn with_token(client: &Client, url: &str, token: &str) -> Result<Self, IoError> {
+        Self::new(client, url, Some(token.to_owned()))
+    }
+
+    fn new(client: &Client, url: &str, token: Option<String>) -> Result<Self, IoError> {
+        Ok(Self {
+            client: client.clone(),
+            url: Url::parse(url).map_err(|error| IoError::new(ErrorKind::InvalidInput, error))?,
+            token,
+            blocks: Vec::new(),
+        })
+    }
+
+    // A PUT request to the blob, with the parameters of the operation added to its query string.
+    fn put(&self, parameters: &[(&str, &str)]) -> RequestBuilder {
+        let mut url = self.url.clone();
+        url.query_pairs_mut().extend_pairs(parameters);
+        let request = self.client.put(url).header("x-ms-version", VERSION);
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+}
+
+impl ChunkedUpload for AzureUpload {
+    async fn put_part(&mut self, part: Vec<u8>) -> Result<(), IoError> {
+        if self.blocks.len() == MAX_BLOCKS {
+            return Err(IoError::new(ErrorKind::InvalidInput, "too many blocks"));
+        }
+        // The IDs of the blocks of a blob must be of the same length.
+        let id = base64::encode(format!("{:08}", self.blocks.len()).as_bytes());
+        self.put(&[("comp", "block"), ("blockid", &id)])
+            .body(part)
+            .send()
+            .await
+            .and_then(Response::error_for_status)
+            .map_err(io_error)?;
+        self.blocks.push(id);
+        Ok(())
+    }
+
+    async fn complete(&mut self) -> Result<(), IoError> {
+        let mut list = r#"<?xml version="1.0" encoding="utf-8"?><BlockList>"#.to_owned();
+        for id in &self.blocks {
+            write!(list, "<Latest>{id}</Latest>").unwrap();
+        }
+        list.push_str("</BlockList>");
+        self.put(&[("comp", "blocklist")])
+            .header("x-ms-blob-content-type", "application/zip")
+            .body(list)
+            .send()
+            .await
+            .and_then(Response::error_for_status)
+            .map_err(io_error)?;
+        Ok(())
+    }
+
+    // The uncommitted blocks are discarded by Azure after a week, the blob (if any) is left untouched.
+    async fn abort(&mut self) -> Result<(), IoError> {
+        self.blocks.clear();
+        Ok(())
+    }
+}
@@ -0,0 +1,117 @@
+use std::error::Error as StdError;
+use std::io::Error as IoError;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use bytes::{Buf, Bytes};
+use futures_core::Stream;
+use http_body::Body;
+use tokio::io::AsyncWrite;
+
+use crate::{Archive, EntryOptions, FileDateTime};
+
+// The data frames of a body, as a stream of chunks, ending once its trailers are received.
+struct BodyStream<B> {
+    body: Pin<Box<B>>,
+    state: State,
+}
+
+enum State {
+    Data,
+    Trailers,
+    Done,
+}
+
+impl<B: Body> Stream for BodyStream<B> {
+    type Item = Result<Bytes, B::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match self.state {
+                State::Data => match ready!(self.body.as_mut().poll_data(cx)) {
+                    Some(Ok(mut data)) => {
+                        return Poll::Ready(Some(Ok(data.copy_to_bytes(data.remaining()))))
+                    }
+                    Some(Err(error)) => {
+                        self.state = State::Done;
+                        return Poll::Ready(Some(Err(error)));
+                    }
+                    None => self.state = State::Trailers,
+                },
+                // The trailers aren't part of the payload, but an error may still be received with them.
+                State::Trailers => {
+                    let trailers = ready!(self.body.as_mut().poll_trailers(cx));
+                    self.state = State::Done;
+                    if let Err(error) = trailers {
+                        return Poll::Ready(Some(Err(error)));
+                    }
+                }
+                State::Done => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+macro_rules! impl_append_body {
+    (
+        $fab:tt, $fabo:tt, $faso:tt,
+    ) => {
+        impl<W: AsyncWrite + Unpin> Archive<W> {
+            /// Append a new file to the archive using the provided name, date/time and HTTP body
+            /// (e.g. a `hyper::Body` received from an upstream server), streaming its data frames into the entry
+            /// without buffering the whole body. Its trailers are awaited but not recorded.
+            ///
+            /// # Error
+            ///
+            /// This function will forward any error found while polling the body's data or trailers
+            /// (as is if it is an `std::io::Error`) or while writing to the underlying sink, see `append_with_options`.
+            pub async fn $fab<B>(
+                &mut self,
+                name: String,
+                datetime: FileDateTime,
+                body: B,
+            ) -> Result<(), IoError>
+            where
+                B: Body,
+                B::Error: Into<Box<dyn StdError + Send + Sync>>,
+            {
+                self.$fabo(name, datetime, EntryOptions::default(), body)
+                    .await
+            }
+
+            /// Append a new file to the archive using the provided name, date/time, options and HTTP body,
+            /// see `append_body` and `append_with_options`.
+            pub async fn $fabo<B>(
+                &mut self,
+                name: String,
+                datetime: FileDateTime,
+                options: EntryOptions,
+                body: B,
+            ) -> Result<(), IoError>
+            where
+                B: Body,
+                B::Error: Into<Box<dyn StdError + Send + Sync>>,
+            {
+                let stream = BodyStream {
+                    body: Box::pin(body),
+                    state: State::Data,
+                };
+                self.$faso(name, datetime, options, stream).await
+            }
+        }
+    };
+}
+
+#[cfg(feature = "futures-async-io")]
+impl_append_body!(
+    tokio_append_body,
+    tokio_append_body_with_options,
+    tokio_append_stream_with_options,
+);
+
+#[cfg(not(feature = "futures-async-io"))]
+impl_append_body!(
+    append_body,
+    append_body_with_options,
+    append_stream_with_options,
+);
@@ -0,0 +1,391 @@
+use super::huffman::{canonical_codes, code_lengths};
+use super::Compressor;
+
+const LEVEL: u8 = 9;
+const MAX_BLOCK_LEN: usize = 100_000 * LEVEL as usize - 19;
+const GROUP_SIZE: usize = 50;
+const MAX_CODE_LEN: u8 = 17;
+const REFINEMENT_ITERATIONS: usize = 4;
+const RUN_A: u16 = 0;
+const RUN_B: u16 = 1;
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u32) << 24;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x80000000 != 0 {
+                crc << 1 ^ 0x04c11db7
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// A streaming bzip2 encoder, producing a single bzip2 stream made of 900k blocks.
+pub(crate) struct Encoder {
+    writer: BitWriter,
+    block: Vec<u8>,
+    run: Option<(u8, usize)>,
+    block_crc: u32,
+    combined_crc: u32,
+}
+
+impl Encoder {
+    pub(crate) fn new() -> Self {
+        let mut writer = BitWriter::default();
+        for &byte in b"BZh" {
+            writer.write(8, byte as u32);
+        }
+        writer.write(8, (b'0' + LEVEL) as u32);
+        Self {
+            writer,
+            block: Vec::new(),
+            run: None,
+            block_crc: 0xffffffff,
+            combined_crc: 0,
+        }
+    }
+
+    // Initial run-length encoding: runs of 4 to 255 bytes are written as 4 bytes followed by the extra count.
+    fn flush_run(&mut self) {
+        if let Some((byte, len)) = self.run.take() {
+            self.block.extend(std::iter::repeat_n(byte, len.min(4)));
+            if len >= 4 {
+                self.block.push((len - 4) as u8);
+            }
+        }
+    }
+
+    fn write_block(&mut self) {
+        if self.block.is_empty() {
+            return;
+        }
+        let block_crc = !self.block_crc;
+        self.combined_crc = self.combined_crc.rotate_left(1) ^ block_crc;
+
+        let n = self.block.len();
+        let rotations = sort_rotations(&self.block);
+        let mut origin = 0;
+        let last_column = rotations
+            .iter()
+            .enumerate()
+            .map(|(i, &rotation)| {
+                if rotation == 0 {
+                    origin = i;
+                }
+                self.block[(rotation as usize + n - 1) % n]
+            })
+            .collect::<Vec<_>>();
+
+        let mut in_use = [false; 256];
+        for &byte in &self.block {
+            in_use[byte as usize] = true;
+        }
+        let mut seq = [0u8; 256];
+        let mut used = 0;
+        for (byte, _) in in_use.iter().enumerate().filter(|(_, &used)| used) {
+            seq[byte] = used as u8;
+            used += 1;
+        }
+        let symbols = move_to_front(&last_column, &seq, used);
+        let alpha_size = used + 2;
+
+        let w = &mut self.writer;
+        w.write(24, 0x314159);
+        w.write(24, 0x265359);
+        w.write(32, block_crc);
+        w.write(1, 0); // Not randomised.
+        w.write(24, origin as u32);
+        let ranges_in_use = (0..16)
+            .map(|range| in_use[range * 16..range * 16 + 16].contains(&true))
+            .collect::<Vec<_>>();
+        for &range_in_use in &ranges_in_use {
+            w.write(1, range_in_use as u32);
+        }
+        for (range, _) in ranges_in_use.iter().enumerate().filter(|(_, &u)| u) {
+            for &byte_in_use in &in_use[range * 16..range * 16 + 16] {
+                w.write(1, byte_in_use as u32);
+            }
+        }
+
+        let (lengths, selectors) = build_tables(&symbols, alpha_size);
+        w.write(3, lengths.len() as u32);
+        w.write(15, selectors.len() as u32);
+        let mut order = (0..lengths.len() as u8).collect::<Vec<_>>();
+        for &selector in &selectors {
+            let position = order.iter().position(|&t| t == selector).unwrap();
+            order[..=position].rotate_right(1);
+            for _ in 0..position {
+                w.write(1, 1);
+            }
+            w.write(1, 0);
+        }
+        for table in &lengths {
+            let mut current = table[0];
+            w.write(5, current as u32);
+            for &length in table {
+                while current < length {
+                    w.write(2, 0b10);
+                    current += 1;
+                }
+                while current > length {
+                    w.write(2, 0b11);
+                    current -= 1;
+                }
+                w.write(1, 0);
+            }
+        }
+        let codes = lengths
+            .iter()
+            .map(|table| canonical_codes(table))
+            .collect::<Vec<_>>();
+        for (group, &selector) in symbols.chunks(GROUP_SIZE).zip(&selectors) {
+            for &symbol in group {
+                w.write(
+                    lengths[selector as usize][symbol as usize] as u32,
+                    codes[selector as usize][symbol as usize],
+                );
+            }
+        }
+
+        self.block.clear();
+        self.block_crc = 0xffffffff;
+    }
+}
+
+impl Compressor for Encoder {
+    fn method(&self) -> u16 {
+        12
+    }
+
+    fn version_needed(&self) -> u16 {
+        46
+    }
+
+    fn compress(&mut self, input: &[u8], output: &mut Vec<u8>) {
+        for &byte in input {
+            match &mut self.run {
+                Some((run_byte, len)) if *run_byte == byte && *len < 255 => *len += 1,
+                _ => {
+                    self.flush_run();
+                    // A flushed run takes at most 5 bytes.
+                    if self.block.len() >= MAX_BLOCK_LEN - 5 {
+                        self.write_block();
+                    }
+                    self.run = Some((byte, 1));
+                }
+            }
+            self.block_crc =
+                self.block_crc << 8 ^ CRC_TABLE[((self.block_crc >> 24) as u8 ^ byte) as usize];
+        }
+        output.append(&mut self.writer.output);
+    }
+
+    fn finish(&mut self, output: &mut Vec<u8>) {
+        self.flush_run();
+        self.write_block();
+        self.writer.write(24, 0x177245);
+        self.writer.write(24, 0x385090);
+        self.writer.write(32, self.combined_crc);
+        self.writer.align();
+        output.append(&mut self.writer.output);
+    }
+}
+
+// Sort all the rotations of the block by prefix doubling, returning their starting positions.
+fn sort_rotations(block: &[u8]) -> Vec<u32> {
+    let n = block.len();
+    let mut positions = vec![0u32; n];
+    let mut count = vec![0usize; 256];
+    for &byte in block {
+        count[byte as usize] += 1;
+    }
+    for i in 1..256 {
+        count[i] += count[i - 1];
+    }
+    for i in (0..n).rev() {
+        count[block[i] as usize] -= 1;
+        positions[count[block[i] as usize]] = i as u32;
+    }
+
+    let mut classes = vec![0u32; n];
+    let mut class_count = 1;
+    for i in 1..n {
+        if block[positions[i] as usize] != block[positions[i - 1] as usize] {
+            class_count += 1;
+        }
+        classes[positions[i] as usize] = class_count as u32 - 1;
+    }
+
+    let mut shifted = vec![0u32; n];
+    let mut new_classes = vec![0u32; n];
+    let mut step = 1;
+    while step < n && class_count < n {
+        for (shift, &position) in shifted.iter_mut().zip(&positions) {
+            *shift = ((position as usize + n - step) % n) as u32;
+        }
+        count.clear();
+        count.resize(class_count, 0);
+        for &shift in &shifted {
+            count[classes[shift as usize] as usize] += 1;
+        }
+        for i in 1..class_count {
+            count[i] += count[i - 1];
+        }
+        for &shift in shifted.iter().rev() {
+            let class = classes[shift as usize] as usize;
+            count[class] -= 1;
+            positions[count[class]] = shift;
+        }
+
+        let key = |position: u32| {
+            (
+                classes[position as usize],
+                classes[(position as usize + step) % n],
+            )
+        };
+        new_classes[positions[0] as usize] = 0;
+        class_count = 1;
+        for i in 1..n {
+            if key(positions[i]) != key(positions[i - 1]) {
+                class_count += 1;
+            }
+            new_classes[positions[i] as usize] = class_count as u32 - 1;
+        }
+        std::mem::swap(&mut classes, &mut new_classes);
+        step *= 2;
+    }
+    positions
+}
+
+// Move-to-front transform followed by the RUNA/RUNB encoding of zero runs, terminated by the end of block symbol.
+fn move_to_front(data: &[u8], seq: &[u8; 256], used: usize) -> Vec<u16> {
+    fn flush_zeros(symbols: &mut Vec<u16>, zeros: &mut usize) {
+        if *zeros == 0 {
+            return;
+        }
+        let mut run = *zeros - 1;
+        loop {
+            symbols.push(if run & 1 == 1 { RUN_B } else { RUN_A });
+            if run < 2 {
+                break;
+            }
+            run = (run - 2) / 2;
+        }
+        *zeros = 0;
+    }
+
+    let mut order = (0..used).map(|s| s as u8).collect::<Vec<_>>();
+    let mut symbols = Vec::with_capacity(data.len() + 1);
+    let mut zeros = 0;
+    for &byte in data {
+        let position = order.iter().position(|&s| s == seq[byte as usize]).unwrap();
+        if position == 0 {
+            zeros += 1;
+            continue;
+        }
+        flush_zeros(&mut symbols, &mut zeros);
+        order[..=position].rotate_right(1);
+        symbols.push(position as u16 + 1);
+    }
+    flush_zeros(&mut symbols, &mut zeros);
+    symbols.push(used as u16 + 1);
+    symbols
+}
+
+// Build the Huffman tables and the per-group selectors, refining them the same way the reference implementation does.
+fn build_tables(symbols: &[u16], alpha_size: usize) -> (Vec<Vec<u8>>, Vec<u8>) {
+    let table_count = match symbols.len() {
+        0..=199 => 2,
+        200..=599 => 3,
+        600..=1199 => 4,
+        1200..=2399 => 5,
+        _ => 6,
+    };
+    let mut freqs = vec![0u32; alpha_size];
+    for &symbol in symbols {
+        freqs[symbol as usize] += 1;
+    }
+
+    // Initial tables: split the alphabet in ranges of roughly equal frequencies, each table favoring one range.
+    let mut lengths = vec![vec![15u8; alpha_size]; table_count];
+    let mut remaining = symbols.len() as u32;
+    let mut start = 0;
+    for part in (1..=table_count).rev() {
+        let target = remaining / part as u32;
+        let mut end = start;
+        let mut accumulated = 0;
+        while accumulated < target && end < alpha_size {
+            accumulated += freqs[end];
+            end += 1;
+        }
+        if end > start + 1 && part != table_count && part != 1 && (table_count - part) % 2 == 1 {
+            end -= 1;
+            accumulated -= freqs[end];
+        }
+        for length in &mut lengths[part - 1][start..end] {
+            *length = 0;
+        }
+        start = end;
+        remaining -= accumulated;
+    }
+
+    let mut selectors = Vec::new();
+    for _ in 0..REFINEMENT_ITERATIONS {
+        selectors.clear();
+        let mut table_freqs = vec![vec![0u32; alpha_size]; table_count];
+        for group in symbols.chunks(GROUP_SIZE) {
+            let best = (0..table_count)
+                .min_by_key(|&t| {
+                    group
+                        .iter()
+                        .map(|&s| lengths[t][s as usize] as u32)
+                        .sum::<u32>()
+                })
+                .unwrap();
+            selectors.push(best as u8);
+            for &symbol in group {
+                table_freqs[best][symbol as usize] += 1;
+            }
+        }
+        for (table, freqs) in lengths.iter_mut().zip(&table_freqs) {
+            // Every symbol of the alphabet must have a code.
+            let freqs = freqs.iter().map(|&f| f.max(1)).collect::<Vec<_>>();
+            *table = code_lengths(&freqs, MAX_CODE_LEN);
+        }
+    }
+    (lengths, selectors)
+}
+
+// Most significant bit first writer.
+#[derive(Default)]
+struct BitWriter {
+    output: Vec<u8>,
+    buffer: u64,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, bits: u32, value: u32) {
+        self.buffer = self.buffer << bits | value as u64;
+        self.bits += bits;
+        while self.bits >= 8 {
+            self.bits -= 8;
+            self.output.push((self.buffer >> self.bits) as u8);
+        }
+    }
+
+    fn align(&mut self) {
+        if self.bits > 0 {
+            self.write(8 - self.bits, 0);
+        }
+    }
+}
@@ -0,0 +1,447 @@
+use super::huffman::{canonical_codes, code_lengths};
+use super::lz77::MatchFinder;
+use super::Compressor;
+
+const BLOCK_SYMBOLS: usize = 1 << 14;
+const END_OF_BLOCK: usize = 256;
+const MAX_CODE_LEN: u8 = 15;
+const MAX_CODE_LENGTH_CODE_LEN: u8 = 7;
+pub(super) const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+pub(super) const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+pub(super) const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+pub(super) const DISTANCE_BASE: [u32; 32] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577, 32769, 49153,
+];
+pub(super) const DISTANCE_EXTRA: [u8; 32] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13, 14, 14,
+];
+
+/// The flavor of the format: Deflate64 extends the window to 64 KiB, adds two distance codes,
+/// and redefines the last length code to carry 16 extra bits.
+#[derive(Copy, Clone)]
+pub(crate) enum Variant {
+    #[cfg(feature = "deflate-compression")]
+    Deflate,
+    #[cfg(feature = "deflate64-compression")]
+    Deflate64,
+}
+
+impl Variant {
+    fn method(self) -> u16 {
+        match self {
+            #[cfg(feature = "deflate-compression")]
+            Variant::Deflate => 8,
+            #[cfg(feature = "deflate64-compression")]
+            Variant::Deflate64 => 9,
+        }
+    }
+
+    fn version_needed(self) -> u16 {
+        match self {
+            #[cfg(feature = "deflate-compression")]
+            Variant::Deflate => 20,
+            #[cfg(feature = "deflate64-compression")]
+            Variant::Deflate64 => 21,
+        }
+    }
+
+    fn window_size(self) -> usize {
+        match self {
+            #[cfg(feature = "deflate-compression")]
+            Variant::Deflate => 1 << 15,
+            #[cfg(feature = "deflate64-compression")]
+            Variant::Deflate64 => 1 << 16,
+        }
+    }
+
+    fn max_match_len(self) -> usize {
+        match self {
+            #[cfg(feature = "deflate-compression")]
+            Variant::Deflate => 258,
+            #[cfg(feature = "deflate64-compression")]
+            Variant::Deflate64 => 65538,
+        }
+    }
+
+    fn distance_codes(self) -> usize {
+        match self {
+            #[cfg(feature = "deflate-compression")]
+            Variant::Deflate => 30,
+            #[cfg(feature = "deflate64-compression")]
+            Variant::Deflate64 => 32,
+        }
+    }
+
+    // Length symbol (257..=285), extra bits count and extra bits value.
+    fn length_code(self, len: usize) -> (usize, u8, u32) {
+        let codes = match self {
+            #[cfg(feature = "deflate-compression")]
+            Variant::Deflate => 29,
+            // The last length code carries 16 extra bits for long matches, the previous one covers 227..=258.
+            #[cfg(feature = "deflate64-compression")]
+            Variant::Deflate64 if len > 258 => return (285, 16, len as u32 - 3),
+            #[cfg(feature = "deflate64-compression")]
+            Variant::Deflate64 => 28,
+        };
+        let index = LENGTH_BASE[..codes].partition_point(|&base| base as usize <= len) - 1;
+        (
+            257 + index,
+            LENGTH_EXTRA[index],
+            (len - LENGTH_BASE[index] as usize) as u32,
+        )
+    }
+}
+
+fn distance_code(distance: usize) -> (usize, u8, u32) {
+    let index = DISTANCE_BASE.partition_point(|&base| base as usize <= distance) - 1;
+    (
+        index,
+        DISTANCE_EXTRA[index],
+        (distance - DISTANCE_BASE[index] as usize) as u32,
+    )
+}
+
+#[derive(Copy, Clone)]
+enum Symbol {
+    Literal(u8),
+    Match(usize, usize),
+}
+
+/// A streaming Deflate / Deflate64 encoder, using greedy matching and per block dynamic Huffman codes.
+///
+/// Each block is emitted with dynamic codes, fixed codes or stored, whichever is the smallest.
+pub(crate) struct Encoder {
+    variant: Variant,
+    matcher: MatchFinder,
+    writer: BitWriter,
+    position: usize,
+    block_start: usize,
+    symbols: Vec<Symbol>,
+}
+
+impl Encoder {
+    pub(crate) fn new(variant: Variant) -> Self {
+        Self {
+            variant,
+            matcher: MatchFinder::new(variant.window_size()),
+            writer: BitWriter::default(),
+            position: 0,
+            block_start: 0,
+            symbols: Vec::new(),
+        }
+    }
+
+    fn encode(&mut self, complete: bool) {
+        let end = self.matcher.end();
+        let max_match_len = self.variant.max_match_len();
+        while self.position < end && (complete || end - self.position >= max_match_len) {
+            match self.matcher.find(self.position, max_match_len) {
+                Some((len, distance)) => {
+                    self.symbols.push(Symbol::Match(len, distance));
+                    for position in self.position..self.position + len {
+                        self.matcher.insert(position);
+                    }
+                    self.position += len;
+                }
+                None => {
+                    self.symbols
+                        .push(Symbol::Literal(self.matcher.byte(self.position)));
+                    self.matcher.insert(self.position);
+                    self.position += 1;
+                }
+            }
+            if self.symbols.len() >= BLOCK_SYMBOLS {
+                self.write_block(false);
+            }
+        }
+        // The current block must stay available in case it has to be stored.
+        self.matcher.trim(
+            self.position
+                .saturating_sub(self.variant.window_size())
+                .min(self.block_start),
+        );
+    }
+
+    fn write_block(&mut self, last: bool) {
+        let mut literal_freqs = vec![0u32; 286];
+        let mut distance_freqs = vec![0u32; self.variant.distance_codes()];
+        for &symbol in &self.symbols {
+            match symbol {
+                Symbol::Literal(byte) => literal_freqs[byte as usize] += 1,
+                Symbol::Match(len, distance) => {
+                    literal_freqs[self.variant.length_code(len).0] += 1;
+                    distance_freqs[distance_code(distance).0] += 1;
+                }
+            }
+        }
+        literal_freqs[END_OF_BLOCK] += 1;
+        // Some decoders reject codes made of a single symbol.
+        for freqs in [&mut literal_freqs, &mut distance_freqs] {
+            if freqs.iter().filter(|&&f| f != 0).count() < 2 {
+                for freq in freqs.iter_mut().take(2) {
+                    *freq = (*freq).max(1);
+                }
+            }
+        }
+
+        let literal_lengths = code_lengths(&literal_freqs, MAX_CODE_LEN);
+        let distance_lengths = code_lengths(&distance_freqs, MAX_CODE_LEN);
+        let literal_count = 257.max(last_used(&literal_lengths));
+        let distance_count = last_used(&distance_lengths);
+        let code_length_symbols = run_lengths(
+            &[
+                &literal_lengths[..literal_count],
+                &distance_lengths[..distance_count],
+            ]
+            .concat(),
+        );
+        let mut code_length_freqs = vec![0u32; 19];
+        for &(symbol, _) in &code_length_symbols {
+            code_length_freqs[symbol as usize] += 1;
+        }
+        let code_length_lengths = code_lengths(&code_length_freqs, MAX_CODE_LENGTH_CODE_LEN);
+        let code_length_count = 4.max(
+            CODE_LENGTH_ORDER
+                .iter()
+                .rposition(|&s| code_length_lengths[s] != 0)
+                .map_or(0, |p| p + 1),
+        );
+
+        let fixed_literal_lengths = (0..288)
+            .map(|s| match s {
+                0..=143 => 8,
+                144..=255 => 9,
+                256..=279 => 7,
+                _ => 8,
+            })
+            .collect::<Vec<_>>();
+        let fixed_distance_lengths = vec![5; 32];
+
+        let dynamic_cost = 5
+            + 5
+            + 4
+            + 3 * code_length_count
+            + code_length_symbols
+                .iter()
+                .map(|&(s, _)| {
+                    code_length_lengths[s as usize] as usize + code_length_extra_bits(s) as usize
+                })
+                .sum::<usize>()
+            + self.data_cost(&literal_lengths, &distance_lengths);
+        let fixed_cost = self.data_cost(&fixed_literal_lengths, &fixed_distance_lengths);
+        let stored_len = self.position - self.block_start;
+        let stored_cost = 8 * (stored_len + 5 * stored_len.div_ceil(65535).max(1)) + 7;
+
+        if stored_cost <= dynamic_cost.min(fixed_cost) {
+            self.write_stored(last);
+        } else {
+            self.writer.write(1, last as u32);
+            if fixed_cost <= dynamic_cost {
+                self.writer.write(2, 0b01);
+                self.write_data(&fixed_literal_lengths, &fixed_distance_lengths);
+            } else {
+                self.writer.write(2, 0b10);
+                self.writer.write(5, literal_count as u32 - 257);
+                self.writer.write(5, distance_count as u32 - 1);
+                self.writer.write(4, code_length_count as u32 - 4);
+                for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+                    self.writer.write(3, code_length_lengths[symbol] as u32);
+                }
+                let codes = reversed_codes(&code_length_lengths);
+                for &(symbol, extra) in &code_length_symbols {
+                    self.writer.write(
+                        code_length_lengths[symbol as usize] as u32,
+                        codes[symbol as usize],
+                    );
+                    self.writer
+                        .write(code_length_extra_bits(symbol), extra as u32);
+                }
+                self.write_data(&literal_lengths, &distance_lengths);
+            }
+        }
+
+        self.symbols.clear();
+        self.block_start = self.position;
+    }
+
+    // Size in bits of the block's symbols (including the block header bits and the end of block symbol) with the given code lengths.
+    fn data_cost(&self, literal_lengths: &[u8], distance_lengths: &[u8]) -> usize {
+        3 + literal_lengths[END_OF_BLOCK] as usize
+            + self
+                .symbols
+                .iter()
+                .map(|&symbol| match symbol {
+                    Symbol::Literal(byte) => literal_lengths[byte as usize] as usize,
+                    Symbol::Match(len, distance) => {
+                        let (symbol, extra, _) = self.variant.length_code(len);
+                        let (distance_symbol, distance_extra, _) = distance_code(distance);
+                        literal_lengths[symbol] as usize
+                            + extra as usize
+                            + distance_lengths[distance_symbol] as usize
+                            + distance_extra as usize
+                    }
+                })
+                .sum::<usize>()
+    }
+
+    fn write_data(&mut self, literal_lengths: &[u8], distance_lengths: &[u8]) {
+        let literal_codes = reversed_codes(literal_lengths);
+        let distance_codes = reversed_codes(distance_lengths);
+        for &symbol in &self.symbols {
+            match symbol {
+                Symbol::Literal(byte) => self.writer.write(
+                    literal_lengths[byte as usize] as u32,
+                    literal_codes[byte as usize],
+                ),
+                Symbol::Match(len, distance) => {
+                    let (symbol, extra_bits, extra) = self.variant.length_code(len);
+                    self.writer
+                        .write(literal_lengths[symbol] as u32, literal_codes[symbol]);
+                    self.writer.write(extra_bits as u32, extra);
+                    let (symbol, extra_bits, extra) = distance_code(distance);
+                    self.writer
+                        .write(distance_lengths[symbol] as u32, distance_codes[symbol]);
+                    self.writer.write(extra_bits as u32, extra);
+                }
+            }
+        }
+        self.writer.write(
+            literal_lengths[END_OF_BLOCK] as u32,
+            literal_codes[END_OF_BLOCK],
+        );
+    }
+
+    fn write_stored(&mut self, last: bool) {
+        let data = self.matcher.bytes(self.block_start, self.position);
+        let mut chunks = data.chunks(65535).peekable();
+        if chunks.peek().is_none() {
+            self.writer.write(3, last as u32);
+            self.writer.align();
+            self.writer.write(16, 0);
+            self.writer.write(16, 0xffff);
+        }
+        while let Some(chunk) = chunks.next() {
+            self.writer
+                .write(3, (last && chunks.peek().is_none()) as u32);
+            self.writer.align();
+            self.writer.write(16, chunk.len() as u32);
+            self.writer.write(16, !chunk.len() as u32 & 0xffff);
+            self.writer.output.extend_from_slice(chunk);
+        }
+    }
+}
+
+impl Compressor for Encoder {
+    fn method(&self) -> u16 {
+        self.variant.method()
+    }
+
+    fn version_needed(&self) -> u16 {
+        self.variant.version_needed()
+    }
+
+    fn compress(&mut self, input: &[u8], output: &mut Vec<u8>) {
+        self.matcher.push(input);
+        self.encode(false);
+        output.append(&mut self.writer.output);
+    }
+
+    fn finish(&mut self, output: &mut Vec<u8>) {
+        self.encode(true);
+        self.write_block(true);
+        self.writer.align();
+        output.append(&mut self.writer.output);
+    }
+}
+
+fn code_length_extra_bits(symbol: u8) -> u32 {
+    match symbol {
+        16 => 2,
+        17 => 3,
+        18 => 7,
+        _ => 0,
+    }
+}
+
+fn last_used(lengths: &[u8]) -> usize {
+    lengths.iter().rposition(|&l| l != 0).map_or(1, |p| p + 1)
+}
+
+fn reversed_codes(lengths: &[u8]) -> Vec<u32> {
+    canonical_codes(lengths)
+        .into_iter()
+        .zip(lengths)
+        .map(|(code, &len)| match len {
+            0 => 0,
+            len => code.reverse_bits() >> (32 - len as u32),
+        })
+        .collect()
+}
+
+// Run-length encode the code lengths with the code length alphabet: 16 repeats the previous length 3 to 6 times,
+// 17 and 18 repeat zero 3 to 10 and 11 to 138 times.
+fn run_lengths(lengths: &[u8]) -> Vec<(u8, u8)> {
+    let mut symbols = Vec::new();
+    let mut i = 0;
+    while i < lengths.len() {
+        let length = lengths[i];
+        let mut run = lengths[i..].iter().take_while(|&&l| l == length).count();
+        i += run;
+        if length == 0 {
+            while run >= 11 {
+                let repeat = run.min(138);
+                symbols.push((18, (repeat - 11) as u8));
+                run -= repeat;
+            }
+            if run >= 3 {
+                symbols.push((17, (run - 3) as u8));
+                run = 0;
+            }
+        } else {
+            symbols.push((length, 0));
+            run -= 1;
+            while run >= 3 {
+                let repeat = run.min(6);
+                symbols.push((16, (repeat - 3) as u8));
+                run -= repeat;
+            }
+        }
+        symbols.extend(std::iter::repeat_n((length, 0), run));
+    }
+    symbols
+}
+
+// Least significant bit first writer.
+#[derive(Default)]
+struct BitWriter {
+    output: Vec<u8>,
+    buffer: u64,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, bits: u32, value: u32) {
+        self.buffer |= (value as u64) << self.bits;
+        self.bits += bits;
+        while self.bits >= 8 {
+            self.output.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn align(&mut self) {
+        if self.bits > 0 {
+            self.write(8 - self.bits, 0);
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+/// Compute length-limited Huffman code lengths for the given symbol frequencies.
+///
+/// Unused symbols (frequency of 0) get a length of 0. If only one symbol is used, it gets a length of 1.
+/// Frequencies are repeatedly halved until the resulting tree fits in `max_len` bits.
+pub(crate) fn code_lengths(freqs: &[u32], max_len: u8) -> Vec<u8> {
+    let mut weights = freqs.iter().map(|&f| f as u64).collect::<Vec<_>>();
+    loop {
+        let lengths = tree_depths(&weights);
+        if lengths.iter().all(|&l| l <= max_len) {
+            return lengths;
+        }
+        for weight in weights.iter_mut().filter(|w| **w != 0) {
+            *weight = 1 + *weight / 2;
+        }
+    }
+}
+
+fn tree_depths(weights: &[u64]) -> Vec<u8> {
+    let mut lengths = vec![0; weights.len()];
+    let mut heap = weights
+        .iter()
+        .enumerate()
+        .filter(|(_, &w)| w != 0)
+        .map(|(i, &w)| Reverse((w, i)))
+        .collect::<BinaryHeap<_>>();
+    match heap.len() {
+        0 => return lengths,
+        1 => {
+            lengths[heap.pop().unwrap().0 .1] = 1;
+            return lengths;
+        }
+        _ => {}
+    }
+
+    // Leaves are nodes 0..n, internal nodes are appended after them.
+    let mut parents = vec![usize::MAX; weights.len()];
+    while heap.len() > 1 {
+        let Reverse((w1, n1)) = heap.pop().unwrap();
+        let Reverse((w2, n2)) = heap.pop().unwrap();
+        let node = parents.len();
+        parents.push(usize::MAX);
+        parents[n1] = node;
+        parents[n2] = node;
+        heap.push(Reverse((w1 + w2, node)));
+    }
+
+    let mut depths = vec![0u8; parents.len()];
+    for node in (0..parents.len()).rev() {
+        if parents[node] != usize::MAX {
+            depths[node] = depths[parents[node]].saturating_add(1);
+        }
+    }
+    for (symbol, length) in lengths.iter_mut().enumerate() {
+        if weights[symbol] != 0 {
+            *length = depths[symbol];
+        }
+    }
+    lengths
+}
+
+/// Assign canonical codes (ordered by length, then by symbol) to the given code lengths.
+pub(crate) fn canonical_codes(lengths: &[u8]) -> Vec<u32> {
+    let max_len = lengths.iter().copied().max().unwrap_or(0) as usize;
+    let mut count = vec![0u32; max_len + 1];
+    for &length in lengths.iter().filter(|&&l| l != 0) {
+        count[length as usize] += 1;
+    }
+    let mut next = vec![0u32; max_len + 1];
+    let mut code = 0;
+    for bits in 1..=max_len {
+        code = (code + count[bits - 1]) << 1;
+        next[bits] = code;
+    }
+    lengths
+        .iter()
+        .map(|&length| {
+            if length == 0 {
+                return 0;
+            }
+            let code = next[length as usize];
+            next[length as usize] += 1;
+            code
+        })
+        .collect()
+}
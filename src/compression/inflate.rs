@@ -0,0 +1,334 @@
+use super::deflate::{CODE_LENGTH_ORDER, DISTANCE_BASE, DISTANCE_EXTRA, LENGTH_BASE, LENGTH_EXTRA};
+
+const WINDOW_SIZE: usize = 1 << 15;
+const DEFLATE64_WINDOW_SIZE: usize = 1 << 16;
+const END_OF_BLOCK: u16 = 256;
+// Stop decoding once this many bytes are waiting to be read.
+const MAX_PENDING: usize = 1 << 16;
+
+/// The compressed data isn't a valid deflate stream.
+#[derive(Debug)]
+pub(crate) struct InvalidData;
+
+// Why a decoding step couldn't complete.
+enum Stop {
+    Input,
+    Invalid,
+}
+
+impl From<InvalidData> for Stop {
+    fn from(_: InvalidData) -> Self {
+        Stop::Invalid
+    }
+}
+
+/// A canonical Huffman code, decoded bit by bit.
+#[derive(Debug)]
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self, InvalidData> {
+        let mut counts = [0u16; 16];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        // Reject over-subscribed codes, incomplete ones are allowed (e.g. a single distance code).
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left = (left << 1) - count as i32;
+            if left < 0 {
+                return Err(InvalidData);
+            }
+        }
+
+        let mut offsets = [0u16; 16];
+        for len in 1..15 {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        Ok(Self { counts, symbols })
+    }
+
+    fn fixed() -> (Self, Self) {
+        let mut lengths = [8u8; 288];
+        lengths[144..256].fill(9);
+        lengths[256..280].fill(7);
+        // Both are valid, complete codes (the last two distances being only used by Deflate64).
+        (
+            Self::new(&lengths).unwrap_or_else(|_| unreachable!()),
+            Self::new(&[5; 32]).unwrap_or_else(|_| unreachable!()),
+        )
+    }
+}
+
+/// The bits of the input, least significant first.
+struct Bits<'a> {
+    input: &'a [u8],
+    pos: usize,
+    buffer: u64,
+    count: u32,
+}
+
+impl Bits<'_> {
+    fn take(&mut self, n: u32) -> Result<u32, Stop> {
+        while self.count < n {
+            let byte = *self.input.get(self.pos).ok_or(Stop::Input)?;
+            self.pos += 1;
+            self.buffer |= (byte as u64) << self.count;
+            self.count += 8;
+        }
+        let value = (self.buffer & ((1 << n) - 1)) as u32;
+        self.buffer >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+
+    fn decode(&mut self, huffman: &Huffman) -> Result<u16, Stop> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for &count in &huffman.counts[1..] {
+            code |= self.take(1)? as i32;
+            if code - first < count as i32 {
+                return Ok(huffman.symbols[(index + code - first) as usize]);
+            }
+            index += count as i32;
+            first = (first + count as i32) << 1;
+            code <<= 1;
+        }
+        Err(Stop::Invalid)
+    }
+}
+
+#[derive(Debug)]
+enum State {
+    Header,
+    Stored(u16),
+    Block(Huffman, Huffman),
+    Done,
+}
+
+/// Decompress a deflate stream fed chunk by chunk, keeping the window of the last 32 KiB written
+/// (64 KiB for Deflate64, only decoded by the tests of the encoder).
+#[derive(Debug)]
+pub(crate) struct Inflater {
+    state: State,
+    deflate64: bool,
+    last: bool,
+    buffer: u64,
+    count: u32,
+    output: Vec<u8>,
+    read: usize,
+}
+
+impl Inflater {
+    pub(crate) fn new() -> Self {
+        Self {
+            state: State::Header,
+            deflate64: false,
+            last: false,
+            buffer: 0,
+            count: 0,
+            output: Vec::new(),
+            read: 0,
+        }
+    }
+
+    #[cfg(all(test, feature = "deflate64-compression"))]
+    pub(crate) fn deflate64() -> Self {
+        Self {
+            deflate64: true,
+            ..Self::new()
+        }
+    }
+
+    /// Decode as much of `input` as possible, returning the number of bytes consumed.
+    /// The bytes of an incomplete symbol (or block header) aren't consumed, and must be fed again with the following ones.
+    pub(crate) fn inflate(&mut self, input: &[u8]) -> Result<usize, InvalidData> {
+        let mut bits = Bits {
+            input,
+            pos: 0,
+            buffer: self.buffer,
+            count: self.count,
+        };
+        while !self.is_done() && self.output.len() - self.read < MAX_PENDING {
+            let checkpoint = (bits.pos, bits.buffer, bits.count);
+            match self.step(&mut bits) {
+                Ok(()) => {}
+                Err(Stop::Input) => {
+                    (bits.pos, bits.buffer, bits.count) = checkpoint;
+                    break;
+                }
+                Err(Stop::Invalid) => return Err(InvalidData),
+            }
+        }
+        (self.buffer, self.count) = (bits.buffer, bits.count);
+        Ok(bits.pos)
+    }
+
+    /// The decompressed bytes not read yet.
+    pub(crate) fn output(&self) -> &[u8] {
+        &self.output[self.read..]
+    }
+
+    /// Mark `n` decompressed bytes as read.
+    pub(crate) fn consume(&mut self, n: usize) {
+        self.read += n;
+        // Only keep the window needed by the following back-references.
+        let window_size = if self.deflate64 {
+            DEFLATE64_WINDOW_SIZE
+        } else {
+            WINDOW_SIZE
+        };
+        let obsolete = self.output.len().saturating_sub(window_size).min(self.read);
+        if obsolete >= window_size {
+            self.output.drain(..obsolete);
+            self.read -= obsolete;
+        }
+    }
+
+    /// Whether the last block was decoded (its output might not be read yet).
+    pub(crate) fn is_done(&self) -> bool {
+        matches!(self.state, State::Done)
+    }
+
+    // Decode a block header, a stored chunk, or a symbol. Nothing is written unless the step completes.
+    fn step(&mut self, bits: &mut Bits) -> Result<(), Stop> {
+        match &mut self.state {
+            State::Header => {
+                let last = bits.take(1)? == 1;
+                self.state = match bits.take(2)? {
+                    0 => {
+                        // Skip to the byte boundary.
+                        bits.take(bits.count % 8)?;
+                        let (len, nlen) = (bits.take(16)?, bits.take(16)?);
+                        if len != !nlen & 0xffff {
+                            return Err(Stop::Invalid);
+                        }
+                        State::Stored(len as u16)
+                    }
+                    1 => {
+                        let (literals, distances) = Huffman::fixed();
+                        State::Block(literals, distances)
+                    }
+                    2 => {
+                        let (literals, distances) = dynamic_codes(bits, self.distance_codes())?;
+                        State::Block(literals, distances)
+                    }
+                    _ => return Err(Stop::Invalid),
+                };
+                self.last = last;
+            }
+            State::Stored(0) => self.end_block(),
+            State::Stored(left) => {
+                // Whole bytes only, the bit buffer is empty after the length fields.
+                let available = &bits.input[bits.pos..];
+                if available.is_empty() {
+                    return Err(Stop::Input);
+                }
+                let n = available.len().min(*left as usize);
+                self.output.extend_from_slice(&available[..n]);
+                bits.pos += n;
+                *left -= n as u16;
+            }
+            State::Block(literals, distances) => {
+                let symbol = bits.decode(literals)?;
+                match symbol {
+                    0..=255 => self.output.push(symbol as u8),
+                    END_OF_BLOCK => self.end_block(),
+                    _ => {
+                        let index = (symbol - 257) as usize;
+                        if index >= LENGTH_BASE.len() {
+                            return Err(Stop::Invalid);
+                        }
+                        // Deflate64 redefines the last length code to carry 16 extra bits.
+                        let len = if self.deflate64 && index == LENGTH_BASE.len() - 1 {
+                            3 + bits.take(16)? as usize
+                        } else {
+                            LENGTH_BASE[index] as usize
+                                + bits.take(LENGTH_EXTRA[index] as u32)? as usize
+                        };
+                        let index = bits.decode(distances)? as usize;
+                        if index >= self.distance_codes() {
+                            return Err(Stop::Invalid);
+                        }
+                        let distance = DISTANCE_BASE[index] as usize
+                            + bits.take(DISTANCE_EXTRA[index] as u32)? as usize;
+                        if distance > self.output.len() {
+                            return Err(Stop::Invalid);
+                        }
+                        for _ in 0..len {
+                            self.output.push(self.output[self.output.len() - distance]);
+                        }
+                    }
+                }
+            }
+            State::Done => {}
+        }
+        Ok(())
+    }
+
+    // Deflate64 adds two distance codes, for distances above 32 KiB.
+    fn distance_codes(&self) -> usize {
+        if self.deflate64 {
+            32
+        } else {
+            30
+        }
+    }
+
+    fn end_block(&mut self) {
+        self.state = if self.last {
+            State::Done
+        } else {
+            State::Header
+        };
+    }
+}
+
+// Read the code lengths of a dynamic block's codes.
+fn dynamic_codes(bits: &mut Bits, distance_codes: usize) -> Result<(Huffman, Huffman), Stop> {
+    let literal_count = bits.take(5)? as usize + 257;
+    let distance_count = bits.take(5)? as usize + 1;
+    let code_length_count = bits.take(4)? as usize + 4;
+    if literal_count > 286 || distance_count > distance_codes {
+        return Err(Stop::Invalid);
+    }
+
+    let mut code_length_lengths = [0u8; 19];
+    for &index in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_length_lengths[index] = bits.take(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_length_lengths)?;
+
+    let mut lengths = vec![0u8; literal_count + distance_count];
+    let mut i = 0;
+    while i < lengths.len() {
+        let (len, repeat) = match bits.decode(&code_lengths)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 if i > 0 => (lengths[i - 1], 3 + bits.take(2)? as usize),
+            17 => (0, 3 + bits.take(3)? as usize),
+            18 => (0, 11 + bits.take(7)? as usize),
+            _ => return Err(Stop::Invalid),
+        };
+        if i + repeat > lengths.len() {
+            return Err(Stop::Invalid);
+        }
+        lengths[i..i + repeat].fill(len);
+        i += repeat;
+    }
+    if lengths[END_OF_BLOCK as usize] == 0 {
+        return Err(Stop::Invalid);
+    }
+    Ok((
+        Huffman::new(&lengths[..literal_count])?,
+        Huffman::new(&lengths[literal_count..])?,
+    ))
+}
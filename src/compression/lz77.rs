@@ -0,0 +1,99 @@
+const HASH_BITS: u32 = 16;
+const MIN_MATCH_LEN: usize = 3;
+const MAX_CHAIN_LEN: usize = 48;
+
+/// A sliding window with hash chains, finding previous occurrences of the upcoming bytes.
+///
+/// Positions are absolute (counted from the start of the input), the window only keeps the bytes that can still be referenced.
+pub(crate) struct MatchFinder {
+    window: Vec<u8>,
+    window_start: usize,
+    window_size: usize,
+    head: Vec<usize>,
+    chain: Vec<usize>,
+}
+
+impl MatchFinder {
+    pub(crate) fn new(window_size: usize) -> Self {
+        Self {
+            window: Vec::new(),
+            window_start: 0,
+            window_size,
+            head: vec![usize::MAX; 1 << HASH_BITS],
+            chain: vec![usize::MAX; window_size],
+        }
+    }
+
+    pub(crate) fn push(&mut self, input: &[u8]) {
+        self.window.extend_from_slice(input);
+    }
+
+    /// The absolute position following the last pushed byte.
+    pub(crate) fn end(&self) -> usize {
+        self.window_start + self.window.len()
+    }
+
+    pub(crate) fn byte(&self, position: usize) -> u8 {
+        self.window[position - self.window_start]
+    }
+
+    pub(crate) fn bytes(&self, start: usize, end: usize) -> &[u8] {
+        &self.window[start - self.window_start..end - self.window_start]
+    }
+
+    fn hash(&self, position: usize) -> usize {
+        let bytes = self.bytes(position, position + MIN_MATCH_LEN);
+        let bytes = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]);
+        (bytes.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+    }
+
+    /// Register the position so it can be referenced by the following matches.
+    pub(crate) fn insert(&mut self, position: usize) {
+        if position + MIN_MATCH_LEN > self.end() {
+            return;
+        }
+        let hash = self.hash(position);
+        self.chain[position % self.window_size] = self.head[hash];
+        self.head[hash] = position;
+    }
+
+    /// Find the longest match (of at least 3 bytes) for the given position, returning its length and distance.
+    pub(crate) fn find(&self, position: usize, max_len: usize) -> Option<(usize, usize)> {
+        let max_len = (self.end() - position).min(max_len);
+        if max_len < MIN_MATCH_LEN {
+            return None;
+        }
+        let current = self.bytes(position, position + max_len);
+        let mut best = None;
+        let mut candidate = self.head[self.hash(position)];
+        for _ in 0..MAX_CHAIN_LEN {
+            if candidate == usize::MAX
+                || candidate >= position
+                || position - candidate > self.window_size
+            {
+                break;
+            }
+            let len = self.window[candidate - self.window_start..]
+                .iter()
+                .zip(current)
+                .take_while(|(a, b)| a == b)
+                .count();
+            if len >= MIN_MATCH_LEN && best.is_none_or(|(best_len, _)| len > best_len) {
+                best = Some((len, position - candidate));
+                if len == max_len {
+                    break;
+                }
+            }
+            candidate = self.chain[candidate % self.window_size];
+        }
+        best
+    }
+
+    /// Drop the bytes before `keep_from`, once enough of them accumulated.
+    pub(crate) fn trim(&mut self, keep_from: usize) {
+        if keep_from > self.window_start + self.window_size {
+            self.window.drain(..keep_from - self.window_start);
+            self.window_start = keep_from;
+        }
+    }
+}
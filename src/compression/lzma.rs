@@ -0,0 +1,310 @@
+use super::lz77::MatchFinder;
+use super::Compressor;
+
+// Literal context bits, literal position bits and position bits, the usual LZMA defaults.
+const LC: u32 = 3;
+const LP: u32 = 0;
+const PB: u32 = 2;
+const DICT_SIZE: usize = 1 << 20;
+const MAX_MATCH_LEN: usize = 273;
+const STATES: usize = 12;
+const PROB_INIT: u16 = 1 << 10;
+const END_MARKER_DISTANCE: u32 = 0xffffffff;
+
+/// A streaming LZMA encoder, producing the header expected by zip readers followed by a raw LZMA stream terminated by an end marker.
+pub(crate) struct Encoder {
+    rc: RangeEncoder,
+    matcher: MatchFinder,
+    position: usize,
+    state: usize,
+    rep0: u32,
+    is_match: [u16; STATES << 4],
+    is_rep: [u16; STATES],
+    literal: Vec<u16>,
+    pos_slot: [[u16; 64]; 4],
+    pos_special: [u16; 115],
+    align: [u16; 16],
+    len: LenEncoder,
+}
+
+impl Encoder {
+    pub(crate) fn new() -> Self {
+        let mut rc = RangeEncoder::default();
+        rc.output.extend_from_slice(&[9, 20]); // LZMA SDK version.
+        rc.output.extend_from_slice(&5u16.to_le_bytes()); // Properties size.
+        rc.output.push(((PB * 5 + LP) * 9 + LC) as u8);
+        rc.output
+            .extend_from_slice(&(DICT_SIZE as u32).to_le_bytes());
+        Self {
+            rc,
+            matcher: MatchFinder::new(DICT_SIZE),
+            position: 0,
+            state: 0,
+            rep0: 0,
+            is_match: [PROB_INIT; STATES << 4],
+            is_rep: [PROB_INIT; STATES],
+            literal: vec![PROB_INIT; 0x300 << (LC + LP)],
+            pos_slot: [[PROB_INIT; 64]; 4],
+            pos_special: [PROB_INIT; 115],
+            align: [PROB_INIT; 16],
+            len: LenEncoder::default(),
+        }
+    }
+
+    // Encode the buffered data, keeping enough lookahead for the longest match unless the input is complete.
+    fn encode(&mut self, complete: bool) {
+        let end = self.matcher.end();
+        while self.position < end && (complete || end - self.position >= MAX_MATCH_LEN) {
+            let pos_state = self.position & ((1 << PB) - 1);
+            match self.matcher.find(self.position, MAX_MATCH_LEN) {
+                Some((len, distance)) => {
+                    self.rc
+                        .encode_bit(&mut self.is_match[self.state << 4 | pos_state], 1);
+                    self.rc.encode_bit(&mut self.is_rep[self.state], 0);
+                    self.len.encode(&mut self.rc, len - 2, pos_state);
+                    self.state = if self.state < 7 { 7 } else { 10 };
+                    self.rep0 = distance as u32 - 1;
+                    self.encode_distance(self.rep0, len - 2);
+                    for position in self.position..self.position + len {
+                        self.matcher.insert(position);
+                    }
+                    self.position += len;
+                }
+                None => {
+                    self.rc
+                        .encode_bit(&mut self.is_match[self.state << 4 | pos_state], 0);
+                    self.encode_literal();
+                    self.matcher.insert(self.position);
+                    self.position += 1;
+                }
+            }
+        }
+        self.matcher
+            .trim(self.position.saturating_sub(DICT_SIZE + 1));
+    }
+
+    fn encode_literal(&mut self) {
+        let byte = self.matcher.byte(self.position) as usize;
+        let previous = if self.position > 0 {
+            self.matcher.byte(self.position - 1) as usize
+        } else {
+            0
+        };
+        let probs = &mut self.literal[0x300 * (previous >> (8 - LC))..][..0x300];
+        let mut symbol = 1;
+        if self.state >= 7 {
+            let match_byte = self.matcher.byte(self.position - self.rep0 as usize - 1) as usize;
+            let mut matched = true;
+            for i in (0..8).rev() {
+                let bit = (byte >> i) & 1;
+                if matched {
+                    let match_bit = (match_byte >> i) & 1;
+                    self.rc
+                        .encode_bit(&mut probs[((1 + match_bit) << 8) + symbol], bit as u32);
+                    matched = match_bit == bit;
+                } else {
+                    self.rc.encode_bit(&mut probs[symbol], bit as u32);
+                }
+                symbol = symbol << 1 | bit;
+            }
+        } else {
+            encode_tree(&mut self.rc, probs, 8, byte as u32);
+        }
+        self.state = match self.state {
+            0..=3 => 0,
+            4..=9 => self.state - 3,
+            _ => self.state - 6,
+        };
+    }
+
+    fn encode_distance(&mut self, distance: u32, len: usize) {
+        let slot = if distance < 4 {
+            distance
+        } else {
+            let bits = 31 - distance.leading_zeros();
+            bits << 1 | (distance >> (bits - 1)) & 1
+        };
+        encode_tree(&mut self.rc, &mut self.pos_slot[len.min(3)], 6, slot);
+        if slot >= 4 {
+            let footer_bits = (slot >> 1) - 1;
+            let base = (2 | slot & 1) << footer_bits;
+            let reduced = distance - base;
+            if slot < 14 {
+                encode_reverse_tree(
+                    &mut self.rc,
+                    &mut self.pos_special[(base - slot) as usize..],
+                    footer_bits,
+                    reduced,
+                );
+            } else {
+                self.rc.encode_direct_bits(reduced >> 4, footer_bits - 4);
+                encode_reverse_tree(&mut self.rc, &mut self.align, 4, reduced & 0xf);
+            }
+        }
+    }
+}
+
+impl Compressor for Encoder {
+    fn method(&self) -> u16 {
+        14
+    }
+
+    fn version_needed(&self) -> u16 {
+        63
+    }
+
+    fn flags(&self) -> u16 {
+        1 << 1 // End of stream marker present.
+    }
+
+    fn compress(&mut self, input: &[u8], output: &mut Vec<u8>) {
+        self.matcher.push(input);
+        self.encode(false);
+        output.append(&mut self.rc.output);
+    }
+
+    fn finish(&mut self, output: &mut Vec<u8>) {
+        self.encode(true);
+        let pos_state = self.position & ((1 << PB) - 1);
+        self.rc
+            .encode_bit(&mut self.is_match[self.state << 4 | pos_state], 1);
+        self.rc.encode_bit(&mut self.is_rep[self.state], 0);
+        self.len.encode(&mut self.rc, 0, pos_state);
+        self.encode_distance(END_MARKER_DISTANCE, 0);
+        self.rc.flush();
+        output.append(&mut self.rc.output);
+    }
+}
+
+struct LenEncoder {
+    choice: u16,
+    choice2: u16,
+    low: [[u16; 8]; 1 << PB],
+    mid: [[u16; 8]; 1 << PB],
+    high: [u16; 256],
+}
+
+impl Default for LenEncoder {
+    fn default() -> Self {
+        Self {
+            choice: PROB_INIT,
+            choice2: PROB_INIT,
+            low: [[PROB_INIT; 8]; 1 << PB],
+            mid: [[PROB_INIT; 8]; 1 << PB],
+            high: [PROB_INIT; 256],
+        }
+    }
+}
+
+impl LenEncoder {
+    // Encode a match length minus the minimum length (2).
+    fn encode(&mut self, rc: &mut RangeEncoder, len: usize, pos_state: usize) {
+        if len < 8 {
+            rc.encode_bit(&mut self.choice, 0);
+            encode_tree(rc, &mut self.low[pos_state], 3, len as u32);
+        } else if len < 16 {
+            rc.encode_bit(&mut self.choice, 1);
+            rc.encode_bit(&mut self.choice2, 0);
+            encode_tree(rc, &mut self.mid[pos_state], 3, len as u32 - 8);
+        } else {
+            rc.encode_bit(&mut self.choice, 1);
+            rc.encode_bit(&mut self.choice2, 1);
+            encode_tree(rc, &mut self.high, 8, len as u32 - 16);
+        }
+    }
+}
+
+fn encode_tree(rc: &mut RangeEncoder, probs: &mut [u16], bits: u32, symbol: u32) {
+    let mut index = 1;
+    for i in (0..bits).rev() {
+        let bit = (symbol >> i) & 1;
+        rc.encode_bit(&mut probs[index], bit);
+        index = index << 1 | bit as usize;
+    }
+}
+
+fn encode_reverse_tree(rc: &mut RangeEncoder, probs: &mut [u16], bits: u32, mut symbol: u32) {
+    let mut index = 1;
+    for _ in 0..bits {
+        let bit = symbol & 1;
+        symbol >>= 1;
+        rc.encode_bit(&mut probs[index], bit);
+        index = index << 1 | bit as usize;
+    }
+}
+
+struct RangeEncoder {
+    output: Vec<u8>,
+    low: u64,
+    range: u32,
+    cache: u8,
+    cache_size: u64,
+}
+
+impl Default for RangeEncoder {
+    fn default() -> Self {
+        Self {
+            output: Vec::new(),
+            low: 0,
+            range: 0xffffffff,
+            cache: 0,
+            cache_size: 1,
+        }
+    }
+}
+
+impl RangeEncoder {
+    fn encode_bit(&mut self, prob: &mut u16, bit: u32) {
+        let bound = (self.range >> 11) * *prob as u32;
+        if bit == 0 {
+            self.range = bound;
+            *prob += ((1 << 11) - *prob) >> 5;
+        } else {
+            self.low += bound as u64;
+            self.range -= bound;
+            *prob -= *prob >> 5;
+        }
+        self.normalize();
+    }
+
+    fn encode_direct_bits(&mut self, value: u32, bits: u32) {
+        for i in (0..bits).rev() {
+            self.range >>= 1;
+            if (value >> i) & 1 == 1 {
+                self.low += self.range as u64;
+            }
+            self.normalize();
+        }
+    }
+
+    fn normalize(&mut self) {
+        while self.range < 1 << 24 {
+            self.range <<= 8;
+            self.shift_low();
+        }
+    }
+
+    fn shift_low(&mut self) {
+        if (self.low as u32) < 0xff000000 || self.low >> 32 != 0 {
+            let carry = (self.low >> 32) as u8;
+            let mut byte = self.cache;
+            loop {
+                self.output.push(byte.wrapping_add(carry));
+                byte = 0xff;
+                self.cache_size -= 1;
+                if self.cache_size == 0 {
+                    break;
+                }
+            }
+            self.cache = (self.low >> 24) as u8;
+        }
+        self.cache_size += 1;
+        self.low = (self.low & 0x00ffffff) << 8;
+    }
+
+    fn flush(&mut self) {
+        for _ in 0..5 {
+            self.shift_low();
+        }
+    }
+}
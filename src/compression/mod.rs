@@ -0,0 +1,236 @@
+#[cfg(feature = "bzip2-compression")]
+mod bzip2;
+#[cfg(any(feature = "deflate-compression", feature = "deflate64-compression"))]
+mod deflate;
+#[cfg(any(
+    feature = "bzip2-compression",
+    feature = "deflate-compression",
+    feature = "deflate64-compression"
+))]
+mod huffman;
+#[cfg(feature = "deflate-compression")]
+pub(crate) mod inflate;
+#[cfg(any(
+    feature = "lzma-compression",
+    feature = "deflate-compression",
+    feature = "deflate64-compression"
+))]
+mod lz77;
+#[cfg(feature = "lzma-compression")]
+mod lzma;
+
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::sync::Arc;
+
+/// The method used to compress an entry's payload.
+///
+/// Every method but `Stored` requires its own feature to be enabled.
+/// Use `EntryOptions::compressor` for methods not provided by the crate.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub enum Compression {
+    /// No compression, the payload is written as is.
+    #[default]
+    Stored,
+    /// Deflate compression (`deflate-compression` feature required).
+    #[cfg(feature = "deflate-compression")]
+    Deflate,
+    /// Deflate64 (enhanced deflate) compression, using a 64 KiB window (`deflate64-compression` feature required).
+    #[cfg(feature = "deflate64-compression")]
+    Deflate64,
+    /// Bzip2 compression, using 900k blocks (`bzip2-compression` feature required).
+    #[cfg(feature = "bzip2-compression")]
+    Bzip2,
+    /// LZMA compression, using a 1 MiB dictionary and an end of stream marker (`lzma-compression` feature required).
+    #[cfg(feature = "lzma-compression")]
+    Lzma,
+}
+
+impl Compression {
+    // The built-in compressor of the method, `None` if the payload is stored.
+    pub(crate) fn compressor(self) -> Option<Box<dyn Compressor + Send>> {
+        match self {
+            Compression::Stored => None,
+            #[cfg(feature = "deflate-compression")]
+            Compression::Deflate => {
+                Some(Box::new(deflate::Encoder::new(deflate::Variant::Deflate)))
+            }
+            #[cfg(feature = "deflate64-compression")]
+            Compression::Deflate64 => {
+                Some(Box::new(deflate::Encoder::new(deflate::Variant::Deflate64)))
+            }
+            #[cfg(feature = "bzip2-compression")]
+            Compression::Bzip2 => Some(Box::new(bzip2::Encoder::new())),
+            #[cfg(feature = "lzma-compression")]
+            Compression::Lzma => Some(Box::new(lzma::Encoder::new())),
+        }
+    }
+}
+
+/// A codec compressing an entry's payload, driven chunk by chunk while the payload is streamed.
+///
+/// Implement this trait to use a compression method (or an implementation) not provided by the crate,
+/// and pass it to `EntryOptions::compressor`. The compressed size is the total of the bytes appended to the outputs.
+///
+/// ## Example
+///
+/// ```
+/// use zipit::Compressor;
+///
+/// // Store the payload reversed by blocks of 4 bytes, method 99 being reserved for proprietary codecs.
+/// struct Reverse(Vec<u8>);
+///
+/// impl Compressor for Reverse {
+///     fn method(&self) -> u16 {
+///         99
+///     }
+///
+///     fn compress(&mut self, input: &[u8], output: &mut Vec<u8>) {
+///         self.0.extend_from_slice(input);
+///         let full = self.0.len() / 4 * 4;
+///         for block in self.0.drain(..full).collect::<Vec<_>>().chunks(4) {
+///             output.extend(block.iter().rev());
+///         }
+///     }
+///
+///     fn finish(&mut self, output: &mut Vec<u8>) {
+///         output.extend(self.0.iter().rev());
+///     }
+/// }
+/// ```
+pub trait Compressor {
+    /// The compression method written in the entry's headers.
+    fn method(&self) -> u16;
+
+    /// The minimum version needed to extract the entry, as written in its headers (2.0 by default).
+    fn version_needed(&self) -> u16 {
+        20
+    }
+
+    /// Method specific bits (1 and 2) of the general purpose flag.
+    fn flags(&self) -> u16 {
+        0
+    }
+
+    /// Compress a chunk of the payload, appending the compressed bytes that are ready to be written to `output`.
+    fn compress(&mut self, input: &[u8], output: &mut Vec<u8>);
+
+    /// Append the remaining compressed bytes to `output`, once the whole payload has been compressed.
+    fn finish(&mut self, output: &mut Vec<u8>);
+}
+
+/// A shareable function creating a new compressor for each entry.
+#[derive(Clone)]
+pub(crate) struct CompressorFactory(Arc<dyn Fn() -> Box<dyn Compressor + Send> + Send + Sync>);
+
+impl CompressorFactory {
+    pub(crate) fn new<F, C>(factory: F) -> Self
+    where
+        F: Fn() -> C + Send + Sync + 'static,
+        C: Compressor + Send + 'static,
+    {
+        Self(Arc::new(move || Box::new(factory())))
+    }
+
+    pub(crate) fn build(&self) -> Box<dyn Compressor + Send> {
+        (self.0)()
+    }
+}
+
+impl Debug for CompressorFactory {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str("CompressorFactory")
+    }
+}
+
+/// The compression related fields of an entry's headers.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Method {
+    pub(crate) code: u16,
+    pub(crate) version_needed: u16,
+    pub(crate) flags: u16,
+}
+
+/// Compress an entry's payload chunk by chunk, passing it through if it is stored.
+pub(crate) struct Encoder {
+    method: Method,
+    compressor: Option<Box<dyn Compressor + Send>>,
+    output: Vec<u8>,
+}
+
+impl Encoder {
+    pub(crate) fn new(compressor: Option<Box<dyn Compressor + Send>>) -> Self {
+        let method = match &compressor {
+            Some(compressor) => Method {
+                code: compressor.method(),
+                version_needed: compressor.version_needed(),
+                flags: compressor.flags(),
+            },
+            None => Method {
+                code: 0,
+                version_needed: 10,
+                flags: 0,
+            },
+        };
+        Self {
+            method,
+            compressor,
+            output: Vec::new(),
+        }
+    }
+
+    /// Pass an already compressed payload through, with the given compression method and the flags describing the payload
+    /// (bits 1 and 2 of the general purpose flag, e.g. copied from another archive).
+    /// Without flags, an LZMA payload is expected to end with an end of stream marker, like the ones of `Compression::Lzma`.
+    pub(crate) fn raw(code: u16, flags: Option<u16>) -> Self {
+        let version_needed = match code {
+            0 => 10,
+            9 => 21,
+            12 => 46,
+            14 => 63,
+            _ => 20,
+        };
+        let flags = flags.unwrap_or(match code {
+            14 => 1 << 1, // End of stream marker present.
+            _ => 0,
+        });
+        Self {
+            method: Method {
+                code,
+                version_needed,
+                flags,
+            },
+            compressor: None,
+            output: Vec::new(),
+        }
+    }
+
+    pub(crate) fn method(&self) -> Method {
+        self.method
+    }
+
+    /// Whether the payload is compressed (and not finished yet).
+    pub(crate) fn compresses(&self) -> bool {
+        self.compressor.is_some()
+    }
+
+    /// Feed a chunk of the payload, returning the compressed bytes that are ready to be written.
+    pub(crate) fn update<'a>(&'a mut self, input: &'a [u8]) -> &'a [u8] {
+        self.output.clear();
+        match &mut self.compressor {
+            Some(compressor) => {
+                compressor.compress(input, &mut self.output);
+                &self.output
+            }
+            None => input,
+        }
+    }
+
+    /// Flush the remaining compressed bytes, nothing is returned if the encoder was already finished.
+    pub(crate) fn finish(&mut self) -> &[u8] {
+        self.output.clear();
+        if let Some(mut compressor) = self.compressor.take() {
+            compressor.finish(&mut self.output);
+        }
+        &self.output
+    }
+}
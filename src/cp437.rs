@@ -0,0 +1,43 @@
+/// The characters of the upper half of code page 437, from `0x80` to `0xFF`.
+/// Its lower half is ASCII.
+const UPPER_HALF: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å', //
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ', //
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»', //
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐', //
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧', //
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀', //
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩', //
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{a0}', //
+];
+
+// The code page 437 byte of a character, if it has one.
+fn byte(c: char) -> Option<u8> {
+    if c.is_ascii() {
+        return Some(c as u8);
+    }
+    UPPER_HALF
+        .iter()
+        .position(|&upper| upper == c)
+        .map(|i| 0x80 + i as u8)
+}
+
+/// Encode a name in code page 437, if it can represent all of its characters.
+pub(crate) fn encode(name: &str) -> Option<Vec<u8>> {
+    name.chars().map(byte).collect()
+}
+
+/// Encode a name in code page 437, replacing the characters it can't represent with an underscore.
+pub(crate) fn transliterate(name: &str) -> Vec<u8> {
+    name.chars().map(|c| byte(c).unwrap_or(b'_')).collect()
+}
+
+/// Decode a name encoded in code page 437.
+pub(crate) fn decode(name: &[u8]) -> String {
+    name.iter()
+        .map(|&byte| match byte {
+            0..=0x7f => byte as char,
+            _ => UPPER_HALF[byte as usize - 0x80],
+        })
+        .collect()
+}
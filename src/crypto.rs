@@ -0,0 +1,97 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+/// Size of the encryption header preceding the encrypted payload.
+pub(crate) const ZIP_CRYPTO_HEADER_SIZE: usize = 12;
+
+// The CRC32 table used by the key updates (same polynomial as the entries' CRC32).
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                0xedb88320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// The traditional PKWARE encryption (ZipCrypto) of an entry's payload.
+pub(crate) struct ZipCrypto {
+    keys: [u32; 3],
+}
+
+impl ZipCrypto {
+    pub(crate) fn new(password: &[u8]) -> Self {
+        let mut cipher = Self {
+            keys: [0x12345678, 0x23456789, 0x34567890],
+        };
+        for &byte in password {
+            cipher.update_keys(byte);
+        }
+        cipher
+    }
+
+    fn update_keys(&mut self, byte: u8) {
+        self.keys[0] = crc32_byte(self.keys[0], byte);
+        self.keys[1] = self.keys[1]
+            .wrapping_add(self.keys[0] & 0xff)
+            .wrapping_mul(134775813)
+            .wrapping_add(1);
+        self.keys[2] = crc32_byte(self.keys[2], (self.keys[1] >> 24) as u8);
+    }
+
+    /// Encrypt a chunk of the payload in place.
+    pub(crate) fn encrypt(&mut self, data: &mut [u8]) {
+        for byte in data {
+            let temp = (self.keys[2] | 2) & 0xffff;
+            let plain = *byte;
+            *byte ^= (temp.wrapping_mul(temp ^ 1) >> 8) as u8;
+            self.update_keys(plain);
+        }
+    }
+
+    /// The encrypted header: 11 random bytes followed by the byte readers check the password against.
+    pub(crate) fn header(&mut self, check: u8) -> [u8; ZIP_CRYPTO_HEADER_SIZE] {
+        let mut header = [0; ZIP_CRYPTO_HEADER_SIZE];
+        for (i, chunk) in header[..11].chunks_mut(8).enumerate() {
+            // Randomly seeded by the standard library.
+            let mut hasher = RandomState::new().build_hasher();
+            hasher.write_usize(i);
+            chunk.copy_from_slice(&hasher.finish().to_le_bytes()[..chunk.len()]);
+        }
+        header[11] = check;
+        self.encrypt(&mut header);
+        header
+    }
+
+    /// Encrypt a chunk of the payload into `buffer` if there is a cipher, or pass it through.
+    pub(crate) fn seal<'a>(
+        cipher: &mut Option<Self>,
+        chunk: &'a [u8],
+        buffer: &'a mut Vec<u8>,
+    ) -> &'a [u8] {
+        match cipher {
+            Some(cipher) => {
+                buffer.clear();
+                buffer.extend_from_slice(chunk);
+                cipher.encrypt(buffer);
+                buffer
+            }
+            None => chunk,
+        }
+    }
+}
+
+fn crc32_byte(crc: u32, byte: u8) -> u32 {
+    (crc >> 8) ^ CRC_TABLE[((crc ^ byte as u32) & 0xff) as usize]
+}
@@ -0,0 +1,90 @@
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::sync::Arc;
+
+use crate::sha256::Sha256;
+use crate::signature::Signer;
+
+/// A hash function computing the digest of data fed chunk by chunk, e.g. `Sha256` (provided) or SHA-1 or BLAKE3.
+///
+/// Implement this trait to pick the algorithm expected downstream, for the digests of the entries listed by `ArchiveOptions::checksums_with`.
+/// Every digest is also a `Signer`, computing the digest of the whole archive with `ArchiveOptions::signer`.
+///
+/// ## Example
+///
+/// ```
+/// use zipit::Digest;
+///
+/// // The (insecure) 32-bit FNV-1a hash, standing for a real hash function.
+/// struct Fnv(u32);
+///
+/// impl Digest for Fnv {
+///     fn update(&mut self, data: &[u8]) {
+///         for &byte in data {
+///             self.0 = (self.0 ^ byte as u32).wrapping_mul(0x01000193);
+///         }
+///     }
+///
+///     fn finish(&mut self) -> Vec<u8> {
+///         self.0.to_be_bytes().to_vec()
+///     }
+/// }
+/// ```
+pub trait Digest {
+    /// Feed the next bytes of the data.
+    fn update(&mut self, data: &[u8]);
+
+    /// Compute the digest of the data fed so far.
+    fn finish(&mut self) -> Vec<u8>;
+}
+
+impl<D: Digest> Signer for D {
+    fn update(&mut self, data: &[u8]) {
+        Digest::update(self, data);
+    }
+
+    fn sign(&mut self) -> Vec<u8> {
+        self.finish()
+    }
+}
+
+/// A shareable function creating a new digest for each entry.
+#[derive(Clone)]
+pub(crate) struct DigestFactory {
+    factory: Arc<dyn Fn() -> Box<dyn Digest + Send> + Send + Sync>,
+    custom: bool,
+}
+
+impl DigestFactory {
+    pub(crate) fn new<F, D>(factory: F) -> Self
+    where
+        F: Fn() -> D + Send + Sync + 'static,
+        D: Digest + Send + 'static,
+    {
+        Self {
+            factory: Arc::new(move || Box::new(factory())),
+            custom: true,
+        }
+    }
+
+    /// The provided SHA-256, whose digests (unlike custom ones) are known from the options.
+    pub(crate) fn sha256() -> Self {
+        Self {
+            custom: false,
+            ..Self::new(Sha256::new)
+        }
+    }
+
+    pub(crate) fn is_custom(&self) -> bool {
+        self.custom
+    }
+
+    pub(crate) fn build(&self) -> Box<dyn Digest + Send> {
+        (self.factory)()
+    }
+}
+
+impl Debug for DigestFactory {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str("DigestFactory")
+    }
+}
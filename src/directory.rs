@@ -0,0 +1,121 @@
+use std::io::Error as IoError;
+
+#[cfg(feature = "stream")]
+use crate::CENTRAL_DIRECTORY_ENTRY_BASE_SIZE;
+use crate::{
+    Error, END_OF_CENTRAL_DIRECTORY_SIZE, ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIZE,
+    ZIP64_END_OF_CENTRAL_DIRECTORY_SIZE,
+};
+
+/// The central directory of an archive (with its Zip64 and end of central directory records), detached from the archive
+/// by `Archive::finalize_detached`, to be cached and written again by `Archive::finalize_with_directory`
+/// when the same archive is generated again, instead of being rebuilt.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DetachedDirectory {
+    bytes: Vec<u8>,
+    offset: u64,
+    entry_count: u64,
+    // The position of the end of central directory record in the bytes.
+    end: usize,
+}
+
+impl DetachedDirectory {
+    /// Load a detached central directory from its bytes (see `as_bytes`), e.g. once read from a cache.
+    ///
+    /// # Error
+    ///
+    /// This function will return an `Error::InvalidArchive` if the bytes don't end with an end of central directory record.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, IoError> {
+        let u16_at = |offset: usize| u16::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+        let u32_at =
+            |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        let u64_at =
+            |offset: usize| u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+
+        // The last record whose comment ends with the bytes.
+        let end = (0..=bytes.len().saturating_sub(END_OF_CENTRAL_DIRECTORY_SIZE))
+            .rev()
+            .find(|&end| {
+                bytes.len() >= END_OF_CENTRAL_DIRECTORY_SIZE
+                    && u32_at(end) == 0x06054b50
+                    && end + END_OF_CENTRAL_DIRECTORY_SIZE + u16_at(end + 20) as usize
+                        == bytes.len()
+            })
+            .ok_or(Error::InvalidArchive { offset: 0 })?;
+        let locator = end.checked_sub(ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIZE);
+        let (entry_count, offset) = match locator.filter(|&locator| u32_at(locator) == 0x07064b50) {
+            // The Zip64 end of central directory record precedes its locator.
+            Some(locator) => {
+                let record = locator
+                    .checked_sub(ZIP64_END_OF_CENTRAL_DIRECTORY_SIZE)
+                    .filter(|&record| u32_at(record) == 0x06064b50)
+                    .ok_or(Error::InvalidArchive { offset: 0 })?;
+                (u64_at(record + 32), u64_at(record + 48))
+            }
+            None => (u16_at(end + 10) as u64, u32_at(end + 16) as u64),
+        };
+        Ok(Self {
+            bytes,
+            offset,
+            entry_count,
+            end,
+        })
+    }
+
+    /// The bytes of the central directory, written at the end of the archive.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// The bytes of the central directory, written at the end of the archive.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    /// The offset of the central directory in the archive, the size of its entries.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// The number of entries of the archive.
+    pub fn entry_count(&self) -> u64 {
+        self.entry_count
+    }
+
+    pub(crate) fn new(bytes: Vec<u8>, offset: u64, entry_count: u64, end: usize) -> Self {
+        Self {
+            bytes,
+            offset,
+            entry_count,
+            end,
+        }
+    }
+
+    // The CRC32 of the entries, read from their central directory records.
+    #[cfg(feature = "stream")]
+    pub(crate) fn crcs(&self) -> Vec<u32> {
+        let mut crcs = Vec::new();
+        let mut offset = 0;
+        let record = &self.bytes[..self.end];
+        while record.len() >= offset + CENTRAL_DIRECTORY_ENTRY_BASE_SIZE
+            && record[offset..offset + 4] == 0x02014b50u32.to_le_bytes()
+        {
+            let u16_at = |at: usize| {
+                u16::from_le_bytes([record[offset + at], record[offset + at + 1]]) as usize
+            };
+            crcs.push(u32::from_le_bytes(
+                record[offset + 16..offset + 20].try_into().unwrap(),
+            ));
+            offset += CENTRAL_DIRECTORY_ENTRY_BASE_SIZE + u16_at(28) + u16_at(30) + u16_at(32);
+        }
+        crcs
+    }
+
+    // The records written before the end of central directory record, and its comment.
+    pub(crate) fn split(&self) -> (&[u8], &[u8]) {
+        (
+            &self.bytes[..self.end],
+            &self.bytes[self.end + END_OF_CENTRAL_DIRECTORY_SIZE..],
+        )
+    }
+}
@@ -0,0 +1,217 @@
+use std::error::Error as StdError;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::io::{Error as IoError, ErrorKind};
+
+/// An error preventing the archive from being written (or read) correctly.
+///
+/// These errors are returned wrapped in an `std::io::Error`, use `std::io::Error::get_ref` and `downcast_ref` to retrieve them.
+/// Once one of them is returned by an `append` function, the archive is incomplete and shouldn't be finalized.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The payload of the entry, compressed or not, doesn't fit in 4 GiB and the entry wasn't appended with `EntryOptions::large_file`.
+    EntryTooLarge {
+        /// The name of the entry.
+        name: String,
+        /// The size of the payload.
+        size: u64,
+    },
+    /// The archive already holds 65,535 entries, the most a regular archive can hold, and Zip64 is disabled by `Zip64Mode::Never`.
+    TooManyEntries,
+    /// The name of the entry is longer than 65,535 bytes, or than the limit set with `ArchiveOptions::max_name_length`.
+    NameTooLong {
+        /// The name of the entry.
+        name: String,
+        /// The maximum length of names, in bytes.
+        max_length: usize,
+    },
+    /// The extra fields of the entry (e.g. its extended attributes) are larger than what its headers can hold.
+    ExtraFieldTooLarge {
+        /// The name of the entry.
+        name: String,
+    },
+    /// The comment of the entry is longer than 65,535 bytes.
+    CommentTooLong {
+        /// The name of the entry.
+        name: String,
+    },
+    /// The payload of the entry doesn't match the size and CRC32 given to `append_with_metadata`,
+    /// or the verified one given to `append_with_crc`, or the source changed between the two readings of `append_two_pass`.
+    MetadataMismatch {
+        /// The name of the entry.
+        name: String,
+    },
+    /// The archive is larger than 4 GiB, so the offsets of its entries or of its central directory can't be written,
+    /// and Zip64 is disabled by `Zip64Mode::Never`.
+    ArchiveTooLarge {
+        /// The size of the archive written so far.
+        size: u64,
+    },
+    /// The archive being read isn't a valid zip archive, or is truncated.
+    InvalidArchive {
+        /// The offset in the archive where the error was found.
+        offset: u64,
+    },
+    /// The payload of the entry being read can't be decompressed: it is encrypted,
+    /// or compressed with a method that isn't supported (or whose feature isn't enabled).
+    UnsupportedEntry {
+        /// The name of the entry.
+        name: String,
+    },
+    /// The payload of the entry being read is corrupted: it can't be decompressed, or doesn't match its CRC32 or sizes.
+    CorruptEntry {
+        /// The name of the entry.
+        name: String,
+    },
+    /// The name of the entry being extracted doesn't resolve to a path inside the destination directory
+    /// (e.g. it has a `..` component, or a Windows drive prefix).
+    UnsafePath {
+        /// The name of the entry.
+        name: String,
+    },
+    /// The payload of the entry isn't as long as declared in an `ArchivePlan` or given to `append_with_size_hint`,
+    /// or the reader given to `append_exact` ends before its size.
+    SizeMismatch {
+        /// The name of the entry.
+        name: String,
+        /// The declared size of the payload.
+        expected: u64,
+    },
+    /// The entries appended to a `PlannedArchive` don't match its plan: more entries were appended,
+    /// or it was finalized before all of them were.
+    PlanMismatch {
+        /// The number of entries of the plan.
+        planned: usize,
+        /// The number of entries appended (or being appended).
+        appended: usize,
+    },
+    /// The archive of an `ArchivePlan` can't be streamed from the offset given to `stream_from`:
+    /// the offset is past the end of the archive, or the archive is encrypted, as its encryption headers are random.
+    NotResumable {
+        /// The offset to resume the archive from.
+        offset: u64,
+    },
+    /// The central directory given to `Archive::finalize_with_directory` doesn't match the archive:
+    /// it doesn't start at the end of its entries, or doesn't have as many entries (or the same embedded signature).
+    DirectoryMismatch {
+        /// The offset of the end of the entries.
+        offset: u64,
+        /// The number of entries.
+        entry_count: u64,
+    },
+    /// Appending the entry would exceed the number of entries or the size of the payloads
+    /// allowed by `DirOptions::max_entries` or `DirOptions::max_size`.
+    DirectoryTooLarge {
+        /// The name of the entry.
+        name: String,
+    },
+    /// The signature to embed in the archive's comment (see `ArchiveOptions::embed_signature`) is longer than 65,535 bytes.
+    SignatureTooLong {
+        /// The size of the signature.
+        size: usize,
+    },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Error::EntryTooLarge { name, size } => write!(
+                f,
+                "entry {name:?} is {size} bytes long, which requires Zip64 (see EntryOptions::large_file)"
+            ),
+            Error::TooManyEntries => write!(
+                f,
+                "archive already holds 65535 entries, more require Zip64 (see ArchiveOptions::zip64)"
+            ),
+            Error::NameTooLong { name, max_length } => write!(
+                f,
+                "entry name is {} bytes long, more than the maximum of {max_length} bytes: {name:?}",
+                name.len()
+            ),
+            Error::ExtraFieldTooLarge { name } => write!(
+                f,
+                "extra fields of entry {name:?} exceed the maximum of 65535 bytes"
+            ),
+            Error::CommentTooLong { name } => write!(
+                f,
+                "comment of entry {name:?} exceeds the maximum of 65535 bytes"
+            ),
+            Error::MetadataMismatch { name } => write!(
+                f,
+                "payload of entry {name:?} doesn't match its declared size and CRC32"
+            ),
+            Error::ArchiveTooLarge { size } => write!(
+                f,
+                "archive is {size} bytes long, which requires Zip64 (see ArchiveOptions::zip64)"
+            ),
+            Error::InvalidArchive { offset } => {
+                write!(f, "archive is invalid or truncated at offset {offset}")
+            }
+            Error::UnsupportedEntry { name } => write!(
+                f,
+                "entry {name:?} is encrypted or compressed with an unsupported method"
+            ),
+            Error::CorruptEntry { name } => write!(
+                f,
+                "payload of entry {name:?} is corrupted or doesn't match its CRC32 and sizes"
+            ),
+            Error::UnsafePath { name } => write!(
+                f,
+                "entry {name:?} would be extracted outside of the destination directory"
+            ),
+            Error::SizeMismatch { name, expected } => write!(
+                f,
+                "payload of entry {name:?} isn't {expected} bytes long as declared"
+            ),
+            Error::PlanMismatch { planned, appended } => write!(
+                f,
+                "archive planned with {planned} entries has {appended} entries appended"
+            ),
+            Error::NotResumable { offset } => {
+                write!(f, "archive can't be streamed from offset {offset}")
+            }
+            Error::DirectoryMismatch {
+                offset,
+                entry_count,
+            } => write!(
+                f,
+                "central directory doesn't match the archive of {entry_count} entries ending at offset {offset}"
+            ),
+            Error::DirectoryTooLarge { name } => write!(
+                f,
+                "entry {name:?} exceeds the maximum number of entries or size of the directory"
+            ),
+            Error::SignatureTooLong { size } => write!(
+                f,
+                "signature is {size} bytes long, more than the maximum of 65535 bytes of the archive comment"
+            ),
+        }
+    }
+}
+
+impl StdError for Error {}
+
+impl From<Error> for IoError {
+    fn from(error: Error) -> Self {
+        let kind = match error {
+            Error::EntryTooLarge { .. }
+            | Error::TooManyEntries
+            | Error::NameTooLong { .. }
+            | Error::ExtraFieldTooLarge { .. }
+            | Error::CommentTooLong { .. }
+            | Error::ArchiveTooLarge { .. }
+            | Error::PlanMismatch { .. }
+            | Error::NotResumable { .. }
+            | Error::DirectoryMismatch { .. }
+            | Error::DirectoryTooLarge { .. }
+            | Error::SignatureTooLong { .. } => ErrorKind::InvalidInput,
+            Error::MetadataMismatch { .. }
+            | Error::SizeMismatch { .. }
+            | Error::InvalidArchive { .. }
+            | Error::CorruptEntry { .. }
+            | Error::UnsafePath { .. } => ErrorKind::InvalidData,
+            Error::UnsupportedEntry { .. } => ErrorKind::Unsupported,
+        };
+        IoError::new(kind, error)
+    }
+}
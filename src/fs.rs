@@ -0,0 +1,909 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::fs::Metadata;
+use std::future::Future;
+use std::io::{Error as IoError, ErrorKind};
+use std::path::{Component, Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::Poll;
+
+use tokio::fs::{self, DirEntry, File};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+
+use crate::read::{ArchiveReader, Entry, Glob};
+use crate::{Archive, EntryOptions, Error, FileDateTime};
+
+const EXTRACT_BUFFER_SIZE: usize = 64 * 1024;
+// The files listing the paths to leave out of an archive, read in every directory with `DirOptions::ignore_files`.
+const IGNORE_FILES: [&str; 2] = [".gitignore", ".zipignore"];
+// The external attributes of a symbolic link entry: its Unix file type and `rwxrwxrwx` permissions.
+const SYMLINK_ATTRIBUTES: u32 = (0o120000 | 0o777) << 16;
+
+/// How `append_dir_all` handles the symbolic links of a directory.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    /// Follow the symbolic links to files, appending the content of their targets, and skip the ones to directories.
+    #[default]
+    FollowFiles,
+    /// Follow every symbolic link, appending the content of their targets, walking the directories too.
+    /// A link to a directory already being walked (a cycle) is skipped.
+    Follow,
+    /// Append the symbolic links as symbolic link entries, whose payload is their target, restored by most extraction tools on Unix.
+    Store,
+    /// Skip the symbolic links.
+    Skip,
+}
+
+/// Why `append_dir_all` skipped a file or directory (with its content).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SkipReason {
+    /// Its name starts with a dot, see `DirOptions::hidden`.
+    Hidden,
+    /// It is a socket, a FIFO or a device node, which are never appended.
+    Special,
+    /// It is a symbolic link, skipped according to `DirOptions::symlinks`.
+    Symlink,
+    /// It is a symbolic link whose target doesn't exist.
+    BrokenSymlink,
+    /// It is a symbolic link to a directory being walked, which would never end.
+    Cycle,
+    /// It is left out by `DirOptions::include`, `DirOptions::exclude` or `DirOptions::ignore_files`.
+    Excluded,
+    /// It is left out by `DirOptions::filter`.
+    Filtered,
+}
+
+/// The paths (relative to the directory) of the files and directories skipped by `Archive::append_dir_all`, in order,
+/// with the reason they were skipped.
+pub type DirSummary = Vec<(String, SkipReason)>;
+
+/// Options used when appending a whole directory of the file system.
+///
+/// The default options append every file (including the hidden ones) with the default `EntryOptions`,
+/// keeping their executable bits, and no entry for the subdirectories.
+#[derive(Clone, Debug)]
+pub struct DirOptions {
+    entry: EntryOptions,
+    directories: bool,
+    preserve_executable: bool,
+    preserve_owner: bool,
+    hidden: bool,
+    includes: Vec<Glob>,
+    excludes: Vec<Glob>,
+    ignore_files: bool,
+    filter: Option<DirFilter>,
+    rename: Option<DirRename>,
+    symlinks: SymlinkPolicy,
+    max_depth: Option<usize>,
+    max_entries: Option<u64>,
+    max_size: Option<u64>,
+    concurrency: usize,
+}
+
+impl Default for DirOptions {
+    fn default() -> Self {
+        Self {
+            entry: EntryOptions::default(),
+            directories: false,
+            preserve_executable: true,
+            preserve_owner: false,
+            hidden: true,
+            includes: Vec::new(),
+            excludes: Vec::new(),
+            ignore_files: false,
+            filter: None,
+            rename: None,
+            symlinks: SymlinkPolicy::default(),
+            max_depth: None,
+            max_entries: None,
+            max_size: None,
+            concurrency: 1,
+        }
+    }
+}
+
+impl DirOptions {
+    /// Create the default directory options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the options used for every file of the directory (e.g. the compression method).
+    pub fn entry_options(mut self, options: EntryOptions) -> Self {
+        self.entry = options;
+        self
+    }
+
+    /// Also append an entry for every subdirectory, keeping the empty ones and their modification time.
+    pub fn directories(mut self, directories: bool) -> Self {
+        self.directories = directories;
+        self
+    }
+
+    /// Add the executable bits of the source files (on Unix) to the permissions of their entries, enabled by default.
+    pub fn preserve_executable(mut self, preserve_executable: bool) -> Self {
+        self.preserve_executable = preserve_executable;
+        self
+    }
+
+    /// Record the user and group ids of the source files (on Unix) in their entries, see `EntryOptions::owner`.
+    pub fn preserve_owner(mut self, preserve_owner: bool) -> Self {
+        self.preserve_owner = preserve_owner;
+        self
+    }
+
+    /// Also append the hidden files and directories, whose name starts with a dot (e.g. `.env` or `.git`), enabled by default.
+    /// The ignore files are still read with `ignore_files`.
+    pub fn hidden(mut self, hidden: bool) -> Self {
+        self.hidden = hidden;
+        self
+    }
+
+    /// Only append the files whose path relative to the directory matches the glob `pattern` (e.g. `"**/*.rs"`), see `Glob`,
+    /// or one of the patterns if called several times. The subdirectories are still walked.
+    pub fn include(mut self, pattern: &str) -> Self {
+        self.includes.push(Glob::new(pattern));
+        self
+    }
+
+    /// Leave out the files and directories (with their content) whose path relative to the directory matches the glob `pattern`
+    /// (e.g. `"target"` or `"**/node_modules"`), see `Glob`. It can be called several times.
+    pub fn exclude(mut self, pattern: &str) -> Self {
+        self.excludes.push(Glob::new(pattern));
+        self
+    }
+
+    /// Leave out the files and directories listed in the `.gitignore` and `.zipignore` files of the walked directories,
+    /// with the syntax of `.gitignore` files (comments, `!` negations, `/` anchors and trailing `/` for directories),
+    /// the patterns of a subdirectory's files taking precedence. The ignore files themselves are still appended.
+    pub fn ignore_files(mut self, ignore_files: bool) -> Self {
+        self.ignore_files = ignore_files;
+        self
+    }
+
+    /// Set how the symbolic links are handled, see `SymlinkPolicy`. The broken ones are always skipped, unless stored.
+    pub fn symlinks(mut self, symlinks: SymlinkPolicy) -> Self {
+        self.symlinks = symlinks;
+        self
+    }
+
+    /// Only walk the subdirectories up to `depth` levels below the directory, 0 appending only the files it directly contains
+    /// (and its subdirectories' entries with `directories`). Unlimited by default.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Fail with an `Error::DirectoryTooLarge` before appending more than `count` entries from the directory. Unlimited by default.
+    pub fn max_entries(mut self, count: u64) -> Self {
+        self.max_entries = Some(count);
+        self
+    }
+
+    /// Fail with an `Error::DirectoryTooLarge` before appending files whose sizes (as reported by their metadata) add up
+    /// to more than `size` bytes. Unlimited by default.
+    pub fn max_size(mut self, size: u64) -> Self {
+        self.max_size = Some(size);
+        self
+    }
+
+    /// Prepare up to `concurrency` entries of a directory at a time (reading their metadata, calling the `filter`
+    /// and opening their files) ahead of the one being appended, to hide the latency of the file system
+    /// when walking many small files. The entries are still appended one after the other, in the same order,
+    /// and up to `concurrency` files are kept open. 1 by default.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Only append the files and walk the directories for which the async `predicate` returns `true`, given their path
+    /// and metadata (of the target of a symbolic link to a file), e.g. to leave out the large or old files.
+    /// It is called once the other filters (e.g. `exclude`) are passed.
+    pub fn filter<F, Fut>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&Path, &Metadata) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = bool> + Send + 'static,
+    {
+        self.filter = Some(DirFilter(Arc::new(move |path, metadata| {
+            Box::pin(predicate(path, metadata))
+        })));
+        self
+    }
+
+    /// Name the entries with `rename`, given the path of their file or directory relative to the directory
+    /// (e.g. `"docs/readme.md"`, without trailing slash), instead of their relative path, decoupling the layout
+    /// of the archive from the one of the directory. The `prefix` of `append_dir_all` is still added to the names,
+    /// and the filters still match the relative paths.
+    pub fn rename<F>(mut self, rename: F) -> Self
+    where
+        F: Fn(&str) -> String + Send + Sync + 'static,
+    {
+        self.rename = Some(DirRename(Arc::new(rename)));
+        self
+    }
+
+    // The name of the entry of the file or directory at `relative`, without the prefix.
+    fn entry_name(&self, relative: &str) -> String {
+        match &self.rename {
+            Some(DirRename(rename)) => rename(relative),
+            None => relative.to_owned(),
+        }
+    }
+
+    // Whether `predicate` selects the file or directory at `path`, if any.
+    async fn filter_selects(&self, path: &Path, metadata: &Metadata) -> bool {
+        match &self.filter {
+            Some(DirFilter(predicate)) => predicate(path, metadata).await,
+            None => true,
+        }
+    }
+
+    // Whether to append (or walk) the file or directory at `relative`, its path relative to the directory.
+    fn selects(&self, ignore_files: &[Arc<IgnoreFile>], relative: &str, is_dir: bool) -> bool {
+        if self.excludes.iter().any(|glob| glob.matches(relative)) {
+            return false;
+        }
+        if !is_dir
+            && !self.includes.is_empty()
+            && !self.includes.iter().any(|glob| glob.matches(relative))
+        {
+            return false;
+        }
+        let mut ignored = false;
+        for file in ignore_files {
+            let Some(path) = relative.strip_prefix(&file.base) else {
+                continue;
+            };
+            for rule in &file.rules {
+                if (is_dir || !rule.directory_only) && rule.glob.matches(path) {
+                    ignored = !rule.negated;
+                }
+            }
+        }
+        !ignored
+    }
+
+    // The options of a file's entry, with the executable bits and the owner of its source if preserved.
+    fn file_entry_options(&self, _metadata: &Metadata) -> EntryOptions {
+        #[allow(unused_mut)]
+        let mut options = self.entry.clone();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+
+            let mode = _metadata.mode();
+            if self.preserve_executable && mode & 0o111 != 0 {
+                let permissions = options.unix_permissions.unwrap_or(0o644);
+                options = options.unix_permissions(permissions | mode & 0o111);
+            }
+            if self.preserve_owner {
+                options = options.owner(_metadata.uid(), _metadata.gid());
+            }
+        }
+        options
+    }
+}
+
+// The rules of an ignore file, matching the paths relative to the directory it is in (`base`).
+struct IgnoreFile {
+    base: String,
+    rules: Vec<IgnoreRule>,
+}
+
+struct IgnoreRule {
+    glob: Glob,
+    negated: bool,
+    directory_only: bool,
+}
+
+impl IgnoreFile {
+    fn parse(base: &str, content: &str) -> Self {
+        let rules = content
+            .lines()
+            .map(str::trim_end)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                let (negated, line) = match line.strip_prefix('!') {
+                    Some(line) => (true, line),
+                    None => (false, line),
+                };
+                let (directory_only, line) = match line.strip_suffix('/') {
+                    Some(line) => (true, line),
+                    None => (false, line),
+                };
+                // A pattern without slash matches at any depth, one with a slash is relative to the ignore file.
+                let pattern = match line.strip_prefix('/') {
+                    Some(line) => line.to_owned(),
+                    None if line.contains('/') => line.to_owned(),
+                    None => format!("**/{line}"),
+                };
+                IgnoreRule {
+                    glob: Glob::new(&pattern),
+                    negated,
+                    directory_only,
+                }
+            })
+            .collect();
+        Self {
+            base: base.to_owned(),
+            rules,
+        }
+    }
+}
+
+// The name of an entry appended from `path`, its file name.
+fn file_name(path: &Path) -> Result<String, IoError> {
+    match path.file_name().map(|file_name| file_name.to_str()) {
+        Some(Some(file_name)) => Ok(file_name.to_owned()),
+        Some(None) => Err(IoError::new(
+            ErrorKind::InvalidData,
+            format!("path isn't valid UTF-8: {path:?}"),
+        )),
+        None => Err(IoError::new(
+            ErrorKind::InvalidInput,
+            format!("path has no file name: {path:?}"),
+        )),
+    }
+}
+
+// Open a file to append, failing if it is a directory.
+async fn open_file(path: &Path) -> Result<(File, Metadata), IoError> {
+    let file = File::open(path).await?;
+    let metadata = file.metadata().await?;
+    if metadata.is_dir() {
+        return Err(IoError::new(
+            ErrorKind::InvalidInput,
+            format!("path is a directory: {path:?}"),
+        ));
+    }
+    Ok((file, metadata))
+}
+
+// The options of an entry appended from a file, with its permissions (on Unix).
+fn path_entry_options(_metadata: &Metadata) -> EntryOptions {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        EntryOptions::new().unix_permissions(_metadata.permissions().mode())
+    }
+    #[cfg(not(unix))]
+    EntryOptions::new()
+}
+
+// An entry of a walked directory selected by the options, with its metadata, ready to be appended.
+struct PreparedEntry {
+    path: PathBuf,
+    relative: String,
+    metadata: Metadata,
+    content: PreparedContent,
+}
+
+enum PreparedContent {
+    // A directory, with its canonical path if the symbolic links to directories are followed.
+    Dir(Option<PathBuf>),
+    // A stored symbolic link, with its target.
+    Symlink(String),
+    File(File),
+}
+
+// An entry of a walked directory, either ready to be appended or skipped.
+enum Prepared {
+    Entry(Box<PreparedEntry>),
+    Skipped(String, SkipReason),
+}
+
+// Read the metadata of an entry of the directory at `relative_dir` and open its file, unless it isn't selected.
+async fn prepare_entry(
+    options: &DirOptions,
+    ignore_files: &[Arc<IgnoreFile>],
+    relative_dir: &str,
+    entry: DirEntry,
+) -> Result<Prepared, IoError> {
+    let path = entry.path();
+    let relative = match entry.file_name().into_string() {
+        Ok(file_name) if !options.hidden && file_name.starts_with('.') => {
+            return Ok(Prepared::Skipped(
+                format!("{relative_dir}{file_name}"),
+                SkipReason::Hidden,
+            ))
+        }
+        Ok(file_name) => format!("{relative_dir}{file_name}"),
+        Err(_) => {
+            return Err(IoError::new(
+                ErrorKind::InvalidData,
+                format!("path isn't valid UTF-8: {path:?}"),
+            ))
+        }
+    };
+    let file_type = entry.file_type().await?;
+    let symlink = file_type.is_symlink();
+    // The metadata of the entry, of the target of a symbolic link if followed, skipping the broken ones.
+    let metadata = match fs::metadata(&path).await {
+        _ if symlink && options.symlinks == SymlinkPolicy::Skip => {
+            return Ok(Prepared::Skipped(relative, SkipReason::Symlink))
+        }
+        _ if symlink && options.symlinks == SymlinkPolicy::Store => entry.metadata().await?,
+        Ok(metadata) if metadata.is_file() => metadata,
+        Ok(metadata)
+            if metadata.is_dir() && (!symlink || options.symlinks == SymlinkPolicy::Follow) =>
+        {
+            metadata
+        }
+        Ok(metadata) if symlink && metadata.is_dir() => {
+            return Ok(Prepared::Skipped(relative, SkipReason::Symlink))
+        }
+        Ok(_) => return Ok(Prepared::Skipped(relative, SkipReason::Special)),
+        Err(error) if symlink && error.kind() == ErrorKind::NotFound => {
+            return Ok(Prepared::Skipped(relative, SkipReason::BrokenSymlink))
+        }
+        Err(error) => return Err(error),
+    };
+    if !options.selects(ignore_files, &relative, metadata.is_dir()) {
+        return Ok(Prepared::Skipped(relative, SkipReason::Excluded));
+    }
+    if !options.filter_selects(&path, &metadata).await {
+        return Ok(Prepared::Skipped(relative, SkipReason::Filtered));
+    }
+
+    let content = if metadata.is_dir() {
+        match options.symlinks {
+            SymlinkPolicy::Follow => PreparedContent::Dir(Some(fs::canonicalize(&path).await?)),
+            _ => PreparedContent::Dir(None),
+        }
+    } else if metadata.is_symlink() {
+        let target = fs::read_link(&path).await?;
+        match target.into_os_string().into_string() {
+            Ok(target) => PreparedContent::Symlink(target),
+            Err(target) => {
+                return Err(IoError::new(
+                    ErrorKind::InvalidData,
+                    format!("symbolic link target isn't valid UTF-8: {target:?}"),
+                ))
+            }
+        }
+    } else {
+        PreparedContent::File(File::open(&path).await?)
+    };
+    Ok(Prepared::Entry(Box::new(PreparedEntry {
+        path,
+        relative,
+        metadata,
+        content,
+    })))
+}
+
+// The futures of an iterator, up to `capacity` of them polled at a time, whose outputs are returned in order.
+struct Buffered<I: Iterator<Item = F>, F: Future> {
+    futures: I,
+    slots: VecDeque<Slot<F>>,
+    capacity: usize,
+}
+
+enum Slot<F: Future> {
+    Pending(Pin<Box<F>>),
+    Ready(Option<F::Output>),
+}
+
+impl<I: Iterator<Item = F>, F: Future> Buffered<I, F> {
+    fn new(futures: I, capacity: usize) -> Self {
+        Self {
+            futures,
+            slots: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    async fn next(&mut self) -> Option<F::Output> {
+        while self.slots.len() < self.capacity {
+            match self.futures.next() {
+                Some(future) => self.slots.push_back(Slot::Pending(Box::pin(future))),
+                None => break,
+            }
+        }
+        self.slots.front()?;
+        let output = std::future::poll_fn(|cx| {
+            for slot in self.slots.iter_mut() {
+                if let Slot::Pending(future) = slot {
+                    if let Poll::Ready(output) = future.as_mut().poll(cx) {
+                        *slot = Slot::Ready(Some(output));
+                    }
+                }
+            }
+            match self.slots.front_mut() {
+                Some(Slot::Ready(output)) => Poll::Ready(output.take()),
+                _ => Poll::Pending,
+            }
+        })
+        .await;
+        self.slots.pop_front();
+        output
+    }
+}
+
+// A directory left to walk: its path relative to the walked one (and its depth), the ignore files applying to it,
+// and the canonical paths of its ancestors if the symbolic links to directories are followed.
+struct PendingDir {
+    path: PathBuf,
+    relative: String,
+    depth: usize,
+    ignore_files: Vec<Arc<IgnoreFile>>,
+    ancestors: Vec<PathBuf>,
+}
+
+/// A shareable async predicate selecting the files and directories to append.
+#[derive(Clone)]
+struct DirFilter(Arc<DirPredicate>);
+
+type DirPredicate =
+    dyn Fn(&Path, &Metadata) -> Pin<Box<dyn Future<Output = bool> + Send>> + Send + Sync;
+
+impl Debug for DirFilter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str("DirFilter")
+    }
+}
+
+/// A shareable function naming the entries of a directory after their relative path.
+#[derive(Clone)]
+struct DirRename(Arc<dyn Fn(&str) -> String + Send + Sync>);
+
+impl Debug for DirRename {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str("DirRename")
+    }
+}
+
+/// A shareable predicate selecting the entries to extract.
+#[derive(Clone)]
+struct EntryFilter(Arc<dyn Fn(&Entry) -> bool + Send + Sync>);
+
+impl Debug for EntryFilter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str("EntryFilter")
+    }
+}
+
+/// Options used when extracting an archive to the file system.
+///
+/// The default options extract every entry, without restoring their permissions.
+#[derive(Clone, Debug, Default)]
+pub struct ExtractOptions {
+    permissions: bool,
+    filter: Option<EntryFilter>,
+}
+
+impl ExtractOptions {
+    /// Create the default extraction options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Restore the Unix permissions of the entries made on Unix or macOS (on Unix, ignored elsewhere).
+    /// They are only written in the central directory, which is read once the entries are extracted.
+    pub fn permissions(mut self, permissions: bool) -> Self {
+        self.permissions = permissions;
+        self
+    }
+
+    /// Only extract the entries matching `predicate`, skipping the payloads of the other ones.
+    pub fn filter<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&Entry) -> bool + Send + Sync + 'static,
+    {
+        self.filter = Some(EntryFilter(Arc::new(predicate)));
+        self
+    }
+
+    /// Only extract the entries whose name matches the glob `pattern` (e.g. `"docs/**/*.md"`), see `Glob`.
+    pub fn glob(self, pattern: &str) -> Self {
+        let glob = Glob::new(pattern);
+        self.filter(move |entry| glob.matches(entry.name()))
+    }
+
+    fn matches(&self, entry: &Entry) -> bool {
+        self.filter.as_ref().is_none_or(|filter| (filter.0)(entry))
+    }
+}
+
+// The path of an entry relative to the destination directory, rejecting the ones escaping it.
+fn entry_path(name: &str) -> Result<PathBuf, IoError> {
+    let mut path = PathBuf::new();
+    for component in name
+        .split('/')
+        .filter(|component| !component.is_empty() && *component != ".")
+    {
+        let mut components = Path::new(component).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(component)), None) => path.push(component),
+            _ => {
+                return Err(Error::UnsafePath {
+                    name: name.to_owned(),
+                }
+                .into())
+            }
+        }
+    }
+    Ok(path)
+}
+
+#[cfg(unix)]
+async fn set_permissions(path: &Path, mode: u32) -> Result<(), IoError> {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::set_permissions(path, std::fs::Permissions::from_mode(mode & 0o777)).await
+}
+
+#[cfg(not(unix))]
+async fn set_permissions(_path: &Path, _mode: u32) -> Result<(), IoError> {
+    Ok(())
+}
+
+macro_rules! impl_fs_methods {
+    (
+        $(#[$($attrss:tt)*])*,
+        $fdd:tt, $fao:tt, $fd:tt, $fe:tt, $fn:tt, $fr:tt, $fcd:tt, $fap:tt, $faps:tt,
+    ) => {
+        impl<W: AsyncWrite + Unpin> Archive<W> {
+            /// Append a file of the file system, keeping its modification time and its permissions (on Unix).
+            /// The entry is named `name`, or after the file name of `path` if `None`. Symbolic links are followed.
+            ///
+            /// # Error
+            ///
+            /// This function will forward any error found while opening or reading the file, or writing to the underlying sink.
+            /// A path that is a directory returns an `InvalidInput` error (see `append_dir_all`),
+            /// and a file name that isn't valid UTF-8 (if `name` is `None`) returns an `InvalidData` error.
+            $(#[$($attrss)*])*
+            pub async fn $fap<P: AsRef<Path>>(&mut self, name: Option<String>, path: P) -> Result<(), IoError> {
+                let path = path.as_ref();
+                let name = match name {
+                    Some(name) => name,
+                    None => file_name(path)?,
+                };
+                let (mut file, metadata) = open_file(path).await?;
+                self.$fao(
+                    name,
+                    metadata.modified().map_or(FileDateTime::Zero, FileDateTime::from_system_time),
+                    path_entry_options(&metadata),
+                    &mut file,
+                ).await
+            }
+
+            /// Append the files of the file system at the given paths one after the other, named after their given names,
+            /// as with `append_path`. The files that can't be opened (e.g. missing, or directories) are skipped,
+            /// their error being returned in the summary of every file, in the order of `paths` (`Ok` if appended).
+            ///
+            /// # Error
+            ///
+            /// This function will forward any error found while reading an opened file or writing to the underlying sink,
+            /// interrupting the archive, whose last entry may be incomplete.
+            $(#[$($attrss)*])*
+            pub async fn $faps<I>(&mut self, paths: I) -> Result<Vec<(String, Result<(), IoError>)>, IoError>
+            where
+                I: IntoIterator<Item = (String, PathBuf)>,
+            {
+                let mut summary = Vec::new();
+                for (name, path) in paths {
+                    let (mut file, metadata) = match open_file(&path).await {
+                        Ok(opened) => opened,
+                        Err(error) => {
+                            summary.push((name, Err(error)));
+                            continue;
+                        }
+                    };
+                    self.$fao(
+                        name.clone(),
+                        metadata.modified().map_or(FileDateTime::Zero, FileDateTime::from_system_time),
+                        path_entry_options(&metadata),
+                        &mut file,
+                    ).await?;
+                    summary.push((name, Ok(())));
+                }
+                Ok(summary)
+            }
+
+            /// Recursively append the files of a directory of the file system, keeping their modification time.
+            /// Entries are named after their path relative to `path` (or after `DirOptions::rename`),
+            /// prefixed by `prefix` (e.g. `"export-2024/"`, or `""` for none),
+            /// and are appended in alphabetical order, each directory's content before its subdirectories' content,
+            /// even if they are prepared concurrently with `DirOptions::concurrency`.
+            /// Symbolic links are handled according to `DirOptions::symlinks`: by default, symbolic links to files are followed,
+            /// and symbolic links to directories are skipped to avoid cycles.
+            /// The files and directories can be filtered with `DirOptions::hidden`, `DirOptions::include`, `DirOptions::exclude`,
+            /// `DirOptions::ignore_files` and `DirOptions::filter`. The special files (sockets, FIFOs and device nodes) are skipped.
+            /// The skipped files and directories are returned with the reason they were skipped, see `SkipReason`.
+            ///
+            /// # Error
+            ///
+            /// This function will forward any error found while walking the directory, reading its files (or its ignore files)
+            /// or writing to the underlying sink. Paths that aren't valid UTF-8 return an `InvalidData` error,
+            /// and an entry exceeding `DirOptions::max_entries` or `DirOptions::max_size` an `Error::DirectoryTooLarge`,
+            /// before it is appended.
+            $(#[$($attrss)*])*
+            pub async fn $fdd<P: AsRef<Path>>(
+                &mut self,
+                prefix: &str,
+                path: P,
+                options: DirOptions,
+            ) -> Result<DirSummary, IoError> {
+                let mut prefix = prefix.to_owned();
+                if !prefix.is_empty() && !prefix.ends_with('/') {
+                    prefix.push('/');
+                }
+
+                let root = path.as_ref().to_path_buf();
+                let ancestors = match options.symlinks {
+                    SymlinkPolicy::Follow => vec![fs::canonicalize(&root).await?],
+                    _ => Vec::new(),
+                };
+                let mut pending = vec![PendingDir {
+                    path: root,
+                    relative: String::new(),
+                    depth: 0,
+                    ignore_files: Vec::new(),
+                    ancestors,
+                }];
+                let mut summary = Vec::new();
+                let (mut entry_count, mut size) = (0, 0);
+                while let Some(PendingDir { path: dir, relative: relative_dir, depth, mut ignore_files, ancestors }) = pending.pop() {
+                    if options.ignore_files {
+                        for file_name in IGNORE_FILES {
+                            match fs::read_to_string(dir.join(file_name)).await {
+                                Ok(content) => ignore_files.push(Arc::new(IgnoreFile::parse(&relative_dir, &content))),
+                                Err(error) if error.kind() == ErrorKind::NotFound => (),
+                                Err(error) => return Err(error),
+                            }
+                        }
+                    }
+
+                    let mut entries = Vec::new();
+                    let mut read_dir = fs::read_dir(&dir).await?;
+                    while let Some(entry) = read_dir.next_entry().await? {
+                        entries.push(entry);
+                    }
+                    entries.sort_by_key(|entry| entry.file_name());
+
+                    let mut prepared = Buffered::new(
+                        entries.into_iter().map(|entry| prepare_entry(&options, &ignore_files, &relative_dir, entry)),
+                        options.concurrency,
+                    );
+                    let mut subdirectories = Vec::new();
+                    while let Some(entry) = prepared.next().await {
+                        let PreparedEntry { path, relative, metadata, content } = match entry? {
+                            Prepared::Entry(entry) => *entry,
+                            Prepared::Skipped(relative, reason) => {
+                                summary.push((relative, reason));
+                                continue;
+                            }
+                        };
+                        if matches!(&content, PreparedContent::Dir(Some(canonical)) if ancestors.contains(canonical)) {
+                            summary.push((relative, SkipReason::Cycle));
+                            continue;
+                        }
+                        let name = format!("{prefix}{}", options.entry_name(&relative));
+                        let appended = !metadata.is_dir() || options.directories;
+                        if metadata.is_file() {
+                            size += metadata.len();
+                        }
+                        entry_count += appended as u64;
+                        if options.max_entries.is_some_and(|max_entries| entry_count > max_entries)
+                            || options.max_size.is_some_and(|max_size| size > max_size)
+                        {
+                            return Err(Error::DirectoryTooLarge { name }.into());
+                        }
+
+                        let datetime = metadata.modified().map_or(FileDateTime::Zero, FileDateTime::from_system_time);
+                        match content {
+                            PreparedContent::Dir(canonical) => {
+                                if options.directories {
+                                    self.$fd(name, datetime).await?;
+                                }
+                                if options.max_depth.is_some_and(|max_depth| depth >= max_depth) {
+                                    continue;
+                                }
+                                subdirectories.push(PendingDir {
+                                    path,
+                                    relative: format!("{relative}/"),
+                                    depth: depth + 1,
+                                    ignore_files: ignore_files.clone(),
+                                    ancestors: ancestors.iter().cloned().chain(canonical).collect(),
+                                });
+                            }
+                            PreparedContent::Symlink(target) => {
+                                let options = options.entry.clone().external_attributes(SYMLINK_ATTRIBUTES);
+                                self.$fao(name, datetime, options, &mut target.as_bytes()).await?;
+                            }
+                            PreparedContent::File(mut file) => {
+                                self.$fao(name, datetime, options.file_entry_options(&metadata), &mut file).await?;
+                            }
+                        }
+                    }
+                    pending.extend(subdirectories.into_iter().rev());
+                }
+
+                Ok(summary)
+            }
+        }
+
+        impl<R: AsyncRead + Unpin> ArchiveReader<R> {
+            /// Extract the entries left to read (or the ones selected by `ExtractOptions::filter`) to the `destination` directory of the file system, creating it if needed.
+            /// Entries are written to their path relative to `destination`, overwriting the existing files,
+            /// the directories are created along the way. Every entry is written as a regular file (or a directory):
+            /// a symbolic link is written as a file holding its target.
+            ///
+            /// # Error
+            ///
+            /// This function will forward any error found while reading the archive or writing the files,
+            /// and an `Error::UnsafePath` if the name of an entry doesn't resolve to a path inside `destination` (e.g. `../file`),
+            /// before it is written.
+            $(#[$($attrss)*])*
+            pub async fn $fe<P: AsRef<Path>>(&mut self, destination: P, options: ExtractOptions) -> Result<(), IoError> {
+                let destination = destination.as_ref();
+                fs::create_dir_all(destination).await?;
+
+                let mut extracted = HashMap::new();
+                let mut buf = vec![0; EXTRACT_BUFFER_SIZE];
+                while let Some(entry) = self.$fn().await? {
+                    if !options.matches(&entry) {
+                        continue;
+                    }
+                    let relative_path = entry_path(entry.name())?;
+                    let path = destination.join(&relative_path);
+                    if entry.is_directory() {
+                        fs::create_dir_all(&path).await?;
+                    } else {
+                        if relative_path.as_os_str().is_empty() {
+                            return Err(Error::UnsafePath { name: entry.name().to_owned() }.into());
+                        }
+                        if let Some(parent) = path.parent() {
+                            fs::create_dir_all(parent).await?;
+                        }
+                        let mut file = File::create(&path).await?;
+                        loop {
+                            let read = self.$fr(&mut buf).await?;
+                            if read == 0 {
+                                break;
+                            }
+                            file.write_all(&buf[..read]).await?;
+                        }
+                        file.flush().await?;
+                    }
+                    if options.permissions {
+                        extracted.insert(entry.offset(), path);
+                    }
+                }
+
+                if options.permissions {
+                    // Once every file is written, as a directory might be read-only.
+                    let central_directory = self.$fcd().await?;
+                    for entry in central_directory.entries() {
+                        let Some(mode) = entry.unix_mode() else {
+                            continue;
+                        };
+                        if let Some(path) = extracted.get(&entry.offset()) {
+                            set_permissions(path, mode).await?;
+                        }
+                    }
+                }
+
+                Ok(())
+            }
+        }
+    };
+}
+
+#[cfg(feature = "futures-async-io")]
+impl_fs_methods!(
+    #[cfg(all(feature = "tokio-fs", feature = "futures-async-io"))],
+    tokio_append_dir_all, tokio_append_with_options, tokio_append_directory,
+    tokio_extract, tokio_next_entry, tokio_read, tokio_central_directory, tokio_append_path,
+    tokio_append_paths,
+);
+
+#[cfg(not(feature = "futures-async-io"))]
+impl_fs_methods!(
+    #[cfg(all(feature = "tokio-fs", not(feature = "futures-async-io")))],
+    append_dir_all, append_with_options, append_directory,
+    extract, next_entry, read, central_directory, append_path, append_paths,
+);
@@ -0,0 +1,291 @@
+use std::fmt::Write;
+use std::io::{Error as IoError, ErrorKind};
+
+use reqwest::header::{CONTENT_RANGE, LOCATION, RANGE};
+use reqwest::{Client, Response, StatusCode};
+
+use crate::url::io_error;
+use crate::ChunkedUpload;
+
+// The alignment of the chunks of a resumable upload, but the last one.
+const ALIGNMENT: usize = 256 << 10;
+// The attempts to upload a chunk, its persisted part being queried after each failure.
+const ATTEMPTS: usize = 3;
+const ENDPOINT: &str = "https://storage.googleapis.com";
+// The status of a chunk received while the upload isn't complete.
+const RESUME_INCOMPLETE: u16 = 308;
+
+/// A Google Cloud Storage [resumable upload](https://cloud.google.com/storage/docs/performing-resumable-uploads)
+/// receiving an archive through a `ChunkedWriter`, authorized with an OAuth 2.0 access token supplied by the caller.
+///
+/// The parts of the writer are uploaded as chunks aligned on 256 KiB (as required by GCS), whatever the part size,
+/// the unaligned rest of a part being sent with the next one. A chunk failing to upload (e.g. on a 503 or a reset
+/// connection) is retried from the offset persisted by GCS, which is queried with a `Content-Range: bytes */*` request.
+///
+/// ## Example
+///
+/// ```no_run
+/// use zipit::{Archive, ChunkedWriter, FileDateTime, GcsUpload};
+/// use tokio::io::AsyncWriteExt;
+///
+/// # async fn upload(token: &str) -> Result<(), std::io::Error> {
+/// let client = reqwest::Client::new();
+/// let upload = GcsUpload::create(&client, "my-bucket", "exports/files.zip", token).await?;
+/// let mut archive = Archive::new(ChunkedWriter::new(upload));
+/// archive.append("file.txt".to_owned(), FileDateTime::Zero, &mut b"hello\n".as_ref()).await?;
+/// archive.finalize().await?.shutdown().await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct GcsUpload {
+    client: Client,
+    session: String,
+    token: String,
+    // The bytes persisted by GCS, and the ones received after them.
+    offset: u64,
+    pending: Vec<u8>,
+}
+
+impl GcsUpload {
+    /// Start the resumable upload of the object `object` of `bucket`, with a `Content-Type` of `application/zip`.
+    ///
+    /// # Error
+    ///
+    /// This function will return an `std::io::Error` if the session can't be created, see `append_url`.
+    pub async fn create(
+        client: &Client,
+        bucket: &str,
+        object: &str,
+        token: &str,
+    ) -> Result<Self, IoError> {
+        Self::create_with_endpoint(client, ENDPOINT, bucket, object, token).await
+    }
+
+    /// Start the resumable upload of the object `object` of `bucket` on another endpoint than GCS's (e.g. an emulator),
+    /// see `create`.
+    pub async fn create_with_endpoint(
+        client: &Client,
+        endpoint: &str,
+        bucket: &str,
+        object: &str,
+        token: &str,
+    ) -> Result<Self, IoError> {
+        let url = format!(
+            "{}/upload/storage/v1/b/{}/o?uploadType=resumable&name={}",
+            endpoint.trim_end_matches('/'),
+            encode(bucket),
+            encode(object),
+        );
+        let response = client
+            .post(url)
+            .bearer_auth(token)
+            .header("X-Upload-Content-Type", "application/zip")
+            .header("Content-Length", 0)
+            .send()
+            .await
+            .and_then(Response::error_for_status)
+            .map_err(io_error)?;
+        let session = response
+            .headers()
+            .get(LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .ok_or_else(|| IoError::new(ErrorKind::InvalidData, "missing session URI"))?;
+        Ok(Self::new(client, session.to_owned(), token))
+    }
+
+    /// Resume an interrupted upload from its session URI, from the offset persisted by GCS (see `offset`),
+    /// e.g. with `ArchivePlan::stream_from`.
+    ///
+    /// # Error
+    ///
+    /// This function will return an `std::io::Error` if the session can't be queried (e.g. if it expired).
+    pub async fn resume(client: &Client, session: String, token: &str) -> Result<Self, IoError> {
+        let mut upload = Self::new(client, session, token);
+        upload.offset = upload.query().await?.ok_or_else(completed)?;
+        Ok(upload)
+    }
+
+    /// The session URI of the upload, to resume it after an interruption (see `resume`).
+    pub fn session(&self) -> &str {
+        &self.session
+    }
+
+    /// The number of bytes persisted by GCS.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    fn new(client: &Client, session: String, token: &str) -> Self {
+        Self {
+            client: client.clone(),
+            session,
+            token: token.to_owned(),
+            offset: 0,
+            pending: Vec::new(),
+        }
+    }
+
+    // Send the first `len` pending bytes, as the last ones of the object if `last`.
+    async fn send(&mut self, mut len: usize, last: bool) -> Result<(), IoError> {
+        let mut attempts = 0;
+        loop {
+            let total = last.then(|| self.offset + self.pending.len() as u64);
+            let mut range = match len {
+                0 => "bytes */".to_owned(),
+                len => format!("bytes {}-{}/", self.offset, self.offset + len as u64 - 1),
+            };
+            match total {
+                Some(total) => write!(range, "{total}").unwrap(),
+                None => range.push('*'),
+            }
+            let result = self
+                .client
+                .put(&self.session)
+                .bearer_auth(&self.token)
+                .header(CONTENT_RANGE, range)
+                .body(self.pending[..len].to_vec())
+                .send()
+                .await;
+
+            // The persisted bytes, unless the chunk failed to upload.
+            let persisted = match result {
+                Ok(response) if last && response.status().is_success() => {
+                    self.offset += len as u64;
+                    self.pending.clear();
+                    return Ok(());
+                }
+                Ok(response) if response.status().as_u16() == RESUME_INCOMPLETE => {
+                    Some(persisted(&response))
+                }
+                Ok(response) if !is_transient(response.status()) => {
+                    return Err(match response.error_for_status() {
+                        Ok(_) => completed(),
+                        Err(error) => io_error(error),
+                    });
+                }
+                Ok(response) => {
+                    attempts += 1;
+                    if attempts == ATTEMPTS {
+                        return Err(io_error(response.error_for_status().unwrap_err()));
+                    }
+                    None
+                }
+                Err(error) => {
+                    attempts += 1;
+                    if attempts == ATTEMPTS {
+                        return Err(io_error(error));
+                    }
+                    None
+                }
+            };
+            let persisted = match persisted {
+                Some(persisted) => persisted,
+                None => match self.query().await? {
+                    Some(persisted) => persisted,
+                    // The last chunk was received before the failure.
+                    None if last => {
+                        self.offset += self.pending.len() as u64;
+                        self.pending.clear();
+                        return Ok(());
+                    }
+                    None => return Err(completed()),
+                },
+            };
+
+            let acknowledged = persisted
+                .checked_sub(self.offset)
+                .filter(|&acknowledged| acknowledged <= self.pending.len() as u64)
+                .ok_or_else(|| IoError::new(ErrorKind::InvalidData, "unexpected persisted range"))?
+                as usize;
+            self.pending.drain(..acknowledged);
+            self.offset = persisted;
+            len = len.saturating_sub(acknowledged);
+            if len == 0 && !last {
+                return Ok(());
+            }
+        }
+    }
+
+    // Query the number of bytes persisted by GCS, `None` if the upload is completed.
+    async fn query(&self) -> Result<Option<u64>, IoError> {
+        let response = self
+            .client
+            .put(&self.session)
+            .bearer_auth(&self.token)
+            .header(CONTENT_RANGE, "bytes */*")
+            .header("Content-Length", 0)
+            .send()
+            .await
+            .map_err(io_error)?;
+        match response.status().as_u16() {
+            RESUME_INCOMPLETE => Ok(Some(persisted(&response))),
+            _ => response.error_for_status().map(|_| None).map_err(io_error),
+        }
+    }
+}
+
+impl ChunkedUpload for GcsUpload {
+    async fn put_part(&mut self, part: Vec<u8>) -> Result<(), IoError> {
+        self.pending.extend_from_slice(&part);
+        let aligned = self.pending.len() / ALIGNMENT * ALIGNMENT;
+        match aligned {
+            0 => Ok(()),
+            aligned => self.send(aligned, false).await,
+        }
+    }
+
+    async fn complete(&mut self) -> Result<(), IoError> {
+        self.send(self.pending.len(), true).await
+    }
+
+    async fn abort(&mut self) -> Result<(), IoError> {
+        self.pending.clear();
+        let response = self
+            .client
+            .delete(&self.session)
+            .bearer_auth(&self.token)
+            .send()
+            .await
+            .map_err(io_error)?;
+        // GCS answers a cancellation with a 499.
+        match response.status().as_u16() {
+            499 => Ok(()),
+            _ => response.error_for_status().map(|_| ()).map_err(io_error),
+        }
+    }
+}
+
+// The number of bytes persisted by GCS, from the `Range: bytes=0-<last>` header of a 308 response (none if missing).
+fn persisted(response: &Response) -> u64 {
+    response
+        .headers()
+        .get(RANGE)
+        .and_then(|range| range.to_str().ok())
+        .and_then(|range| range.strip_prefix("bytes=0-"))
+        .and_then(|last| last.parse::<u64>().ok())
+        .map_or(0, |last| last + 1)
+}
+
+fn completed() -> IoError {
+    IoError::new(ErrorKind::InvalidInput, "upload already completed")
+}
+
+// Whether an upload failing with `status` can be resumed.
+fn is_transient(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+// Percent-encode a bucket or object name in a URL.
+fn encode(name: &str) -> String {
+    let mut encoded = String::with_capacity(name.len());
+    for &byte in name.as_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            byte => write!(encoded, "%{byte:02X}").unwrap(),
+        }
+    }
+    encoded
+}
@@ -0,0 +1,92 @@
+/// A glob pattern matching the names of entries, e.g. `docs/**/*.md`.
+///
+/// `*` matches any sequence of characters but `/`, `**` any sequence including `/` (`**/` also matches no directory at all),
+/// `?` any single character but `/`, and `[abc]`, `[a-z]` or `[!a-z]` any (or none) of the characters of the class.
+/// A special character is matched literally when escaped by a backslash.
+///
+/// ## Example
+///
+/// ```
+/// use zipit::read::Glob;
+///
+/// let glob = Glob::new("src/**/*.rs");
+/// assert!(glob.matches("src/lib.rs"));
+/// assert!(glob.matches("src/compression/mod.rs"));
+/// assert!(!glob.matches("README.md"));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Glob {
+    pattern: Vec<char>,
+}
+
+impl Glob {
+    /// Create a glob from its pattern. An unclosed class (e.g. `[a`) is matched literally.
+    pub fn new(pattern: &str) -> Self {
+        Self {
+            pattern: pattern.chars().collect(),
+        }
+    }
+
+    /// Whether `name` matches the whole pattern.
+    pub fn matches(&self, name: &str) -> bool {
+        let name: Vec<char> = name.chars().collect();
+        matches(&self.pattern, &name)
+    }
+}
+
+fn matches(pattern: &[char], name: &[char]) -> bool {
+    match pattern {
+        [] => name.is_empty(),
+        ['*', '*', rest @ ..] => {
+            (rest.first() == Some(&'/') && matches(&rest[1..], name))
+                || (0..=name.len()).any(|i| matches(rest, &name[i..]))
+        }
+        ['*', rest @ ..] => (0..=name.len())
+            .take_while(|&i| i == 0 || name[i - 1] != '/')
+            .any(|i| matches(rest, &name[i..])),
+        ['?', rest @ ..] => name.first().is_some_and(|&c| c != '/') && matches(rest, &name[1..]),
+        ['[', rest @ ..] => match class(rest) {
+            Some((class, rest)) => {
+                name.first()
+                    .is_some_and(|&c| c != '/' && class_matches(class, c))
+                    && matches(rest, &name[1..])
+            }
+            None => name.first() == Some(&'[') && matches(rest, &name[1..]),
+        },
+        ['\\', c, rest @ ..] | [c, rest @ ..] => {
+            name.first() == Some(c) && matches(rest, &name[1..])
+        }
+    }
+}
+
+// Split the class following a `[` from the rest of the pattern, `None` if it isn't closed.
+fn class(pattern: &[char]) -> Option<(&[char], &[char])> {
+    let start = match pattern.first() {
+        Some('!' | '^') => 1,
+        _ => 0,
+    };
+    // A `]` right after the opening bracket is part of the class.
+    let end = start + 1 + pattern.get(start + 1..)?.iter().position(|&c| c == ']')?;
+    Some((&pattern[..end], &pattern[end + 1..]))
+}
+
+fn class_matches(class: &[char], c: char) -> bool {
+    let (negated, mut items) = match class {
+        ['!' | '^', items @ ..] => (true, items),
+        items => (false, items),
+    };
+    let mut found = false;
+    loop {
+        match items {
+            [start, '-', end, rest @ ..] => {
+                found |= (*start..=*end).contains(&c);
+                items = rest;
+            }
+            [item, rest @ ..] => {
+                found |= *item == c;
+                items = rest;
+            }
+            [] => return found != negated,
+        }
+    }
+}
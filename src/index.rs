@@ -0,0 +1,142 @@
+use std::fmt::Write;
+use std::ops::Range;
+
+use crate::read::decode_name;
+use crate::Archive;
+
+/// The location of an entry in an archive written by `Archive`, to fetch its payload alone later,
+/// e.g. with an HTTP `Range` request to the stored archive, without downloading the whole archive.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexEntry {
+    name: String,
+    header_offset: u64,
+    data_offset: Option<u64>,
+    compressed_size: u64,
+    size: u64,
+    crc: u32,
+    method: u16,
+}
+
+impl IndexEntry {
+    /// The name of the entry.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The offset of the entry's local header in the archive.
+    pub fn header_offset(&self) -> u64 {
+        self.header_offset
+    }
+
+    /// The offset of the entry's payload in the archive, right after its local header,
+    /// `None` for the entries of an existing archive opened with `open_existing`, whose local headers aren't read.
+    pub fn data_offset(&self) -> Option<u64> {
+        self.data_offset
+    }
+
+    /// The size of the payload in the archive, compressed (and encrypted, with its 12-byte encryption header) or not.
+    pub fn compressed_size(&self) -> u64 {
+        self.compressed_size
+    }
+
+    /// The size of the payload once decompressed.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The CRC32 of the decompressed payload.
+    pub fn crc(&self) -> u32 {
+        self.crc
+    }
+
+    /// The compression method of the payload (e.g. 0 if stored, 8 if deflated).
+    pub fn method(&self) -> u16 {
+        self.method
+    }
+
+    /// The range of bytes of the payload in the archive (e.g. `bytes=start-(end - 1)` for an HTTP `Range` header),
+    /// `None` if its offset is unknown (see `data_offset`).
+    pub fn range(&self) -> Option<Range<u64>> {
+        self.data_offset
+            .map(|offset| offset..offset + self.compressed_size)
+    }
+}
+
+/// The locations of the entries appended to an archive, returned by `Archive::index`, to fetch them alone later.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ArchiveIndex {
+    entries: Vec<IndexEntry>,
+}
+
+impl ArchiveIndex {
+    /// The entries of the archive, in the order they were appended.
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+
+    /// The first entry named `name`.
+    pub fn get(&self, name: &str) -> Option<&IndexEntry> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+
+    /// Export the index as a JSON array of objects, one per entry, with the fields `name`, `header_offset`,
+    /// `data_offset` (`null` if unknown), `compressed_size`, `size`, `crc` and `method`.
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("[");
+        for (i, entry) in self.entries.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            json.push_str("{\"name\":");
+            push_json_string(&mut json, &entry.name);
+            let data_offset = entry
+                .data_offset
+                .map_or_else(|| "null".to_owned(), |offset| offset.to_string());
+            write!(
+                json,
+                ",\"header_offset\":{},\"data_offset\":{data_offset},\"compressed_size\":{},\"size\":{},\"crc\":{},\"method\":{}}}",
+                entry.header_offset, entry.compressed_size, entry.size, entry.crc, entry.method,
+            )
+            .unwrap();
+        }
+        json.push(']');
+        json
+    }
+}
+
+fn push_json_string(json: &mut String, value: &str) {
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c < ' ' => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+impl<W> Archive<W> {
+    /// The locations of the entries appended so far (see `IndexEntry`), e.g. retrieved before `finalize`
+    /// and stored alongside the archive, to later fetch a single entry from it with HTTP `Range` requests.
+    pub fn index(&self) -> ArchiveIndex {
+        let entries = self
+            .files_info
+            .iter()
+            .map(|file_info| IndexEntry {
+                name: decode_name(&file_info.name, file_info.flags, &file_info.extra_field),
+                header_offset: file_info.offset,
+                data_offset: file_info.data_offset,
+                compressed_size: file_info.compressed_size,
+                size: file_info.size,
+                crc: file_info.crc,
+                method: file_info.method.code,
+            })
+            .collect();
+        ArchiveIndex { entries }
+    }
+}
@@ -0,0 +1,142 @@
+use std::io::{Error as IoError, ErrorKind, Write};
+use std::mem;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::thread;
+
+use bytes::Bytes;
+use futures_core::Stream;
+use serde::Serialize;
+use tokio::io::AsyncWrite;
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+
+use crate::{Archive, EntryOptions, FileDateTime};
+
+// The size of the chunks of the serialized document sent to the archive.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+// A writer sending the serialized document to the archive a chunk at a time, waiting for the archive to receive them.
+struct ChunkWriter {
+    sender: Sender<Result<Bytes, IoError>>,
+    chunk: Vec<u8>,
+}
+
+impl ChunkWriter {
+    fn send(&mut self) -> Result<(), IoError> {
+        let chunk = mem::replace(&mut self.chunk, Vec::with_capacity(CHUNK_SIZE));
+        self.sender
+            .blocking_send(Ok(Bytes::from(chunk)))
+            .map_err(|_| IoError::new(ErrorKind::BrokenPipe, "entry no longer written"))
+    }
+}
+
+impl Write for ChunkWriter {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, IoError> {
+        let written = buf.len().min(CHUNK_SIZE - self.chunk.len());
+        self.chunk.extend_from_slice(&buf[..written]);
+        if self.chunk.len() == CHUNK_SIZE {
+            self.send()?;
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> Result<(), IoError> {
+        match self.chunk.is_empty() {
+            true => Ok(()),
+            false => self.send(),
+        }
+    }
+}
+
+// The chunks of a document serialized by another thread, as a stream.
+struct Chunks(Receiver<Result<Bytes, IoError>>);
+
+impl Stream for Chunks {
+    type Item = Result<Bytes, IoError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0.poll_recv(cx)
+    }
+}
+
+// Serialize `value` as JSON in a new thread, its chunks being received as they are written.
+fn serialize<T>(value: T) -> Chunks
+where
+    T: Serialize + Send + 'static,
+{
+    let (sender, receiver) = channel(1);
+    thread::spawn(move || {
+        let mut writer = ChunkWriter {
+            sender: sender.clone(),
+            chunk: Vec::with_capacity(CHUNK_SIZE),
+        };
+        let result = serde_json::to_writer(&mut writer, &value)
+            .map_err(IoError::from)
+            .and_then(|_| writer.flush());
+        if let Err(error) = result {
+            // Unless the archive stopped receiving them.
+            let _ = sender.blocking_send(Err(error));
+        }
+    });
+    Chunks(receiver)
+}
+
+macro_rules! impl_append_json {
+    (
+        $faj:tt, $fajo:tt, $faso:tt,
+    ) => {
+        impl<W: AsyncWrite + Unpin> Archive<W> {
+            /// Append a new file to the archive using the provided name, date/time and `value` serialized as JSON
+            /// (e.g. the metadata of an export), like `append_stream`.
+            ///
+            /// The value is serialized in a separate thread while the entry is written, a chunk at a time,
+            /// so the document is never held in memory as a whole.
+            ///
+            /// # Error
+            ///
+            /// This function will return an `std::io::Error` of kind `InvalidData` if the value fails to serialize
+            /// (e.g. a map with non-string keys), or the same errors as `append_with_options`.
+            pub async fn $faj<T>(
+                &mut self,
+                name: String,
+                datetime: FileDateTime,
+                value: T,
+            ) -> Result<(), IoError>
+            where
+                T: Serialize + Send + 'static,
+            {
+                self.$fajo(name, datetime, EntryOptions::default(), value)
+                    .await
+            }
+
+            /// Append a new file to the archive using the provided name, date/time, options and `value` serialized as JSON,
+            /// see `append_json` and `append_with_options`.
+            pub async fn $fajo<T>(
+                &mut self,
+                name: String,
+                datetime: FileDateTime,
+                options: EntryOptions,
+                value: T,
+            ) -> Result<(), IoError>
+            where
+                T: Serialize + Send + 'static,
+            {
+                self.$faso(name, datetime, options, serialize(value)).await
+            }
+        }
+    };
+}
+
+#[cfg(feature = "futures-async-io")]
+impl_append_json!(
+    tokio_append_json,
+    tokio_append_json_with_options,
+    tokio_append_stream_with_options,
+);
+
+#[cfg(not(feature = "futures-async-io"))]
+impl_append_json!(
+    append_json,
+    append_json_with_options,
+    append_stream_with_options,
+);
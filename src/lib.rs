@@ -3,13 +3,29 @@
 //! - Stream on the fly an archive from multiple AsyncRead objects.
 //! - Single read / seek free implementation (the CRC and file size are calculated while streaming and are sent afterwards).
 //! - Archive size pre-calculation (useful if you want to set the `Content-Length` before streaming).
+//! - Zip64 entries larger than 4 GiB (see `EntryOptions::large_file`).
 //! - [futures](https://docs.rs/futures/latest/futures/) and [tokio](https://docs.rs/tokio/latest/tokio/io/index.html) `AsyncRead` / `AsyncWrite` compatible. Enable either the `futures-async-io` or the `tokio-async-io` feature accordingly.
+//! - Archiving of file system files with `append_path` and of whole directories with `append_dir_all` (`tokio-fs` feature).
+//! - Files read and written with io_uring on Linux with `UringFile` (`tokio-uring` feature).
+//! - Archives as a `Stream` of `Bytes` polled on demand with `ArchiveStream`, e.g. for HTTP response bodies, and entries appended from a `Stream` of `Bytes` with `append_stream` or from a channel with `append_from_channel` (`stream` feature).
+//! - Entries appended from HTTP bodies (e.g. upstream responses) with `append_body` (`body` feature).
+//! - Entries fetched from remote HTTP resources with `append_url` (`reqwest` feature).
+//! - Entries serialized as JSON on the fly with `append_json` (`serde` feature).
+//! - Files uploaded in multipart bodies appended as they are received with `append_multipart` (`multer` feature).
+//! - Zip-on-demand endpoints for tower-based stacks with `ZipService` (`tower` feature).
+//! - Archives as http-body 1.0 bodies (e.g. for hyper 1.x) with `ZipBody` (`http-body1` feature).
+//! - Archive downloads returned by axum, actix-web, warp or Rocket handlers with `ZipResponse` (`axum`, `actix`, `warp` or `rocket` feature).
+//! - Archives written in a spawned task and read from a pipe with `zip_pipe`, reporting the task's errors (`tokio-rt` feature).
+//! - Manifests for nginx's mod_zip module with `mod_zip_manifest`, to switch between mod_zip and in-process zipping.
+//! - Archives streamed to multipart uploads of any storage backend implementing `ChunkedUpload`, with `ChunkedWriter`,
+//!   such as Google Cloud Storage resumable uploads with `GcsUpload` (`gcs` feature) and Azure block blobs with `AzureUpload` (`azure` feature).
+//! - Streamed reading of existing archives with `read::ArchiveReader`, and their extraction to the file system with `extract` (`tokio-fs` feature).
 //!
 //! ## Limitations
 //!
-//! - No compression (stored method only).
-//! - Only files (no directories).
-//! - No customizable external file attributes.
+//! - Compression methods other than stored require their own feature (e.g. `deflate-compression`, `bzip2-compression`).
+//! - No OpenDAL integration: an OpenDAL `Writer` can receive an archive through a `ChunkedUpload` implemented by the caller.
+//! - Only the traditional ZipCrypto encryption: no WinZip AES, hence no key derivation (PBKDF2) settings.
 //!
 //! ## Examples
 //!
@@ -42,50 +58,31 @@
 //!
 //! ### [Hyper](examples/hyper.rs)
 //!
-//! Stream a zip archive as a [`hyper`](https://docs.rs/hyper/0.14.14/hyper/) response:
+//! Stream a zip archive as a [`hyper`](https://docs.rs/hyper/0.14.14/hyper/) response (`stream` feature):
 //!
 //! ```
 //! use std::io::Cursor;
 //! use hyper::{header, Body, Request, Response, Server, StatusCode};
-//! use tokio::io::duplex;
-//! use tokio_util::io::ReaderStream;
-//! use zipit::{archive_size, Archive, FileDateTime};
+//! use zipit::{archive_size, ArchiveStream, EntrySource, FileDateTime};
 //!
 //! async fn zip_archive(_req: Request<Body>) -> Result<Response<Body>, hyper::http::Error> {
-//!     let (filename_1, mut fd_1) = (String::from("file1.txt"), Cursor::new(b"hello\n".to_vec()));
-//!     let (filename_2, mut fd_2) = (String::from("file2.txt"), Cursor::new(b"world\n".to_vec()));
+//!     let (filename_1, fd_1) = (String::from("file1.txt"), Cursor::new(b"hello\n".to_vec()));
+//!     let (filename_2, fd_2) = (String::from("file2.txt"), Cursor::new(b"world\n".to_vec()));
 //!     let archive_size = archive_size([
 //!         (filename_1.as_ref(), fd_1.get_ref().len()),
 //!         (filename_2.as_ref(), fd_2.get_ref().len()),
 //!     ]);
 //!
-//!     let (w, r) = duplex(4096);
-//!     tokio::spawn(async move {
-//!         let mut archive = Archive::new(w);
-//!         archive
-//!             .append(
-//!                 filename_1,
-//!                 FileDateTime::now(),
-//!                 &mut fd_1,
-//!             )
-//!             .await
-//!             .unwrap();
-//!         archive
-//!             .append(
-//!                 filename_2,
-//!                 FileDateTime::now(),
-//!                 &mut fd_2,
-//!             )
-//!             .await
-//!             .unwrap();
-//!         archive.finalize().await.unwrap();
-//!     });
+//!     let stream = ArchiveStream::new([
+//!         EntrySource::file(filename_1, FileDateTime::now(), fd_1),
+//!         EntrySource::file(filename_2, FileDateTime::now(), fd_2),
+//!     ]);
 //!
 //!     Response::builder()
 //!         .status(StatusCode::OK)
 //!         .header(header::CONTENT_LENGTH, archive_size)
 //!         .header(header::CONTENT_TYPE, "application/zip")
-//!         .body(Body::wrap_stream(ReaderStream::new(r)))
+//!         .body(Body::wrap_stream(stream))
 //! }
 //! ```
 
@@ -93,8 +90,23 @@
 #![cfg_attr(docsrs, feature(doc_auto_cfg))]
 
 #[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
-use std::io::Error as IoError;
+use std::collections::HashMap;
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+use std::io::{Error as IoError, SeekFrom};
 use std::mem::size_of;
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+use std::mem::size_of_val;
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+use std::path::Path;
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+use std::sync::{Arc, OnceLock};
+use std::time::SystemTime;
+#[cfg(any(
+    not(feature = "chrono-datetime"),
+    feature = "futures-async-io",
+    feature = "tokio-async-io"
+))]
+use std::time::UNIX_EPOCH;
 
 #[cfg(feature = "chrono-datetime")]
 use chrono::{DateTime, Datelike, Local, TimeZone, Timelike};
@@ -102,13 +114,897 @@ use chrono::{DateTime, Datelike, Local, TimeZone, Timelike};
 use crc32fast::Hasher;
 
 #[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
-#[derive(Debug)]
+pub use crate::compression::{Compression, Compressor};
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+use crate::compression::{CompressorFactory, Encoder, Method};
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+use crate::crypto::{ZipCrypto, ZIP_CRYPTO_HEADER_SIZE};
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+pub use crate::digest::Digest;
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+use crate::digest::DigestFactory;
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+pub use crate::directory::DetachedDirectory;
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+pub use crate::error::Error;
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+pub use crate::index::{ArchiveIndex, IndexEntry};
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+use crate::read::{ArchiveReader, CentralDirectory, Entry, RawPayload};
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+pub use crate::sha256::Sha256;
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+use crate::sha256::SHA256_SIZE;
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+pub use crate::signature::Signer;
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+use crate::signature::{Signature, SignerFactory};
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+pub use crate::source::{archive_size_of_sources, SourcePolicy, SourceSummary, ZipEntrySource};
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+use crate::spill::SpillBuffer;
+
+#[cfg(feature = "azure")]
+mod azure;
+#[cfg(any(feature = "tower", feature = "http-body1", feature = "azure"))]
+mod base64;
+#[cfg(feature = "body")]
+mod body;
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+mod compression;
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+mod cp437;
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+mod crypto;
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+mod digest;
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+mod directory;
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+mod error;
+#[cfg(feature = "tokio-fs")]
+mod fs;
+#[cfg(feature = "gcs")]
+mod gcs;
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+mod glob;
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+mod index;
+#[cfg(feature = "serde")]
+mod json;
+mod mod_zip;
+#[cfg(feature = "multer")]
+mod multipart;
+#[cfg(feature = "tokio-rt")]
+mod pipe;
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+mod plan;
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+pub mod read;
+#[cfg(any(
+    feature = "axum",
+    feature = "actix",
+    feature = "warp",
+    feature = "rocket"
+))]
+mod response;
+#[cfg(feature = "tower")]
+mod service;
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+mod sha256;
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+mod signature;
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+mod source;
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+mod spill;
+#[cfg(feature = "stream")]
+mod stream;
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+mod upload;
+#[cfg(all(feature = "tokio-uring", target_os = "linux"))]
+mod uring;
+#[cfg(feature = "reqwest")]
+mod url;
+#[cfg(feature = "http-body1")]
+mod zip_body;
+#[cfg(feature = "azure")]
+pub use crate::azure::AzureUpload;
+#[cfg(feature = "tokio-fs")]
+pub use crate::fs::{DirOptions, DirSummary, ExtractOptions, SkipReason, SymlinkPolicy};
+#[cfg(feature = "gcs")]
+pub use crate::gcs::GcsUpload;
+pub use crate::mod_zip::mod_zip_manifest;
+#[cfg(feature = "tokio-rt")]
+pub use crate::pipe::{zip_pipe, zip_pipe_with_options};
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+use crate::plan::SizedReader;
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+pub use crate::plan::{ArchivePlan, PlannedArchive, PlannedEntry};
+#[cfg(any(
+    feature = "axum",
+    feature = "actix",
+    feature = "warp",
+    feature = "rocket"
+))]
+pub use crate::response::ZipResponse;
+#[cfg(feature = "tower")]
+pub use crate::service::ZipService;
+#[cfg(feature = "stream")]
+pub use crate::stream::{ArchiveStream, EntrySource};
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+pub use crate::upload::{ChunkedUpload, ChunkedWriter};
+#[cfg(all(feature = "tokio-uring", target_os = "linux"))]
+pub use crate::uring::UringFile;
+#[cfg(feature = "http-body1")]
+pub use crate::zip_body::ZipBody;
+
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+macro_rules! header {
+    [$capacity:expr; $($elem:expr),*$(,)?] => {
+        {
+            let mut header = Vec::with_capacity($capacity);
+            $(
+                header.extend_from_slice(&$elem.to_le_bytes());
+            )*
+            header
+        }
+    };
+}
+
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+#[derive(Clone, Debug)]
 struct FileInfo {
-    name: String,
-    size: usize,
+    name: Vec<u8>,
+    flags: u16,
+    size: u64,
+    compressed_size: u64,
     crc: u32,
-    offset: usize,
+    offset: u64,
+    // The offset of the payload, unknown for the entries of an existing archive.
+    data_offset: Option<u64>,
     datetime: (u16, u16),
+    method: Method,
+    large_file: bool,
+    text: bool,
+    external_attributes: u32,
+    extra_field: Vec<u8>,
+    comment: String,
+    // The central directory record of an entry of an existing archive, written again as is.
+    record: Option<Vec<u8>>,
+}
+
+/// Options applied to a single entry of the archive.
+///
+/// The default options store the payload without compression.
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+#[derive(Clone, Debug, Default)]
+pub struct EntryOptions {
+    compression: Compression,
+    compressor: Option<CompressorFactory>,
+    large_file: bool,
+    directory: bool,
+    unix_permissions: Option<u32>,
+    dos_attributes: DosAttributes,
+    external_attributes: Option<u32>,
+    text: bool,
+    ntfs_times: Option<[u64; 3]>,
+    owner: Option<(u32, u32)>,
+    xattrs: Vec<(String, Vec<u8>)>,
+    local_extra_field: Vec<u8>,
+    central_extra_field: Vec<u8>,
+    comment: String,
+    name_encoding: Option<NameEncoding>,
+    metadata: Option<(u64, u32)>,
+    compressed_size: Option<u64>,
+    seekable: bool,
+    raw: Option<(u16, Option<u16>, RawMetadata)>,
+    crc: Option<u32>,
+    password: Option<Vec<u8>>,
+}
+
+/// The (uncompressed) size and CRC32 of a raw payload, provided beforehand,
+/// or set once the payload is read when copied from a data descriptor.
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+#[derive(Clone, Debug)]
+enum RawMetadata {
+    Provided(u64, u32),
+    Deferred(Arc<OnceLock<(u64, u32)>>),
+}
+
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+impl RawMetadata {
+    fn get(&self) -> Option<(u64, u32)> {
+        match self {
+            RawMetadata::Provided(size, crc) => Some((*size, *crc)),
+            RawMetadata::Deferred(metadata) => metadata.get().copied(),
+        }
+    }
+}
+
+/// The MS-DOS attributes of an entry, restored by the Windows extraction tools.
+///
+/// The directory attribute is set automatically for directory entries.
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub struct DosAttributes {
+    read_only: bool,
+    hidden: bool,
+    system: bool,
+    archive: bool,
+}
+
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+impl DosAttributes {
+    /// Create attributes with none of the flags set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the read-only flag.
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Set the hidden flag.
+    pub fn hidden(mut self, hidden: bool) -> Self {
+        self.hidden = hidden;
+        self
+    }
+
+    /// Set the system flag.
+    pub fn system(mut self, system: bool) -> Self {
+        self.system = system;
+        self
+    }
+
+    /// Set the archive flag, marking the file as modified since the last backup.
+    pub fn archive(mut self, archive: bool) -> Self {
+        self.archive = archive;
+        self
+    }
+
+    fn bits(self) -> u32 {
+        self.read_only as u32
+            | (self.hidden as u32) << 1
+            | (self.system as u32) << 2
+            | (self.archive as u32) << 5
+    }
+}
+
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+impl EntryOptions {
+    /// Create the default entry options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the method used to compress the entry's payload.
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self.compressor = None;
+        self
+    }
+
+    /// Compress the entry's payload with a custom `Compressor`, created by `factory` when the entry is appended.
+    /// This replaces the method set with `compression`.
+    pub fn compressor<F, C>(mut self, factory: F) -> Self
+    where
+        F: Fn() -> C + Send + Sync + 'static,
+        C: Compressor + Send + 'static,
+    {
+        self.compressor = Some(CompressorFactory::new(factory));
+        self
+    }
+
+    /// Write the entry's sizes using Zip64, required if its payload (compressed or not) might exceed 4 GiB.
+    /// The size of the payload is only known once streamed, so this must be decided before appending the entry.
+    /// See `ArchiveOptions::zip64` to enable or disable Zip64 for all the entries.
+    pub fn large_file(mut self, large_file: bool) -> Self {
+        self.large_file = large_file;
+        self
+    }
+
+    /// Set the Unix permission bits of the entry (e.g. `0o755` for an executable), restored by most extraction tools.
+    /// Only the permission bits (`0o7777`) are used, the file type is set by the archive. Files default to `0o644` (`rw-r--r--`).
+    pub fn unix_permissions(mut self, mode: u32) -> Self {
+        self.unix_permissions = Some(mode & 0o7777);
+        self
+    }
+
+    /// Mark the entry as a text file in its internal file attributes,
+    /// letting the tools that convert line endings when extracting (e.g. `unzip -a`) process it.
+    pub fn text(mut self, text: bool) -> Self {
+        self.text = text;
+        self
+    }
+
+    /// Add the NTFS extra field to the entry, holding its modification, access and creation times with a 100 ns precision.
+    /// Windows tools use it in place of the regular MS-DOS date and time, which is limited to 2 s precision and has no timezone.
+    pub fn ntfs_times(
+        mut self,
+        modified: SystemTime,
+        accessed: SystemTime,
+        created: SystemTime,
+    ) -> Self {
+        self.ntfs_times = Some([modified, accessed, created].map(ntfs_time));
+        self
+    }
+
+    /// Add the Info-ZIP Unix extra field to the entry, holding the user and group ids of its owner,
+    /// restored when extracting as root (e.g. `unzip -X`).
+    pub fn owner(mut self, uid: u32, gid: u32) -> Self {
+        self.owner = Some((uid, gid));
+        self
+    }
+
+    /// Attach an extended attribute (e.g. `user.checksum`) to the entry, this can be called multiple times.
+    ///
+    /// Extended attributes are stored in a custom extra field, ignored by the other tools, with the header id `0x6178` ("xa").
+    /// Its data is made of a version byte (1) followed by the attributes, each one made of its name length (u8),
+    /// its UTF-8 name, its value length (u16, little endian) and its value. The extra fields of an entry must fit in 64 KiB.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the name is longer than 255 bytes or the value longer than 65,535 bytes.
+    pub fn xattr<N: Into<String>, V: Into<Vec<u8>>>(mut self, name: N, value: V) -> Self {
+        let (name, value) = (name.into(), value.into());
+        assert!(
+            name.len() <= u8::MAX as usize,
+            "extended attribute name too long"
+        );
+        assert!(
+            value.len() <= u16::MAX as usize,
+            "extended attribute value too long"
+        );
+        self.xattrs.push((name, value));
+        self
+    }
+
+    /// Add a custom extra field, identified by its header id, to both the local header and the central directory entry.
+    /// The data is written as is after the fields written by the crate (e.g. Zip64 or NTFS timestamps),
+    /// so it must not use one of their header ids. The extra fields of an entry must fit in 64 KiB.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the data is longer than 65,535 bytes.
+    pub fn extra_field<D: AsRef<[u8]>>(self, id: u16, data: D) -> Self {
+        self.local_extra_field(id, data.as_ref())
+            .central_extra_field(id, data.as_ref())
+    }
+
+    /// Add a custom extra field to the local header only, see `extra_field`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the data is longer than 65,535 bytes.
+    pub fn local_extra_field<D: AsRef<[u8]>>(mut self, id: u16, data: D) -> Self {
+        push_extra_field(&mut self.local_extra_field, id, data.as_ref());
+        self
+    }
+
+    /// Add a custom extra field to the central directory entry only, see `extra_field`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the data is longer than 65,535 bytes.
+    pub fn central_extra_field<D: AsRef<[u8]>>(mut self, id: u16, data: D) -> Self {
+        push_extra_field(&mut self.central_extra_field, id, data.as_ref());
+        self
+    }
+
+    /// Encrypt the entry with the traditional PKWARE encryption (ZipCrypto), supported by most tools.
+    /// Its payload is preceded by a 12-byte encryption header, so the entry is 12 bytes larger than predicted by `archive_size`.
+    ///
+    /// ZipCrypto is weak and can be broken, it should only be used for interoperability with tools that don't support anything else.
+    ///
+    /// Each entry may have its own password, overriding `ArchiveOptions::password`.
+    pub fn password<P: AsRef<[u8]>>(mut self, password: P) -> Self {
+        self.password = Some(password.as_ref().to_vec());
+        self
+    }
+
+    /// Set how the name of the entry is encoded, overriding `ArchiveOptions::name_encoding`.
+    pub fn name_encoding(mut self, encoding: NameEncoding) -> Self {
+        self.name_encoding = Some(encoding);
+        self
+    }
+
+    /// Attach a comment to the entry, written in the central directory (e.g. provenance or checksum notes).
+    /// It must not be longer than 65,535 bytes (UTF-8 encoded).
+    pub fn comment<C: Into<String>>(mut self, comment: C) -> Self {
+        self.comment = comment.into();
+        self
+    }
+
+    /// Set the MS-DOS attributes of the entry (e.g. hidden or read-only), stored in the low byte of its external file attributes.
+    pub fn dos_attributes(mut self, attributes: DosAttributes) -> Self {
+        self.dos_attributes = attributes;
+        self
+    }
+
+    /// Set the raw 32-bit external file attributes of the entry: the Unix mode (file type and permissions) in the high 16 bits,
+    /// and the MS-DOS attributes in the low byte. This replaces the attributes derived from the kind of the entry and its permissions.
+    pub fn external_attributes(mut self, attributes: u32) -> Self {
+        self.external_attributes = Some(attributes);
+        self
+    }
+
+    fn attributes(&self) -> u32 {
+        if let Some(attributes) = self.external_attributes {
+            return attributes;
+        }
+        let (file_type, default_permissions, dos_attributes) = if self.directory {
+            (0o040000, 0o755, 0x10) // Directory / rwxr-xr-x, MS-DOS directory flag.
+        } else {
+            (0o100000, 0o644, 0) // Regular file / rw-r--r--.
+        };
+        (file_type | self.unix_permissions.unwrap_or(default_permissions)) << 16
+            | dos_attributes
+            | self.dos_attributes.bits()
+    }
+
+    // The extra fields of the local header and of the central directory entry, Zip64 excluded.
+    fn extra_fields(&self) -> (Vec<u8>, Vec<u8>) {
+        let mut extra_field = Vec::new();
+        if let Some([modified, accessed, created]) = self.ntfs_times {
+            extra_field.extend_from_slice(&header![
+                36;
+                0x000au16,      // NTFS extra field header id.
+                32u16,          // Size of the extra field's data.
+                0u32,           // Reserved.
+                0x0001u16,      // Timestamps attribute tag.
+                24u16,          // Size of the attribute.
+                modified,       // Modification time.
+                accessed,       // Access time.
+                created,        // Creation time.
+            ]);
+        }
+        if let Some((uid, gid)) = self.owner {
+            extra_field.extend_from_slice(&header![
+                15;
+                0x7875u16,      // Info-ZIP Unix extra field header id.
+                11u16,          // Size of the extra field's data.
+                1u8,            // Version.
+                4u8,            // Size of the user id.
+                uid,            // User id.
+                4u8,            // Size of the group id.
+                gid,            // Group id.
+            ]);
+        }
+        if !self.xattrs.is_empty() {
+            let mut data = vec![1u8]; // Version.
+            for (name, value) in &self.xattrs {
+                data.push(name.len() as u8);
+                data.extend_from_slice(name.as_bytes());
+                data.extend_from_slice(&(value.len() as u16).to_le_bytes());
+                data.extend_from_slice(value);
+            }
+            extra_field.extend_from_slice(&header![
+                4;
+                0x6178u16,          // Extended attributes extra field header id.
+                data.len() as u16,  // Size of the extra field's data (checked when appending).
+            ]);
+            extra_field.extend_from_slice(&data);
+        }
+        let mut local_extra_field = extra_field.clone();
+        local_extra_field.extend_from_slice(&self.local_extra_field);
+        extra_field.extend_from_slice(&self.central_extra_field);
+        (local_extra_field, extra_field)
+    }
+
+    fn compresses(&self) -> bool {
+        self.compressor.is_some() || self.compression != Compression::Stored
+    }
+
+    fn encoder(&self) -> Encoder {
+        Encoder::new(match &self.compressor {
+            Some(factory) => Some(factory.build()),
+            None => self.compression.compressor(),
+        })
+    }
+}
+
+// Append an extra field, made of its header id, its data size and its data.
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+fn push_extra_field(extra_field: &mut Vec<u8>, id: u16, data: &[u8]) {
+    assert!(data.len() <= u16::MAX as usize, "extra field data too long");
+    extra_field.extend_from_slice(&id.to_le_bytes());
+    extra_field.extend_from_slice(&(data.len() as u16).to_le_bytes());
+    extra_field.extend_from_slice(data);
+}
+
+// Number of 100 ns intervals since 1601, January 1th (UTC).
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+fn ntfs_time(time: SystemTime) -> u64 {
+    const EPOCH_OFFSET: u64 = 11_644_473_600 * 10_000_000;
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => EPOCH_OFFSET + since.as_nanos() as u64 / 100,
+        Err(before) => EPOCH_OFFSET.saturating_sub(before.duration().as_nanos() as u64 / 100),
+    }
+}
+
+/// Extensions of common file formats that are already compressed, see `ArchiveOptions::store_extensions`.
+pub const INCOMPRESSIBLE_EXTENSIONS: &[&str] = &[
+    "7z", "aac", "avi", "br", "bz2", "docx", "flac", "gif", "gz", "heic", "jpeg", "jpg", "lz4",
+    "m4a", "mkv", "mov", "mp3", "mp4", "ogg", "png", "pptx", "rar", "webm", "webp", "xlsx", "xz",
+    "zip", "zst",
+];
+
+/// How the names of the entries are encoded.
+///
+/// Readers decode the names flagged as UTF-8 (bit 11 of the general purpose flag) as such,
+/// and the other ones as code page 437, the original encoding of the format, or as the encoding of their system.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub enum NameEncoding {
+    /// Encode the names in UTF-8 and always flag them as such, even the ASCII ones.
+    #[default]
+    Utf8,
+    /// Encode the names in UTF-8 and only flag the non-ASCII ones, for legacy readers confused by the flag.
+    Utf8WhenNeeded,
+    /// Encode the names in code page 437 when it can represent all of their characters, without the flag.
+    /// The other names are encoded in UTF-8 and flagged (or transliterated, see `ArchiveOptions::unicode_path_field`).
+    Cp437,
+}
+
+/// The system the archive claims to be made on, written in the upper byte of the "version made by" fields.
+///
+/// Readers use it to interpret the external file attributes: the Unix permissions are only applied for `Unix` and `Osx`.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub enum HostSystem {
+    /// MS-DOS and OS/2 (FAT file systems).
+    MsDos,
+    /// Unix.
+    #[default]
+    Unix,
+    /// Windows NTFS.
+    WindowsNtfs,
+    /// macOS.
+    Osx,
+    /// Any other system, by its code in the specification.
+    Other(u8),
+}
+
+impl HostSystem {
+    #[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+    fn code(self) -> u16 {
+        match self {
+            HostSystem::MsDos => 0,
+            HostSystem::Unix => 3,
+            HostSystem::WindowsNtfs => 10,
+            HostSystem::Osx => 19,
+            HostSystem::Other(code) => code as u16,
+        }
+    }
+
+    #[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+    pub(crate) fn from_code(code: u8) -> Self {
+        match code {
+            0 => HostSystem::MsDos,
+            3 => HostSystem::Unix,
+            10 => HostSystem::WindowsNtfs,
+            19 => HostSystem::Osx,
+            code => HostSystem::Other(code),
+        }
+    }
+}
+
+/// When the entries are followed by a data descriptor.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub enum DataDescriptor {
+    /// Write the CRC32 and sizes of every entry in a data descriptor following its payload,
+    /// and set bit 3 of its general purpose flag, so it is streamed without being buffered.
+    #[default]
+    Always,
+    /// Write the CRC32 and sizes of the entries whose payload is read ahead entirely (4 KiB, or more with
+    /// `ArchiveOptions::store_smaller_than` and `ArchiveOptions::compression_trial`) in their local header,
+    /// without data descriptor nor bit 3, for readers ignoring it. The larger entries still use a data descriptor.
+    /// The archive is then smaller than predicted by `archive_size`.
+    WhenNeeded,
+    /// Buffer every entry until its CRC32 and sizes are known, to write them in its local header,
+    /// without data descriptor nor bit 3, for the strictest readers. This delays the output of each entry until it is fully read,
+    /// and keeps it in memory, unless the `spill-to-disk` feature is enabled (see `ArchiveOptions::spill_threshold`).
+    /// The archive is then smaller than predicted by `archive_size`.
+    Never,
+}
+
+/// A family of extractors whose quirks an archive should accommodate, see `ArchiveOptions::profile`.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Profile {
+    /// The Windows Explorer, which decodes the names not flagged as UTF-8 in the system code page and ignores the Unix permissions:
+    /// ASCII names aren't flagged, the host system is Windows NTFS and the version needed is at least 2.0.
+    WindowsExplorer,
+    /// The macOS Archive Utility, which applies the Unix permissions and mishandles the unneeded Zip64 records:
+    /// the names are always flagged as UTF-8, the host system is Unix and Zip64 is only used where needed.
+    MacArchiveUtility,
+    /// The `java.util.zip` streams, which can't read stored entries followed by a data descriptor:
+    /// the CRC32 and sizes of every entry are written in their local header (see `DataDescriptor::Never`),
+    /// buffering the entries whose size isn't known beforehand.
+    Java,
+    /// The streaming extractors, which rely on the data descriptors to find the end of the entries:
+    /// every entry is followed by a data descriptor with its signature. These are the default options.
+    Streaming,
+}
+
+/// When the Zip64 extensions are used.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub enum Zip64Mode {
+    /// Use the Zip64 extensions only where needed: in the local headers of the entries appended with `EntryOptions::large_file`,
+    /// and in the central directory when the sizes, offsets or number of entries don't fit in the regular fields.
+    #[default]
+    Auto,
+    /// Use the Zip64 extensions for every entry and for the end of central directory, whatever their sizes.
+    /// The layout of the archive then only depends on the names of the entries, not on the actual sizes.
+    Always,
+    /// Never use the Zip64 extensions, for readers that don't support them. `EntryOptions::large_file` is ignored,
+    /// and appending more entries, or more data, than a regular archive can hold returns an error instead.
+    Never,
+}
+
+/// Options applied to the whole archive.
+///
+/// The default options never override the entries' options.
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+#[derive(Clone, Debug)]
+pub struct ArchiveOptions {
+    store_threshold: usize,
+    store_extensions: Vec<String>,
+    trial_size: usize,
+    zip64: Zip64Mode,
+    max_name_length: usize,
+    unicode_path_field: bool,
+    name_encoding: NameEncoding,
+    host_system: HostSystem,
+    version_needed: u16,
+    data_descriptor: DataDescriptor,
+    descriptor_signature: bool,
+    spill_threshold: usize,
+    verify_crc: bool,
+    dedup_size: usize,
+    password: Option<Vec<u8>>,
+    signer: Option<SignerFactory>,
+    embed_signature: bool,
+    checksums: Option<(String, DigestFactory)>,
+    checksums_datetime: Option<FileDateTime>,
+}
+
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+impl Default for ArchiveOptions {
+    fn default() -> Self {
+        Self {
+            store_threshold: 0,
+            store_extensions: Vec::new(),
+            trial_size: 0,
+            zip64: Zip64Mode::default(),
+            max_name_length: u16::MAX as usize,
+            unicode_path_field: false,
+            name_encoding: NameEncoding::default(),
+            host_system: HostSystem::default(),
+            version_needed: 10,
+            data_descriptor: DataDescriptor::default(),
+            descriptor_signature: true,
+            spill_threshold: 16 << 20,
+            verify_crc: false,
+            dedup_size: 0,
+            password: None,
+            signer: None,
+            embed_signature: false,
+            checksums: None,
+            checksums_datetime: None,
+        }
+    }
+}
+
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+impl ArchiveOptions {
+    /// Create the default archive options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store (rather than compress) the entries smaller than `size` bytes, as compressing them is unlikely to be worth it.
+    /// Up to `size` bytes of each compressed entry are buffered in memory to check its size before writing its header.
+    pub fn store_smaller_than(mut self, size: usize) -> Self {
+        self.store_threshold = size;
+        self
+    }
+
+    /// Store (rather than compress) the entries whose name has one of these extensions (without the dot, case-insensitive),
+    /// as their payload is most likely already compressed. See `INCOMPRESSIBLE_EXTENSIONS` for a list of common ones.
+    pub fn store_extensions<I, S>(mut self, extensions: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.store_extensions = extensions.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Buffer up to `size` bytes of each compressed entry's payload, and its compressed counterpart, before writing its header.
+    /// The entries that fit in this buffer are stored if compressing them doesn't make them smaller.
+    /// Larger entries are always compressed, as the sink can't be rewound.
+    pub fn compression_trial(mut self, size: usize) -> Self {
+        self.trial_size = size;
+        self
+    }
+
+    /// Set when the Zip64 extensions are used for the archive-wide records, see `Zip64Mode`.
+    pub fn zip64(mut self, mode: Zip64Mode) -> Self {
+        self.zip64 = mode;
+        self
+    }
+
+    /// Reject the entries whose name is longer than `length` bytes (UTF-8 encoded), for readers or file systems with tighter limits.
+    /// Names can't be longer than 65,535 bytes anyway, which is the default.
+    pub fn max_name_length(mut self, length: usize) -> Self {
+        self.max_name_length = length.min(u16::MAX as usize);
+        self
+    }
+
+    /// Write the non-ASCII names in code page 437, replacing the characters it can't represent with an underscore,
+    /// and store the original UTF-8 names in an Info-ZIP Unicode Path extra field (header id `0x7075`),
+    /// rather than flagging the names as UTF-8. Legacy extractors, unaware of the flag, then use the transliterated names,
+    /// while the others restore the original ones.
+    pub fn unicode_path_field(mut self, enabled: bool) -> Self {
+        self.unicode_path_field = enabled;
+        self
+    }
+
+    /// Set how the names of the entries are encoded, see `NameEncoding`.
+    pub fn name_encoding(mut self, encoding: NameEncoding) -> Self {
+        self.name_encoding = encoding;
+        self
+    }
+
+    /// Set the system written in the "version made by" fields, `HostSystem::Unix` by default.
+    pub fn host_system(mut self, system: HostSystem) -> Self {
+        self.host_system = system;
+        self
+    }
+
+    /// Set the minimum "version needed to extract" of the entries, as major * 10 + minor (e.g. `20` for 2.0), 1.0 by default.
+    /// It is still raised when an entry needs a later version (e.g. 2.0 for Deflate or 4.5 for Zip64).
+    /// The "version made by" fields are never lower than the version needed, nor than 3.0.
+    pub fn version_needed(mut self, version: u16) -> Self {
+        self.version_needed = version;
+        self
+    }
+
+    /// Set the name encoding, host system, version needed, data descriptors and Zip64 options suited to a family of extractors,
+    /// see `Profile`. The options set afterwards override the profile's ones.
+    pub fn profile(self, profile: Profile) -> Self {
+        let options = self
+            .zip64(Zip64Mode::Auto)
+            .data_descriptor(DataDescriptor::Always)
+            .descriptor_signature(true);
+        match profile {
+            Profile::WindowsExplorer => options
+                .name_encoding(NameEncoding::Utf8WhenNeeded)
+                .host_system(HostSystem::WindowsNtfs)
+                .version_needed(20),
+            Profile::MacArchiveUtility => options
+                .name_encoding(NameEncoding::Utf8)
+                .host_system(HostSystem::Unix),
+            Profile::Java => options
+                .name_encoding(NameEncoding::Utf8)
+                .data_descriptor(DataDescriptor::Never),
+            Profile::Streaming => options.name_encoding(NameEncoding::Utf8),
+        }
+    }
+
+    /// Set when the entries are followed by a data descriptor, see `DataDescriptor`.
+    pub fn data_descriptor(mut self, mode: DataDescriptor) -> Self {
+        self.data_descriptor = mode;
+        self
+    }
+
+    /// Start the data descriptors with their optional signature (`0x08074b50`), enabled by default.
+    /// Disabling it saves 4 bytes per entry, but `archive_size` doesn't take it into account.
+    pub fn descriptor_signature(mut self, enabled: bool) -> Self {
+        self.descriptor_signature = enabled;
+        self
+    }
+
+    /// Keep up to `size` bytes of each entry buffered by `DataDescriptor::Never` in memory (16 MiB by default),
+    /// and spill the rest to a temporary file. The temporary file is accessed with blocking calls.
+    #[cfg(feature = "spill-to-disk")]
+    pub fn spill_threshold(mut self, size: usize) -> Self {
+        self.spill_threshold = size;
+        self
+    }
+
+    /// Still compute the CRC32 of the payloads appended with `append_with_crc`, and check it against the provided one.
+    pub fn verify_crc(mut self, verify: bool) -> Self {
+        self.verify_crc = verify;
+        self
+    }
+
+    /// Read ahead the payloads smaller than `size` bytes entirely, and only write the first of the identical ones (by SHA-256),
+    /// the central directory records of the others pointing to its local header, e.g. for a license file bundled many times.
+    /// The encrypted entries, the directories and the raw payloads aren't deduplicated.
+    ///
+    /// The names (and extra fields) of the duplicates only appear in the central directory. Only the readers trusting it
+    /// (e.g. Go's `archive/zip`) extract them: streaming readers only see the first entry, the readers checking that the local header
+    /// matches the central directory (e.g. Python's `zipfile`) reject them, and Info-ZIP's `unzip` rejects the archive as a possible zip bomb.
+    pub fn deduplicate(mut self, size: usize) -> Self {
+        self.dedup_size = size;
+        self
+    }
+
+    /// Encrypt every entry with ZipCrypto using `password`, unless the entry sets its own with `EntryOptions::password`.
+    pub fn password<P: AsRef<[u8]>>(mut self, password: P) -> Self {
+        self.password = Some(password.as_ref().to_vec());
+        self
+    }
+
+    /// Sign the archive with a custom `Signer`, created by `factory` for each archive and fed every byte written.
+    /// The signature is returned by `finalize_signed`, without a second pass over the archive.
+    /// Use `Sha256::new` (or any other `Digest`) as the factory to get the archive's digest instead.
+    ///
+    /// The headers of the entries appended with `append_seekable` aren't patched (they use a data descriptor instead),
+    /// as the signed bytes can't be rewritten.
+    pub fn signer<F, S>(mut self, factory: F) -> Self
+    where
+        F: Fn() -> S + Send + Sync + 'static,
+        S: Signer + Send + 'static,
+    {
+        self.signer = Some(SignerFactory::new(factory));
+        self
+    }
+
+    /// Embed the signature in the archive's comment, in the end of central directory record.
+    /// The signature then covers the archive up to this record (excluded), rather than the whole archive.
+    /// Its bytes are embedded as is, a signer producing text (e.g. hex or base64) keeps the comment readable.
+    pub fn embed_signature(mut self, embed: bool) -> Self {
+        self.embed_signature = embed;
+        self
+    }
+
+    /// Append an entry named `name` (e.g. `sha256sums.txt`) when finalizing the archive,
+    /// listing the SHA-256 digest of each entry's payload in the format of `sha256sum`, computed while streaming.
+    ///
+    /// The directories and the entries appended with `append_raw` (whose uncompressed payload isn't read) aren't listed.
+    /// The entry is dated like the newest entry (`FileDateTime::Zero` if none), or with `checksums_datetime`,
+    /// so the same entries always make the same archive (e.g. for `ArchivePlan::etag` and `ArchivePlan::stream_from`).
+    pub fn checksums<N: Into<String>>(mut self, name: N) -> Self {
+        self.checksums = Some((name.into(), DigestFactory::sha256()));
+        self
+    }
+
+    /// Append an entry listing the digests of the entries like `checksums`, computed by a custom `Digest` created by `factory` for each entry
+    /// (e.g. SHA-1 for a `sha1sums.txt` entry).
+    pub fn checksums_with<N, F, D>(mut self, name: N, factory: F) -> Self
+    where
+        N: Into<String>,
+        F: Fn() -> D + Send + Sync + 'static,
+        D: Digest + Send + 'static,
+    {
+        self.checksums = Some((name.into(), DigestFactory::new(factory)));
+        self
+    }
+
+    /// Set the date and time of the checksums entry (see `checksums`), instead of the newest entry's one.
+    pub fn checksums_datetime(mut self, datetime: FileDateTime) -> Self {
+        self.checksums_datetime = Some(datetime);
+        self
+    }
+
+    // The "version made by" field for an entry that needs `version_needed`.
+    fn version_made_by(&self, version_needed: u16) -> u16 {
+        self.host_system.code() << 8 | version_needed.max(0x1e)
+    }
+
+    fn stores_extension(&self, name: &str) -> bool {
+        Path::new(name)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| {
+                self.store_extensions
+                    .iter()
+                    .any(|e| e.eq_ignore_ascii_case(extension))
+            })
+    }
 }
 
 /// The (timezone-less) date and time that will be written in the archive alongside the file.
@@ -139,7 +1035,7 @@ pub enum FileDateTime {
 
 #[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
 impl FileDateTime {
-    fn tuple(&self) -> (u16, u16, u16, u16, u16, u16) {
+    pub(crate) fn tuple(&self) -> (u16, u16, u16, u16, u16, u16) {
         match self {
             FileDateTime::Zero => Default::default(),
             &FileDateTime::Custom {
@@ -160,6 +1056,58 @@ impl FileDateTime {
             (sec / 2) | min << 5 | hour << 11,
         )
     }
+    // The inverse of `ms_dos`, the zero date and time being `FileDateTime::Zero`.
+    pub(crate) fn from_ms_dos(date: u16, time: u16) -> Self {
+        if date == 0 && time == 0 {
+            return FileDateTime::Zero;
+        }
+        FileDateTime::Custom {
+            year: (date >> 9) + 1980,
+            month: date >> 5 & 0xf,
+            day: date & 0x1f,
+            hour: time >> 11,
+            minute: time >> 5 & 0x3f,
+            second: (time & 0x1f) * 2,
+        }
+    }
+}
+
+impl FileDateTime {
+    /// Use the date and time of a `SystemTime` (e.g. a file's modification time),
+    /// converted to the local timezone if the `chrono-datetime` feature is enabled, to UTC otherwise.
+    pub fn from_system_time(time: SystemTime) -> Self {
+        #[cfg(feature = "chrono-datetime")]
+        {
+            Self::from_chrono_datetime(DateTime::<Local>::from(time))
+        }
+        #[cfg(not(feature = "chrono-datetime"))]
+        {
+            let seconds = time
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_secs());
+            // Days since the epoch to civil date, see http://howardhinnant.github.io/date_algorithms.html#civil_from_days.
+            let days = seconds / 86400 + 719468;
+            let (era, day_of_era) = (days / 146097, days % 146097);
+            let year_of_era =
+                (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+            let day_of_year =
+                day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+            let month_index = (5 * day_of_year + 2) / 153;
+            let month = if month_index < 10 {
+                month_index + 3
+            } else {
+                month_index - 9
+            };
+            Self::Custom {
+                year: (year_of_era + era * 400 + (month <= 2) as u64) as u16,
+                month: month as u16,
+                day: (day_of_year - (153 * month_index + 2) / 5 + 1) as u16,
+                hour: (seconds % 86400 / 3600) as u16,
+                minute: (seconds % 3600 / 60) as u16,
+                second: (seconds % 60) as u16,
+            }
+        }
+    }
 }
 
 #[cfg(feature = "chrono-datetime")]
@@ -182,27 +1130,73 @@ impl FileDateTime {
     }
 }
 
-#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
-macro_rules! header {
-    [$capacity:expr; $($elem:expr),*$(,)?] => {
-        {
-            let mut header = Vec::with_capacity($capacity);
-            $(
-                header.extend_from_slice(&$elem.to_le_bytes());
-            )*
-            header
-        }
-    };
-}
-
 const FILE_HEADER_BASE_SIZE: usize = 7 * size_of::<u16>() + 4 * size_of::<u32>();
 const DESCRIPTOR_SIZE: usize = 4 * size_of::<u32>();
+const ZIP64_DESCRIPTOR_SIZE: usize = 2 * size_of::<u32>() + 2 * size_of::<u64>();
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+const ZIP64_VERSION_NEEDED: u16 = 45;
+const ZIP64_END_OF_CENTRAL_DIRECTORY_SIZE: usize =
+    2 * size_of::<u16>() + 3 * size_of::<u32>() + 5 * size_of::<u64>();
+const ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIZE: usize =
+    2 * size_of::<u32>() + size_of::<u64>() + size_of::<u32>();
 const CENTRAL_DIRECTORY_ENTRY_BASE_SIZE: usize = 11 * size_of::<u16>() + 6 * size_of::<u32>();
 const END_OF_CENTRAL_DIRECTORY_SIZE: usize = 5 * size_of::<u16>() + 3 * size_of::<u32>();
 
-/// A streamed zip archive.
-///
-/// Create an archive using the `new` function and a `AsyncWrite`. Then, append files one by one using the `append` function. When finished, use the `finalize` function.
+// The raw name of an entry and its UTF-8 flag, with its local and central extra fields,
+// preceded by an Info-ZIP Unicode Path extra field if its name is transliterated.
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+fn encode_name(
+    name: &str,
+    encoding: NameEncoding,
+    unicode_path_field: bool,
+    mut local_extra_field: Vec<u8>,
+    mut central_extra_field: Vec<u8>,
+) -> (Vec<u8>, u16, Vec<u8>, Vec<u8>) {
+    let (raw_name, flag) = match cp437::encode(name) {
+        // ASCII names are the same in both encodings.
+        Some(_) if name.is_ascii() && encoding != NameEncoding::Utf8 => {
+            (name.as_bytes().to_vec(), 0)
+        }
+        Some(raw_name) if encoding == NameEncoding::Cp437 => (raw_name, 0),
+        _ if unicode_path_field && !name.is_ascii() => {
+            let raw_name = cp437::transliterate(name);
+            let mut unicode_path_field = header![
+                9 + name.len();
+                0x7075u16,                  // Info-ZIP Unicode Path extra field header id.
+                (5 + name.len()) as u16,    // Size of the extra field's data (checked when appending).
+                1u8,                        // Version.
+                crc32fast::hash(&raw_name), // CRC32 of the transliterated name.
+            ];
+            unicode_path_field.extend_from_slice(name.as_bytes()); // UTF-8 name.
+            local_extra_field.splice(0..0, unicode_path_field.iter().copied());
+            central_extra_field.splice(0..0, unicode_path_field);
+            (raw_name, 0)
+        }
+        _ => (name.as_bytes().to_vec(), 1 << 11),
+    };
+    (raw_name, flag, local_extra_field, central_extra_field)
+}
+
+// Zip64 extended information extra field, holding the values that don't fit in their 32-bit header field.
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+fn zip64_extra_field(values: &[u64]) -> Vec<u8> {
+    if values.is_empty() {
+        return Vec::new();
+    }
+    let mut field = header![
+        2 * size_of::<u16>() + size_of_val(values);
+        0x0001u16,                      // Zip64 extra field header id.
+        size_of_val(values) as u16,     // Size of the extra field's data.
+    ];
+    for value in values {
+        field.extend_from_slice(&value.to_le_bytes());
+    }
+    field
+}
+
+/// A streamed zip archive.
+///
+/// Create an archive using the `new` function and a `AsyncWrite`. Then, append files one by one using the `append` function. When finished, use the `finalize` function.
 ///
 /// ## Example
 ///
@@ -232,7 +1226,15 @@ const END_OF_CENTRAL_DIRECTORY_SIZE: usize = 5 * size_of::<u16>() + 3 * size_of:
 pub struct Archive<W> {
     sink: W,
     files_info: Vec<FileInfo>,
-    written: usize,
+    written: u64,
+    options: ArchiveOptions,
+    signature: Option<Signature>,
+    digests: Vec<(String, Vec<u8>)>,
+    // The comment and Zip64 records of an existing archive, kept so it isn't shortened.
+    comment: Vec<u8>,
+    zip64_records: bool,
+    // The entries written with a payload to deduplicate, by compression method and payload's SHA-256.
+    duplicates: HashMap<(u16, [u8; SHA256_SIZE]), usize>,
 }
 
 #[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
@@ -241,9 +1243,57 @@ macro_rules! impl_methods {
         $(#[$($attrss:tt)*])*,
         $w:path, $r:path,
         $we:path, $re: path,
-        $fa:tt, $ff:tt,
+        $s:path, $se:path,
+        $fa:tt, $fao:tt, $fam:tt, $fac:tt, $fas:tt, $fap:tt, $far:tt, $fd:tt, $ff:tt, $ffs:tt, $fo:tt, $fcd:tt, $fcz:tt, $faa:tt, $frn:tt, $fsh:tt, $fex:tt, $ffdt:tt, $ffwd:tt, $ffi:tt, $fab:tt, $fabo:tt, $fae:tt,
     ) => {
         impl<W> Archive<W> {
+            /// Open the existing archive in `sink` to append new entries to it.
+            /// Its central directory is read, then overwritten by the new entries, and written again (with theirs) on `finalize`.
+            ///
+            /// The existing entries, their central directory records, the archive's comment and its Zip64 records are kept as is,
+            /// so the archive doesn't get shorter and no stale byte is left at its end (unless its comment is replaced by an embedded signature).
+            /// A signer (see `ArchiveOptions::signer`) and the checksums only cover what is written from then on.
+            ///
+            /// # Error
+            ///
+            /// This function will forward any error found while reading from or seeking the sink,
+            /// and an `Error::InvalidArchive` if it doesn't hold a valid archive.
+            $(#[$($attrss)*])*
+            pub async fn $fo(mut sink: W, options: ArchiveOptions) -> Result<Self, IoError> where W: $w + $r + $s + Unpin {
+                use $se;
+
+                let central_directory = CentralDirectory::$fcd(&mut sink).await?;
+                sink.seek(SeekFrom::Start(central_directory.offset())).await?;
+                let mut archive = Self::with_options(sink, options);
+                archive.written = central_directory.offset();
+                archive.comment = central_directory.comment().to_vec();
+                archive.zip64_records = central_directory.is_zip64();
+                for entry in central_directory.entries() {
+                    archive.files_info.push(FileInfo {
+                        name: entry.raw_name().to_vec(),
+                        flags: entry.flags(),
+                        size: entry.size(),
+                        compressed_size: entry.compressed_size(),
+                        crc: entry.crc(),
+                        offset: entry.offset(),
+                        data_offset: None,
+                        datetime: entry.ms_dos_datetime(),
+                        method: Method {
+                            code: entry.method(),
+                            version_needed: entry.version_needed(),
+                            flags: 0,
+                        },
+                        large_file: false,
+                        text: entry.is_text(),
+                        external_attributes: entry.external_attributes(),
+                        extra_field: entry.extra_field().to_vec(),
+                        comment: entry.comment().to_owned(),
+                        record: Some(entry.record().to_vec()),
+                    });
+                }
+                Ok(archive)
+            }
+
             /// Append a new file to the archive using the provided name, date/time and `AsyncRead` object.
             /// Filename must be valid UTF-8. Some (very) old zip utilities might mess up filenames during extraction if they contain non-ascii characters.
             /// File's payload is not compressed and is given `rw-r--r--` permissions.
@@ -251,276 +1301,6218 @@ macro_rules! impl_methods {
             /// # Error
             ///
             /// This function will forward any error found while trying to read from the file stream or while writing to the underlying sink.
+            /// An `Error::EntryTooLarge` is returned if the payload exceeds 4 GiB, such files must be appended with `EntryOptions::large_file`.
             $(#[$($attrss)*])*
             pub async fn $fa<R>(
                 &mut self,
                 name: String,
                 datetime: FileDateTime,
                 reader: &mut R,
+            ) -> Result<(), IoError> where W: $w + Unpin, R: $r + Unpin {
+                self.$fao(name, datetime, EntryOptions::default(), reader).await
+            }
+
+            /// Append a new file to the archive using the provided name, date/time, options and `AsyncRead` object.
+            /// See `EntryOptions` for the available options (e.g. the compression method).
+            /// The payload might be stored instead of compressed depending on the archive's options.
+            ///
+            /// # Error
+            ///
+            /// This function will forward any error found while trying to read from the file stream or while writing to the underlying sink.
+            /// An `Error::EntryTooLarge` is returned if the payload exceeds 4 GiB without `EntryOptions::large_file`,
+            /// an `Error::TooManyEntries` if the archive already holds 65,535 entries with `Zip64Mode::Never`,
+            /// an `Error::NameTooLong` if the name exceeds the archive's maximum name length,
+            /// an `Error::ExtraFieldTooLarge` if the entry's extra fields (e.g. its extended attributes) don't fit in the headers,
+            /// an `Error::CommentTooLong` if the entry's comment is longer than 65,535 bytes,
+            /// and an `Error::ArchiveTooLarge` if the archive already exceeds 4 GiB with `Zip64Mode::Never`.
+            $(#[$($attrss)*])*
+            pub async fn $fao<R>(
+                &mut self,
+                name: String,
+                datetime: FileDateTime,
+                options: EntryOptions,
+                reader: &mut R,
             ) -> Result<(), IoError> where W: $w + Unpin, R: $r + Unpin {
                 use $we;
                 use $re;
 
+                if self.options.zip64 == Zip64Mode::Never && self.files_info.len() >= u16::MAX as usize {
+                    return Err(Error::TooManyEntries.into());
+                }
+                if self.options.zip64 == Zip64Mode::Never && self.written >= u32::MAX as u64 {
+                    return Err(Error::ArchiveTooLarge { size: self.written }.into());
+                }
+                if name.len() > self.options.max_name_length {
+                    return Err(Error::NameTooLong {
+                        max_length: self.options.max_name_length,
+                        name,
+                    }.into());
+                }
+                let (local_extra_field, central_extra_field) = options.extra_fields();
+                let name_encoding = options.name_encoding.unwrap_or(self.options.name_encoding);
+                let (raw_name, name_flag, local_extra_field, central_extra_field) =
+                    encode_name(&name, name_encoding, self.options.unicode_path_field, local_extra_field, central_extra_field);
+                // Leave room for the largest Zip64 extra fields.
+                if local_extra_field.len() + 20 > u16::MAX as usize || central_extra_field.len() + 28 > u16::MAX as usize {
+                    return Err(Error::ExtraFieldTooLarge { name }.into());
+                }
+                if options.comment.len() > u16::MAX as usize {
+                    return Err(Error::CommentTooLong { name }.into());
+                }
+
+                let mut buf = vec![0; 4096];
+                let mut sealed = Vec::new();
+                let compresses = match options.metadata {
+                    // The payload is only compressed if its compressed size is known too.
+                    Some(_) => options.compressed_size.is_some(),
+                    None => options.compresses() && !self.options.stores_extension(&name),
+                };
+                let mut encoder = match options.raw {
+                    Some((method, flags, _)) => Encoder::raw(method, flags),
+                    None if compresses => options.encoder(),
+                    None => Encoder::new(None),
+                };
+
+                // Read ahead the beginning of the payload, to decide whether it is worth compressing before writing the header,
+                // or whether its CRC32 and sizes can be written in the header.
+                let mut total_read = 0u64;
+                let mut hasher = Hasher::new();
+                // Skip hashing the payload if its CRC32 is provided, unless it should be verified.
+                let hashes = options.raw.is_none() && (options.crc.is_none() || self.options.verify_crc);
+                let mut digest = match &self.options.checksums {
+                    Some((_, factory)) if options.raw.is_none() && !options.directory => Some(factory.build()),
+                    _ => None,
+                };
+                let mut lookahead = Vec::new();
+                let mut compressed = Vec::new();
+                let mut eof = false;
+                let mut lookahead_size = self.options.store_threshold.max(self.options.trial_size);
+                if self.options.data_descriptor != DataDescriptor::Always {
+                    lookahead_size = lookahead_size.max(buf.len());
+                }
+                let deduplicates = self.options.dedup_size > 0
+                    && options.raw.is_none()
+                    && !options.directory
+                    && options.password.is_none()
+                    && self.options.password.is_none();
+                if deduplicates {
+                    lookahead_size = lookahead_size.max(self.options.dedup_size);
+                }
+                let mut dedup_key = None;
+                if options.metadata.is_none() && (encoder.compresses() || self.options.data_descriptor != DataDescriptor::Always || deduplicates) {
+                    while lookahead.len() < lookahead_size {
+                        let read = reader.read(&mut buf).await?;
+                        if read == 0 {
+                            eof = true;
+                            break;
+                        }
+
+                        total_read += read as u64;
+                        if hashes {
+                            hasher.update(&buf[..read]);
+                        }
+                        if let Some(digest) = &mut digest {
+                            digest.update(&buf[..read]);
+                        }
+                        lookahead.extend_from_slice(&buf[..read]);
+                        compressed.extend_from_slice(encoder.update(&buf[..read]));
+                    }
+                    if eof {
+                        if deduplicates && !lookahead.is_empty() {
+                            let mut hasher = Sha256::new();
+                            hasher.update(&lookahead);
+                            dedup_key = Some(hasher.finalize());
+                        }
+                        compressed.extend_from_slice(encoder.finish());
+                        if options.raw.is_none() && (lookahead.len() < self.options.store_threshold || compressed.len() >= lookahead.len()) {
+                            encoder = Encoder::new(None);
+                            compressed = lookahead;
+                        }
+                    }
+                }
+
+                // Buffer the rest of the payload to write its CRC32 and sizes in the header, if required.
+                let mut spilled = None;
+                if !eof && self.options.data_descriptor == DataDescriptor::Never && options.metadata.is_none() && !options.seekable {
+                    let mut buffer = SpillBuffer::new(compressed, self.options.spill_threshold);
+                    loop {
+                        let read = reader.read(&mut buf).await?;
+                        if read == 0 {
+                            break;
+                        }
+
+                        total_read += read as u64;
+                        if hashes {
+                            hasher.update(&buf[..read]);
+                        }
+                        if let Some(digest) = &mut digest {
+                            digest.update(&buf[..read]);
+                        }
+                        buffer.write(encoder.update(&buf[..read]))?;
+                    }
+                    buffer.write(encoder.finish())?;
+                    (compressed, spilled) = buffer.into_parts()?;
+                    eof = true;
+                }
+
                 let (date, time) = datetime.ms_dos();
                 let offset = self.written;
+                let method = encoder.method();
+                // Directories have no payload to encrypt.
+                let password = options.password.as_deref().or(self.options.password.as_deref()).filter(|_| !options.directory);
+                let mut cipher = password.map(ZipCrypto::new);
+                let encryption_header_size = if cipher.is_some() { ZIP_CRYPTO_HEADER_SIZE as u64 } else { 0 };
+                let large_file = match self.options.zip64 {
+                    Zip64Mode::Auto => {
+                        options.large_file
+                            || options.metadata.is_some_and(|(size, _)| size.max(options.compressed_size.unwrap_or(0)) >= u32::MAX as u64)
+                            || options.raw.as_ref().and_then(|(_, _, metadata)| metadata.get()).is_some_and(|(size, _)| size >= u32::MAX as u64)
+                    }
+                    Zip64Mode::Always => true,
+                    Zip64Mode::Never => false,
+                };
+                // Point the central directory record of a duplicate to the local header of the first entry with the same payload.
+                let dedup_key = dedup_key.map(|key| (method.code, key));
+                if let Some(&index) = dedup_key.as_ref().and_then(|key| self.duplicates.get(key)) {
+                    let original = &self.files_info[index];
+                    if self.options.verify_crc && options.crc.is_some_and(|crc| crc != original.crc) {
+                        return Err(Error::MetadataMismatch { name }.into());
+                    }
+                    let file_info = FileInfo {
+                        name: raw_name,
+                        // The UTF-8 flag applies to the name of the record.
+                        flags: original.flags & !(1 << 11) | name_flag,
+                        datetime: (date, time),
+                        text: options.text,
+                        extra_field: central_extra_field,
+                        external_attributes: options.attributes(),
+                        comment: options.comment,
+                        ..original.clone()
+                    };
+                    self.files_info.push(file_info);
+                    if let Some(digest) = &mut digest {
+                        self.digests.push((name, digest.finish()));
+                    }
+                    return Ok(());
+                }
+
+                // The CRC32 and sizes are only known if they were provided or if the whole payload was read ahead,
+                // otherwise they are written in the data descriptor.
+                let (descriptor, header_crc, sizes) = match options.metadata {
+                    Some((size, crc)) => {
+                        let compressed_size = options.compressed_size.unwrap_or(size) + encryption_header_size;
+                        if !large_file && size.max(compressed_size) >= u32::MAX as u64 {
+                            return Err(Error::EntryTooLarge { name, size: size.max(compressed_size) }.into());
+                        }
+                        (false, crc, [size, compressed_size])
+                    }
+                    None if eof && self.options.data_descriptor != DataDescriptor::Always => {
+                        let compressed_size = encryption_header_size + compressed.len() as u64 + spilled.as_ref().map_or(0, |(_, size)| *size);
+                        match &options.raw {
+                            Some((_, _, metadata)) => {
+                                let (size, crc) = metadata.get().unwrap_or_default();
+                                (false, crc, [size, compressed_size])
+                            }
+                            None => (false, options.crc.unwrap_or_else(|| hasher.clone().finalize()), [total_read, compressed_size]),
+                        }
+                    }
+                    // Patched once the payload is written (except for the encrypted entries, whose encryption header depends on the CRC32,
+                    // and for the signed archives, whose signed bytes can't be rewritten).
+                    None if options.seekable && cipher.is_none() && self.signature.is_none() => (false, 0, [0, 0]),
+                    None => (true, 0, [0, 0]),
+                };
+                let (version_needed, header_sizes, mut extra_field) = if large_file {
+                    (method.version_needed.max(ZIP64_VERSION_NEEDED), [u32::MAX; 2], zip64_extra_field(&sizes))
+                } else {
+                    (method.version_needed, sizes.map(|size| size as u32), Vec::new())
+                };
+                let version_needed = version_needed.max(self.options.version_needed).max(if cipher.is_some() { 20 } else { 10 });
+                extra_field.extend_from_slice(&local_extra_field);
+                // Encryption + data descriptor (temporary crc and sizes) + UTF-8 filename (unless transliterated) + method flags.
+                let flags = cipher.is_some() as u16 | (descriptor as u16) << 3 | name_flag | method.flags;
                 let mut header = header![
-                    FILE_HEADER_BASE_SIZE + name.len();
-                    0x04034b50u32,          // Local file header signature.
-                    10u16,                  // Version needed to extract.
-                    1u16 << 3 | 1 << 11,    // General purpose flag (temporary crc and sizes + UTF-8 filename).
-                    0u16,                   // Compression method (store).
+                    FILE_HEADER_BASE_SIZE + raw_name.len() + extra_field.len();
+                    0x04034b50u32,                  // Local file header signature.
+                    version_needed,                 // Version needed to extract.
+                    flags,                          // General purpose flag.
+                    method.code,                    // Compression method.
                     time,                   // Modification time.
                     date,                   // Modification date.
-                    0u32,                   // Temporary CRC32.
-                    0u32,                   // Temporary compressed size.
-                    0u32,                   // Temporary uncompressed size.
-                    name.len() as u16,      // Filename length.
-                    0u16,                   // Extra field length.
+                    header_crc,             // CRC32 (or temporary).
+                    header_sizes[1],        // Compressed size (or temporary).
+                    header_sizes[0],        // Uncompressed size (or temporary).
+                    raw_name.len() as u16,  // Filename length.
+                    extra_field.len() as u16, // Extra field length.
                 ];
-                header.extend_from_slice(name.as_bytes()); // Filename.
+                header.extend_from_slice(&raw_name); // Filename.
+                header.extend_from_slice(&extra_field); // Zip64 (sizes or temporary) and entry's extra fields.
                 self.sink.write_all(&header).await?;
-                self.written += header.len();
+                Signature::update(&mut self.signature, &header);
+                self.written += header.len() as u64;
+                let data_offset = self.written;
 
-                let mut total_read = 0;
-                let mut hasher = Hasher::new();
-                let mut buf = vec![0; 4096];
-                loop {
+                let mut total_written = 0;
+                if let Some(cipher) = &mut cipher {
+                    // Readers check the password against the last byte of the header: the CRC32's high byte, or the time's if unknown yet.
+                    let check = if descriptor { (time >> 8) as u8 } else { (header_crc >> 24) as u8 };
+                    let encryption_header = cipher.header(check);
+                    self.sink.write_all(&encryption_header).await?; // Encryption header.
+                    Signature::update(&mut self.signature, &encryption_header);
+                    total_written += encryption_header_size;
+                    cipher.encrypt(&mut compressed);
+                }
+                self.sink.write_all(&compressed).await?; // Read ahead (or buffered) payload chunk.
+                Signature::update(&mut self.signature, &compressed);
+                total_written += compressed.len() as u64;
+                if let Some((mut file, _)) = spilled {
+                    loop {
+                        let read = std::io::Read::read(&mut file, &mut buf)?;
+                        if read == 0 {
+                            break;
+                        }
+
+                        if let Some(cipher) = &mut cipher {
+                            cipher.encrypt(&mut buf[..read]);
+                        }
+                        self.sink.write_all(&buf[..read]).await?; // Spilled payload chunk.
+                        Signature::update(&mut self.signature, &buf[..read]);
+                        total_written += read as u64;
+                    }
+                }
+                while !eof {
                     let read = reader.read(&mut buf).await?;
                     if read == 0 {
                         break;
                     }
 
-                    total_read += read;
-                    hasher.update(&buf[..read]);
-                    self.sink.write_all(&buf[..read]).await?; // Payload chunk.
+                    total_read += read as u64;
+                    if hashes {
+                        hasher.update(&buf[..read]);
+                    }
+                    if let Some(digest) = &mut digest {
+                        digest.update(&buf[..read]);
+                    }
+                    let chunk = ZipCrypto::seal(&mut cipher, encoder.update(&buf[..read]), &mut sealed);
+                    self.sink.write_all(chunk).await?; // Payload chunk.
+                    Signature::update(&mut self.signature, chunk);
+                    total_written += chunk.len() as u64;
+                }
+                let chunk = ZipCrypto::seal(&mut cipher, encoder.finish(), &mut sealed);
+                self.sink.write_all(chunk).await?; // Last payload chunk.
+                Signature::update(&mut self.signature, chunk);
+                total_written += chunk.len() as u64;
+                // The payload read is already compressed if raw, its CRC32 and uncompressed size are provided.
+                let computed_crc = hasher.finalize();
+                let (crc, total_read) = match &options.raw {
+                    Some((_, _, metadata)) => {
+                        let (size, crc) = metadata.get().unwrap_or_default();
+                        (crc, size)
+                    }
+                    None => (options.crc.unwrap_or(computed_crc), total_read),
+                };
+                self.written += total_written;
+                if !large_file && total_read.max(total_written) >= u32::MAX as u64 {
+                    return Err(Error::EntryTooLarge {
+                        name,
+                        size: total_read.max(total_written),
+                    }.into());
+                }
+                if options.metadata.is_some_and(|metadata| metadata != (total_read, crc))
+                    || options.compressed_size.is_some_and(|size| size + encryption_header_size != total_written)
+                    || (self.options.verify_crc && options.crc.is_some_and(|crc| crc != computed_crc))
+                {
+                    return Err(Error::MetadataMismatch { name }.into());
+                }
+
+                if descriptor {
+                    let descriptor = if large_file {
+                        header![
+                            ZIP64_DESCRIPTOR_SIZE;
+                            0x08074b50u32,          // Data descriptor signature.
+                            crc,                    // CRC32.
+                            total_written,          // Compressed size.
+                            total_read,             // Uncompressed size.
+                        ]
+                    } else {
+                        header![
+                            DESCRIPTOR_SIZE;
+                            0x08074b50u32,          // Data descriptor signature.
+                            crc,                    // CRC32.
+                            total_written as u32,   // Compressed size.
+                            total_read as u32,      // Uncompressed size.
+                        ]
+                    };
+                    // Skip the optional signature if disabled.
+                    let descriptor = &descriptor[if self.options.descriptor_signature { 0 } else { 4 }..];
+                    self.sink.write_all(descriptor).await?;
+                    Signature::update(&mut self.signature, descriptor);
+                    self.written += descriptor.len() as u64;
                 }
-                let crc = hasher.finalize();
-                self.written += total_read;
-
-                let descriptor = header![
-                    DESCRIPTOR_SIZE;
-                    0x08074b50u32,      // Data descriptor signature.
-                    crc,                // CRC32.
-                    total_read as u32,  // Compressed size.
-                    total_read as u32,  // Uncompressed size.
-                ];
-                self.sink.write_all(&descriptor).await?;
-                self.written += descriptor.len();
 
+                if let Some(digest) = &mut digest {
+                    self.digests.push((name, digest.finish()));
+                }
                 self.files_info.push(FileInfo {
-                    name,
+                    name: raw_name,
+                    flags,
                     size: total_read,
+                    compressed_size: total_written,
                     crc,
                     offset,
+                    data_offset: Some(data_offset),
                     datetime: (date, time),
+                    method,
+                    large_file,
+                    text: options.text,
+                    extra_field: central_extra_field,
+                    external_attributes: options.attributes(),
+                    comment: options.comment,
+                    record: None,
                 });
+                if let Some(key) = dedup_key {
+                    self.duplicates.insert(key, self.files_info.len() - 1);
+                }
 
                 Ok(())
             }
 
-            /// Finalize the archive by writing the necessary metadata to the end of the archive.
+            /// Append a new file to the archive like `append_with_options`, with its (uncompressed) size and CRC32 known beforehand,
+            /// e.g. from a database. They are written in the local header rather than in a data descriptor,
+            /// for readers ignoring it. The payload is always stored, as its compressed size isn't known before writing the header,
+            /// and Zip64 is used if needed. Without data descriptor, the entry is 16 (or 24) bytes smaller than predicted by `archive_size`.
             ///
             /// # Error
             ///
-            /// This function will forward any error found while writing to the underlying sink.
+            /// This function will return an `Error::MetadataMismatch` if the payload read doesn't match the given size and CRC32,
+            /// or the same errors as `append_with_options`.
             $(#[$($attrss)*])*
-            pub async fn $ff(mut self) -> Result<W, IoError> where W: $w + Unpin {
-                use $we;
+            pub async fn $fam<R>(
+                &mut self,
+                name: String,
+                datetime: FileDateTime,
+                options: EntryOptions,
+                size: u64,
+                crc: u32,
+                reader: &mut R,
+            ) -> Result<(), IoError> where W: $w + Unpin, R: $r + Unpin {
+                let options = EntryOptions {
+                    metadata: Some((size, crc)),
+                    ..options
+                };
+                self.$fao(name, datetime, options, reader).await
+            }
 
-                let mut central_directory_size = 0;
-                for file_info in &self.files_info {
-                    let mut entry = header![
-                        CENTRAL_DIRECTORY_ENTRY_BASE_SIZE + file_info.name.len();
-                        0x02014b50u32,                  // Central directory entry signature.
-                        0x031eu16,                      // Version made by.
-                        10u16,                          // Version needed to extract.
-                        1u16 << 3 | 1 << 11,            // General purpose flag (temporary crc and sizes + UTF-8 filename).
-                        0u16,                           // Compression method (store).
-                        file_info.datetime.1,           // Modification time.
-                        file_info.datetime.0,           // Modification date.
-                        file_info.crc,                  // CRC32.
-                        file_info.size as u32,          // Compressed size.
-                        file_info.size as u32,          // Uncompressed size.
-                        file_info.name.len() as u16,    // Filename length.
-                        0u16,                           // Extra field length.
-                        0u16,                           // File comment length.
-                        0u16,                           // File's Disk number.
-                        0u16,                           // Internal file attributes.
-                        (0o100000u32 | 0o0000400 | 0o0000200 | 0o0000040 | 0o0000004) << 16, // External file attributes (regular file / rw-r--r--).
-                        file_info.offset as u32,        // Offset from start of file to local file header.
-                    ];
-                    entry.extend_from_slice(file_info.name.as_bytes()); // Filename.
-                    self.sink.write_all(&entry).await?;
-                    central_directory_size += entry.len();
-                }
+            /// Append a new file to the archive like `append_with_options`, with the size of its payload known beforehand
+            /// (e.g. counted by `archive_size`), failing if the reader doesn't produce exactly `size` bytes.
+            /// The reader isn't read further than one byte past `size`.
+            ///
+            /// # Error
+            ///
+            /// This function will return an `Error::SizeMismatch` if the payload isn't `size` bytes long,
+            /// or the same errors as `append_with_options`.
+            $(#[$($attrss)*])*
+            pub async fn $fsh<R>(
+                &mut self,
+                name: String,
+                datetime: FileDateTime,
+                options: EntryOptions,
+                size: u64,
+                reader: &mut R,
+            ) -> Result<(), IoError> where W: $w + Unpin, R: $r + Unpin {
+                let mut reader = SizedReader::new(reader, name.clone(), size, false);
+                self.$fao(name, datetime, options, &mut reader).await
+            }
 
-                let end_of_central_directory = header![
-                    END_OF_CENTRAL_DIRECTORY_SIZE;
-                    0x06054b50u32,                  // End of central directory signature.
-                    0u16,                           // Number of this disk.
-                    0u16,                           // Number of the disk where central directory starts.
-                    self.files_info.len() as u16,   // Number of central directory records on this disk.
-                    self.files_info.len() as u16,   // Total number of central directory records.
-                    central_directory_size as u32,  // Size of central directory.
-                    self.written as u32,            // Offset from start of file to central directory.
-                    0u16,                           // Comment length.
-                ];
-                self.sink.write_all(&end_of_central_directory).await?;
+            /// Append a new file to the archive like `append_with_options`, with the first `size` bytes read from the reader as payload,
+            /// which isn't read further, e.g. to slice an entry out of a larger stream (such as a tar archive or concatenated files).
+            ///
+            /// # Error
+            ///
+            /// This function will return an `Error::SizeMismatch` if the reader ends before `size` bytes,
+            /// or the same errors as `append_with_options`.
+            $(#[$($attrss)*])*
+            pub async fn $fex<R>(
+                &mut self,
+                name: String,
+                datetime: FileDateTime,
+                options: EntryOptions,
+                size: u64,
+                reader: &mut R,
+            ) -> Result<(), IoError> where W: $w + Unpin, R: $r + Unpin {
+                let mut reader = SizedReader::new(reader, name.clone(), size, true);
+                self.$fao(name, datetime, options, &mut reader).await
+            }
 
-                Ok(self.sink)
+            /// Append a new file to the archive using the provided name, date/time and in-memory payload,
+            /// without wrapping it in a reader. Its size and CRC32 are computed beforehand and written in the local header,
+            /// without data descriptor, see `append_with_metadata`.
+            ///
+            /// # Error
+            ///
+            /// This function will return the same errors as `append_with_options`.
+            $(#[$($attrss)*])*
+            pub async fn $fab<D: AsRef<[u8]>>(
+                &mut self,
+                name: String,
+                datetime: FileDateTime,
+                data: D,
+            ) -> Result<(), IoError> where W: $w + Unpin {
+                self.$fabo(name, datetime, EntryOptions::default(), data).await
             }
-        }
-    };
-}
 
-#[cfg(all(feature = "futures-async-io", feature = "tokio-async-io"))]
-impl_methods!(
-    #[cfg(all(feature = "futures-async-io", feature = "tokio-async-io"))],
-    futures_util::AsyncWrite, futures_util::AsyncRead,
-    futures_util::AsyncWriteExt, futures_util::AsyncReadExt,
-    futures_append, futures_finalize,
-);
-#[cfg(all(feature = "futures-async-io", feature = "tokio-async-io"))]
-impl_methods!(
-    #[cfg(all(feature = "futures-async-io", feature = "tokio-async-io"))],
-    tokio::io::AsyncWrite, tokio::io::AsyncRead,
-    tokio::io::AsyncWriteExt, tokio::io::AsyncReadExt,
-    tokio_append, tokio_finalize,
-);
+            /// Append an empty file to the archive using the provided name and date/time (e.g. a `.keep` or `SUCCESS` marker),
+            /// with a CRC32 and sizes of 0 written in the local header, see `append_bytes`.
+            ///
+            /// # Error
+            ///
+            /// This function will return the same errors as `append_with_options`.
+            $(#[$($attrss)*])*
+            pub async fn $fae(&mut self, name: String, datetime: FileDateTime) -> Result<(), IoError> where W: $w + Unpin {
+                self.$fab(name, datetime, []).await
+            }
 
-#[cfg(all(feature = "futures-async-io", not(feature = "tokio-async-io")))]
-impl_methods!(
-    #[cfg(all(feature = "futures-async-io", not(feature = "tokio-async-io")))],
-    futures_util::AsyncWrite, futures_util::AsyncRead,
-    futures_util::AsyncWriteExt, futures_util::AsyncReadExt,
-    append, finalize,
-);
+            /// Append a new file to the archive using the provided name, date/time, options and in-memory payload, see `append_bytes`.
+            /// A compressed payload is appended with its CRC32 computed beforehand (see `append_with_crc`),
+            /// as its compressed size isn't known before writing the header.
+            ///
+            /// # Error
+            ///
+            /// This function will return the same errors as `append_with_options`.
+            $(#[$($attrss)*])*
+            pub async fn $fabo<D: AsRef<[u8]>>(
+                &mut self,
+                name: String,
+                datetime: FileDateTime,
+                options: EntryOptions,
+                data: D,
+            ) -> Result<(), IoError> where W: $w + Unpin {
+                let data = data.as_ref();
+                let mut hasher = Hasher::new();
+                hasher.update(data);
+                let crc = hasher.finalize();
+                let mut reader = data;
+                if options.compresses() && !self.options.stores_extension(&name) {
+                    let options = EntryOptions {
+                        crc: Some(crc),
+                        ..options
+                    };
+                    return self.$fao(name, datetime, options, &mut reader).await;
+                }
+                self.$fam(name, datetime, options, data.len() as u64, crc, &mut reader).await
+            }
 
-#[cfg(all(not(feature = "futures-async-io"), feature = "tokio-async-io"))]
-impl_methods!(
-    #[cfg(all(not(feature = "futures-async-io"), feature = "tokio-async-io"))],
-    tokio::io::AsyncWrite, tokio::io::AsyncRead,
-    tokio::io::AsyncWriteExt, tokio::io::AsyncReadExt,
-    append, finalize,
-);
+            /// Append a new file to the archive like `append_with_options`, with its CRC32 known beforehand (e.g. from the object's metadata),
+            /// skipping its computation. The CRC32 isn't checked, unless enabled with `ArchiveOptions::verify_crc`.
+            ///
+            /// # Error
+            ///
+            /// This function will return an `Error::MetadataMismatch` if the CRC32 is verified and doesn't match the payload,
+            /// or the same errors as `append_with_options`.
+            $(#[$($attrss)*])*
+            pub async fn $fac<R>(
+                &mut self,
+                name: String,
+                datetime: FileDateTime,
+                options: EntryOptions,
+                crc: u32,
+                reader: &mut R,
+            ) -> Result<(), IoError> where W: $w + Unpin, R: $r + Unpin {
+                let options = EntryOptions {
+                    crc: Some(crc),
+                    ..options
+                };
+                self.$fao(name, datetime, options, reader).await
+            }
 
-#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
-impl<W> Archive<W> {
-    /// Create a new zip archive, using the underlying `AsyncWrite` to write files' header and payload.
-    pub fn new(sink: W) -> Self {
-        Self {
-            sink,
-            files_info: Vec::new(),
-            written: 0,
-        }
-    }
-}
+            /// Append a new file to the archive like `append_with_options`, then seek back to write its CRC32 and sizes
+            /// in its local header rather than in a data descriptor, for readers ignoring it.
+            /// Requires a seekable sink (e.g. a file), whose position is restored to the end of the entry.
+            ///
+            /// # Error
+            ///
+            /// This function will forward any error found while seeking the underlying sink, or return the same errors as `append_with_options`.
+            $(#[$($attrss)*])*
+            pub async fn $fas<R>(
+                &mut self,
+                name: String,
+                datetime: FileDateTime,
+                options: EntryOptions,
+                reader: &mut R,
+            ) -> Result<(), IoError> where W: $w + $s + Unpin, R: $r + Unpin {
+                use $we;
+                use $se;
 
-/// Calculate the size that an archive could be based on the names and sizes of files.
-///
-/// ## Example
-///
-/// ```
-/// assert_eq!(
-///     zipit::archive_size([
-///         ("file1.txt", b"hello\n".len()),
-///         ("file2.txt", b"world\n".len()),
-///     ]),
-///     254,
-/// );
-/// ```
-pub fn archive_size<'a, I: IntoIterator<Item = (&'a str, usize)>>(files: I) -> usize {
-    files
-        .into_iter()
-        .map(|(name, size)| {
-            FILE_HEADER_BASE_SIZE
-                + name.len()
-                + size
-                + DESCRIPTOR_SIZE
-                + CENTRAL_DIRECTORY_ENTRY_BASE_SIZE
-                + name.len()
-        })
-        .sum::<usize>()
-        + END_OF_CENTRAL_DIRECTORY_SIZE
-}
+                let options = EntryOptions {
+                    seekable: true,
+                    ..options
+                };
+                self.$fao(name, datetime, options, reader).await?;
 
-#[cfg(test)]
-mod tests {
-    use crate::{Archive, FileDateTime};
-    use std::io::Cursor;
+                let file_info = self.files_info.last().expect("entry just appended");
+                if file_info.flags & 1 << 3 != 0 {
+                    // Followed by a data descriptor (encrypted).
+                    return Ok(());
+                }
+                let mut patch = header![
+                    12;
+                    file_info.crc,                  // CRC32.
+                ];
+                if file_info.large_file {
+                    patch.extend_from_slice(&[0xff; 8]); // Sizes moved to the Zip64 extra field.
+                } else {
+                    patch.extend_from_slice(&(file_info.compressed_size as u32).to_le_bytes()); // Compressed size.
+                    patch.extend_from_slice(&(file_info.size as u32).to_le_bytes()); // Uncompressed size.
+                }
+                self.sink.flush().await?;
+                let end = self.sink.seek(SeekFrom::Current(0)).await?;
+                let header = end - (self.written - file_info.offset);
+                self.sink.seek(SeekFrom::Start(header + 14)).await?;
+                self.sink.write_all(&patch).await?;
+                if file_info.large_file {
+                    // The Zip64 extra field comes first, after the filename.
+                    self.sink.seek(SeekFrom::Start(header + FILE_HEADER_BASE_SIZE as u64 + file_info.name.len() as u64 + 4)).await?;
+                    let sizes = header![
+                        16;
+                        file_info.size,             // Uncompressed size.
+                        file_info.compressed_size,  // Compressed size.
+                    ];
+                    self.sink.write_all(&sizes).await?;
+                }
+                self.sink.flush().await?;
+                self.sink.seek(SeekFrom::Start(end)).await?;
 
-    #[test]
-    fn archive_size() {
-        assert_eq!(
-            crate::archive_size([
-                ("file1.txt", b"hello\n".len()),
-                ("file2.txt", b"world\n".len()),
-            ]),
-            254,
-        );
-        assert_eq!(
-            crate::archive_size([
-                ("file1.txt", b"hello\n".len()),
-                ("file2.txt", b"world\n".len()),
-                ("file3.txt", b"how are you?\n".len()),
-            ]),
-            377,
-        );
-    }
+                Ok(())
+            }
 
-    #[tokio::test]
-    async fn archive_structure() {
-        let mut archive = Archive::new(Vec::new());
-        archive
-            .tokio_append(
+            /// Append a new file to the archive like `append_with_options`, reading it twice: first to compute its CRC32 and sizes,
+            /// then, after seeking back to its initial position, to write them in its local header followed by its payload,
+            /// rather than in a data descriptor, for readers ignoring it. Requires a seekable source (e.g. a file),
+            /// whose payload is compressed twice, so custom compressors must be deterministic.
+            /// Without data descriptor, the entry is 16 (or 24) bytes smaller than predicted by `archive_size`.
+            ///
+            /// # Error
+            ///
+            /// This function will forward any error found while seeking the source, return an `Error::MetadataMismatch`
+            /// if the source changed between the two readings, or the same errors as `append_with_options`.
+            $(#[$($attrss)*])*
+            pub async fn $fap<R>(
+                &mut self,
+                name: String,
+                datetime: FileDateTime,
+                options: EntryOptions,
+                reader: &mut R,
+            ) -> Result<(), IoError> where W: $w + Unpin, R: $r + $s + Unpin {
+                use $re;
+                use $se;
+
+                let start = reader.seek(SeekFrom::Current(0)).await?;
+                let mut encoder = if options.compresses() && !self.options.stores_extension(&name) {
+                    options.encoder()
+                } else {
+                    Encoder::new(None)
+                };
+                let compresses = encoder.compresses();
+                let mut buf = vec![0; 4096];
+                let mut hasher = Hasher::new();
+                let (mut size, mut compressed_size) = (0u64, 0u64);
+                loop {
+                    let read = reader.read(&mut buf).await?;
+                    if read == 0 {
+                        break;
+                    }
+
+                    size += read as u64;
+                    hasher.update(&buf[..read]);
+                    compressed_size += encoder.update(&buf[..read]).len() as u64;
+                }
+                compressed_size += encoder.finish().len() as u64;
+                reader.seek(SeekFrom::Start(start)).await?;
+
+                // Store the payload if compressing it isn't worth it, as for the payloads read ahead by `append_with_options`.
+                let compressed = compresses && size >= self.options.store_threshold as u64 && compressed_size < size;
+                let options = EntryOptions {
+                    metadata: Some((size, hasher.finalize())),
+                    compressed_size: compressed.then_some(compressed_size),
+                    ..options
+                };
+                self.$fao(name, datetime, options, reader).await
+            }
+
+            /// Append a new file to the archive from its already compressed payload (e.g. copied from another archive or from a cache),
+            /// written as is with the compression method `method` (e.g. `8` for Deflate) and the given (uncompressed) size and CRC32,
+            /// which aren't checked. The compression options of `options` are ignored.
+            /// An LZMA payload (method `14`) must end with an end of stream marker, announced in the entry's general purpose flag.
+            ///
+            /// # Error
+            ///
+            /// This function will return the same errors as `append_with_options`.
+            #[allow(clippy::too_many_arguments)]
+            $(#[$($attrss)*])*
+            pub async fn $far<R>(
+                &mut self,
+                name: String,
+                datetime: FileDateTime,
+                options: EntryOptions,
+                method: u16,
+                size: u64,
+                crc: u32,
+                reader: &mut R,
+            ) -> Result<(), IoError> where W: $w + Unpin, R: $r + Unpin {
+                let options = EntryOptions {
+                    raw: Some((method, None, RawMetadata::Provided(size, crc))),
+                    ..options
+                };
+                self.$fao(name, datetime, options, reader).await
+            }
+
+            /// Copy the entries of the archive streamed by `reader` to this archive, without recompressing their payloads.
+            /// `map` is called with each entry and returns its new name and date/time, or `None` to leave it out,
+            /// e.g. to filter, rename or re-date the entries on the fly.
+            ///
+            /// Only the names, dates/times, payloads, compression methods (and the flags describing the payloads), CRC32s and sizes are copied:
+            /// the permissions, comments and extra fields of the entries aren't, nor is the archive's comment.
+            ///
+            /// # Error
+            ///
+            /// This function will forward any error found while reading the source archive (see `ArchiveReader::read_raw`),
+            /// an `Error::UnsupportedEntry` if an encrypted entry is copied,
+            /// and the same errors as `append_with_options` for each entry copied.
+            $(#[$($attrss)*])*
+            pub async fn $fcz<R, F>(&mut self, reader: &mut ArchiveReader<R>, mut map: F) -> Result<(), IoError>
+            where
+                W: $w + Unpin,
+                R: $r + Unpin,
+                F: FnMut(&Entry) -> Option<(String, FileDateTime)>,
+            {
+                while let Some(entry) = reader.$frn().await? {
+                    let Some((name, datetime)) = map(&entry) else {
+                        continue;
+                    };
+                    if entry.is_directory() {
+                        self.$fd(name, datetime).await?;
+                        continue;
+                    }
+                    if entry.is_encrypted() {
+                        return Err(Error::UnsupportedEntry { name: entry.name().to_owned() }.into());
+                    }
+
+                    // The size and CRC32 are only known once the payload is read if they are in a data descriptor.
+                    let (metadata, deferred) = match (entry.size(), entry.crc()) {
+                        (Some(size), Some(crc)) => (RawMetadata::Provided(size, crc), Arc::default()),
+                        _ => {
+                            let deferred = Arc::new(OnceLock::new());
+                            (RawMetadata::Deferred(Arc::clone(&deferred)), deferred)
+                        }
+                    };
+                    // The flags describing the payload (e.g. the end of stream marker of LZMA) are kept.
+                    let options = EntryOptions {
+                        raw: Some((entry.method(), Some(entry.flags() & 0b110), metadata)),
+                        large_file: entry.is_zip64(),
+                        ..EntryOptions::default()
+                    };
+                    self.$fao(name, datetime, options, &mut RawPayload::new(reader, deferred)).await?;
+                }
+                Ok(())
+            }
+
+            /// Append all the entries of the archive streamed by `reader` to this archive, without recompressing their payloads,
+            /// e.g. to bundle several archives into one in a single pass. Their offsets are rewritten in the central directory.
+            ///
+            /// The same metadata as `copy_zip` is copied: the permissions, comments and extra fields of the entries aren't.
+            ///
+            /// # Error
+            ///
+            /// This function will return the same errors as `copy_zip`.
+            $(#[$($attrss)*])*
+            pub async fn $faa<R>(&mut self, reader: &mut R) -> Result<(), IoError> where W: $w + Unpin, R: $r + Unpin {
+                self.$fcz(&mut ArchiveReader::new(reader), |entry| Some((entry.name().to_owned(), entry.datetime())))
+                    .await
+            }
+
+            /// Append a new empty directory to the archive using the provided name and date/time.
+            /// A trailing slash is added to the name if missing. Directory's entry is given `rwxr-xr-x` permissions.
+            ///
+            /// Appending the directories containing files isn't required, but allows to set their date/time and keeps them if empty.
+            ///
+            /// # Error
+            ///
+            /// This function will forward any error found while writing to the underlying sink,
+            /// or the same errors as the file appending functions for the entry's name and the archive's limits.
+            $(#[$($attrss)*])*
+            pub async fn $fd(
+                &mut self,
+                mut name: String,
+                datetime: FileDateTime,
+            ) -> Result<(), IoError> where W: $w + Unpin {
+                if !name.ends_with('/') {
+                    name.push('/');
+                }
+                let options = EntryOptions {
+                    directory: true,
+                    ..EntryOptions::default()
+                };
+                self.$fao(name, datetime, options, &mut &[][..]).await
+            }
+
+            /// Finalize the archive by writing the necessary metadata to the end of the archive.
+            ///
+            /// # Error
+            ///
+            /// This function will forward any error found while writing to the underlying sink.
+            /// An `Error::ArchiveTooLarge` is returned if the central directory ends beyond 4 GiB with `Zip64Mode::Never`.
+            $(#[$($attrss)*])*
+            pub async fn $ff(self) -> Result<W, IoError> where W: $w + Unpin {
+                self.$ffs().await.map(|(sink, _)| sink)
+            }
+
+            /// Finalize the archive like `finalize`, also returning its signature if it has a signer (see `ArchiveOptions::signer`).
+            ///
+            /// # Error
+            ///
+            /// The same errors as `finalize` are returned,
+            /// and an `Error::SignatureTooLong` if the signature is longer than 65,535 bytes with `ArchiveOptions::embed_signature`.
+            $(#[$($attrss)*])*
+            pub async fn $ffs(self) -> Result<(W, Option<Vec<u8>>), IoError> where W: $w + Unpin {
+                self.$ffi(None, false).await.map(|(sink, signature, _)| (sink, signature))
+            }
+
+            /// Finalize the archive like `finalize`, also returning its central directory (see `DetachedDirectory`),
+            /// to be cached and written again by `finalize_with_directory` when the same archive is generated again.
+            ///
+            /// # Error
+            ///
+            /// The same errors as `finalize_signed` are returned.
+            $(#[$($attrss)*])*
+            pub async fn $ffdt(self) -> Result<(W, DetachedDirectory), IoError> where W: $w + Unpin {
+                self.$ffi(None, true).await.map(|(sink, _, directory)| (sink, directory.unwrap()))
+            }
+
+            /// Finalize the archive like `finalize`, writing the central directory detached by `finalize_detached`
+            /// from a previous generation of the same archive, instead of rebuilding it.
+            /// The entries must be appended the same way, with the same payloads and dates/times,
+            /// as only the layout of the archive is checked against the directory.
+            ///
+            /// # Error
+            ///
+            /// This function will return an `Error::DirectoryMismatch` if the directory doesn't start at the end of the entries,
+            /// or doesn't have the same number of entries (or the same embedded signature, see `ArchiveOptions::embed_signature`),
+            /// and forward any error found while writing to the underlying sink.
+            $(#[$($attrss)*])*
+            pub async fn $ffwd(self, directory: &DetachedDirectory) -> Result<W, IoError> where W: $w + Unpin {
+                self.$ffi(Some(directory), false).await.map(|(sink, _, _)| sink)
+            }
+
+            // Write the central directory, or the cached one, keeping its bytes if detached.
+            async fn $ffi(
+                mut self,
+                cached: Option<&DetachedDirectory>,
+                detach: bool,
+            ) -> Result<(W, Option<Vec<u8>>, Option<DetachedDirectory>), IoError> where W: $w + Unpin {
+                use $we;
+
+                if let Some((name, _)) = self.options.checksums.take() {
+                    let checksums = checksums(&self.digests);
+                    let options = EntryOptions::new().text(true);
+                    let datetime = self.options.checksums_datetime.unwrap_or_else(|| {
+                        let newest = self.files_info.iter().map(|file_info| file_info.datetime).max();
+                        newest.map_or(FileDateTime::Zero, |(date, time)| FileDateTime::from_ms_dos(date, time))
+                    });
+                    self.$fao(name, datetime, options, &mut checksums.as_bytes()).await?;
+                }
+
+                if let Some(directory) = cached {
+                    let mismatch = Error::DirectoryMismatch {
+                        offset: self.written,
+                        entry_count: self.files_info.len() as u64,
+                    };
+                    if directory.offset() != self.written || directory.entry_count() != self.files_info.len() as u64 {
+                        return Err(mismatch.into());
+                    }
+                    // An embedded signature covers the archive up to the end of central directory record.
+                    let (records, comment) = directory.split();
+                    let signature = if self.options.embed_signature && self.signature.is_some() {
+                        Signature::update(&mut self.signature, records);
+                        let signature = self.signature.as_mut().map(Signature::sign);
+                        if signature.as_deref() != Some(comment) {
+                            return Err(mismatch.into());
+                        }
+                        signature
+                    } else {
+                        Signature::update(&mut self.signature, directory.as_bytes());
+                        self.signature.as_mut().map(Signature::sign)
+                    };
+                    self.sink.write_all(directory.as_bytes()).await?;
+                    return Ok((self.sink, signature, None));
+                }
+
+                let mut detached = detach.then(Vec::new);
+                let mut central_directory_size = 0;
+                for file_info in &self.files_info {
+                    if let Some(record) = &file_info.record {
+                        self.sink.write_all(record).await?;
+                        detached.iter_mut().for_each(|detached| detached.extend_from_slice(record));
+                        Signature::update(&mut self.signature, record);
+                        central_directory_size += record.len() as u64;
+                        continue;
+                    }
+                    // Values that don't fit in 32 bits (or all of them if Zip64 is always used) are moved to the Zip64 extra field, in this order.
+                    let mut zip64_values = Vec::new();
+                    let mut field = |value: u64| {
+                        if self.options.zip64 == Zip64Mode::Always || value >= u32::MAX as u64 {
+                            zip64_values.push(value);
+                            u32::MAX
+                        } else {
+                            value as u32
+                        }
+                    };
+                    let size = field(file_info.size);
+                    let compressed_size = field(file_info.compressed_size);
+                    let offset = field(file_info.offset);
+                    let mut extra_field = zip64_extra_field(&zip64_values);
+                    let version_needed = if file_info.large_file || !extra_field.is_empty() {
+                        file_info.method.version_needed.max(ZIP64_VERSION_NEEDED)
+                    } else {
+                        file_info.method.version_needed
+                    }
+                    .max(self.options.version_needed)
+                    // Like in the local header, encryption requires 2.0.
+                    .max(if file_info.flags & 1 != 0 { 20 } else { 0 });
+                    extra_field.extend_from_slice(&file_info.extra_field);
+
+                    let mut entry = header![
+                        CENTRAL_DIRECTORY_ENTRY_BASE_SIZE + file_info.name.len() + extra_field.len() + file_info.comment.len();
+                        0x02014b50u32,                  // Central directory entry signature.
+                        self.options.version_made_by(version_needed), // Version made by.
+                        version_needed,                 // Version needed to extract.
+                        file_info.flags,                // General purpose flag.
+                        file_info.method.code,          // Compression method.
+                        file_info.datetime.1,           // Modification time.
+                        file_info.datetime.0,           // Modification date.
+                        file_info.crc,                  // CRC32.
+                        compressed_size,                // Compressed size.
+                        size,                           // Uncompressed size.
+                        file_info.name.len() as u16,    // Filename length.
+                        extra_field.len() as u16,       // Extra field length.
+                        file_info.comment.len() as u16, // File comment length.
+                        0u16,                           // File's Disk number.
+                        file_info.text as u16,          // Internal file attributes (text flag).
+                        file_info.external_attributes,  // External file attributes.
+                        offset,                         // Offset from start of file to local file header.
+                    ];
+                    entry.extend_from_slice(&file_info.name); // Filename.
+                    entry.extend_from_slice(&extra_field); // Zip64 and entry's extra fields.
+                    entry.extend_from_slice(file_info.comment.as_bytes()); // File comment.
+                    self.sink.write_all(&entry).await?;
+                    detached.iter_mut().for_each(|detached| detached.extend_from_slice(&entry));
+                    Signature::update(&mut self.signature, &entry);
+                    central_directory_size += entry.len() as u64;
+                }
+
+                let central_directory_offset = self.written;
+                self.written += central_directory_size;
+                if self.options.zip64 == Zip64Mode::Always
+                    || self.zip64_records
+                    // 65,535 entries is the placeholder of the Zip64 count, but fits without Zip64 otherwise.
+                    || (self.files_info.len() >= u16::MAX as usize && self.options.zip64 != Zip64Mode::Never)
+                    || central_directory_size >= u32::MAX as u64
+                    || central_directory_offset >= u32::MAX as u64
+                {
+                    if self.options.zip64 == Zip64Mode::Never {
+                        return Err(Error::ArchiveTooLarge { size: self.written }.into());
+                    }
+                    let zip64_version_needed = ZIP64_VERSION_NEEDED.max(self.options.version_needed);
+                    let zip64_end_of_central_directory = header![
+                        ZIP64_END_OF_CENTRAL_DIRECTORY_SIZE + ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIZE;
+                        0x06064b50u32,                  // Zip64 end of central directory signature.
+                        (ZIP64_END_OF_CENTRAL_DIRECTORY_SIZE - 12) as u64, // Size of the remaining record.
+                        self.options.version_made_by(zip64_version_needed), // Version made by.
+                        zip64_version_needed,           // Version needed to extract.
+                        0u32,                           // Number of this disk.
+                        0u32,                           // Number of the disk where central directory starts.
+                        self.files_info.len() as u64,   // Number of central directory records on this disk.
+                        self.files_info.len() as u64,   // Total number of central directory records.
+                        central_directory_size,         // Size of central directory.
+                        central_directory_offset,       // Offset from start of file to central directory.
+                        0x07064b50u32,                  // Zip64 end of central directory locator signature.
+                        0u32,                           // Number of the disk where the zip64 end of central directory starts.
+                        self.written,                   // Offset from start of file to zip64 end of central directory.
+                        1u32,                           // Total number of disks.
+                    ];
+                    self.sink.write_all(&zip64_end_of_central_directory).await?;
+                    detached.iter_mut().for_each(|detached| detached.extend_from_slice(&zip64_end_of_central_directory));
+                    Signature::update(&mut self.signature, &zip64_end_of_central_directory);
+                }
+
+                // An embedded signature covers the archive up to the end of central directory record, whose comment holds it.
+                let embedded_signature = if self.options.embed_signature {
+                    self.signature.as_mut().map(Signature::sign)
+                } else {
+                    None
+                };
+                let comment = embedded_signature.as_deref().unwrap_or(&self.comment);
+                if comment.len() > u16::MAX as usize {
+                    return Err(Error::SignatureTooLong { size: comment.len() }.into());
+                }
+                let mut end_of_central_directory = header![
+                    END_OF_CENTRAL_DIRECTORY_SIZE + comment.len();
+                    0x06054b50u32,                  // End of central directory signature.
+                    0u16,                           // Number of this disk.
+                    0u16,                           // Number of the disk where central directory starts.
+                    self.files_info.len().min(u16::MAX as usize) as u16, // Number of central directory records on this disk (or Zip64 placeholder).
+                    self.files_info.len().min(u16::MAX as usize) as u16, // Total number of central directory records (or Zip64 placeholder).
+                    central_directory_size.min(u32::MAX as u64) as u32,   // Size of central directory (or Zip64 placeholder).
+                    central_directory_offset.min(u32::MAX as u64) as u32, // Offset from start of file to central directory (or Zip64 placeholder).
+                    comment.len() as u16,           // Comment length.
+                ];
+                end_of_central_directory.extend_from_slice(comment); // Comment (embedded signature or existing comment).
+                self.sink.write_all(&end_of_central_directory).await?;
+                let directory = detached.map(|mut detached| {
+                    let end = detached.len();
+                    detached.extend_from_slice(&end_of_central_directory);
+                    DetachedDirectory::new(detached, central_directory_offset, self.files_info.len() as u64, end)
+                });
+
+                let signature = match embedded_signature {
+                    Some(signature) => Some(signature),
+                    None => {
+                        Signature::update(&mut self.signature, &end_of_central_directory);
+                        self.signature.as_mut().map(Signature::sign)
+                    }
+                };
+                Ok((self.sink, signature, directory))
+            }
+        }
+    };
+}
+
+#[cfg(all(feature = "futures-async-io", feature = "tokio-async-io"))]
+impl_methods!(
+    #[cfg(all(feature = "futures-async-io", feature = "tokio-async-io"))],
+    futures_util::AsyncWrite, futures_util::AsyncRead,
+    futures_util::AsyncWriteExt, futures_util::AsyncReadExt,
+    futures_util::AsyncSeek, futures_util::AsyncSeekExt,
+    futures_append, futures_append_with_options, futures_append_with_metadata, futures_append_with_crc, futures_append_seekable, futures_append_two_pass, futures_append_raw, futures_append_directory, futures_finalize, futures_finalize_signed, futures_open_existing, futures_read, futures_copy_zip, futures_append_archive, futures_next_entry, futures_append_with_size_hint, futures_append_exact, futures_finalize_detached, futures_finalize_with_directory, futures_finish, futures_append_bytes, futures_append_bytes_with_options, futures_append_empty_file,
+);
+#[cfg(all(feature = "futures-async-io", feature = "tokio-async-io"))]
+impl_methods!(
+    #[cfg(all(feature = "futures-async-io", feature = "tokio-async-io"))],
+    tokio::io::AsyncWrite, tokio::io::AsyncRead,
+    tokio::io::AsyncWriteExt, tokio::io::AsyncReadExt,
+    tokio::io::AsyncSeek, tokio::io::AsyncSeekExt,
+    tokio_append, tokio_append_with_options, tokio_append_with_metadata, tokio_append_with_crc, tokio_append_seekable, tokio_append_two_pass, tokio_append_raw, tokio_append_directory, tokio_finalize, tokio_finalize_signed, tokio_open_existing, tokio_read, tokio_copy_zip, tokio_append_archive, tokio_next_entry, tokio_append_with_size_hint, tokio_append_exact, tokio_finalize_detached, tokio_finalize_with_directory, tokio_finish, tokio_append_bytes, tokio_append_bytes_with_options, tokio_append_empty_file,
+);
+
+#[cfg(all(feature = "futures-async-io", not(feature = "tokio-async-io")))]
+impl_methods!(
+    #[cfg(all(feature = "futures-async-io", not(feature = "tokio-async-io")))],
+    futures_util::AsyncWrite, futures_util::AsyncRead,
+    futures_util::AsyncWriteExt, futures_util::AsyncReadExt,
+    futures_util::AsyncSeek, futures_util::AsyncSeekExt,
+    append, append_with_options, append_with_metadata, append_with_crc, append_seekable, append_two_pass, append_raw, append_directory, finalize, finalize_signed, open_existing, read, copy_zip, append_archive, next_entry, append_with_size_hint, append_exact, finalize_detached, finalize_with_directory, finish, append_bytes, append_bytes_with_options, append_empty_file,
+);
+
+#[cfg(all(not(feature = "futures-async-io"), feature = "tokio-async-io"))]
+impl_methods!(
+    #[cfg(all(not(feature = "futures-async-io"), feature = "tokio-async-io"))],
+    tokio::io::AsyncWrite, tokio::io::AsyncRead,
+    tokio::io::AsyncWriteExt, tokio::io::AsyncReadExt,
+    tokio::io::AsyncSeek, tokio::io::AsyncSeekExt,
+    append, append_with_options, append_with_metadata, append_with_crc, append_seekable, append_two_pass, append_raw, append_directory, finalize, finalize_signed, open_existing, read, copy_zip, append_archive, next_entry, append_with_size_hint, append_exact, finalize_detached, finalize_with_directory, finish, append_bytes, append_bytes_with_options, append_empty_file,
+);
+
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+impl<W> Archive<W> {
+    /// Create a new zip archive, using the underlying `AsyncWrite` to write files' header and payload.
+    pub fn new(sink: W) -> Self {
+        Self::with_options(sink, ArchiveOptions::default())
+    }
+
+    /// Create a new zip archive with custom archive-wide options, using the underlying `AsyncWrite` to write files' header and payload.
+    pub fn with_options(sink: W, options: ArchiveOptions) -> Self {
+        Self {
+            sink,
+            files_info: Vec::new(),
+            written: 0,
+            signature: options.signer.as_ref().map(Signature::new),
+            digests: Vec::new(),
+            comment: Vec::new(),
+            zip64_records: false,
+            duplicates: HashMap::new(),
+            options,
+        }
+    }
+}
+
+// List the digests in the format of `sha256sum`, escaping the names containing a backslash or a newline.
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+fn checksums(digests: &[(String, Vec<u8>)]) -> String {
+    let mut checksums = String::new();
+    for (name, digest) in digests {
+        if name.contains(['\\', '\n']) {
+            checksums.push('\\');
+        }
+        for byte in digest {
+            checksums.push_str(&format!("{byte:02x}"));
+        }
+        checksums.push_str("  ");
+        checksums.push_str(&name.replace('\\', "\\\\").replace('\n', "\\n"));
+        checksums.push('\n');
+    }
+    checksums
+}
+
+/// The size of an entry's local header, data descriptor and central directory record, besides its name (twice),
+/// for the entries counted by `archive_size` (without Zip64).
+pub const ENTRY_OVERHEAD: usize =
+    FILE_HEADER_BASE_SIZE + DESCRIPTOR_SIZE + CENTRAL_DIRECTORY_ENTRY_BASE_SIZE;
+
+/// The size of the end of central directory record, added once to the entries by `archive_size` (without Zip64).
+pub const ARCHIVE_OVERHEAD: usize = END_OF_CENTRAL_DIRECTORY_SIZE;
+
+/// The size added by an entry whose name is `name_len` bytes long to its payload, as counted by `archive_size` (without Zip64),
+/// so the size of an archive is `ARCHIVE_OVERHEAD` plus the sizes of its payloads and the overheads of its entries,
+/// e.g. summed by a database query.
+///
+/// ## Example
+///
+/// ```
+/// use zipit::{entry_overhead, ARCHIVE_OVERHEAD};
+///
+/// let files = [("file1.txt", 6), ("file2.txt", 6)];
+/// let size: usize = files.iter().map(|(name, size)| entry_overhead(name.len()) + size).sum();
+/// assert_eq!(ARCHIVE_OVERHEAD + size, zipit::archive_size(files));
+/// ```
+pub const fn entry_overhead(name_len: usize) -> usize {
+    ENTRY_OVERHEAD + 2 * name_len
+}
+
+/// Calculate the size that an archive could be based on the names and sizes of files.
+///
+/// The calculated size is only exact for archives made of stored (uncompressed) entries without extra metadata (e.g. NTFS times),
+/// see `archive_size_with_options` for the others. It saturates at `usize::MAX` if it doesn't fit (on 32-bit targets), see `archive_size_u64` for larger archives.
+///
+/// ## Example
+///
+/// ```
+/// assert_eq!(
+///     zipit::archive_size([
+///         ("file1.txt", b"hello\n".len()),
+///         ("file2.txt", b"world\n".len()),
+///     ]),
+///     254,
+/// );
+/// ```
+pub fn archive_size<'a, I: IntoIterator<Item = (&'a str, usize)>>(files: I) -> usize {
+    archive_size_u64(
+        files.into_iter().map(|(name, size)| (name, size as u64)),
+        Zip64Mode::Auto,
+    )
+    .try_into()
+    .unwrap_or(usize::MAX)
+}
+
+/// Calculate the size that an archive could be based on the names and sizes of files, and on the archive's `Zip64Mode`.
+///
+/// The calculated size is only exact for archives made of stored (uncompressed) entries without extra metadata (e.g. NTFS times).
+/// With `Zip64Mode::Auto`, the files larger than 4 GiB are expected to be appended with `EntryOptions::large_file`, and only them.
+///
+/// ## Example
+///
+/// ```
+/// use zipit::Zip64Mode;
+///
+/// assert_eq!(
+///     zipit::archive_size_u64([("file1.txt", 6), ("large.bin", 5_000_000_000)], Zip64Mode::Auto),
+///     5_000_000_372,
+/// );
+/// ```
+pub fn archive_size_u64<'a, I: IntoIterator<Item = (&'a str, u64)>>(
+    files: I,
+    zip64: Zip64Mode,
+) -> u64 {
+    let always = zip64 == Zip64Mode::Always;
+    let mut offset = 0;
+    let mut central_directory_size = 0;
+    let mut count = 0;
+    for (name, size) in files {
+        let large_file = always || (zip64 == Zip64Mode::Auto && size >= u32::MAX as u64);
+        // Same rules as the central directory entries written by `finalize`.
+        let zip64_values = if always {
+            3
+        } else {
+            2 * (size >= u32::MAX as u64) as usize + (offset >= u32::MAX as u64) as usize
+        };
+        let (extra_field_size, descriptor_size) = if large_file {
+            (
+                2 * size_of::<u16>() + 2 * size_of::<u64>(),
+                ZIP64_DESCRIPTOR_SIZE,
+            )
+        } else {
+            (0, DESCRIPTOR_SIZE)
+        };
+        offset +=
+            (FILE_HEADER_BASE_SIZE + name.len() + extra_field_size + descriptor_size) as u64 + size;
+        central_directory_size += (CENTRAL_DIRECTORY_ENTRY_BASE_SIZE + name.len()) as u64;
+        if zip64_values > 0 {
+            central_directory_size +=
+                (2 * size_of::<u16>() + zip64_values * size_of::<u64>()) as u64;
+        }
+        count += 1;
+    }
+
+    let mut size = offset + central_directory_size + END_OF_CENTRAL_DIRECTORY_SIZE as u64;
+    if always
+        || (count >= u16::MAX as usize && zip64 != Zip64Mode::Never)
+        || central_directory_size >= u32::MAX as u64
+        || offset >= u32::MAX as u64
+    {
+        size += (ZIP64_END_OF_CENTRAL_DIRECTORY_SIZE + ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIZE)
+            as u64;
+    }
+    size
+}
+
+/// Calculate the exact size of an archive written with `options`, based on the names, sizes and options of its entries,
+/// as appended with `append_with_options` (or with `append_directory` for the names ending with a slash, whose size is 0).
+///
+/// Unlike `archive_size`, the names' encoding, the extra fields (e.g. NTFS times), the comments, the `Zip64Mode`,
+/// the data descriptors (see `ArchiveOptions::data_descriptor`), the encryption headers,
+/// the checksums entry (see `ArchiveOptions::checksums`) and the embedded signature are taken into account.
+/// The payloads are expected to be stored (the compression of the entries is ignored), and not to be deduplicated.
+///
+/// ## Example
+///
+/// ```
+/// use zipit::{ArchiveOptions, DataDescriptor, EntryOptions};
+///
+/// let options = ArchiveOptions::new().data_descriptor(DataDescriptor::WhenNeeded);
+/// let entry = EntryOptions::new().comment("greeting");
+/// assert_eq!(
+///     zipit::archive_size_with_options([("file.txt", 6, &entry), ("dir/", 0, &EntryOptions::new())], &options),
+///     212,
+/// );
+/// ```
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+pub fn archive_size_with_options<'a, I>(files: I, options: &ArchiveOptions) -> u64
+where
+    I: IntoIterator<Item = (&'a str, u64, &'a EntryOptions)>,
+{
+    let mut estimator = ArchiveSizeEstimator::with_options(options.clone());
+    for (name, size, entry_options) in files {
+        estimator.add_file_with_options(name, size, entry_options);
+    }
+    estimator.total()
+}
+
+/// Calculate the size of an archive like `archive_size_with_options`, from its entries added one by one,
+/// e.g. while they are discovered, without collecting them first.
+///
+/// ## Example
+///
+/// ```
+/// use zipit::ArchiveSizeEstimator;
+///
+/// let mut estimator = ArchiveSizeEstimator::new();
+/// estimator.add_file("file1.txt", 6);
+/// estimator.add_file("file2.txt", 6);
+/// assert_eq!(estimator.total(), 254);
+///
+/// estimator.add_directory("dir");
+/// assert_eq!(estimator.total(), 354);
+/// ```
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+#[derive(Clone, Debug)]
+pub struct ArchiveSizeEstimator {
+    options: ArchiveOptions,
+    digest_size: Option<usize>,
+    comment_size: usize,
+    offset: u64,
+    central_directory_size: u64,
+    count: usize,
+    checksums_size: u64,
+}
+
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+impl Default for ArchiveSizeEstimator {
+    fn default() -> Self {
+        Self::with_options(ArchiveOptions::default())
+    }
+}
+
+#[cfg(any(feature = "futures-async-io", feature = "tokio-async-io"))]
+impl ArchiveSizeEstimator {
+    /// Create an estimator for an archive written with the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an estimator for an archive written with `options`.
+    pub fn with_options(options: ArchiveOptions) -> Self {
+        let digest_size = options
+            .checksums
+            .as_ref()
+            .map(|(_, factory)| factory.build().finish().len());
+        let comment_size = match &options.signer {
+            Some(factory) if options.embed_signature => Signature::new(factory).sign().len(),
+            _ => 0,
+        };
+        Self {
+            options,
+            digest_size,
+            comment_size,
+            offset: 0,
+            central_directory_size: 0,
+            count: 0,
+            checksums_size: 0,
+        }
+    }
+
+    /// Add a file of `size` bytes, appended with the default entry options.
+    pub fn add_file(&mut self, name: &str, size: u64) {
+        self.add_file_with_options(name, size, &EntryOptions::default());
+    }
+
+    /// Add a file of `size` bytes, appended with `options` (see `archive_size_with_options`),
+    /// or a directory if its name ends with a slash.
+    pub fn add_file_with_options(&mut self, name: &str, size: u64, options: &EntryOptions) {
+        self.add(name, size, options);
+        // The line of the entry in the checksums entry, in the format of `sha256sum`.
+        if let Some(digest_size) = self.digest_size.filter(|_| !name.ends_with('/')) {
+            let escaped = name.contains(['\\', '\n']) as usize;
+            let name_size = name.len() + name.matches(['\\', '\n']).count();
+            self.checksums_size += (escaped + 2 * digest_size + 2 + name_size + 1) as u64;
+        }
+    }
+
+    /// Add a directory, appended with `append_directory`. A trailing slash is added to the name if missing.
+    pub fn add_directory(&mut self, name: &str) {
+        if name.ends_with('/') {
+            self.add_file(name, 0);
+        } else {
+            self.add_file(&format!("{name}/"), 0);
+        }
+    }
+
+    /// Set the length of the archive's comment, 0 by default (or the length of the embedded signature, see `ArchiveOptions::embed_signature`).
+    pub fn set_comment(&mut self, length: usize) {
+        self.comment_size = length;
+    }
+
+    /// The size of the archive made of the entries added so far, once finalized.
+    pub fn total(&self) -> u64 {
+        let mut estimator = self.clone();
+        if let Some((name, _)) = &self.options.checksums {
+            estimator.add(name, self.checksums_size, &EntryOptions::new().text(true));
+        }
+
+        let mut size = estimator.offset
+            + estimator.central_directory_size
+            + (END_OF_CENTRAL_DIRECTORY_SIZE + estimator.comment_size) as u64;
+        if self.options.zip64 == Zip64Mode::Always
+            || (estimator.count >= u16::MAX as usize && self.options.zip64 != Zip64Mode::Never)
+            || estimator.central_directory_size >= u32::MAX as u64
+            || estimator.offset >= u32::MAX as u64
+        {
+            size += (ZIP64_END_OF_CENTRAL_DIRECTORY_SIZE
+                + ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIZE) as u64;
+        }
+        size
+    }
+
+    // The offset of the payload of the next entry, once its local header is written.
+    #[cfg(feature = "stream")]
+    pub(crate) fn payload_offset(&self, name: &str, entry_options: &EntryOptions) -> u64 {
+        let (raw_name, local_extra_field, _) = self.encoded_name(name, entry_options);
+        let mut local_size = FILE_HEADER_BASE_SIZE + raw_name.len() + local_extra_field.len();
+        if self.large_file(entry_options) {
+            local_size += 2 * size_of::<u16>() + 2 * size_of::<u64>();
+        }
+        self.offset + local_size as u64
+    }
+
+    // The name of an entry as written, with its local and central extra fields.
+    fn encoded_name(
+        &self,
+        name: &str,
+        entry_options: &EntryOptions,
+    ) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+        let (local_extra_field, central_extra_field) = entry_options.extra_fields();
+        let encoding = entry_options
+            .name_encoding
+            .unwrap_or(self.options.name_encoding);
+        let (raw_name, _, local_extra_field, central_extra_field) = encode_name(
+            name,
+            encoding,
+            self.options.unicode_path_field,
+            local_extra_field,
+            central_extra_field,
+        );
+        (raw_name, local_extra_field, central_extra_field)
+    }
+
+    fn large_file(&self, entry_options: &EntryOptions) -> bool {
+        self.options.zip64 == Zip64Mode::Always
+            || (self.options.zip64 == Zip64Mode::Auto && entry_options.large_file)
+    }
+
+    fn add(&mut self, name: &str, size: u64, entry_options: &EntryOptions) {
+        let (raw_name, local_extra_field, central_extra_field) =
+            self.encoded_name(name, entry_options);
+        let large_file = self.large_file(entry_options);
+        let options = &self.options;
+        let always = options.zip64 == Zip64Mode::Always;
+        let directory = name.ends_with('/');
+        let encrypted =
+            !directory && (entry_options.password.is_some() || options.password.is_some());
+        let compressed_size = size
+            + if encrypted {
+                ZIP_CRYPTO_HEADER_SIZE as u64
+            } else {
+                0
+            };
+        // The payloads smaller than the read ahead size are followed by a data descriptor only with `DataDescriptor::Always`.
+        let lookahead_size = options.store_threshold.max(options.trial_size).max(4096) as u64;
+        let descriptor = match options.data_descriptor {
+            DataDescriptor::Always => true,
+            DataDescriptor::WhenNeeded => size >= lookahead_size,
+            DataDescriptor::Never => false,
+        };
+
+        let mut local_size = FILE_HEADER_BASE_SIZE + raw_name.len() + local_extra_field.len();
+        if large_file {
+            local_size += 2 * size_of::<u16>() + 2 * size_of::<u64>();
+        }
+        if descriptor {
+            local_size += if large_file {
+                ZIP64_DESCRIPTOR_SIZE
+            } else {
+                DESCRIPTOR_SIZE
+            };
+            if !options.descriptor_signature {
+                local_size -= size_of::<u32>();
+            }
+        }
+        // Same rules as the central directory entries written by `finalize`.
+        let zip64_values = if always {
+            3
+        } else {
+            [size, compressed_size, self.offset]
+                .iter()
+                .filter(|&&value| value >= u32::MAX as u64)
+                .count()
+        };
+        let mut central_size = CENTRAL_DIRECTORY_ENTRY_BASE_SIZE
+            + raw_name.len()
+            + central_extra_field.len()
+            + entry_options.comment.len();
+        if zip64_values > 0 {
+            central_size += 2 * size_of::<u16>() + zip64_values * size_of::<u64>();
+        }
+        self.offset += local_size as u64 + compressed_size;
+        self.central_directory_size += central_size as u64;
+        self.count += 1;
+    }
+}
+
+#[cfg(all(
+    test,
+    feature = "futures-async-io",
+    feature = "tokio-async-io",
+    feature = "chrono-datetime"
+))]
+mod tests {
+    use crate::read::{ArchiveReader, CentralDirectory, Entry, EntryStatus, Glob};
+    use crate::{
+        Archive, ArchiveOptions, ArchivePlan, ArchiveSizeEstimator, Compression, Compressor,
+        DataDescriptor, Digest, DosAttributes, EntryOptions, Error, FileDateTime, HostSystem,
+        NameEncoding, Profile, Sha256, Signer, Zip64Mode,
+    };
+    use std::io::Cursor;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    // Stores every byte twice, followed by a trailing zero.
+    struct Doubler;
+
+    impl Compressor for Doubler {
+        fn method(&self) -> u16 {
+            99
+        }
+
+        fn compress(&mut self, input: &[u8], output: &mut Vec<u8>) {
+            output.extend(input.iter().flat_map(|&b| [b, b]));
+        }
+
+        fn finish(&mut self, output: &mut Vec<u8>) {
+            output.push(0);
+        }
+    }
+
+    #[test]
+    fn archive_size() {
+        assert_eq!(
+            crate::archive_size([
+                ("file1.txt", b"hello\n".len()),
+                ("file2.txt", b"world\n".len()),
+            ]),
+            254,
+        );
+        assert_eq!(
+            crate::archive_size([
+                ("file1.txt", b"hello\n".len()),
+                ("file2.txt", b"world\n".len()),
+                ("file3.txt", b"how are you?\n".len()),
+            ]),
+            377,
+        );
+        assert_eq!(
+            crate::archive_size_u64([("file1.txt", 6)], crate::Zip64Mode::Always),
+            270,
+        );
+    }
+
+    #[test]
+    fn entry_overhead() {
+        const OVERHEAD: usize = crate::entry_overhead(9);
+        assert_eq!(OVERHEAD, 110);
+        assert_eq!(
+            crate::ARCHIVE_OVERHEAD + 3 * OVERHEAD + 6,
+            crate::archive_size([("file1.txt", 6), ("file2.txt", 0), ("file3.txt", 0)]),
+        );
+    }
+
+    #[tokio::test]
+    async fn archive_size_with_options() {
+        let large = vec![b'a'; 5000];
+        let entries = [
+            ("small.txt", &b"hello\n"[..], EntryOptions::new()),
+            ("dir/", &b""[..], EntryOptions::new()),
+            (
+                "dir/large.bin",
+                &large[..],
+                EntryOptions::new().comment("large"),
+            ),
+            (
+                "dir/ñame.txt",
+                &b"name"[..],
+                EntryOptions::new().ntfs_times(UNIX_EPOCH, UNIX_EPOCH, UNIX_EPOCH),
+            ),
+            (
+                "back\\slash",
+                &b"escaped"[..],
+                EntryOptions::new().owner(1000, 1000),
+            ),
+            (
+                "locked.txt",
+                &b"secret"[..],
+                EntryOptions::new().password("password"),
+            ),
+        ];
+        let options = [
+            ArchiveOptions::new(),
+            ArchiveOptions::new().data_descriptor(DataDescriptor::WhenNeeded),
+            ArchiveOptions::new()
+                .data_descriptor(DataDescriptor::Never)
+                .zip64(Zip64Mode::Always),
+            ArchiveOptions::new()
+                .descriptor_signature(false)
+                .unicode_path_field(true),
+            ArchiveOptions::new()
+                .name_encoding(NameEncoding::Utf8)
+                .checksums("SHA256SUMS"),
+            ArchiveOptions::new()
+                .signer(Sha256::new)
+                .embed_signature(true)
+                .password("password"),
+        ];
+        for options in options {
+            let mut archive = Archive::with_options(Vec::new(), options.clone());
+            for (name, payload, entry_options) in &entries {
+                match name.strip_suffix('/') {
+                    Some(name) => archive
+                        .tokio_append_directory(name.to_owned(), FileDateTime::Zero)
+                        .await
+                        .unwrap(),
+                    None => archive
+                        .tokio_append_with_options(
+                            name.to_string(),
+                            FileDateTime::Zero,
+                            entry_options.clone(),
+                            &mut Cursor::new(payload),
+                        )
+                        .await
+                        .unwrap(),
+                }
+            }
+            let data = archive.tokio_finalize().await.unwrap();
+            let size = crate::archive_size_with_options(
+                entries.iter().map(|(name, payload, entry_options)| {
+                    (*name, payload.len() as u64, entry_options)
+                }),
+                &options,
+            );
+            assert_eq!(size, data.len() as u64, "{options:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn archive_size_estimator() {
+        let options = ArchiveOptions::new().checksums("SHA256SUMS");
+        let mut archive = Archive::with_options(Vec::new(), options.clone());
+        let mut estimator = ArchiveSizeEstimator::with_options(options);
+        for i in 0..3 {
+            let name = format!("dir{i}");
+            archive
+                .tokio_append_directory(name.clone(), FileDateTime::Zero)
+                .await
+                .unwrap();
+            estimator.add_directory(&name);
+
+            let name = format!("dir{i}/file.txt");
+            let payload = "hello\n".repeat(i + 1);
+            archive
+                .tokio_append(name.clone(), FileDateTime::Zero, &mut Cursor::new(&payload))
+                .await
+                .unwrap();
+            estimator.add_file(&name, payload.len() as u64);
+        }
+        let data = archive.tokio_finalize().await.unwrap();
+        assert_eq!(estimator.total(), data.len() as u64);
+        // The total can be computed again after adding more entries.
+        estimator.add_file("more.txt", 10);
+        assert!(estimator.total() > data.len() as u64 + 10);
+
+        let mut estimator = ArchiveSizeEstimator::new();
+        estimator.add_file("file.txt", 6);
+        estimator.set_comment(10);
+        assert_eq!(
+            estimator.total(),
+            crate::archive_size([("file.txt", 6)]) as u64 + 10
+        );
+    }
+
+    #[tokio::test]
+    async fn append_with_size_hint() {
+        let mut archive = Archive::new(Vec::new());
+        archive
+            .tokio_append_with_size_hint(
+                "file.txt".to_owned(),
+                FileDateTime::Zero,
+                EntryOptions::new(),
+                6,
+                &mut Cursor::new(b"hello\n"),
+            )
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+        assert_eq!(data.len(), crate::archive_size([("file.txt", 6)]));
+
+        for (payload, size) in [(&b"hello\n"[..], 5), (b"hello\n", 7), (b"", 1)] {
+            let error = Archive::new(Vec::new())
+                .tokio_append_with_size_hint(
+                    "file.txt".to_owned(),
+                    FileDateTime::Zero,
+                    EntryOptions::new(),
+                    size,
+                    &mut Cursor::new(payload),
+                )
+                .await
+                .unwrap_err();
+            assert!(matches!(
+                error.get_ref().unwrap().downcast_ref::<Error>(),
+                Some(Error::SizeMismatch { name, expected }) if name == "file.txt" && *expected == size
+            ));
+        }
+
+        // The reader isn't read further than one byte past the size.
+        let mut reader = Cursor::new([&b"hello\n"[..], &[b'a'; 10_000]].concat());
+        Archive::new(Vec::new())
+            .tokio_append_with_size_hint(
+                "file.txt".to_owned(),
+                FileDateTime::Zero,
+                EntryOptions::new(),
+                6,
+                &mut reader,
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(reader.position(), 7);
+    }
+
+    #[tokio::test]
+    async fn append_exact() {
+        // Two entries sliced out of a single stream.
+        let mut source = Cursor::new(b"hello\nworld\n!");
+        let mut archive = Archive::new(Vec::new());
+        for name in ["file1.txt", "file2.txt"] {
+            archive
+                .tokio_append_exact(
+                    name.to_owned(),
+                    FileDateTime::Zero,
+                    EntryOptions::new(),
+                    6,
+                    &mut source,
+                )
+                .await
+                .unwrap();
+        }
+        assert_eq!(source.position(), 12);
+        let data = archive.tokio_finalize().await.unwrap();
+
+        let mut reader = ArchiveReader::new(&data[..]);
+        for expected in [b"hello\n", b"world\n"] {
+            reader.tokio_next_entry().await.unwrap().unwrap();
+            let mut payload = vec![0; 100];
+            let read = reader.tokio_read(&mut payload).await.unwrap();
+            assert_eq!(&payload[..read], expected);
+        }
+
+        let error = Archive::new(Vec::new())
+            .tokio_append_exact(
+                "file.txt".to_owned(),
+                FileDateTime::Zero,
+                EntryOptions::new(),
+                6,
+                &mut Cursor::new(b"hello"),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.get_ref().unwrap().downcast_ref::<Error>(),
+            Some(Error::SizeMismatch { expected: 6, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn archive_plan() {
+        let mut plan = ArchivePlan::with_options(
+            ArchiveOptions::new().data_descriptor(DataDescriptor::WhenNeeded),
+        );
+        plan.add_file("file1.txt".to_owned(), 6, FileDateTime::Zero);
+        plan.add_directory("dir".to_owned(), FileDateTime::Zero);
+        plan.add_file_with_options(
+            "dir/file2.txt".to_owned(),
+            10_000,
+            FileDateTime::Zero,
+            EntryOptions::new().comment("comment"),
+        );
+        let size = plan.size();
+
+        let mut archive = plan.clone().into_archive(Vec::new());
+        assert_eq!(archive.next_entry().unwrap().name(), "file1.txt");
+        archive
+            .tokio_append_next(&mut Cursor::new(b"hello\n"))
+            .await
+            .unwrap();
+        archive.tokio_append_next(&mut &b""[..]).await.unwrap();
+        archive
+            .tokio_append_next(&mut Cursor::new(vec![b'a'; 10_000]))
+            .await
+            .unwrap();
+        assert!(archive.next_entry().is_none());
+        let data = archive.tokio_finalize().await.unwrap();
+        assert_eq!(data.len() as u64, size);
+
+        // Payloads shorter or longer than declared.
+        for payload in [&b"hello"[..], b"hello\n!"] {
+            let mut archive = plan.clone().into_archive(Vec::new());
+            let error = archive
+                .tokio_append_next(&mut Cursor::new(payload))
+                .await
+                .unwrap_err();
+            assert!(matches!(
+                error.get_ref().unwrap().downcast_ref::<Error>(),
+                Some(Error::SizeMismatch { name, expected: 6 }) if name == "file1.txt"
+            ));
+        }
+
+        // A payload followed by other data isn't read further than one byte past its size.
+        let mut reader = Cursor::new([&b"hello\n"[..], &[b'a'; 10_000]].concat());
+        let mut archive = plan.clone().into_archive(Vec::new());
+        archive.tokio_append_next(&mut reader).await.unwrap_err();
+        assert_eq!(reader.position(), 7);
+
+        // Too many or too few entries.
+        let mut archive = ArchivePlan::new().into_archive(Vec::new());
+        let error = archive.tokio_append_next(&mut &b""[..]).await.unwrap_err();
+        assert!(matches!(
+            error.get_ref().unwrap().downcast_ref::<Error>(),
+            Some(Error::PlanMismatch {
+                planned: 0,
+                appended: 1
+            })
+        ));
+        let mut archive = plan.into_archive(Vec::new());
+        archive
+            .tokio_append_next(&mut Cursor::new(b"hello\n"))
+            .await
+            .unwrap();
+        let error = archive.tokio_finalize().await.unwrap_err();
+        assert!(matches!(
+            error.get_ref().unwrap().downcast_ref::<Error>(),
+            Some(Error::PlanMismatch {
+                planned: 3,
+                appended: 1
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn archive_plan_append_all() {
+        let mut plan = ArchivePlan::new();
+        plan.add_file("file1.txt".to_owned(), 6, FileDateTime::Zero);
+        plan.add_directory("dir".to_owned(), FileDateTime::Zero);
+        plan.add_file("dir/file2.txt".to_owned(), 6, FileDateTime::Zero);
+        let size = plan.size();
+
+        // Each reader is only opened once the previous entry is appended.
+        let opened = std::cell::RefCell::new(Vec::new());
+        let mut archive = plan.into_archive(Vec::new());
+        archive
+            .tokio_append_all(|entry| {
+                opened.borrow_mut().push(entry.name().to_owned());
+                let payload = if entry.name() == "file1.txt" {
+                    "hello\n"
+                } else {
+                    "world\n"
+                };
+                let opened = &opened;
+                async move {
+                    assert_eq!(
+                        opened.borrow().len() as u64,
+                        1 + (payload == "world\n") as u64
+                    );
+                    Ok(Cursor::new(payload))
+                }
+            })
+            .await
+            .unwrap();
+        assert_eq!(*opened.borrow(), ["file1.txt", "dir/file2.txt"]);
+        let data = archive.tokio_finalize().await.unwrap();
+        assert_eq!(data.len() as u64, size);
+
+        // Errors opening a reader are forwarded.
+        let mut plan = ArchivePlan::new();
+        plan.add_file("missing.txt".to_owned(), 6, FileDateTime::Zero);
+        let error = plan
+            .into_archive(Vec::new())
+            .tokio_append_all(|_| async {
+                Err::<Cursor<Vec<u8>>, _>(std::io::Error::from(std::io::ErrorKind::NotFound))
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn archive_plan_etag() {
+        let plan = |name: &str, crc: u32| {
+            let mut plan = ArchivePlan::new();
+            plan.add_directory("dir".to_owned(), FileDateTime::Zero);
+            plan.add_file(name.to_owned(), 6, FileDateTime::Zero)
+                .crc(crc);
+            plan.add_file("dir/b.txt".to_owned(), 6, FileDateTime::Zero)
+                .content_hash("etag-of-b");
+            plan
+        };
+        let etag = plan("a.txt", 1).etag().unwrap();
+        assert_eq!(etag.len(), 66);
+        assert!(etag.starts_with('"') && etag.ends_with('"'));
+        assert_eq!(plan("a.txt", 1).etag().unwrap(), etag);
+        assert_ne!(plan("c.txt", 1).etag().unwrap(), etag);
+        assert_ne!(plan("a.txt", 2).etag().unwrap(), etag);
+
+        let mut unidentified = plan("a.txt", 1);
+        unidentified.add_file("c.txt".to_owned(), 6, FileDateTime::Zero);
+        assert_eq!(unidentified.etag(), None);
+
+        let mut encrypted = ArchivePlan::with_options(ArchiveOptions::new().password("password"));
+        encrypted
+            .add_file("a.txt".to_owned(), 6, FileDateTime::Zero)
+            .crc(1);
+        assert_eq!(encrypted.etag(), None);
+    }
+
+    #[test]
+    fn archive_plan_etag_factories() {
+        let plan = |options: ArchiveOptions, entry_options: EntryOptions| {
+            let mut plan = ArchivePlan::with_options(options);
+            plan.add_file_with_options("a.txt".to_owned(), 6, FileDateTime::Zero, entry_options)
+                .crc(1);
+            plan
+        };
+        let checksums = ArchiveOptions::new().checksums("sha256sums.txt");
+        assert!(plan(checksums, EntryOptions::new()).etag().is_some());
+
+        // Different factories have the same options' Debug output, so no ETag could tell their archives apart.
+        let signed = ArchiveOptions::new().signer(Sha256::new);
+        assert_eq!(plan(signed, EntryOptions::new()).etag(), None);
+        let custom_checksums = ArchiveOptions::new().checksums_with("sha256sums.txt", Sha256::new);
+        assert_eq!(plan(custom_checksums, EntryOptions::new()).etag(), None);
+        let stored = plan(ArchiveOptions::new(), EntryOptions::new()).etag();
+        let compressed = EntryOptions::new().compressor(|| Doubler);
+        assert_eq!(plan(ArchiveOptions::new(), compressed).etag(), stored);
+    }
+
+    #[tokio::test]
+    async fn archive_structure() {
+        let mut archive = Archive::new(Vec::new());
+        archive
+            .tokio_append(
+                "file1.txt".to_owned(),
+                FileDateTime::now(),
+                &mut Cursor::new(b"hello\n".to_vec()),
+            )
+            .await
+            .unwrap();
+        archive
+            .tokio_append(
+                "file2.txt".to_owned(),
+                FileDateTime::now(),
+                &mut Cursor::new(b"world\n".to_vec()),
+            )
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+
+        fn match_except_datetime(a1: &[u8], a2: &[u8]) -> bool {
+            let datetime_ranges = [
+                10..12,
+                12..14,
+                71..73,
+                73..75,
+                134..136,
+                136..138,
+                189..191,
+                191..193,
+            ];
+            let size_ranges = [18..22, 22..26, 79..83, 83..87];
+            a1.len() == a2.len()
+                && a1
+                    .into_iter()
+                    .zip(a2)
+                    .enumerate()
+                    .filter(|(i, _)| {
+                        datetime_ranges
+                            .iter()
+                            .chain(&size_ranges)
+                            .all(|range| !range.contains(i))
+                    })
+                    .all(|(_, (b1, b2))| b1 == b2)
+        }
+        assert!(match_except_datetime(
+            &data,
+            include_bytes!("timeless_test_archive.zip")
+        ));
+        assert!(match_except_datetime(
+            &data,
+            include_bytes!("zip_command_test_archive.zip")
+        ));
+    }
+
+    #[tokio::test]
+    async fn custom_compressor() {
+        let mut archive = Archive::new(Vec::new());
+        archive
+            .tokio_append_with_options(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                EntryOptions::new().compressor(|| Doubler),
+                &mut Cursor::new(b"hello\n".to_vec()),
+            )
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+
+        assert_eq!(&data[8..10], &99u16.to_le_bytes()); // Compression method.
+        assert_eq!(&data[39..52], b"hheelllloo\n\n\0"); // Payload.
+        assert_eq!(&data[60..64], &13u32.to_le_bytes()); // Compressed size.
+        assert_eq!(&data[64..68], &6u32.to_le_bytes()); // Uncompressed size.
+    }
+
+    #[tokio::test]
+    async fn store_heuristics() {
+        let mut archive = Archive::with_options(
+            Vec::new(),
+            ArchiveOptions::new()
+                .store_smaller_than(8)
+                .store_extensions(["jpg"]),
+        );
+        for (name, payload) in [
+            ("small.txt", &b"hello\n"[..]),
+            ("photo.JPG", &b"not a jpeg\n"[..]),
+            ("large.txt", &b"hello world\n"[..]),
+        ] {
+            archive
+                .tokio_append_with_options(
+                    name.to_owned(),
+                    FileDateTime::Zero,
+                    EntryOptions::new().compressor(|| Doubler),
+                    &mut Cursor::new(payload.to_vec()),
+                )
+                .await
+                .unwrap();
+        }
+        let data = archive.tokio_finalize().await.unwrap();
+
+        assert_eq!(&data[8..10], &0u16.to_le_bytes()); // Small entry stored.
+        assert_eq!(&data[39..45], b"hello\n");
+        assert_eq!(&data[69..71], &0u16.to_le_bytes()); // Extension matched.
+        assert_eq!(&data[100..111], b"not a jpeg\n");
+        assert_eq!(&data[135..137], &99u16.to_le_bytes()); // Large entry compressed.
+        assert_eq!(&data[166..191], b"hheelllloo  wwoorrlldd\n\n\0");
+    }
+
+    #[tokio::test]
+    async fn compression_trial() {
+        let mut archive =
+            Archive::with_options(Vec::new(), ArchiveOptions::new().compression_trial(8));
+        for (name, payload) in [
+            ("file1.txt", &b"hello\n"[..]),
+            ("file2.txt", &b"hello world\n"[..]),
+        ] {
+            archive
+                .tokio_append_with_options(
+                    name.to_owned(),
+                    FileDateTime::Zero,
+                    EntryOptions::new().compressor(|| Doubler),
+                    &mut Cursor::new(payload.to_vec()),
+                )
+                .await
+                .unwrap();
+        }
+        let data = archive.tokio_finalize().await.unwrap();
+
+        assert_eq!(&data[8..10], &0u16.to_le_bytes()); // Larger once compressed, stored.
+        assert_eq!(&data[39..45], b"hello\n");
+        assert_eq!(&data[69..71], &99u16.to_le_bytes()); // Larger than the trial, compressed.
+        assert_eq!(&data[100..125], b"hheelllloo  wwoorrlldd\n\n\0");
+    }
+
+    #[tokio::test]
+    async fn large_file() {
+        let mut archive = Archive::new(Vec::new());
+        archive
+            .tokio_append_with_options(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                EntryOptions::new().large_file(true),
+                &mut Cursor::new(b"hello\n".to_vec()),
+            )
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+
+        assert_eq!(&data[4..6], &45u16.to_le_bytes()); // Version needed to extract.
+        assert_eq!(&data[18..26], &[0xff; 8]); // Temporary sizes.
+        assert_eq!(&data[28..30], &20u16.to_le_bytes()); // Extra field length.
+        assert_eq!(
+            &data[39..59],
+            &[1, 0, 16, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+        ); // Zip64 extra field.
+        assert_eq!(&data[59..65], b"hello\n"); // Payload.
+        assert_eq!(&data[73..81], &6u64.to_le_bytes()); // Compressed size.
+        assert_eq!(&data[81..89], &6u64.to_le_bytes()); // Uncompressed size.
+        assert_eq!(&data[95..97], &45u16.to_le_bytes()); // Central directory version needed to extract.
+        assert_eq!(&data[119..121], &0u16.to_le_bytes()); // No central directory extra field.
+    }
+
+    #[tokio::test]
+    async fn too_many_entries() {
+        for mode in [Zip64Mode::Auto, Zip64Mode::Never] {
+            let mut archive = Archive::with_options(Vec::new(), ArchiveOptions::new().zip64(mode));
+            for _ in 0..u16::MAX {
+                archive
+                    .tokio_append(
+                        "a".to_owned(),
+                        FileDateTime::Zero,
+                        &mut Cursor::new(Vec::new()),
+                    )
+                    .await
+                    .unwrap();
+            }
+            let result = archive
+                .tokio_append(
+                    "a".to_owned(),
+                    FileDateTime::Zero,
+                    &mut Cursor::new(Vec::new()),
+                )
+                .await;
+
+            if mode == Zip64Mode::Never {
+                let error = result.unwrap_err();
+                assert!(matches!(
+                    error.get_ref().unwrap().downcast_ref::<Error>(),
+                    Some(Error::TooManyEntries)
+                ));
+                continue;
+            }
+            result.unwrap();
+            let data = archive.tokio_finalize().await.unwrap();
+            let (zip64, end) = data[data.len() - 98..].split_at(76);
+            assert_eq!(&zip64[0..4], &0x06064b50u32.to_le_bytes()); // Zip64 end of central directory signature.
+            assert_eq!(
+                &zip64[24..40],
+                &[65536u64.to_le_bytes(), 65536u64.to_le_bytes()].concat()
+            ); // Number of records.
+            assert_eq!(&zip64[56..60], &0x07064b50u32.to_le_bytes()); // Zip64 end of central directory locator signature.
+            assert_eq!(&end[8..12], &[0xff; 4]); // Number of records placeholders.
+        }
+    }
+
+    #[tokio::test]
+    async fn max_entries() {
+        for mode in [Zip64Mode::Auto, Zip64Mode::Never] {
+            let mut archive = Archive::with_options(Vec::new(), ArchiveOptions::new().zip64(mode));
+            for _ in 0..u16::MAX {
+                archive
+                    .tokio_append(
+                        "a".to_owned(),
+                        FileDateTime::Zero,
+                        &mut Cursor::new(Vec::new()),
+                    )
+                    .await
+                    .unwrap();
+            }
+            let data = archive.tokio_finalize().await.unwrap();
+            assert_eq!(
+                data.len() as u64,
+                crate::archive_size_u64(vec![("a", 0); u16::MAX as usize], mode)
+            );
+
+            let end = &data[data.len() - 22..];
+            assert_eq!(&end[8..12], &[0xff; 4]); // Number of records (or placeholders).
+            let zip64 = &data[data.len() - 98..data.len() - 22];
+            if mode == Zip64Mode::Never {
+                assert_ne!(&zip64[56..60], &0x07064b50u32.to_le_bytes());
+                continue;
+            }
+            assert_eq!(&zip64[0..4], &0x06064b50u32.to_le_bytes()); // Zip64 end of central directory signature.
+            assert_eq!(
+                &zip64[24..40],
+                &[65535u64.to_le_bytes(), 65535u64.to_le_bytes()].concat()
+            ); // Number of records.
+            assert_eq!(&zip64[56..60], &0x07064b50u32.to_le_bytes()); // Zip64 end of central directory locator signature.
+        }
+    }
+
+    #[tokio::test]
+    async fn name_too_long() {
+        let mut archive = Archive::new(Vec::new());
+        let error = archive
+            .tokio_append(
+                "a".repeat(65536),
+                FileDateTime::Zero,
+                &mut Cursor::new(Vec::new()),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.get_ref().unwrap().downcast_ref::<Error>(),
+            Some(Error::NameTooLong {
+                max_length: 65535,
+                ..
+            })
+        ));
+
+        let mut archive =
+            Archive::with_options(Vec::new(), ArchiveOptions::new().max_name_length(8));
+        archive
+            .tokio_append(
+                "file.txt".to_owned(),
+                FileDateTime::Zero,
+                &mut Cursor::new(Vec::new()),
+            )
+            .await
+            .unwrap();
+        assert!(archive
+            .tokio_append(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                &mut Cursor::new(Vec::new())
+            )
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn zip64_modes() {
+        let mut archive =
+            Archive::with_options(Vec::new(), ArchiveOptions::new().zip64(Zip64Mode::Always));
+        archive
+            .tokio_append(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                &mut Cursor::new(b"hello\n".to_vec()),
+            )
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+
+        assert_eq!(data.len(), 270);
+        assert_eq!(&data[28..30], &20u16.to_le_bytes()); // Local header extra field length.
+        assert_eq!(&data[119..121], &28u16.to_le_bytes()); // Central directory extra field length.
+        assert_eq!(&data[172..176], &0x06064b50u32.to_le_bytes()); // Zip64 end of central directory signature.
+
+        let mut archive =
+            Archive::with_options(Vec::new(), ArchiveOptions::new().zip64(Zip64Mode::Never));
+        archive
+            .tokio_append_with_options(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                EntryOptions::new().large_file(true),
+                &mut Cursor::new(b"hello\n".to_vec()),
+            )
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+
+        assert_eq!(data.len(), 138);
+        assert_eq!(&data[28..30], &0u16.to_le_bytes()); // Local header extra field length.
+    }
+
+    // The values of the Zip64 extra field of each central directory record, found by their signature.
+    fn central_zip64_values(data: &[u8]) -> Vec<Vec<u64>> {
+        (0..data.len() - 4)
+            .filter(|&i| data[i..i + 4] == 0x02014b50u32.to_le_bytes())
+            .map(|i| {
+                let name_length = u16::from_le_bytes([data[i + 28], data[i + 29]]) as usize;
+                let extra_field = &data[i + 46 + name_length..];
+                if extra_field[0..2] != 1u16.to_le_bytes() {
+                    return Vec::new();
+                }
+                let size = u16::from_le_bytes([extra_field[2], extra_field[3]]) as usize;
+                extra_field[4..4 + size]
+                    .chunks(8)
+                    .map(|value| u64::from_le_bytes(value.try_into().unwrap()))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn zip64_end_of_central_directory() {
+        let start = 5 << 30;
+        let mut archive = Archive::new(Vec::new());
+        archive.written = start; // As if 5 GiB were already written.
+        archive
+            .tokio_append(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                &mut Cursor::new(b"hello\n"),
+            )
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+
+        let central_directory_offset = 30 + 9 + 6 + 16;
+        let zip64_offset = data.len() - 98;
+        let central_directory_size = (zip64_offset - central_directory_offset) as u64;
+        let central_directory = &data[central_directory_offset..zip64_offset];
+        assert_eq!(&central_directory[6..8], &45u16.to_le_bytes()); // Version needed to extract.
+        assert_eq!(&central_directory[42..46], &[0xff; 4]); // Offset placeholder.
+        assert_eq!(central_zip64_values(&data), [[start]]);
+
+        let (zip64, end) = data[zip64_offset..].split_at(76);
+        assert_eq!(&zip64[0..4], &0x06064b50u32.to_le_bytes()); // Zip64 end of central directory signature.
+        assert_eq!(&zip64[4..12], &44u64.to_le_bytes()); // Size of the remaining record.
+        assert_eq!(&zip64[24..40], &[1u64.to_le_bytes(); 2].concat()); // Number of records.
+        assert_eq!(&zip64[40..48], &central_directory_size.to_le_bytes()); // Size of central directory.
+        assert_eq!(
+            &zip64[48..56],
+            &(start + central_directory_offset as u64).to_le_bytes()
+        ); // Offset of central directory.
+        assert_eq!(&zip64[56..60], &0x07064b50u32.to_le_bytes()); // Zip64 end of central directory locator signature.
+        assert_eq!(&zip64[64..72], &(start + zip64_offset as u64).to_le_bytes()); // Offset of Zip64 end of central directory.
+        assert_eq!(&zip64[72..76], &1u32.to_le_bytes()); // Total number of disks.
+        assert_eq!(&end[0..4], &0x06054b50u32.to_le_bytes()); // End of central directory signature.
+        assert_eq!(&end[8..12], &[1, 0, 1, 0]); // Number of records.
+        assert_eq!(&end[12..16], &(central_directory_size as u32).to_le_bytes()); // Size of central directory.
+        assert_eq!(&end[16..20], &[0xff; 4]); // Offset placeholder.
+    }
+
+    #[tokio::test]
+    async fn entry_too_large() {
+        let size = 5 << 30;
+        let mut archive =
+            Archive::with_options(Vec::new(), ArchiveOptions::new().zip64(Zip64Mode::Never));
+        // The declared size is checked before the payload is read.
+        let error = archive
+            .tokio_append_with_metadata(
+                "declared.bin".to_owned(),
+                FileDateTime::Zero,
+                EntryOptions::new(),
+                u32::MAX as u64,
+                0,
+                &mut Cursor::new(Vec::new()),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.get_ref().unwrap().downcast_ref::<Error>(),
+            Some(Error::EntryTooLarge { name, size: 0xffff_ffff }) if name == "declared.bin"
+        ));
+        // The size of a streamed payload is checked once it is written.
+        let error = archive
+            .tokio_append_raw(
+                "streamed.bin".to_owned(),
+                FileDateTime::Zero,
+                EntryOptions::new(),
+                0,
+                size,
+                0,
+                &mut Cursor::new(vec![0; 10]),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.get_ref().unwrap().downcast_ref::<Error>(),
+            Some(Error::EntryTooLarge { name, size: 0x1_4000_0000 }) if name == "streamed.bin"
+        ));
+
+        // The large entries are written with Zip64 extra fields otherwise.
+        let mut archive = Archive::new(Vec::new());
+        archive
+            .tokio_append_raw(
+                "streamed.bin".to_owned(),
+                FileDateTime::Zero,
+                EntryOptions::new(),
+                0,
+                size,
+                0,
+                &mut Cursor::new(vec![0; 10]),
+            )
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+        assert_eq!(&data[18..26], &[0xff; 8]); // Sizes placeholders.
+        assert_eq!(&data[6..8], &(1u16 << 3 | 1 << 11).to_le_bytes()); // Data descriptor.
+        assert_eq!(
+            &data[30 + 12 + 20 + 10..30 + 12 + 20 + 10 + 24],
+            &[
+                &0x08074b50u32.to_le_bytes()[..],
+                &0u32.to_le_bytes(),
+                &10u64.to_le_bytes(),
+                &size.to_le_bytes(),
+            ]
+            .concat()
+        ); // Zip64 data descriptor.
+           // Only the uncompressed size is moved to the central directory's Zip64 extra field.
+        assert_eq!(central_zip64_values(&data), [[size]]);
+    }
+
+    #[tokio::test]
+    async fn archive_too_large() {
+        let never = || ArchiveOptions::new().zip64(Zip64Mode::Never);
+        async fn append(archive: &mut Archive<Vec<u8>>) -> Result<(), std::io::Error> {
+            archive
+                .tokio_append(
+                    "file1.txt".to_owned(),
+                    FileDateTime::Zero,
+                    &mut Cursor::new(b"hello\n"),
+                )
+                .await
+        }
+
+        // No entry can start beyond 4 GiB.
+        let mut archive = Archive::with_options(Vec::new(), never());
+        archive.written = u32::MAX as u64;
+        let error = append(&mut archive).await.unwrap_err();
+        assert!(matches!(
+            error.get_ref().unwrap().downcast_ref::<Error>(),
+            Some(Error::ArchiveTooLarge { size: 0xffff_ffff })
+        ));
+
+        // Nor can the central directory.
+        let mut archive = Archive::with_options(Vec::new(), never());
+        archive.written = u32::MAX as u64 - 40;
+        append(&mut archive).await.unwrap();
+        let error = archive.tokio_finalize().await.unwrap_err();
+        assert!(matches!(
+            error.get_ref().unwrap().downcast_ref::<Error>(),
+            Some(&Error::ArchiveTooLarge { size }) if size == u32::MAX as u64 - 40 + 61 + 55
+        ));
+
+        // The offsets fit until then.
+        let mut archive = Archive::with_options(Vec::new(), never());
+        archive.written = u32::MAX as u64 - 62;
+        append(&mut archive).await.unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+        assert_eq!(data.len(), 61 + 55 + 22);
+        assert_eq!(
+            &data[61 + 55 + 16..61 + 55 + 20],
+            &(u32::MAX - 1).to_le_bytes()
+        ); // Offset of central directory.
+    }
+
+    #[tokio::test]
+    async fn large_offsets() {
+        let start = u32::MAX as u64 - 61;
+        let mut archive = Archive::new(Vec::new());
+        archive.written = start;
+        for name in ["file1.txt", "file2.txt", "file3.txt"] {
+            archive
+                .tokio_append(
+                    name.to_owned(),
+                    FileDateTime::Zero,
+                    &mut Cursor::new(b"hello\n"),
+                )
+                .await
+                .unwrap();
+        }
+        let data = archive.tokio_finalize().await.unwrap();
+
+        // The first offset fits in 32 bits, the next ones (from 0xffffffff) are moved to the Zip64 extra field.
+        assert_eq!(
+            &data[3 * 61 + 42..3 * 61 + 46],
+            &(start as u32).to_le_bytes()
+        );
+        assert_eq!(
+            central_zip64_values(&data),
+            [vec![], vec![u32::MAX as u64], vec![u32::MAX as u64 + 61]]
+        );
+        let zip64_offset = data.len() - 98;
+        assert_eq!(
+            &data[zip64_offset + 48..zip64_offset + 56],
+            &(start + 3 * 61).to_le_bytes()
+        ); // Offset of central directory.
+        assert_eq!(
+            &data[zip64_offset + 64..zip64_offset + 72],
+            &(start + zip64_offset as u64).to_le_bytes()
+        ); // Offset of Zip64 end of central directory.
+    }
+
+    #[tokio::test]
+    async fn directory() {
+        let mut archive = Archive::new(Vec::new());
+        archive
+            .tokio_append_directory("dir".to_owned(), FileDateTime::Zero)
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+
+        assert_eq!(data.len(), crate::archive_size([("dir/", 0)]));
+        assert_eq!(&data[30..34], b"dir/"); // Filename.
+        assert_eq!(&data[50 + 38..50 + 42], &0x41ed0010u32.to_le_bytes()); // External file attributes.
+        assert_eq!(&data[50 + 46..50 + 50], b"dir/"); // Central directory filename.
+    }
+
+    // An in-memory source: its name, its payload, whether its size is declared and whether it fails to open.
+    struct MemorySource(&'static str, &'static [u8], bool, bool);
+
+    impl crate::ZipEntrySource for MemorySource {
+        type Reader = Cursor<&'static [u8]>;
+
+        fn name(&self) -> String {
+            self.0.to_owned()
+        }
+
+        fn datetime(&self) -> FileDateTime {
+            FileDateTime::Zero
+        }
+
+        fn size_hint(&self) -> Option<u64> {
+            self.2.then_some(self.1.len() as u64)
+        }
+
+        async fn open(&mut self) -> Result<Self::Reader, std::io::Error> {
+            match self.3 {
+                true => Err(std::io::ErrorKind::NotFound.into()),
+                false => Ok(Cursor::new(self.1)),
+            }
+        }
+    }
+
+    // List the names of the entries, as written in the central directory.
+    #[cfg(feature = "tokio-fs")]
+    fn entry_names(data: &[u8]) -> Vec<String> {
+        let end = &data[data.len() - 22..];
+        let count = u16::from_le_bytes([end[10], end[11]]) as usize;
+        let mut offset = u32::from_le_bytes([end[16], end[17], end[18], end[19]]) as usize;
+        (0..count)
+            .map(|_| {
+                let entry = &data[offset..];
+                let lengths =
+                    [28, 30, 32].map(|i| u16::from_le_bytes([entry[i], entry[i + 1]]) as usize);
+                offset += 46 + lengths.iter().sum::<usize>();
+                String::from_utf8(entry[46..46 + lengths[0]].to_vec()).unwrap()
+            })
+            .collect()
+    }
+
+    #[cfg(feature = "tokio-fs")]
+    #[tokio::test]
+    async fn append_dir_all() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("sub")).unwrap();
+        std::fs::create_dir_all(dir.path().join("empty")).unwrap();
+        std::fs::write(dir.path().join("b.txt"), b"hello\n").unwrap();
+        std::fs::write(dir.path().join("sub/a.txt"), b"world\n").unwrap();
+
+        let mut archive = Archive::new(Vec::new());
+        archive
+            .tokio_append_dir_all("root", dir.path(), crate::DirOptions::new())
+            .await
+            .unwrap();
+        archive
+            .tokio_append_dir_all("", dir.path(), crate::DirOptions::new().directories(true))
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+
+        assert_eq!(
+            entry_names(&data),
+            [
+                "root/b.txt",
+                "root/sub/a.txt",
+                "b.txt",
+                "empty/",
+                "sub/",
+                "sub/a.txt"
+            ],
+        );
+    }
+
+    #[cfg(all(feature = "tokio-uring", target_os = "linux"))]
+    #[test]
+    fn uring_file() {
+        use tokio::io::AsyncWriteExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let payload: Vec<u8> = (0..700_000u32).map(|i| (i * 7 % 251) as u8).collect();
+        std::fs::write(dir.path().join("data.bin"), &payload).unwrap();
+
+        let (data, expected) = tokio_uring::start(async {
+            let mut archive =
+                Archive::new(crate::UringFile::create(dir.path().join("archive.zip")).await?);
+            let mut file = crate::UringFile::open(dir.path().join("data.bin")).await?;
+            archive
+                .tokio_append("data.bin".to_owned(), FileDateTime::Zero, &mut file)
+                .await?;
+            archive.tokio_finalize().await?.shutdown().await?;
+
+            let mut archive = Archive::new(Vec::new());
+            archive
+                .tokio_append(
+                    "data.bin".to_owned(),
+                    FileDateTime::Zero,
+                    &mut payload.as_slice(),
+                )
+                .await?;
+            let expected = archive.tokio_finalize().await?;
+            std::fs::read(dir.path().join("archive.zip")).map(|data| (data, expected))
+        })
+        .unwrap();
+        assert_eq!(data, expected);
+    }
+
+    #[cfg(feature = "tokio-fs")]
+    #[tokio::test]
+    async fn append_dir_all_filters() {
+        let dir = tempfile::tempdir().unwrap();
+        for path in [
+            "a.txt",
+            "debug.log",
+            "keep.log",
+            "target/out.bin",
+            "build/b.txt",
+            "sub/build",
+            "sub/c.txt",
+            "sub/secret.txt",
+            "sub/deep/secret.txt",
+            "sub/node_modules/d.txt",
+        ] {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, b"").unwrap();
+        }
+        std::fs::write(
+            dir.path().join(".gitignore"),
+            "# Logs\n*.log\n!keep.log\n\nbuild/\n",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("sub/.zipignore"), "/secret.txt\n").unwrap();
+
+        let mut archive = Archive::new(Vec::new());
+        let options = crate::DirOptions::new()
+            .exclude("target")
+            .exclude("**/node_modules");
+        archive
+            .tokio_append_dir_all("", dir.path(), options.clone().ignore_files(true))
+            .await
+            .unwrap();
+        archive
+            .tokio_append_dir_all("txt", dir.path(), options.include("**/*.txt"))
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+
+        assert_eq!(
+            entry_names(&data),
+            [
+                ".gitignore",
+                "a.txt",
+                "keep.log",
+                "sub/.zipignore",
+                "sub/build",
+                "sub/c.txt",
+                "sub/deep/secret.txt",
+                "txt/a.txt",
+                "txt/build/b.txt",
+                "txt/sub/c.txt",
+                "txt/sub/secret.txt",
+                "txt/sub/deep/secret.txt",
+            ],
+        );
+    }
+
+    #[cfg(feature = "tokio-fs")]
+    #[tokio::test]
+    async fn append_dir_all_filter() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("cache")).unwrap();
+        std::fs::create_dir_all(dir.path().join("sub")).unwrap();
+        std::fs::write(dir.path().join("small.txt"), b"hello\n").unwrap();
+        std::fs::write(dir.path().join("large.bin"), vec![0; 1024]).unwrap();
+        std::fs::write(dir.path().join("cache/a.txt"), b"").unwrap();
+        std::fs::write(dir.path().join("sub/b.txt"), b"").unwrap();
+
+        let options = crate::DirOptions::new()
+            .directories(true)
+            .filter(|path, metadata| {
+                let cache = path.ends_with("cache");
+                let size = metadata.len();
+                let is_dir = metadata.is_dir();
+                async move { !cache && (is_dir || size < 512) }
+            });
+        let mut archive = Archive::new(Vec::new());
+        archive
+            .tokio_append_dir_all("", dir.path(), options)
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+
+        assert_eq!(entry_names(&data), ["small.txt", "sub/", "sub/b.txt"]);
+    }
+
+    #[cfg(feature = "tokio-fs")]
+    #[tokio::test]
+    async fn append_dir_all_limits() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("a/b/c")).unwrap();
+        std::fs::write(dir.path().join("root.txt"), b"hello\n").unwrap();
+        std::fs::write(dir.path().join("a/one.txt"), b"hello\n").unwrap();
+        std::fs::write(dir.path().join("a/b/two.txt"), b"hello\n").unwrap();
+        std::fs::write(dir.path().join("a/b/c/three.txt"), b"hello\n").unwrap();
+
+        let path = dir.path();
+        let append = |options| async move {
+            let mut archive = Archive::new(Vec::new());
+            archive.tokio_append_dir_all("", path, options).await?;
+            archive.tokio_finalize().await
+        };
+
+        let data = append(crate::DirOptions::new().directories(true).max_depth(1))
+            .await
+            .unwrap();
+        assert_eq!(entry_names(&data), ["a/", "root.txt", "a/b/", "a/one.txt"]);
+
+        let data = append(crate::DirOptions::new().max_entries(4).max_size(24))
+            .await
+            .unwrap();
+        assert_eq!(entry_names(&data).len(), 4);
+
+        for options in [
+            crate::DirOptions::new().max_entries(3),
+            crate::DirOptions::new().max_size(23),
+        ] {
+            let error = append(options).await.unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+            assert!(matches!(
+                error.get_ref().unwrap().downcast_ref::<Error>(),
+                Some(Error::DirectoryTooLarge { .. })
+            ));
+        }
+    }
+
+    #[cfg(feature = "tokio-fs")]
+    #[tokio::test]
+    async fn append_dir_all_concurrency() {
+        let dir = tempfile::tempdir().unwrap();
+        for i in 0..20 {
+            std::fs::create_dir_all(dir.path().join(format!("dir{i}"))).unwrap();
+            for j in 0..10 {
+                std::fs::write(
+                    dir.path().join(format!("dir{i}/file{j}.txt")),
+                    format!("{i} {j}\n"),
+                )
+                .unwrap();
+            }
+        }
+
+        let path = dir.path();
+        let append = |options| async move {
+            let mut archive = Archive::new(Vec::new());
+            archive
+                .tokio_append_dir_all("", path, options)
+                .await
+                .unwrap();
+            archive.tokio_finalize().await.unwrap()
+        };
+        let sequential = append(crate::DirOptions::new().directories(true)).await;
+        let concurrent = append(crate::DirOptions::new().directories(true).concurrency(16)).await;
+
+        assert_eq!(entry_names(&sequential).len(), 220);
+        assert_eq!(concurrent, sequential);
+    }
+
+    #[cfg(feature = "tokio-fs")]
+    #[tokio::test]
+    async fn append_dir_all_rename() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("src/data")).unwrap();
+        std::fs::write(dir.path().join("src/data/a.csv"), b"1,2\n").unwrap();
+        std::fs::write(dir.path().join("src/b.csv"), b"3,4\n").unwrap();
+
+        let options = crate::DirOptions::new()
+            .directories(true)
+            .exclude("src/b.csv")
+            .rename(|relative| {
+                relative
+                    .strip_prefix("src/")
+                    .unwrap_or(relative)
+                    .replace('/', "-")
+            });
+        let mut archive = Archive::new(Vec::new());
+        archive
+            .tokio_append_dir_all("export-2024", dir.path(), options)
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+
+        assert_eq!(
+            entry_names(&data),
+            [
+                "export-2024/src/",
+                "export-2024/data/",
+                "export-2024/data-a.csv"
+            ]
+        );
+    }
+
+    #[cfg(all(feature = "tokio-fs", unix))]
+    #[tokio::test]
+    async fn append_dir_all_skipped() {
+        use crate::SkipReason;
+        use std::os::unix::fs::symlink;
+        use std::os::unix::net::UnixListener;
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join(".git")).unwrap();
+        std::fs::write(dir.path().join(".env"), b"SECRET=1\n").unwrap();
+        std::fs::write(dir.path().join(".git/HEAD"), b"main\n").unwrap();
+        std::fs::write(dir.path().join("a.txt"), b"hello\n").unwrap();
+        std::fs::write(dir.path().join("b.log"), b"world\n").unwrap();
+        symlink("missing.txt", dir.path().join("broken.txt")).unwrap();
+        let _listener = UnixListener::bind(dir.path().join("socket")).unwrap();
+
+        let mut archive = Archive::new(Vec::new());
+        let summary = archive
+            .tokio_append_dir_all(
+                "",
+                dir.path(),
+                crate::DirOptions::new().hidden(false).exclude("*.log"),
+            )
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+
+        assert_eq!(entry_names(&data), ["a.txt"]);
+        assert_eq!(
+            summary,
+            [
+                (".env".to_owned(), SkipReason::Hidden),
+                (".git".to_owned(), SkipReason::Hidden),
+                ("b.log".to_owned(), SkipReason::Excluded),
+                ("broken.txt".to_owned(), SkipReason::BrokenSymlink),
+                ("socket".to_owned(), SkipReason::Special),
+            ]
+        );
+    }
+
+    #[cfg(all(feature = "tokio-fs", unix))]
+    #[tokio::test]
+    async fn append_dir_all_symlinks() {
+        use crate::SymlinkPolicy;
+        use std::os::unix::fs::symlink;
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("sub")).unwrap();
+        std::fs::write(dir.path().join("a.txt"), b"hello\n").unwrap();
+        std::fs::write(dir.path().join("sub/b.txt"), b"world\n").unwrap();
+        symlink("a.txt", dir.path().join("link.txt")).unwrap();
+        symlink("sub", dir.path().join("link")).unwrap();
+        symlink("..", dir.path().join("sub/up")).unwrap();
+        symlink("missing.txt", dir.path().join("broken.txt")).unwrap();
+
+        let path = dir.path();
+        let names = |symlinks| async move {
+            let mut archive = Archive::new(Vec::new());
+            archive
+                .tokio_append_dir_all("", path, crate::DirOptions::new().symlinks(symlinks))
+                .await
+                .unwrap();
+            entry_names(&archive.tokio_finalize().await.unwrap())
+        };
+        assert_eq!(
+            names(SymlinkPolicy::FollowFiles).await,
+            ["a.txt", "link.txt", "sub/b.txt"],
+        );
+        assert_eq!(
+            names(SymlinkPolicy::Follow).await,
+            ["a.txt", "link.txt", "link/b.txt", "sub/b.txt"],
+        );
+        assert_eq!(names(SymlinkPolicy::Skip).await, ["a.txt", "sub/b.txt"]);
+
+        let mut archive = Archive::new(Vec::new());
+        archive
+            .tokio_append_dir_all(
+                "",
+                dir.path(),
+                crate::DirOptions::new().symlinks(SymlinkPolicy::Store),
+            )
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+        let mut reader = ArchiveReader::new(Cursor::new(data));
+        let mut links = Vec::new();
+        while let Some(entry) = reader.tokio_next_entry().await.unwrap() {
+            let mut payload = vec![0; 100];
+            let read = reader.tokio_read(&mut payload).await.unwrap();
+            payload.truncate(read);
+            links.push((entry.name().to_owned(), String::from_utf8(payload).unwrap()));
+        }
+        let central_directory = reader.tokio_central_directory().await.unwrap();
+        let modes: Vec<_> = central_directory
+            .entries()
+            .iter()
+            .map(|entry| entry.unix_mode().unwrap() & 0o170000)
+            .collect();
+        assert_eq!(
+            links
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>(),
+            [
+                "a.txt",
+                "broken.txt",
+                "link",
+                "link.txt",
+                "sub/b.txt",
+                "sub/up"
+            ],
+        );
+        assert_eq!(links[1].1, "missing.txt");
+        assert_eq!(links[5].1, "..");
+        assert_eq!(
+            modes,
+            [0o100000, 0o120000, 0o120000, 0o120000, 0o100000, 0o120000]
+        );
+    }
+
+    #[cfg(feature = "tokio-fs")]
+    #[tokio::test]
+    async fn extract() {
+        let mut archive = Archive::new(Vec::new());
+        archive
+            .tokio_append_with_options(
+                "sub/run.sh".to_owned(),
+                FileDateTime::Zero,
+                EntryOptions::new().unix_permissions(0o755),
+                &mut Cursor::new(b"#!/bin/sh\n"),
+            )
+            .await
+            .unwrap();
+        archive
+            .tokio_append(
+                "./b.txt".to_owned(),
+                FileDateTime::Zero,
+                &mut Cursor::new(b"hello\n"),
+            )
+            .await
+            .unwrap();
+        archive
+            .tokio_append_directory("empty".to_owned(), FileDateTime::Zero)
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        ArchiveReader::new(&data[..])
+            .tokio_extract(dir.path(), crate::ExtractOptions::new().permissions(true))
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read(dir.path().join("sub/run.sh")).unwrap(),
+            b"#!/bin/sh\n"
+        );
+        assert_eq!(std::fs::read(dir.path().join("b.txt")).unwrap(), b"hello\n");
+        assert!(dir.path().join("empty").is_dir());
+
+        let selected = tempfile::tempdir().unwrap();
+        ArchiveReader::new(&data[..])
+            .tokio_extract(selected.path(), crate::ExtractOptions::new().glob("sub/*"))
+            .await
+            .unwrap();
+        assert!(selected.path().join("sub/run.sh").exists());
+        assert!(!selected.path().join("b.txt").exists());
+        assert!(!selected.path().join("empty").exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let metadata = std::fs::metadata(dir.path().join("sub/run.sh")).unwrap();
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o755);
+        }
+
+        for name in ["../evil.txt", "sub/../../evil.txt"] {
+            let mut archive = Archive::new(Vec::new());
+            archive
+                .tokio_append(
+                    name.to_owned(),
+                    FileDateTime::Zero,
+                    &mut Cursor::new(b"evil"),
+                )
+                .await
+                .unwrap();
+            let data = archive.tokio_finalize().await.unwrap();
+
+            let dir = tempfile::tempdir().unwrap();
+            let destination = dir.path().join("destination");
+            let error = ArchiveReader::new(&data[..])
+                .tokio_extract(&destination, crate::ExtractOptions::new())
+                .await
+                .unwrap_err();
+            assert!(matches!(
+                error.get_ref().unwrap().downcast_ref::<Error>(),
+                Some(Error::UnsafePath { .. })
+            ));
+            assert!(!dir.path().join("evil.txt").exists());
+        }
+    }
+
+    #[tokio::test]
+    async fn attributes() {
+        let mut archive = Archive::new(Vec::new());
+        archive
+            .tokio_append_with_options(
+                "run.sh".to_owned(),
+                FileDateTime::Zero,
+                EntryOptions::new()
+                    .unix_permissions(0o100755)
+                    .dos_attributes(DosAttributes::new().read_only(true).hidden(true))
+                    .text(true),
+                &mut Cursor::new(Vec::new()),
+            )
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+
+        // External file attributes.
+        assert_eq!(&data[90..94], &(0o100755u32 << 16 | 0x03).to_le_bytes());
+        assert_eq!(&data[88..90], &1u16.to_le_bytes()); // Internal file attributes.
+
+        let mut archive = Archive::new(Vec::new());
+        archive
+            .tokio_append_with_options(
+                "run.sh".to_owned(),
+                FileDateTime::Zero,
+                EntryOptions::new()
+                    .unix_permissions(0o755)
+                    .external_attributes(0x12345678),
+                &mut Cursor::new(Vec::new()),
+            )
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+
+        assert_eq!(&data[90..94], &0x12345678u32.to_le_bytes()); // Raw external file attributes.
+    }
+
+    #[cfg(all(feature = "tokio-fs", unix))]
+    #[tokio::test]
+    async fn append_dir_all_executable() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("run.sh"), b"").unwrap();
+        std::fs::set_permissions(dir.path().join("run.sh"), PermissionsExt::from_mode(0o750))
+            .unwrap();
+
+        for (preserve, mode) in [(true, 0o100754u32), (false, 0o100644)] {
+            let mut archive = Archive::new(Vec::new());
+            archive
+                .tokio_append_dir_all(
+                    "",
+                    dir.path(),
+                    crate::DirOptions::new().preserve_executable(preserve),
+                )
+                .await
+                .unwrap();
+            let data = archive.tokio_finalize().await.unwrap();
+
+            assert_eq!(&data[90..94], &(mode << 16).to_le_bytes()); // External file attributes.
+        }
+    }
+
+    #[cfg(all(feature = "tokio-fs", unix))]
+    #[tokio::test]
+    async fn append_path() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("run.sh");
+        std::fs::write(&path, b"").unwrap();
+        std::fs::set_permissions(&path, PermissionsExt::from_mode(0o750)).unwrap();
+        let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+
+        let mut archive = Archive::new(Vec::new());
+        archive.tokio_append_path(None, &path).await.unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+        assert_eq!(&data[30..36], b"run.sh");
+        assert_eq!(&data[90..94], &(0o100750u32 << 16).to_le_bytes()); // External file attributes.
+        let (date, time) = FileDateTime::from_system_time(modified).ms_dos();
+        assert_eq!(
+            &data[10..14],
+            &[time.to_le_bytes(), date.to_le_bytes()].concat()
+        );
+
+        let mut archive = Archive::new(Vec::new());
+        archive
+            .tokio_append_path(Some("bin/run".to_owned()), &path)
+            .await
+            .unwrap();
+        assert_eq!(
+            entry_names(&archive.tokio_finalize().await.unwrap()),
+            ["bin/run"]
+        );
+
+        let error = Archive::new(Vec::new())
+            .tokio_append_path(None, dir.path())
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[cfg(feature = "tokio-fs")]
+    #[tokio::test]
+    async fn append_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), b"hello\n").unwrap();
+        std::fs::write(dir.path().join("b.txt"), b"world\n").unwrap();
+
+        let mut archive = Archive::new(Vec::new());
+        let summary = archive
+            .tokio_append_paths([
+                ("a.txt".to_owned(), dir.path().join("a.txt")),
+                ("missing.txt".to_owned(), dir.path().join("missing.txt")),
+                ("dir".to_owned(), dir.path().to_path_buf()),
+                ("sub/b.txt".to_owned(), dir.path().join("b.txt")),
+            ])
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+
+        assert_eq!(entry_names(&data), ["a.txt", "sub/b.txt"]);
+        let summary: Vec<_> = summary
+            .iter()
+            .map(|(name, result)| {
+                (
+                    name.as_str(),
+                    result.as_ref().map(|_| ()).map_err(|error| error.kind()),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("a.txt", Ok(())),
+                ("missing.txt", Err(std::io::ErrorKind::NotFound)),
+                ("dir", Err(std::io::ErrorKind::InvalidInput)),
+                ("sub/b.txt", Ok(())),
+            ],
+        );
+    }
+
+    #[tokio::test]
+    async fn ntfs_times() {
+        let time = UNIX_EPOCH + Duration::from_nanos(1_500);
+        let mut archive = Archive::new(Vec::new());
+        archive
+            .tokio_append_with_options(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                EntryOptions::new().ntfs_times(time, time, UNIX_EPOCH),
+                &mut Cursor::new(Vec::new()),
+            )
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+
+        let field = [
+            &[0x0a, 0, 32, 0, 0, 0, 0, 0, 1, 0, 24, 0][..],
+            &116_444_736_000_000_015u64.to_le_bytes(),
+            &116_444_736_000_000_015u64.to_le_bytes(),
+            &116_444_736_000_000_000u64.to_le_bytes(),
+        ]
+        .concat();
+        assert_eq!(&data[28..30], &36u16.to_le_bytes()); // Extra field length.
+        assert_eq!(&data[39..75], &field); // Local header extra field.
+        assert_eq!(&data[91 + 30..91 + 32], &36u16.to_le_bytes()); // Central directory extra field length.
+        assert_eq!(&data[91 + 55..91 + 91], &field); // Central directory extra field.
+    }
+
+    #[tokio::test]
+    async fn owner() {
+        let mut archive = Archive::new(Vec::new());
+        archive
+            .tokio_append_with_options(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                EntryOptions::new().owner(1000, 100),
+                &mut Cursor::new(Vec::new()),
+            )
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+
+        let field = [0x75, 0x78, 11, 0, 1, 4, 0xe8, 3, 0, 0, 4, 100, 0, 0, 0];
+        assert_eq!(&data[39..54], &field); // Local header extra field.
+        assert_eq!(&data[70 + 55..70 + 70], &field); // Central directory extra field.
+    }
+
+    #[tokio::test]
+    async fn xattrs() {
+        let mut archive = Archive::new(Vec::new());
+        archive
+            .tokio_append_with_options(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                EntryOptions::new()
+                    .xattr("user.a", "1")
+                    .xattr("user.bc", vec![2, 3]),
+                &mut Cursor::new(Vec::new()),
+            )
+            .await
+            .unwrap();
+        let error = archive
+            .tokio_append_with_options(
+                "file2.txt".to_owned(),
+                FileDateTime::Zero,
+                EntryOptions::new()
+                    .xattr("user.a", vec![0; 40000])
+                    .xattr("user.b", vec![0; 40000]),
+                &mut Cursor::new(Vec::new()),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.get_ref().unwrap().downcast_ref::<Error>(),
+            Some(Error::ExtraFieldTooLarge { .. })
+        ));
+        let data = archive.tokio_finalize().await.unwrap();
+
+        assert_eq!(&data[28..30], &27u16.to_le_bytes()); // Extra field length.
+        assert_eq!(
+            &data[39..66],
+            b"xa\x17\x00\x01\x06user.a\x01\x001\x07user.bc\x02\x00\x02\x03"
+        );
+    }
+
+    #[tokio::test]
+    async fn custom_extra_fields() {
+        let mut archive = Archive::new(Vec::new());
+        archive
+            .tokio_append_with_options(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                EntryOptions::new()
+                    .local_extra_field(0xcafe, [1, 2])
+                    .central_extra_field(0xbeef, [3])
+                    .extra_field(0x1234, []),
+                &mut Cursor::new(Vec::new()),
+            )
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+
+        assert_eq!(&data[28..30], &10u16.to_le_bytes()); // Extra field length.
+        assert_eq!(&data[39..49], b"\xfe\xca\x02\x00\x01\x02\x34\x12\x00\x00");
+        assert_eq!(&data[65 + 30..65 + 32], &9u16.to_le_bytes()); // Extra field length.
+        assert_eq!(
+            &data[65 + 55..65 + 64],
+            b"\xef\xbe\x01\x00\x03\x34\x12\x00\x00"
+        );
+    }
+
+    #[tokio::test]
+    async fn unicode_path_field() {
+        let mut archive =
+            Archive::with_options(Vec::new(), ArchiveOptions::new().unicode_path_field(true));
+        archive
+            .tokio_append_with_options(
+                "café☃.txt".to_owned(),
+                FileDateTime::Zero,
+                EntryOptions::new(),
+                &mut Cursor::new(Vec::new()),
+            )
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+
+        assert_eq!(&data[6..8], &0x0008u16.to_le_bytes()); // General purpose flag (no UTF-8 filename).
+        assert_eq!(&data[26..30], &[9, 0, 21, 0]); // Filename and extra field lengths.
+        assert_eq!(&data[30..39], b"caf\x82_.txt");
+        assert_eq!(&data[39..44], b"up\x11\x00\x01");
+        assert_eq!(
+            &data[44..48],
+            &crc32fast::hash(b"caf\x82_.txt").to_le_bytes()
+        );
+        assert_eq!(&data[48..60], "café☃.txt".as_bytes());
+        assert_eq!(&data[76 + 8..76 + 10], &0x0008u16.to_le_bytes()); // General purpose flag.
+        assert_eq!(&data[76 + 46..76 + 76], &data[30..60]); // Filename and extra field.
+    }
+
+    #[tokio::test]
+    async fn name_encoding() {
+        let mut archive = Archive::with_options(
+            Vec::new(),
+            ArchiveOptions::new().name_encoding(NameEncoding::Utf8WhenNeeded),
+        );
+        for (name, options) in [
+            ("a.txt", EntryOptions::new()),
+            ("é.txt", EntryOptions::new()),
+            (
+                "é.txt",
+                EntryOptions::new().name_encoding(NameEncoding::Cp437),
+            ),
+            ("☃", EntryOptions::new().name_encoding(NameEncoding::Cp437)),
+        ] {
+            archive
+                .tokio_append_with_options(
+                    name.to_owned(),
+                    FileDateTime::Zero,
+                    options,
+                    &mut Cursor::new(Vec::new()),
+                )
+                .await
+                .unwrap();
+        }
+        let data = archive.tokio_finalize().await.unwrap();
+
+        assert_eq!(&data[6..8], &0x0008u16.to_le_bytes()); // General purpose flag.
+        assert_eq!(&data[51 + 6..51 + 8], &0x0808u16.to_le_bytes()); // General purpose flag (UTF-8 filename).
+        assert_eq!(&data[103 + 6..103 + 8], &0x0008u16.to_le_bytes()); // General purpose flag.
+        assert_eq!(&data[103 + 30..103 + 35], b"\x82.txt");
+        assert_eq!(&data[154 + 6..154 + 8], &0x0808u16.to_le_bytes()); // General purpose flag (UTF-8 filename).
+        assert_eq!(&data[154 + 30..154 + 33], "☃".as_bytes());
+    }
+
+    #[tokio::test]
+    async fn version_fields() {
+        let mut archive = Archive::with_options(
+            Vec::new(),
+            ArchiveOptions::new()
+                .host_system(HostSystem::WindowsNtfs)
+                .version_needed(20),
+        );
+        archive
+            .tokio_append(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                &mut Cursor::new(Vec::new()),
+            )
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+
+        assert_eq!(&data[4..6], &20u16.to_le_bytes()); // Version needed to extract.
+        assert_eq!(&data[55 + 4..55 + 6], &0x0a1eu16.to_le_bytes()); // Version made by (Windows NTFS, 3.0).
+        assert_eq!(&data[55 + 6..55 + 8], &20u16.to_le_bytes()); // Version needed to extract.
+    }
+
+    #[tokio::test]
+    async fn data_descriptor() {
+        let mut archive = Archive::with_options(
+            Vec::new(),
+            ArchiveOptions::new()
+                .data_descriptor(DataDescriptor::WhenNeeded)
+                .descriptor_signature(false),
+        );
+        archive
+            .tokio_append(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                &mut Cursor::new(b"hello"),
+            )
+            .await
+            .unwrap();
+        archive
+            .tokio_append(
+                "file2.txt".to_owned(),
+                FileDateTime::Zero,
+                &mut Cursor::new(vec![0; 5000]),
+            )
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+
+        let crc = crc32fast::hash(b"hello");
+        assert_eq!(&data[6..8], &0x0800u16.to_le_bytes()); // General purpose flag (no data descriptor).
+        assert_eq!(&data[14..18], &crc.to_le_bytes()); // CRC32.
+        assert_eq!(&data[18..26], &[5, 0, 0, 0, 5, 0, 0, 0]); // Compressed and uncompressed sizes.
+        assert_eq!(&data[44..48], &0x04034b50u32.to_le_bytes()); // Next local file header signature.
+        assert_eq!(&data[44 + 6..44 + 8], &0x0808u16.to_le_bytes()); // General purpose flag (data descriptor).
+        assert_eq!(&data[44 + 14..44 + 26], &[0; 12]); // Temporary CRC32 and sizes.
+        let crc = crc32fast::hash(&[0; 5000]);
+        assert_eq!(&data[5083..5087], &crc.to_le_bytes()); // Data descriptor without signature.
+        assert_eq!(&data[5095..5099], &0x02014b50u32.to_le_bytes()); // Central directory entry signature.
+        assert_eq!(&data[5095 + 8..5095 + 10], &0x0800u16.to_le_bytes()); // General purpose flag.
+        assert_eq!(
+            &data[5095 + 16..5095 + 20],
+            &crc32fast::hash(b"hello").to_le_bytes()
+        ); // CRC32.
+    }
+
+    #[tokio::test]
+    async fn profiles() {
+        for (profile, flags, version_needed, version_made_by) in [
+            (Profile::WindowsExplorer, 0x0008u16, 20u16, 0x0a1eu16),
+            (Profile::MacArchiveUtility, 0x0808, 10, 0x031e),
+            (Profile::Java, 0x0800, 10, 0x031e),
+            (Profile::Streaming, 0x0808, 10, 0x031e),
+        ] {
+            let mut archive =
+                Archive::with_options(Vec::new(), ArchiveOptions::new().profile(profile));
+            archive
+                .tokio_append(
+                    "file1.txt".to_owned(),
+                    FileDateTime::Zero,
+                    &mut Cursor::new(b""),
+                )
+                .await
+                .unwrap();
+            let data = archive.tokio_finalize().await.unwrap();
+
+            let offset = data.len() - 22 - 55; // Central directory entry.
+            assert_eq!(&data[4..6], &version_needed.to_le_bytes()); // Version needed to extract.
+            assert_eq!(&data[6..8], &flags.to_le_bytes()); // General purpose flag.
+            assert_eq!(
+                &data[offset + 4..offset + 6],
+                &version_made_by.to_le_bytes()
+            ); // Version made by.
+        }
+    }
+
+    #[tokio::test]
+    async fn profile_java() {
+        let mut archive =
+            Archive::with_options(Vec::new(), ArchiveOptions::new().profile(Profile::Java));
+        archive
+            .tokio_append(
+                "small.txt".to_owned(),
+                FileDateTime::Zero,
+                &mut Cursor::new(b"hello\n"),
+            )
+            .await
+            .unwrap();
+        archive
+            .tokio_append(
+                "large.bin".to_owned(),
+                FileDateTime::Zero,
+                &mut Cursor::new(vec![7; 64 * 1024]),
+            )
+            .await
+            .unwrap();
+        archive
+            .tokio_append_directory("dir".to_owned(), FileDateTime::Zero)
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+
+        let entries = read_entries(&data).await;
+        assert_eq!(entries.len(), 3);
+        for (entry, _) in &entries {
+            assert_eq!(entry.method(), 0, "{}", entry.name());
+            assert_eq!(entry.flags() & 1 << 3, 0, "{}", entry.name());
+        }
+        assert_eq!(entries[1].1, vec![7; 64 * 1024]);
+    }
+
+    #[tokio::test]
+    async fn append_bytes() {
+        let options = ArchiveOptions::new().data_descriptor(DataDescriptor::Always);
+        let mut archive = Archive::with_options(Vec::new(), options.clone());
+        archive
+            .tokio_append_bytes("file1.txt".to_owned(), FileDateTime::Zero, b"hello\n")
+            .await
+            .unwrap();
+        archive
+            .tokio_append_bytes_with_options(
+                "file2.txt".to_owned(),
+                FileDateTime::Zero,
+                EntryOptions::new().compressor(|| Doubler),
+                "world\n".to_owned(),
+            )
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+
+        // Without data descriptor if stored, with the CRC32 declared otherwise.
+        let crc = crc32fast::hash(b"hello\n");
+        let mut expected = Archive::with_options(Vec::new(), options);
+        expected
+            .tokio_append_with_metadata(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                EntryOptions::new(),
+                6,
+                crc,
+                &mut Cursor::new(b"hello\n"),
+            )
+            .await
+            .unwrap();
+        expected
+            .tokio_append_with_options(
+                "file2.txt".to_owned(),
+                FileDateTime::Zero,
+                EntryOptions::new().compressor(|| Doubler),
+                &mut Cursor::new(b"world\n"),
+            )
+            .await
+            .unwrap();
+        assert_eq!(data, expected.tokio_finalize().await.unwrap());
+        assert_eq!(data[6] & 0x08, 0); // General purpose flags, without data descriptor.
+    }
+
+    #[tokio::test]
+    async fn append_empty_file() {
+        let mut archive = Archive::new(Vec::new());
+        archive
+            .tokio_append_empty_file("SUCCESS".to_owned(), FileDateTime::Zero)
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+
+        assert_eq!(data[6] & 0x08, 0); // General purpose flags, without data descriptor.
+        assert_eq!(&data[14..26], &[0; 12]); // CRC32, compressed and uncompressed sizes.
+        assert_eq!(&data[30..37], b"SUCCESS");
+        assert_eq!(data.len(), 30 + 7 + 46 + 7 + 22);
+    }
+
+    #[tokio::test]
+    async fn append_with_metadata() {
+        let crc = crc32fast::hash(b"hello");
+        let mut archive = Archive::new(Vec::new());
+        archive
+            .tokio_append_with_metadata(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                EntryOptions::new().compressor(|| Doubler),
+                5,
+                crc,
+                &mut Cursor::new(b"hello"),
+            )
+            .await
+            .unwrap();
+        let error = archive
+            .tokio_append_with_metadata(
+                "file2.txt".to_owned(),
+                FileDateTime::Zero,
+                EntryOptions::new(),
+                5,
+                crc,
+                &mut Cursor::new(b"hell0"),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(matches!(
+            error.get_ref().unwrap().downcast_ref::<Error>(),
+            Some(Error::MetadataMismatch { .. })
+        ));
+        let data = archive.sink;
+
+        assert_eq!(&data[6..8], &0x0800u16.to_le_bytes()); // General purpose flag (no data descriptor).
+        assert_eq!(&data[8..10], &0u16.to_le_bytes()); // Compression method (stored).
+        assert_eq!(&data[14..18], &crc.to_le_bytes()); // CRC32.
+        assert_eq!(&data[18..26], &[5, 0, 0, 0, 5, 0, 0, 0]); // Compressed and uncompressed sizes.
+        assert_eq!(&data[39..44], b"hello");
+        assert_eq!(&data[44..48], &0x04034b50u32.to_le_bytes()); // Next local file header signature.
+    }
+
+    #[tokio::test]
+    async fn append_seekable() {
+        let mut archive = Archive::new(Cursor::new(Vec::new()));
+        for (name, large_file) in [("file1.txt", false), ("file2.txt", true)] {
+            archive
+                .tokio_append_seekable(
+                    name.to_owned(),
+                    FileDateTime::Zero,
+                    EntryOptions::new()
+                        .compressor(|| Doubler)
+                        .large_file(large_file),
+                    &mut Cursor::new(b"hello"),
+                )
+                .await
+                .unwrap();
+        }
+        let data = archive.tokio_finalize().await.unwrap().into_inner();
+
+        let crc = crc32fast::hash(b"hello");
+        assert_eq!(&data[6..8], &0x0800u16.to_le_bytes()); // General purpose flag (no data descriptor).
+        assert_eq!(&data[14..18], &crc.to_le_bytes()); // CRC32.
+        assert_eq!(&data[18..26], &[11, 0, 0, 0, 5, 0, 0, 0]); // Compressed and uncompressed sizes.
+        assert_eq!(&data[50..54], &0x04034b50u32.to_le_bytes()); // Next local file header signature.
+        assert_eq!(&data[50 + 14..50 + 18], &crc.to_le_bytes()); // CRC32.
+        assert_eq!(&data[50 + 18..50 + 26], &[0xff; 8]); // Sizes in the Zip64 extra field.
+        assert_eq!(&data[50 + 43..50 + 51], &5u64.to_le_bytes()); // Uncompressed size.
+        assert_eq!(&data[50 + 51..50 + 59], &11u64.to_le_bytes()); // Compressed size.
+        assert_eq!(&data[120..124], &0x02014b50u32.to_le_bytes()); // Central directory entry signature.
+    }
+
+    #[cfg(feature = "spill-to-disk")]
+    #[tokio::test]
+    async fn buffered_entries() {
+        let payload: Vec<u8> = (0..10000u32).map(|i| i as u8).collect();
+        let mut archive = Archive::with_options(
+            Vec::new(),
+            ArchiveOptions::new()
+                .data_descriptor(DataDescriptor::Never)
+                .spill_threshold(5000),
+        );
+        archive
+            .tokio_append_with_options(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                EntryOptions::new().compressor(|| Doubler),
+                &mut Cursor::new(&payload),
+            )
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+
+        assert_eq!(&data[6..8], &0x0800u16.to_le_bytes()); // General purpose flag (no data descriptor).
+        assert_eq!(&data[14..18], &crc32fast::hash(&payload).to_le_bytes()); // CRC32.
+        assert_eq!(&data[18..22], &20001u32.to_le_bytes()); // Compressed size.
+        assert_eq!(&data[22..26], &10000u32.to_le_bytes()); // Uncompressed size.
+        assert_eq!(&data[39..41], &[0, 0]);
+        assert_eq!(&data[39 + 19998..39 + 20001], &[15, 15, 0]);
+        assert_eq!(&data[39 + 20001..39 + 20005], &0x02014b50u32.to_le_bytes());
+        // Central directory entry signature.
+    }
+
+    #[tokio::test]
+    async fn append_two_pass() {
+        let mut archive = Archive::new(Vec::new());
+        let mut source = Cursor::new(b"xhello");
+        source.set_position(1);
+        archive
+            .tokio_append_two_pass(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                EntryOptions::new().compressor(|| Doubler),
+                &mut source,
+            )
+            .await
+            .unwrap();
+        #[cfg(feature = "deflate-compression")]
+        archive
+            .tokio_append_two_pass(
+                "file2.txt".to_owned(),
+                FileDateTime::Zero,
+                EntryOptions::new().compression(crate::Compression::Deflate),
+                &mut Cursor::new(vec![0; 10000]),
+            )
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+
+        assert_eq!(&data[6..8], &0x0800u16.to_le_bytes()); // General purpose flag (no data descriptor).
+        assert_eq!(&data[8..10], &0u16.to_le_bytes()); // Compression method (stored, as the compressor doesn't shrink it).
+        assert_eq!(&data[14..18], &crc32fast::hash(b"hello").to_le_bytes()); // CRC32.
+        assert_eq!(&data[18..26], &[5, 0, 0, 0, 5, 0, 0, 0]); // Compressed and uncompressed sizes.
+        assert_eq!(&data[39..44], b"hello");
+        #[cfg(feature = "deflate-compression")]
+        {
+            assert_eq!(&data[44 + 6..44 + 8], &0x0800u16.to_le_bytes()); // General purpose flag (no data descriptor).
+            assert_eq!(&data[44 + 8..44 + 10], &8u16.to_le_bytes()); // Compression method (deflate).
+            let compressed_size =
+                u32::from_le_bytes(data[44 + 18..44 + 22].try_into().unwrap()) as usize;
+            assert!(compressed_size < 10000);
+            assert_eq!(&data[44 + 22..44 + 26], &10000u32.to_le_bytes()); // Uncompressed size.
+            let central_directory = 44 + 39 + compressed_size;
+            assert_eq!(
+                &data[central_directory..central_directory + 4],
+                &0x02014b50u32.to_le_bytes()
+            ); // Central directory entry signature.
+        }
+    }
+
+    #[tokio::test]
+    async fn append_raw() {
+        let crc = crc32fast::hash(b"hello");
+        let mut archive = Archive::new(Vec::new());
+        archive
+            .tokio_append_raw(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                EntryOptions::new().compressor(|| Doubler),
+                8,
+                5,
+                crc,
+                &mut Cursor::new(b"\x01\x05\x00\xfa\xffhello"), // Deflate stored block.
+            )
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+
+        assert_eq!(&data[4..6], &20u16.to_le_bytes()); // Version needed to extract.
+        assert_eq!(&data[8..10], &8u16.to_le_bytes()); // Compression method (deflate).
+        assert_eq!(&data[39..49], b"\x01\x05\x00\xfa\xffhello");
+        assert_eq!(&data[53..57], &crc.to_le_bytes()); // CRC32.
+        assert_eq!(&data[57..65], &[10, 0, 0, 0, 5, 0, 0, 0]); // Compressed and uncompressed sizes.
+        assert_eq!(&data[65 + 10..65 + 12], &8u16.to_le_bytes()); // Compression method (deflate).
+        assert_eq!(&data[65 + 16..65 + 20], &crc.to_le_bytes()); // CRC32.
+
+        let mut archive = Archive::new(Vec::new());
+        archive
+            .tokio_append_raw(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                EntryOptions::new(),
+                14,
+                0,
+                0,
+                // The properties header and the end of stream marker of an empty payload.
+                &mut Cursor::new(
+                    b"\x09\x14\x05\x00\x5d\x00\x00\x10\x00\x00\x83\xff\xfb\xff\xff\xc0\x00\x00\x00",
+                ),
+            )
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+        assert_eq!(&data[4..6], &63u16.to_le_bytes()); // Version needed to extract.
+        let flags = u16::from_le_bytes([data[6], data[7]]);
+        assert_eq!(flags & 0b110, 1 << 1); // End of stream marker present.
+        let central = data
+            .windows(4)
+            .position(|window| window == 0x02014b50u32.to_le_bytes())
+            .unwrap();
+        assert_eq!(
+            u16::from_le_bytes([data[central + 8], data[central + 9]]) & 0b110,
+            1 << 1
+        );
+    }
+
+    #[tokio::test]
+    async fn append_with_crc() {
+        let mut archive = Archive::new(Vec::new());
+        archive
+            .tokio_append_with_crc(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                EntryOptions::new(),
+                0x12345678,
+                &mut Cursor::new(b"hello"),
+            )
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+        assert_eq!(&data[48..52], &0x12345678u32.to_le_bytes()); // CRC32 (not computed).
+
+        let mut archive = Archive::with_options(Vec::new(), ArchiveOptions::new().verify_crc(true));
+        archive
+            .tokio_append_with_crc(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                EntryOptions::new(),
+                crc32fast::hash(b"hello"),
+                &mut Cursor::new(b"hello"),
+            )
+            .await
+            .unwrap();
+        let error = archive
+            .tokio_append_with_crc(
+                "file2.txt".to_owned(),
+                FileDateTime::Zero,
+                EntryOptions::new(),
+                0x12345678,
+                &mut Cursor::new(b"hello"),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.get_ref().unwrap().downcast_ref::<Error>(),
+            Some(Error::MetadataMismatch { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn zip_crypto() {
+        let mut data = *b"hello";
+        crate::crypto::ZipCrypto::new(b"secret").encrypt(&mut data);
+        assert_eq!(&data, b"\xa0%M{s");
+
+        let mut archive = Archive::new(Vec::new());
+        archive
+            .tokio_append_with_options(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                EntryOptions::new().password("secret"),
+                &mut Cursor::new(b"hello"),
+            )
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+
+        assert_eq!(&data[4..6], &20u16.to_le_bytes()); // Version needed to extract.
+        assert_eq!(&data[6..8], &0x0809u16.to_le_bytes()); // General purpose flag (encrypted).
+        assert_ne!(&data[51..56], b"hello");
+        assert_eq!(&data[56..60], &0x08074b50u32.to_le_bytes()); // Data descriptor signature.
+        assert_eq!(&data[64..72], &[17, 0, 0, 0, 5, 0, 0, 0]); // Compressed (with encryption header) and uncompressed sizes.
+        assert_eq!(&data[72 + 6..72 + 8], &20u16.to_le_bytes()); // Version needed to extract, as in the local header.
+        assert_eq!(&data[72 + 8..72 + 10], &0x0809u16.to_le_bytes()); // General purpose flag (encrypted).
+    }
+
+    #[tokio::test]
+    async fn archive_password() {
+        let mut archive =
+            Archive::with_options(Vec::new(), ArchiveOptions::new().password("archive"));
+        archive
+            .tokio_append_directory("dir/".to_owned(), FileDateTime::Zero)
+            .await
+            .unwrap();
+        archive
+            .tokio_append_with_options(
+                "dir/file1.txt".to_owned(),
+                FileDateTime::Zero,
+                EntryOptions::new().password("recipient"),
+                &mut Cursor::new(b"hello"),
+            )
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+
+        assert_eq!(&data[6..8], &0x0808u16.to_le_bytes()); // Directory not encrypted.
+        assert_eq!(&data[56..58], &0x0809u16.to_le_bytes()); // Entry encrypted with its own password.
+    }
+
+    // A "signer" returning the signed bytes, to check which ones are.
+    struct Collect(Vec<u8>);
+
+    impl Signer for Collect {
+        fn update(&mut self, data: &[u8]) {
+            self.0.extend_from_slice(data);
+        }
+
+        fn sign(&mut self) -> Vec<u8> {
+            self.0.clone()
+        }
+    }
+
+    #[tokio::test]
+    async fn signature() {
+        for embed in [false, true] {
+            let options = ArchiveOptions::new()
+                .signer(|| Collect(Vec::new()))
+                .embed_signature(embed);
+            let mut archive = Archive::with_options(Cursor::new(Vec::new()), options);
+            archive
+                .tokio_append_seekable(
+                    "file1.txt".to_owned(),
+                    FileDateTime::Zero,
+                    EntryOptions::new(),
+                    &mut Cursor::new(b"hello"),
+                )
+                .await
+                .unwrap();
+            let (data, signature) = archive.tokio_finalize_signed().await.unwrap();
+            let (data, signature) = (data.into_inner(), signature.unwrap());
+
+            assert_eq!(&data[6..8], &0x0808u16.to_le_bytes()); // Data descriptor used rather than patching the signed header.
+            if embed {
+                let end = data.len() - signature.len();
+                assert_eq!(&data[end - 2..end], &(signature.len() as u16).to_le_bytes()); // Comment length.
+                assert_eq!(&data[end..], &data[..end - 22]); // Comment.
+            } else {
+                assert_eq!(signature, data);
+            }
+        }
+
+        let archive = Archive::new(Vec::new());
+        let (_, signature) = archive.tokio_finalize_signed().await.unwrap();
+        assert_eq!(signature, None);
+    }
+
+    #[tokio::test]
+    async fn checksums() {
+        let mut archive = Archive::with_options(
+            Vec::new(),
+            ArchiveOptions::new().checksums("sha256sums.txt"),
+        );
+        archive
+            .tokio_append(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                &mut Cursor::new(b"hello"),
+            )
+            .await
+            .unwrap();
+        archive
+            .tokio_append_directory("dir".to_owned(), FileDateTime::Zero)
+            .await
+            .unwrap();
+        archive
+            .tokio_append(
+                "dir/file2.txt".to_owned(),
+                FileDateTime::Zero,
+                &mut Cursor::new(vec![b'a'; 1000]),
+            )
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+
+        let checksums = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824  file1.txt\n\
+                         41edece42d63e8d9bf515a9ba6932e1c20cbc9f5a5d134645adb5db1b9737ea3  dir/file2.txt\n";
+        let start = data
+            .windows(14)
+            .position(|window| window == b"sha256sums.txt")
+            .unwrap()
+            + 14;
+        assert_eq!(&data[start..start + checksums.len()], checksums.as_bytes());
+        assert_eq!(
+            crate::checksums(&[("a\\b".to_owned(), vec![0; 32])]),
+            format!("\\{}  a\\\\b\n", "0".repeat(64))
+        );
+    }
+
+    #[tokio::test]
+    async fn checksums_datetime() {
+        let datetime = |day| FileDateTime::Custom {
+            year: 2024,
+            month: 3,
+            day,
+            hour: 12,
+            minute: 30,
+            second: 0,
+        };
+        let mut plan = ArchivePlan::with_options(ArchiveOptions::new().checksums("SHA256SUMS"));
+        plan.add_file("old.txt".to_owned(), 6, datetime(1))
+            .crc(crc32fast::hash(b"hello\n"));
+        plan.add_file("new.txt".to_owned(), 6, datetime(9))
+            .crc(crc32fast::hash(b"world\n"));
+        plan.add_file("mid.txt".to_owned(), 6, datetime(5))
+            .crc(crc32fast::hash(b"again\n"));
+
+        let write = |plan: ArchivePlan| async move {
+            let mut archive = plan.into_archive(Vec::new());
+            archive
+                .tokio_append_all(|entry| {
+                    let payload = match entry.name() {
+                        "old.txt" => b"hello\n",
+                        "new.txt" => b"world\n",
+                        _ => b"again\n",
+                    };
+                    async move { Ok(Cursor::new(payload)) }
+                })
+                .await
+                .unwrap();
+            archive.tokio_finalize().await.unwrap()
+        };
+        let data = write(plan.clone()).await;
+        assert_eq!(write(plan.clone()).await, data);
+        assert!(plan.etag().is_some());
+
+        let entries = read_entries(&data).await;
+        assert_eq!(entries[3].0.name(), "SHA256SUMS");
+        assert_eq!(entries[3].0.datetime(), datetime(9));
+
+        let mut archive = Archive::with_options(
+            Vec::new(),
+            ArchiveOptions::new()
+                .checksums("SHA256SUMS")
+                .checksums_datetime(datetime(20)),
+        );
+        archive
+            .tokio_append(
+                "file.txt".to_owned(),
+                datetime(1),
+                &mut Cursor::new(b"hello\n"),
+            )
+            .await
+            .unwrap();
+        let entries = read_entries(&archive.tokio_finalize().await.unwrap()).await;
+        assert_eq!(entries[1].0.datetime(), datetime(20));
+
+        let archive =
+            Archive::with_options(Vec::new(), ArchiveOptions::new().checksums("SHA256SUMS"));
+        let entries = read_entries(&archive.tokio_finalize().await.unwrap()).await;
+        assert_eq!(entries[0].0.datetime(), FileDateTime::Zero);
+    }
+
+    #[tokio::test]
+    async fn archive_digest() {
+        for (data, digest) in [
+            (
+                &b""[..],
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            ),
+            (
+                b"hello",
+                "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824",
+            ),
+            (
+                &[b'a'; 1000],
+                "41edece42d63e8d9bf515a9ba6932e1c20cbc9f5a5d134645adb5db1b9737ea3",
+            ),
+        ] {
+            let mut hasher = Sha256::new();
+            for chunk in data.chunks(100) {
+                hasher.update(chunk);
+            }
+            let hex: String = hasher
+                .finalize()
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect();
+            assert_eq!(hex, digest);
+        }
+
+        let mut archive =
+            Archive::with_options(Vec::new(), ArchiveOptions::new().signer(Sha256::new));
+        archive
+            .tokio_append(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                &mut Cursor::new(b"hello"),
+            )
+            .await
+            .unwrap();
+        let (data, digest) = archive.tokio_finalize_signed().await.unwrap();
+
+        let mut hasher = Sha256::new();
+        hasher.update(&data);
+        assert_eq!(digest.unwrap(), hasher.finalize());
+    }
+
+    // Counts the bytes, standing for a custom hash function.
+    struct Count(u8);
+
+    impl Digest for Count {
+        fn update(&mut self, data: &[u8]) {
+            self.0 = self.0.wrapping_add(data.len() as u8);
+        }
+
+        fn finish(&mut self) -> Vec<u8> {
+            vec![self.0]
+        }
+    }
+
+    #[tokio::test]
+    async fn custom_digest() {
+        let options = ArchiveOptions::new()
+            .checksums_with("counts.txt", || Count(0))
+            .signer(|| Count(0));
+        let mut archive = Archive::with_options(Vec::new(), options);
+        archive
+            .tokio_append(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                &mut Cursor::new(b"hello"),
+            )
+            .await
+            .unwrap();
+        let (data, digest) = archive.tokio_finalize_signed().await.unwrap();
+
+        assert_eq!(&data[30 + 9 + 5 + 16 + 30 + 10..][..14], b"05  file1.txt\n"); // Checksums entry's payload.
+        assert_eq!(digest.unwrap(), [data.len() as u8]);
+    }
+
+    // Read every entry of an archive, with its payload.
+    async fn read_entries(data: &[u8]) -> Vec<(Entry, Vec<u8>)> {
+        let mut reader = ArchiveReader::new(data);
+        let mut entries = Vec::new();
+        while reader.tokio_next_entry().await.unwrap().is_some() {
+            let mut payload = Vec::new();
+            let mut buf = [0; 1000];
+            loop {
+                let read = reader.tokio_read(&mut buf).await.unwrap();
+                if read == 0 {
+                    break;
+                }
+                payload.extend_from_slice(&buf[..read]);
+            }
+            entries.push((reader.entry().unwrap().clone(), payload));
+        }
+        entries
+    }
+
+    #[tokio::test]
+    async fn read_archive() {
+        let big: Vec<u8> = (0..100_000u32).map(|i| (i * 7 % 251) as u8).collect();
+        let mut compressions = vec![Compression::Stored];
+        #[cfg(feature = "deflate-compression")]
+        compressions.push(Compression::Deflate);
+        for compression in compressions {
+            for mode in [
+                DataDescriptor::Always,
+                DataDescriptor::WhenNeeded,
+                DataDescriptor::Never,
+            ] {
+                for signature in [true, false] {
+                    let options = ArchiveOptions::new()
+                        .data_descriptor(mode)
+                        .descriptor_signature(signature);
+                    let mut archive = Archive::with_options(Vec::new(), options);
+                    for (name, payload) in [
+                        ("file1.txt", &b"hello"[..]),
+                        ("empty.txt", b""),
+                        ("big.bin", &big),
+                    ] {
+                        archive
+                            .tokio_append_with_options(
+                                name.to_owned(),
+                                FileDateTime::Zero,
+                                EntryOptions::new().compression(compression),
+                                &mut Cursor::new(payload),
+                            )
+                            .await
+                            .unwrap();
+                    }
+                    archive
+                        .tokio_append_directory("dir".to_owned(), FileDateTime::Zero)
+                        .await
+                        .unwrap();
+                    let data = archive.tokio_finalize().await.unwrap();
+
+                    let entries = read_entries(&data).await;
+                    assert_eq!(entries.len(), 4);
+                    for ((entry, payload), (name, expected)) in entries.iter().zip([
+                        ("file1.txt", &b"hello"[..]),
+                        ("empty.txt", b""),
+                        ("big.bin", &big),
+                        ("dir/", b""),
+                    ]) {
+                        assert_eq!(entry.name(), name);
+                        assert_eq!(payload, expected);
+                        assert_eq!(entry.size(), Some(expected.len() as u64));
+                        assert!(entry.crc().is_some());
+                    }
+                    assert!(entries[3].0.is_directory());
+
+                    // Skip the payloads.
+                    let mut reader = ArchiveReader::new(&data[..]);
+                    let mut names = Vec::new();
+                    while let Some(entry) = reader.tokio_next_entry().await.unwrap() {
+                        names.push(entry.name().to_owned());
+                    }
+                    assert_eq!(names, ["file1.txt", "empty.txt", "big.bin", "dir/"]);
+                }
+            }
+        }
+
+        let entries = read_entries(include_bytes!("zip_command_test_archive.zip")).await;
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].0.name(), "file2.txt");
+        assert_eq!(entries[1].1, b"world\n");
+        assert_eq!(
+            entries[1].0.datetime(),
+            FileDateTime::Custom {
+                year: 2021,
+                month: 11,
+                day: 13,
+                hour: 0,
+                minute: 11,
+                second: 58,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn read_corrupt_archive() {
+        let mut archive = Archive::new(Vec::new());
+        archive
+            .tokio_append(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                &mut Cursor::new(b"hello"),
+            )
+            .await
+            .unwrap();
+        let mut data = archive.tokio_finalize().await.unwrap();
+        data[39] = b'j'; // Payload.
+
+        let mut reader = ArchiveReader::new(&data[..]);
+        reader.tokio_next_entry().await.unwrap();
+        let error = reader.tokio_read(&mut [0; 100]).await.unwrap_err();
+        assert!(matches!(
+            error.get_ref().unwrap().downcast_ref::<Error>(),
+            Some(Error::CorruptEntry { .. })
+        ));
+
+        let mut reader = ArchiveReader::new(&data[..20]);
+        let error = reader.tokio_next_entry().await.unwrap_err();
+        assert!(matches!(
+            error.get_ref().unwrap().downcast_ref::<Error>(),
+            Some(Error::InvalidArchive { offset: 20 })
+        ));
+    }
+
+    #[tokio::test]
+    async fn central_directory() {
+        for mode in [Zip64Mode::Auto, Zip64Mode::Always] {
+            let mut archive = Archive::with_options(Vec::new(), ArchiveOptions::new().zip64(mode));
+            archive
+                .tokio_append_with_options(
+                    "file1.txt".to_owned(),
+                    FileDateTime::Zero,
+                    EntryOptions::new()
+                        .unix_permissions(0o755)
+                        .text(true)
+                        .comment("a comment"),
+                    &mut Cursor::new(b"hello"),
+                )
+                .await
+                .unwrap();
+            archive
+                .tokio_append_directory("dir".to_owned(), FileDateTime::Zero)
+                .await
+                .unwrap();
+            let data = archive.tokio_finalize().await.unwrap();
+
+            let directory = CentralDirectory::tokio_read(&mut Cursor::new(&data))
+                .await
+                .unwrap();
+            let entries = directory.entries();
+            assert_eq!(entries.len(), 2);
+            assert_eq!(entries[0].name(), "file1.txt");
+            assert_eq!(entries[0].size(), 5);
+            assert_eq!(entries[0].compressed_size(), 5);
+            assert_eq!(entries[0].crc(), 0x3610a686);
+            assert_eq!(entries[0].offset(), 0);
+            assert_eq!(entries[0].comment(), "a comment");
+            assert_eq!(entries[0].host_system(), HostSystem::Unix);
+            assert_eq!(entries[0].unix_mode(), Some(0o100755));
+            assert!(entries[0].is_text());
+            assert!(entries[1].is_directory());
+            assert!(entries[1].offset() > 0);
+            assert_eq!(entries[1].datetime(), FileDateTime::Zero);
+            assert_eq!(directory.offset() + directory.size(), {
+                let end = if mode == Zip64Mode::Always {
+                    56 + 20 + 22
+                } else {
+                    22
+                };
+                data.len() as u64 - end
+            });
+        }
+
+        // Followed by a comment.
+        let mut data = include_bytes!("zip_command_test_archive.zip").to_vec();
+        let len = data.len();
+        data[len - 2..].copy_from_slice(&7u16.to_le_bytes());
+        data.extend_from_slice(b"comment");
+        let directory = CentralDirectory::tokio_read(&mut Cursor::new(&data))
+            .await
+            .unwrap();
+        assert_eq!(directory.comment(), b"comment");
+        let names: Vec<_> = directory
+            .entries()
+            .iter()
+            .map(|entry| entry.name())
+            .collect();
+        assert_eq!(names, ["file1.txt", "file2.txt"]);
+
+        // Not an archive.
+        let error = CentralDirectory::tokio_read(&mut Cursor::new(b"not an archive"))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.get_ref().unwrap().downcast_ref::<Error>(),
+            Some(Error::InvalidArchive { offset: 14 })
+        ));
+    }
+
+    #[tokio::test]
+    async fn open_existing() {
+        let mut archive = Archive::new(Cursor::new(Vec::new()));
+        archive
+            .tokio_append_with_options(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                EntryOptions::new().comment("a comment"),
+                &mut Cursor::new(b"hello"),
+            )
+            .await
+            .unwrap();
+        let sink = archive.tokio_finalize().await.unwrap();
+
+        let options = ArchiveOptions::new().host_system(HostSystem::MsDos);
+        let mut archive = Archive::tokio_open_existing(sink, options).await.unwrap();
+        archive
+            .tokio_append(
+                "file2.txt".to_owned(),
+                FileDateTime::Zero,
+                &mut Cursor::new(b"world"),
+            )
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap().into_inner();
+
+        let directory = CentralDirectory::tokio_read(&mut Cursor::new(&data))
+            .await
+            .unwrap();
+        let entries = directory.entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].comment(), "a comment");
+        assert_eq!(entries[0].host_system(), HostSystem::Unix);
+        assert_eq!(entries[1].host_system(), HostSystem::MsDos);
+        let entries = read_entries(&data).await;
+        assert_eq!(entries[0].1, b"hello");
+        assert_eq!(entries[1].0.name(), "file2.txt");
+        assert_eq!(entries[1].1, b"world");
+
+        // Written by another tool.
+        let sink = Cursor::new(include_bytes!("zip_command_test_archive.zip").to_vec());
+        let archive = Archive::tokio_open_existing(sink, ArchiveOptions::new())
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap().into_inner();
+        assert_eq!(data, include_bytes!("zip_command_test_archive.zip"));
+
+        // Never shortened, keeping the Zip64 records and the comment.
+        let mut archive = Archive::with_options(
+            Cursor::new(Vec::new()),
+            ArchiveOptions::new().zip64(Zip64Mode::Always),
+        );
+        archive
+            .tokio_append(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                &mut Cursor::new(b"hello"),
+            )
+            .await
+            .unwrap();
+        let mut existing = archive.tokio_finalize().await.unwrap().into_inner();
+        let len = existing.len();
+        existing[len - 2..].copy_from_slice(&7u16.to_le_bytes());
+        existing.extend_from_slice(b"comment");
+        let archive =
+            Archive::tokio_open_existing(Cursor::new(existing.clone()), ArchiveOptions::new())
+                .await
+                .unwrap();
+        let data = archive.tokio_finalize().await.unwrap().into_inner();
+        assert_eq!(data, existing);
+    }
+
+    #[tokio::test]
+    async fn list() {
+        let mut archive = Archive::new(Vec::new());
+        archive
+            .tokio_append(
+                "file1.txt".to_owned(),
+                FileDateTime::Custom {
+                    year: 2021,
+                    month: 11,
+                    day: 13,
+                    hour: 0,
+                    minute: 11,
+                    second: 58,
+                },
+                &mut Cursor::new(b"hello"),
+            )
+            .await
+            .unwrap();
+        archive
+            .tokio_append_directory("dir".to_owned(), FileDateTime::Zero)
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+
+        let entries = ArchiveReader::new(&data[..]).tokio_list().await.unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].crc(), 0x3610a686);
+        assert_eq!(entries[0].size(), 5);
+        assert!(entries[1].is_directory());
+        assert_eq!(
+            entries[0].to_string(),
+            "       5  Stored        5   0% 2021-11-13 00:11 3610a686  file1.txt"
+        );
+        assert_eq!(
+            entries[1].to_string(),
+            "       0  Stored        0   0% 1980-00-00 00:00 00000000  dir/"
+        );
+    }
+
+    #[tokio::test]
+    async fn verify() {
+        for mode in [DataDescriptor::Never, DataDescriptor::Always] {
+            let mut archive =
+                Archive::with_options(Vec::new(), ArchiveOptions::new().data_descriptor(mode));
+            for name in ["file1.txt", "file2.txt"] {
+                archive
+                    .tokio_append(
+                        name.to_owned(),
+                        FileDateTime::Zero,
+                        &mut Cursor::new(b"hello"),
+                    )
+                    .await
+                    .unwrap();
+            }
+            let data = archive.tokio_finalize().await.unwrap();
+            let report = ArchiveReader::new(&data[..]).tokio_verify().await.unwrap();
+            assert!(report.is_valid());
+            assert_eq!(report.entries().len(), 2);
+
+            // A corrupted payload.
+            let mut corrupt = data.clone();
+            corrupt[39] = b'j';
+            let report = ArchiveReader::new(&corrupt[..])
+                .tokio_verify()
+                .await
+                .unwrap();
+            assert!(!report.is_valid());
+            let statuses: Vec<_> = report
+                .entries()
+                .iter()
+                .map(|entry| entry.status())
+                .collect();
+            assert_eq!(statuses, [EntryStatus::Corrupt, EntryStatus::Valid]);
+
+            // A central directory record differing from the local header.
+            let mut mismatch = data.clone();
+            let record = mismatch
+                .windows(4)
+                .position(|window| window == b"PK\x01\x02")
+                .unwrap();
+            mismatch[record + 16] ^= 1; // CRC32 of the first record.
+            let report = ArchiveReader::new(&mismatch[..])
+                .tokio_verify()
+                .await
+                .unwrap();
+            let statuses: Vec<_> = report
+                .entries()
+                .iter()
+                .map(|entry| entry.status())
+                .collect();
+            assert_eq!(statuses, [EntryStatus::Mismatch, EntryStatus::Valid]);
+            assert!(report.missing().is_empty());
+        }
+    }
+
+    #[test]
+    fn glob() {
+        for (pattern, name, expected) in [
+            ("*.txt", "file.txt", true),
+            ("*.txt", "dir/file.txt", false),
+            ("**/*.txt", "file.txt", true),
+            ("**/*.txt", "a/b/file.txt", true),
+            ("dir/**", "dir/a/b", true),
+            ("dir/**", "other/a", false),
+            ("file?.txt", "file1.txt", true),
+            ("file?.txt", "file10.txt", false),
+            ("file[0-9].txt", "file7.txt", true),
+            ("file[!0-9].txt", "file7.txt", false),
+            ("file[!0-9].txt", "filea.txt", true),
+            ("[]]", "]", true),
+            ("[a", "[a", true),
+            ("\\*", "*", true),
+            ("\\*", "a", false),
+            ("é*", "été", true),
+        ] {
+            assert_eq!(
+                Glob::new(pattern).matches(name),
+                expected,
+                "{pattern} {name}"
+            );
+        }
+    }
+
+    #[cfg(feature = "deflate-compression")]
+    #[tokio::test]
+    async fn copy_zip() {
+        let payload = "hello world ".repeat(1000);
+        for source_mode in [DataDescriptor::Never, DataDescriptor::Always] {
+            for mode in [DataDescriptor::Never, DataDescriptor::Always] {
+                let mut archive = Archive::with_options(
+                    Vec::new(),
+                    ArchiveOptions::new().data_descriptor(source_mode),
+                );
+                archive
+                    .tokio_append_directory("dir".to_owned(), FileDateTime::Zero)
+                    .await
+                    .unwrap();
+                for (name, compression) in [
+                    ("dir/stored.txt", Compression::Stored),
+                    ("dir/deflated.txt", Compression::Deflate),
+                    ("skipped.txt", Compression::Deflate),
+                ] {
+                    archive
+                        .tokio_append_with_options(
+                            name.to_owned(),
+                            FileDateTime::Zero,
+                            EntryOptions::new().compression(compression),
+                            &mut Cursor::new(&payload),
+                        )
+                        .await
+                        .unwrap();
+                }
+                let data = archive.tokio_finalize().await.unwrap();
+
+                let datetime = FileDateTime::Custom {
+                    year: 2024,
+                    month: 5,
+                    day: 17,
+                    hour: 12,
+                    minute: 30,
+                    second: 0,
+                };
+                let mut copy =
+                    Archive::with_options(Vec::new(), ArchiveOptions::new().data_descriptor(mode));
+                copy.tokio_copy_zip(&mut ArchiveReader::new(&data[..]), |entry| {
+                    let name = entry.name().strip_prefix("dir/")?;
+                    Some((format!("copy/{name}"), datetime))
+                })
+                .await
+                .unwrap();
+                let copy = copy.tokio_finalize().await.unwrap();
+
+                let mut reader = ArchiveReader::new(&copy[..]);
+                let mut entries = Vec::new();
+                while let Some(entry) = reader.tokio_next_entry().await.unwrap() {
+                    assert_eq!(entry.datetime().tuple(), datetime.tuple());
+                    entries.push((entry.name().to_owned(), entry.method()));
+                    if entry.is_directory() {
+                        continue;
+                    }
+                    let mut read = Vec::new();
+                    let mut buf = vec![0; 4096];
+                    loop {
+                        let n = reader.tokio_read(&mut buf).await.unwrap();
+                        if n == 0 {
+                            break;
+                        }
+                        read.extend_from_slice(&buf[..n]);
+                    }
+                    assert_eq!(read, payload.as_bytes());
+                }
+                assert_eq!(
+                    entries,
+                    [
+                        ("copy/".to_owned(), 0),
+                        ("copy/stored.txt".to_owned(), 0),
+                        ("copy/deflated.txt".to_owned(), 8),
+                    ]
+                );
+                assert!(ArchiveReader::new(&copy[..])
+                    .tokio_verify()
+                    .await
+                    .unwrap()
+                    .is_valid());
+            }
+        }
+    }
+
+    #[cfg(feature = "lzma-compression")]
+    #[tokio::test]
+    async fn copy_zip_lzma() {
+        // The end of an LZMA payload can't be found without its compressed size in the local header.
+        let options = ArchiveOptions::new().data_descriptor(DataDescriptor::Never);
+        let mut archive = Archive::with_options(Vec::new(), options);
+        archive
+            .tokio_append_with_options(
+                "file.txt".to_owned(),
+                FileDateTime::Zero,
+                EntryOptions::new().compression(Compression::Lzma),
+                &mut Cursor::new("hello world ".repeat(1000)),
+            )
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+
+        let flags =
+            |data: &[u8], offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+        let central = |data: &[u8]| {
+            data.windows(4)
+                .position(|window| window == 0x02014b50u32.to_le_bytes())
+                .unwrap()
+        };
+        assert_eq!(flags(&data, 6) & 0b110, 1 << 1);
+        // The same entry, claiming its payload doesn't end with an end of stream marker.
+        let mut unmarked = data.clone();
+        let offset = central(&unmarked) + 8;
+        unmarked[6] &= !0b10;
+        unmarked[offset] &= !0b10;
+
+        for source in [data, unmarked] {
+            let mut copy = Archive::new(Vec::new());
+            copy.tokio_copy_zip(&mut ArchiveReader::new(&source[..]), |entry| {
+                Some((entry.name().to_owned(), entry.datetime()))
+            })
+            .await
+            .unwrap();
+            let copy = copy.tokio_finalize().await.unwrap();
+
+            // The flags describing the payload are kept, in the local and central headers.
+            assert_eq!(flags(&copy, 8), 14); // Compression method (LZMA).
+            assert_eq!(flags(&copy, 6) & 0b110, flags(&source, 6) & 0b110);
+            assert_eq!(
+                flags(&copy, central(&copy) + 8) & 0b110,
+                flags(&source, central(&source) + 8) & 0b110
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn append_archive() {
+        let mut parts = Vec::new();
+        for names in [["a.txt", "b.txt"], ["c.txt", "d.txt"]] {
+            let mut archive = Archive::new(Vec::new());
+            for name in names {
+                archive
+                    .tokio_append(name.to_owned(), FileDateTime::Zero, &mut Cursor::new(name))
+                    .await
+                    .unwrap();
+            }
+            parts.push(archive.tokio_finalize().await.unwrap());
+        }
+
+        let mut archive = Archive::new(Vec::new());
+        for part in &parts {
+            archive.tokio_append_archive(&mut &part[..]).await.unwrap();
+        }
+        let data = archive.tokio_finalize().await.unwrap();
+
+        let mut reader = ArchiveReader::new(&data[..]);
+        let mut names = Vec::new();
+        while let Some(entry) = reader.tokio_next_entry().await.unwrap() {
+            let mut payload = vec![0; 100];
+            let read = reader.tokio_read(&mut payload).await.unwrap();
+            assert_eq!(&payload[..read], entry.name().as_bytes());
+            names.push(entry.name().to_owned());
+        }
+        assert_eq!(names, ["a.txt", "b.txt", "c.txt", "d.txt"]);
+
+        // The offsets of the central directory point to the copied local headers.
+        let report = ArchiveReader::new(&data[..]).tokio_verify().await.unwrap();
+        assert!(report.is_valid());
+        assert!(report.missing().is_empty());
+    }
+
+    #[tokio::test]
+    async fn deduplicate() {
+        let license = "Permission is hereby granted, free of charge. ".repeat(20);
+        let mut sizes = Vec::new();
+        for dedup_size in [0, 4096] {
+            let mut archive =
+                Archive::with_options(Vec::new(), ArchiveOptions::new().deduplicate(dedup_size));
+            for (name, payload) in [
+                ("a/LICENSE", license.as_str()),
+                ("b/LICENSE", license.as_str()),
+                ("README", "readme"),
+                ("c/LICENSE", license.as_str()),
+            ] {
+                archive
+                    .tokio_append(
+                        name.to_owned(),
+                        FileDateTime::Zero,
+                        &mut Cursor::new(payload),
+                    )
+                    .await
+                    .unwrap();
+            }
+            let data = archive.tokio_finalize().await.unwrap();
+            sizes.push(data.len());
+
+            let central_directory = CentralDirectory::tokio_read(&mut Cursor::new(&data))
+                .await
+                .unwrap();
+            let entries = central_directory.entries();
+            assert_eq!(entries.len(), 4);
+            assert_eq!(entries[3].name(), "c/LICENSE");
+            assert_eq!(entries[3].size(), license.len() as u64);
+            let shared = entries[1].offset() == entries[0].offset();
+            assert_eq!(shared, dedup_size > 0);
+            assert_eq!(entries[3].offset() == entries[0].offset(), shared);
+        }
+        assert_eq!(
+            sizes[0] - sizes[1],
+            2 * (30 + "a/LICENSE".len() + license.len() + 16)
+        );
+    }
+
+    #[tokio::test]
+    async fn next_entry_matching() {
+        let mut archive = Archive::new(Vec::new());
+        for name in ["a.txt", "b.bin", "dir/c.txt"] {
+            archive
+                .tokio_append(name.to_owned(), FileDateTime::Zero, &mut Cursor::new(name))
+                .await
+                .unwrap();
+        }
+        let data = archive.tokio_finalize().await.unwrap();
+
+        let glob = Glob::new("**/*.txt");
+        let mut reader = ArchiveReader::new(&data[..]);
+        let mut names = Vec::new();
+        while let Some(entry) = reader
+            .tokio_next_entry_matching(|entry| glob.matches(entry.name()))
+            .await
+            .unwrap()
+        {
+            let mut payload = vec![0; 100];
+            let read = reader.tokio_read(&mut payload).await.unwrap();
+            assert_eq!(&payload[..read], entry.name().as_bytes());
+            names.push(entry.name().to_owned());
+        }
+        assert_eq!(names, ["a.txt", "dir/c.txt"]);
+    }
+
+    #[cfg(feature = "deflate-compression")]
+    #[test]
+    fn inflate() {
+        // Fixed Huffman codes, with back-references.
+        let mut inflater = crate::compression::inflate::Inflater::new();
+        let input = b"\xcbH\xcd\xc9\xc9W\xc8@'\x01trailing";
+        assert_eq!(inflater.inflate(input).unwrap(), 10);
+        assert!(inflater.is_done());
+        assert_eq!(inflater.output(), b"hello hello hello hello");
+
+        // A stored block, fed byte by byte.
+        let mut inflater = crate::compression::inflate::Inflater::new();
+        let input = b"\x01\x05\x00\xfa\xffhello";
+        let mut pos = 0;
+        for end in 1..=input.len() {
+            pos += inflater.inflate(&input[pos..end]).unwrap();
+        }
+        assert_eq!(pos, input.len());
+        assert_eq!(inflater.output(), b"hello");
+    }
+
+    #[cfg(any(
+        feature = "bzip2-compression",
+        feature = "lzma-compression",
+        feature = "deflate-compression"
+    ))]
+    fn compress(compression: Compression, input: &[u8]) -> Vec<u8> {
+        let mut compressor = compression.compressor().unwrap();
+        let mut output = Vec::new();
+        for chunk in input.chunks(10_000) {
+            compressor.compress(chunk, &mut output);
+        }
+        compressor.finish(&mut output);
+        output
+    }
+
+    // Incompressible bytes, from a xorshift generator.
+    #[cfg(any(feature = "bzip2-compression", feature = "deflate-compression"))]
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x2545f4914f6cdd1du64;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state >> 56) as u8
+            })
+            .collect()
+    }
+
+    #[cfg(any(
+        feature = "bzip2-compression",
+        feature = "lzma-compression",
+        feature = "deflate-compression"
+    ))]
+    fn hex(data: &[u8]) -> String {
+        data.iter().map(|byte| format!("{byte:02x}")).collect()
+    }
+
+    #[cfg(feature = "bzip2-compression")]
+    #[test]
+    fn bzip2() {
+        // Known vectors, checked with the reference decoder.
+        assert_eq!(
+            hex(&compress(Compression::Bzip2, b"")),
+            "425a683917724538509000000000"
+        );
+        assert_eq!(
+            hex(&compress(Compression::Bzip2, b"hello hello hello hello\n")),
+            "425a68393141592653596f4f10f3000005d100001040000244a00030c002a81a438dade20f1772453850906f4f10f3"
+        );
+
+        // The magics aren't byte aligned after the first block, count them at every bit position.
+        fn count_magic(data: &[u8], magic: u64) -> usize {
+            let mut window = 0u64;
+            (0..data.len() * 8)
+                .filter(|&i| {
+                    window =
+                        (window << 1 | (data[i / 8] >> (7 - i % 8)) as u64 & 1) & ((1 << 48) - 1);
+                    i >= 47 && window == magic
+                })
+                .count()
+        }
+        let data = compress(Compression::Bzip2, &noise(1_000_000));
+        assert_eq!(&data[..4], b"BZh9");
+        assert_eq!(&data[4..10], b"\x31\x41\x59\x26\x53\x59");
+        assert_eq!(count_magic(&data, 0x314159265359), 2); // One per 900k block.
+        assert_eq!(count_magic(&data, 0x177245385090), 1); // End of stream.
+    }
+
+    #[cfg(feature = "lzma-compression")]
+    #[test]
+    fn lzma() {
+        // Known vectors, checked with the reference decoder (the stream of an empty payload being only the end of stream marker).
+        assert_eq!(
+            hex(&compress(Compression::Lzma, b"")),
+            "091405005d000010000083fffbffffc0000000"
+        );
+        assert_eq!(
+            hex(&compress(Compression::Lzma, b"hello hello hello hello\n")),
+            "091405005d0000100000341949db855c67de761ec92ffffffba10000"
+        );
+
+        // The version and the size of the properties, then lc = 3, lp = 0, pb = 2 and a 1 MiB dictionary.
+        let data = compress(Compression::Lzma, &[7; 100_000]);
+        assert_eq!(&data[..4], [9, 20, 5, 0]);
+        assert_eq!(&data[4..9], [0x5d, 0x00, 0x00, 0x10, 0x00]);
+        assert_eq!(data[9], 0); // The first byte of the range coder.
+        assert!(data.len() < 200);
+        // The end of stream marker is announced in the general purpose flags.
+        assert_eq!(Compression::Lzma.compressor().unwrap().flags(), 1 << 1);
+    }
+
+    // Decompress a whole stream, reading the output as it goes to keep only the window.
+    #[cfg(feature = "deflate-compression")]
+    fn inflate_all(mut inflater: crate::compression::inflate::Inflater, data: &[u8]) -> Vec<u8> {
+        let mut output = Vec::new();
+        let mut pos = 0;
+        while !inflater.is_done() || !inflater.output().is_empty() {
+            let consumed = inflater.inflate(&data[pos..]).unwrap();
+            let read = inflater.output().len();
+            assert!(consumed != 0 || read != 0, "truncated stream");
+            output.extend_from_slice(inflater.output());
+            inflater.consume(read);
+            pos += consumed;
+        }
+        assert_eq!(pos, data.len());
+        output
+    }
+
+    #[cfg(feature = "deflate-compression")]
+    #[test]
+    fn deflate() {
+        use crate::compression::inflate::Inflater;
+
+        // Known vectors, checked with the reference decoder.
+        assert_eq!(hex(&compress(Compression::Deflate, b"")), "0300");
+        assert_eq!(
+            hex(&compress(
+                Compression::Deflate,
+                b"hello hello hello hello\n"
+            )),
+            "cb48cdc9c957c020b900"
+        );
+        assert_eq!(
+            hex(&compress(Compression::Deflate, &[b'a'; 1000])),
+            "4b1c05a360140c7b0000"
+        );
+
+        let repetitive = b"abcabcabd".repeat(50_000);
+        let random = noise(100_000);
+        for input in [&b""[..], b"a", &repetitive, &random] {
+            let data = compress(Compression::Deflate, input);
+            assert_eq!(inflate_all(Inflater::new(), &data), input);
+        }
+        assert!(compress(Compression::Deflate, &repetitive).len() < 1_000);
+        // Incompressible blocks are stored, beyond the 32 KiB window.
+        assert!(compress(Compression::Deflate, &random).len() < random.len() + 100);
+    }
+
+    #[cfg(all(feature = "deflate-compression", feature = "deflate64-compression"))]
+    #[test]
+    fn deflate64() {
+        use crate::compression::inflate::Inflater;
+
+        // Known vector, checked with Info-ZIP's decoder: a match longer than 258 bytes, using the 16 extra bits of the last length code.
+        assert_eq!(
+            hex(&compress(Compression::Deflate64, &[b'a'; 1000])),
+            "4b1c251f0000"
+        );
+
+        let repetitive = b"abcabcabd".repeat(50_000);
+        let random = noise(100_000);
+        for input in [&b""[..], b"a", &repetitive, &random] {
+            let data = compress(Compression::Deflate64, input);
+            assert_eq!(inflate_all(Inflater::deflate64(), &data), input);
+        }
+
+        // Repeated 40 KiB, only matched with distances beyond the 32 KiB window of Deflate.
+        let far = noise(40_000).repeat(2);
+        let data = compress(Compression::Deflate64, &far);
+        assert_eq!(inflate_all(Inflater::deflate64(), &data), far);
+        assert!(data.len() < 41_000);
+        assert!(compress(Compression::Deflate, &far).len() > 80_000);
+    }
+
+    #[tokio::test]
+    async fn entry_comment() {
+        let mut archive = Archive::new(Vec::new());
+        archive
+            .tokio_append_with_options(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                EntryOptions::new().comment("sha256: e3b0c442"),
+                &mut Cursor::new(Vec::new()),
+            )
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+
+        assert_eq!(&data[55 + 32..55 + 34], &16u16.to_le_bytes()); // File comment length.
+        assert_eq!(&data[55 + 55..55 + 71], b"sha256: e3b0c442"); // File comment.
+        assert_eq!(&data[data.len() - 10..data.len() - 6], &71u32.to_le_bytes());
+        // Size of central directory.
+    }
+
+    #[cfg(feature = "stream")]
+    #[tokio::test]
+    async fn archive_stream() {
+        use crate::{ArchiveStream, EntrySource};
+        use futures_core::Stream;
+        use std::future::poll_fn;
+        use std::pin::Pin;
+
+        let large = vec![7; 200 * 1024];
+        let mut archive = Archive::new(Vec::new());
+        archive
+            .tokio_append(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                &mut Cursor::new(b"hello\n"),
+            )
+            .await
+            .unwrap();
+        archive
+            .tokio_append_directory("dir/".to_owned(), FileDateTime::Zero)
+            .await
+            .unwrap();
+        archive
+            .tokio_append(
+                "dir/file2.txt".to_owned(),
+                FileDateTime::Zero,
+                &mut Cursor::new(&large),
+            )
+            .await
+            .unwrap();
+        let expected = archive.tokio_finalize().await.unwrap();
+
+        let mut stream = ArchiveStream::new([
+            EntrySource::file(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                Cursor::new(b"hello\n"),
+            ),
+            EntrySource::directory("dir/".to_owned(), FileDateTime::Zero),
+            EntrySource::lazy("dir/file2.txt".to_owned(), FileDateTime::Zero, || async {
+                Ok(Cursor::new(large))
+            }),
+        ]);
+        let mut data = Vec::new();
+        while let Some(chunk) = poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await {
+            let chunk = chunk.unwrap();
+            assert!(chunk.len() <= 64 * 1024);
+            data.extend_from_slice(&chunk);
+        }
+        assert_eq!(data, expected);
+
+        // The stream ends after the error of a source.
+        let mut stream = ArchiveStream::new([EntrySource::lazy(
+            "file1.txt".to_owned(),
+            FileDateTime::Zero,
+            || async { Err::<Cursor<Vec<u8>>, _>(std::io::Error::other("unavailable")) },
+        )]);
+        let error = poll_fn(|cx| Pin::new(&mut stream).poll_next(cx))
+            .await
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(error.to_string(), "unavailable");
+        assert!(poll_fn(|cx| Pin::new(&mut stream).poll_next(cx))
+            .await
+            .is_none());
+    }
+
+    #[cfg(feature = "tower")]
+    #[tokio::test]
+    async fn zip_service() {
+        use crate::{EntrySource, ZipService};
+        use futures_core::Stream;
+        use http::header::{CONTENT_LENGTH, CONTENT_TYPE, TRAILER};
+        use http::Request;
+        use http_body::Body;
+        use std::future::poll_fn;
+        use std::pin::Pin;
+        use tower_service::Service;
+
+        let mut service = ZipService::new(|request: Request<()>| {
+            let name = request.uri().path().trim_start_matches('/').to_owned();
+            async move {
+                Ok(vec![
+                    EntrySource::file(name, FileDateTime::Zero, Cursor::new(b"hello\n")).size(6),
+                    EntrySource::directory("dir/".to_owned(), FileDateTime::Zero),
+                ])
+            }
+        });
+        let size = crate::archive_size([("file1.txt", 6), ("dir/", 0)]);
+        let request = Request::builder().uri("/file1.txt").body(()).unwrap();
+        let mut response = service.call(request).await.unwrap();
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/zip");
+        assert_eq!(response.headers()[CONTENT_LENGTH], size.to_string());
+        assert_eq!(Body::size_hint(response.body()).exact(), Some(size as u64));
+
+        let mut data = Vec::new();
+        while let Some(chunk) = poll_fn(|cx| Pin::new(response.body_mut()).poll_next(cx)).await {
+            data.extend_from_slice(&chunk.unwrap());
+        }
+        assert_eq!(data.len(), size);
+        assert_eq!(&data[30..39], b"file1.txt");
+        let trailers = poll_fn(|cx| Pin::new(response.body_mut()).poll_trailers(cx)).await;
+        assert_eq!(trailers.unwrap(), None);
+
+        // With the digest of the archive and the CRC32 of its entries as trailers.
+        let mut service = service.trailers(true);
+        let request = Request::builder().uri("/file1.txt").body(()).unwrap();
+        let mut response = service.call(request).await.unwrap();
+        assert_eq!(response.headers()[TRAILER], "content-digest, x-zip-crc32");
+        let mut hasher = Sha256::new();
+        while let Some(chunk) = poll_fn(|cx| Pin::new(response.body_mut()).poll_data(cx)).await {
+            hasher.update(&chunk.unwrap());
+        }
+        let digest = hasher.finalize();
+        assert_eq!(response.body().digest(), Some(digest));
+        assert_eq!(response.body().entry_crcs(), Some(vec![0x363a3020, 0]));
+        let trailers = poll_fn(|cx| Pin::new(response.body_mut()).poll_trailers(cx))
+            .await
+            .unwrap()
+            .unwrap();
+        let encoded = trailers["content-digest"].to_str().unwrap();
+        assert!(encoded.starts_with("sha-256=:") && encoded.ends_with("=:"));
+        assert_eq!(encoded.len(), "sha-256=:".len() + 44 + 1);
+        assert_eq!(trailers["x-zip-crc32"], "363a3020,00000000");
+
+        // A payload shorter than declared.
+        let mut service = ZipService::new(|_: Request<()>| async {
+            Ok(vec![EntrySource::file(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                Cursor::new(b"hello\n"),
+            )
+            .size(7)])
+        });
+        let mut response = service.call(Request::new(())).await.unwrap();
+        let mut error = None;
+        while let Some(chunk) = poll_fn(|cx| Pin::new(response.body_mut()).poll_data(cx)).await {
+            error = chunk.err();
+        }
+        let error = error.unwrap();
+        assert!(matches!(
+            error.get_ref().unwrap().downcast_ref::<Error>(),
+            Some(Error::SizeMismatch { expected: 7, .. }),
+        ));
+    }
+
+    #[cfg(feature = "http-body1")]
+    #[tokio::test]
+    async fn zip_body() {
+        use crate::{ArchiveStream, EntrySource, ZipBody};
+        use http_body1::Body;
+        use http_body_util::BodyExt;
+
+        let sources = || {
+            [
+                EntrySource::file(
+                    "file1.txt".to_owned(),
+                    FileDateTime::Zero,
+                    Cursor::new(b"hello\n"),
+                )
+                .size(6),
+                EntrySource::directory("dir/".to_owned(), FileDateTime::Zero),
+            ]
+        };
+        let size = crate::archive_size([("file1.txt", 6), ("dir/", 0)]);
+        let mut body = ZipBody::new(ArchiveStream::new(sources()));
+        assert_eq!(body.size_hint().exact(), Some(size as u64));
+        let mut data = Vec::new();
+        while let Some(frame) = body.frame().await {
+            data.extend_from_slice(&frame.unwrap().into_data().unwrap());
+        }
+        assert!(body.is_end_stream());
+        assert_eq!(data.len(), size);
+        assert_eq!(&data[30..39], b"file1.txt");
+
+        // With the digest of the archive and the CRC32 of its entries as trailers.
+        let body = ZipBody::from(ArchiveStream::new(sources()).trailers(true));
+        let collected = body.collect().await.unwrap();
+        let trailers = collected.trailers().unwrap();
+        assert!(trailers["content-digest"]
+            .to_str()
+            .unwrap()
+            .starts_with("sha-256=:"));
+        assert_eq!(trailers["x-zip-crc32"], "363a3020,00000000");
+        assert_eq!(collected.to_bytes(), data);
+
+        // A payload shorter than declared ends the body with an error.
+        let mut body = ZipBody::new(ArchiveStream::new([EntrySource::file(
+            "file1.txt".to_owned(),
+            FileDateTime::Zero,
+            Cursor::new(b"hello\n"),
+        )
+        .size(7)]));
+        let mut error = None;
+        while let Some(frame) = body.frame().await {
+            error = frame.err();
+        }
+        assert!(matches!(
+            error.unwrap().get_ref().unwrap().downcast_ref::<Error>(),
+            Some(Error::SizeMismatch { expected: 7, .. }),
+        ));
+    }
+
+    #[cfg(feature = "axum")]
+    #[tokio::test]
+    async fn zip_response_axum() {
+        use crate::{ArchiveStream, EntrySource, ZipResponse};
+        use axum::http::header::{CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_TYPE};
+        use axum::response::IntoResponse;
+        use http_body_util::BodyExt;
+
+        let source = || {
+            EntrySource::file(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                Cursor::new(b"hello\n"),
+            )
+        };
+        let size = crate::archive_size([("file1.txt", 6)]);
+        let response = ZipResponse::new([source().size(6)])
+            .filename("files.zip")
+            .into_response();
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/zip");
+        assert_eq!(
+            response.headers()[CONTENT_DISPOSITION],
+            "attachment; filename=\"files.zip\"",
+        );
+        assert_eq!(response.headers()[CONTENT_LENGTH], size.to_string());
+        let data = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(data.len(), size);
+
+        // Without a declared size, and with a non-ASCII name.
+        let response = ZipResponse::from(ArchiveStream::new([source()]))
+            .filename("fichiers \"été\".zip")
+            .into_response();
+        assert_eq!(
+            response.headers()[CONTENT_DISPOSITION],
+            "attachment; filename=\"fichiers __t__.zip\"; filename*=UTF-8''fichiers%20%22%C3%A9t%C3%A9%22.zip",
+        );
+        assert!(!response.headers().contains_key(CONTENT_LENGTH));
+        let data = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(data.len(), size);
+
+        // An error aborts the body.
+        let response = ZipResponse::new([source().size(7)]).into_response();
+        assert!(response.into_body().collect().await.is_err());
+    }
+
+    #[cfg(feature = "actix")]
+    #[tokio::test]
+    async fn zip_response_actix() {
+        use crate::{EntrySource, ZipResponse};
+        use actix_web::body::{to_bytes, BodySize, MessageBody};
+        use actix_web::http::header::{CONTENT_DISPOSITION, CONTENT_TYPE};
+        use actix_web::test::TestRequest;
+        use actix_web::Responder;
+
+        let source = || {
+            EntrySource::file(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                Cursor::new(b"hello\n"),
+            )
+        };
+        let size = crate::archive_size([("file1.txt", 6)]);
+        let request = TestRequest::default().to_http_request();
+        let response = ZipResponse::new([source().size(6)])
+            .filename("files.zip")
+            .respond_to(&request);
+        assert_eq!(
+            response.headers().get(CONTENT_TYPE).unwrap(),
+            "application/zip"
+        );
+        assert_eq!(
+            response.headers().get(CONTENT_DISPOSITION).unwrap(),
+            "attachment; filename=\"files.zip\"",
+        );
+        assert_eq!(response.body().size(), BodySize::Sized(size as u64));
+        let data = to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(data.len(), size);
+
+        // Without a declared size, the body is streamed.
+        let response = ZipResponse::new([source()]).respond_to(&request);
+        assert_eq!(response.body().size(), BodySize::Stream);
+        let data = to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(data.len(), size);
+
+        // An error aborts the body.
+        let response = ZipResponse::new([source().size(7)]).respond_to(&request);
+        assert!(to_bytes(response.into_body()).await.is_err());
+    }
+
+    #[cfg(feature = "warp")]
+    #[tokio::test]
+    async fn zip_response_warp() {
+        use crate::{EntrySource, ZipResponse};
+        use warp::http::header::{CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_TYPE};
+        use warp::hyper::body::to_bytes;
+        use warp::Reply;
+
+        let source = || {
+            EntrySource::file(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                Cursor::new(b"hello\n"),
+            )
+        };
+        let size = crate::archive_size([("file1.txt", 6)]);
+        let response = ZipResponse::new([source().size(6)])
+            .filename("files.zip")
+            .into_response();
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/zip");
+        assert_eq!(
+            response.headers()[CONTENT_DISPOSITION],
+            "attachment; filename=\"files.zip\"",
+        );
+        assert_eq!(response.headers()[CONTENT_LENGTH], size.to_string());
+        let data = to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(data.len(), size);
+
+        // Without a declared size.
+        let response = ZipResponse::new([source()]).into_response();
+        assert!(!response.headers().contains_key(CONTENT_LENGTH));
+
+        // An error aborts the body.
+        let response = ZipResponse::new([source().size(7)]).into_response();
+        assert!(to_bytes(response.into_body()).await.is_err());
+    }
+
+    #[cfg(feature = "rocket")]
+    #[tokio::test]
+    async fn zip_response_rocket() {
+        use crate::{EntrySource, ZipResponse};
+        use rocket::local::asynchronous::Client;
+        use rocket::response::Responder;
+
+        let source = || {
+            EntrySource::file(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                Cursor::new(b"hello\n"),
+            )
+        };
+        let size = crate::archive_size([("file1.txt", 6)]);
+        let client = Client::untracked(rocket::build()).await.unwrap();
+        let request = client.get("/download");
+        let mut response = ZipResponse::new([source().size(6)])
+            .filename("files.zip")
+            .respond_to(request.inner())
+            .unwrap();
+        let headers = response.headers();
+        assert_eq!(headers.get_one("Content-Type"), Some("application/zip"));
+        assert_eq!(
+            headers.get_one("Content-Disposition"),
+            Some("attachment; filename=\"files.zip\""),
+        );
+        assert_eq!(
+            headers.get_one("Content-Length"),
+            Some(size.to_string().as_str()),
+        );
+        let data = response.body_mut().to_bytes().await.unwrap();
+        assert_eq!(data.len(), size);
+
+        // Without a declared size.
+        let response = ZipResponse::new([source()])
+            .respond_to(request.inner())
+            .unwrap();
+        assert!(!response.headers().contains("Content-Length"));
+
+        // An error aborts the body.
+        let mut response = ZipResponse::new([source().size(7)])
+            .respond_to(request.inner())
+            .unwrap();
+        assert!(response.body_mut().to_bytes().await.is_err());
+    }
+
+    #[cfg(feature = "reqwest")]
+    #[tokio::test]
+    async fn append_url() {
+        use hyper::service::{make_service_fn, service_fn};
+        use hyper::{Body, Response, Server, StatusCode};
+        use std::convert::Infallible;
+        use std::io::ErrorKind;
+
+        let service = make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(|request| async move {
+                let response = match request.uri().path() {
+                    "/hello.txt" => Response::builder()
+                        .header("Last-Modified", "Sun, 03 Mar 2024 12:30:00 GMT")
+                        .body(Body::from("hello\n")),
+                    "/moved" => Response::builder()
+                        .status(StatusCode::FOUND)
+                        .header("Location", "/hello.txt")
+                        .body(Body::empty()),
+                    "/chunked" => Response::builder().body(Body::wrap_stream(
+                        tokio_util::io::ReaderStream::new(Cursor::new(b"world\n")),
+                    )),
+                    _ => Response::builder()
+                        .status(StatusCode::NOT_FOUND)
+                        .body(Body::empty()),
+                };
+                Ok::<_, Infallible>(response.unwrap())
+            }))
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(service);
+        let address = server.local_addr();
+        tokio::spawn(server);
+
+        let mut archive = Archive::new(Vec::new());
+        for (name, path) in [
+            ("file1.txt", "/hello.txt"),
+            ("file2.txt", "/moved"),
+            ("file3.txt", "/chunked"),
+        ] {
+            archive
+                .tokio_append_url(
+                    name.to_owned(),
+                    FileDateTime::Zero,
+                    format!("http://{address}{path}"),
+                )
+                .await
+                .unwrap();
+        }
+        let error = archive
+            .tokio_append_url(
+                "file4.txt".to_owned(),
+                FileDateTime::Zero,
+                format!("http://{address}/missing"),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+        let data = archive.tokio_finalize().await.unwrap();
+
+        let entries = read_entries(&data).await;
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].1, b"hello\n");
+        assert_eq!(
+            entries[0].0.datetime(),
+            FileDateTime::from_system_time(UNIX_EPOCH + Duration::from_secs(1_709_469_000)),
+        );
+        assert_eq!(entries[1].1, b"hello\n");
+        assert_eq!(entries[2].1, b"world\n");
+        assert_eq!(entries[2].0.datetime(), FileDateTime::Zero);
+    }
+
+    #[cfg(feature = "serde")]
+    #[tokio::test]
+    async fn append_json() {
+        use std::collections::HashMap;
+        use std::io::ErrorKind;
+
+        let records: Vec<_> = (0..20_000)
+            .map(|i| serde_json::json!({ "id": i, "name": format!("file{i}.txt") }))
+            .collect();
+        let mut archive = Archive::new(Vec::new());
+        archive
+            .tokio_append_json(
+                "records.json".to_owned(),
+                FileDateTime::Zero,
+                records.clone(),
+            )
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+
+        let entries = read_entries(&data).await;
+        assert_eq!(entries[0].0.name(), "records.json");
+        // Larger than a chunk.
+        assert!(entries[0].1.len() > 64 * 1024);
+        assert_eq!(entries[0].1, serde_json::to_vec(&records).unwrap());
+
+        // Maps with non-string keys can't be serialized.
+        let error = Archive::new(Vec::new())
+            .tokio_append_json(
+                "invalid.json".to_owned(),
+                FileDateTime::Zero,
+                HashMap::from([((1, 2), 3)]),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[cfg(feature = "multer")]
+    #[tokio::test]
+    async fn append_multipart() {
+        use multer::Multipart;
+        use std::io::ErrorKind;
+
+        let body = "--boundary\r\n\
+            Content-Disposition: form-data; name=\"files\"; filename=\"../file1.txt\"\r\n\r\n\
+            hello\n\r\n\
+            --boundary\r\n\
+            Content-Disposition: form-data; name=\"notes.txt\"\r\n\r\n\
+            world\n\r\n\
+            --boundary\r\n\
+            Content-Disposition: form-data; name=\"..\"\r\n\r\n\
+            skipped\r\n\
+            --boundary--\r\n";
+        let multipart = |body: &'static str| {
+            let chunks = body
+                .as_bytes()
+                .chunks(7)
+                .map(|chunk| Ok::<_, std::io::Error>(bytes::Bytes::from_static(chunk)));
+            Multipart::new(futures_util::stream::iter(chunks), "boundary")
+        };
+
+        let mut archive = Archive::new(Vec::new());
+        let names = archive
+            .tokio_append_multipart(multipart(body), FileDateTime::Zero)
+            .await
+            .unwrap();
+        assert_eq!(names, ["file1.txt", "notes.txt"]);
+        let entries = read_entries(&archive.tokio_finalize().await.unwrap()).await;
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].0.name(), "file1.txt");
+        assert_eq!(entries[0].1, b"hello\n");
+        assert_eq!(entries[1].0.name(), "notes.txt");
+        assert_eq!(entries[1].1, b"world\n");
+
+        let error = Archive::new(Vec::new())
+            .tokio_append_multipart(multipart("not multipart"), FileDateTime::Zero)
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn mod_zip_manifest() {
+        assert_eq!(crate::mod_zip_manifest([]), "");
+        assert_eq!(
+            crate::mod_zip_manifest([(Some(0xab), 1, "/a b/ç?.txt", "dir/ç.txt")]),
+            "000000ab 1 /a%20b/%C3%A7%3F.txt dir/ç.txt\n",
+        );
+
+        let mut plan = ArchivePlan::new();
+        plan.add_file("file1.txt".to_owned(), 6, FileDateTime::Zero)
+            .crc(0x363a3020);
+        plan.add_directory("dir".to_owned(), FileDateTime::Zero);
+        plan.add_file("dir/file2.txt".to_owned(), 12, FileDateTime::Zero);
+        assert_eq!(
+            plan.mod_zip_manifest(|entry| format!("/storage/{}", entry.name())),
+            "363a3020 6 /storage/file1.txt file1.txt\n- 12 /storage/dir/file2.txt dir/file2.txt\n",
+        );
+    }
+
+    #[tokio::test]
+    async fn append_source() {
+        use crate::archive_size_of_sources;
+
+        let sources = [
+            MemorySource("file1.txt", b"hello\n", true, false),
+            MemorySource("dir/", b"", false, false),
+            MemorySource("dir/file2.txt", b"world\n", true, false),
+        ];
+        let size = archive_size_of_sources(&sources, &ArchiveOptions::new());
+        let mut archive = Archive::new(Vec::new());
+        for source in sources {
+            archive.tokio_append_source(source).await.unwrap();
+        }
+        let data = archive.tokio_finalize().await.unwrap();
+        assert_eq!(Some(data.len() as u64), size);
+        assert_eq!(entry_names(&data), ["file1.txt", "dir/", "dir/file2.txt"]);
+
+        let sources = [MemorySource("file1.txt", b"hello\n", false, false)];
+        assert_eq!(
+            archive_size_of_sources(&sources, &ArchiveOptions::new()),
+            None
+        );
+        let mut archive = Archive::new(Vec::new());
+        archive
+            .tokio_append_source(MemorySource("file1.txt", b"hello\n", false, false))
+            .await
+            .unwrap();
+        assert_eq!(
+            entry_names(&archive.tokio_finalize().await.unwrap()),
+            ["file1.txt"]
+        );
+    }
+
+    #[tokio::test]
+    async fn append_all() {
+        use crate::SourcePolicy;
+
+        let sources = || {
+            [
+                MemorySource("file1.txt", b"hello\n", true, false),
+                MemorySource("missing.txt", b"", false, true),
+                MemorySource("dir/", b"", false, false),
+                MemorySource("dir/file2.txt", b"world\n", false, false),
+            ]
+        };
+        let mut archive = Archive::new(Vec::new());
+        let summary = archive
+            .tokio_append_all(sources(), SourcePolicy::Skip)
+            .await
+            .unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+        assert_eq!(entry_names(&data), ["file1.txt", "dir/", "dir/file2.txt"]);
+        let summary: Vec<_> = summary
+            .iter()
+            .map(|(name, result)| {
+                (
+                    name.as_str(),
+                    result.as_ref().map(|_| ()).map_err(|error| error.kind()),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("file1.txt", Ok(())),
+                ("missing.txt", Err(std::io::ErrorKind::NotFound)),
+                ("dir/", Ok(())),
+                ("dir/file2.txt", Ok(())),
+            ],
+        );
+
+        let mut archive = Archive::new(Vec::new());
+        let error = archive
+            .tokio_append_all(sources(), SourcePolicy::Abort)
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+        assert_eq!(
+            entry_names(&archive.tokio_finalize().await.unwrap()),
+            ["file1.txt"]
+        );
+
+        #[cfg(feature = "stream")]
+        {
+            let mut archive = Archive::new(Vec::new());
+            let summary = archive
+                .tokio_append_all_stream(futures_util::stream::iter(sources()), SourcePolicy::Skip)
+                .await
+                .unwrap();
+            assert_eq!(summary.len(), 4);
+            assert_eq!(archive.tokio_finalize().await.unwrap(), data);
+        }
+    }
+
+    #[cfg(feature = "tokio-rt")]
+    #[tokio::test]
+    async fn zip_pipe() {
+        use tokio::io::AsyncReadExt;
+
+        let (mut reader, handle) = crate::zip_pipe(16, |mut archive| async move {
+            archive
+                .tokio_append(
+                    "file1.txt".to_owned(),
+                    FileDateTime::Zero,
+                    &mut Cursor::new(b"hello\n"),
+                )
+                .await?;
+            archive.tokio_finalize().await?;
+            Ok(())
+        });
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).await.unwrap();
+        handle.await.unwrap().unwrap();
+        assert_eq!(data.len(), crate::archive_size([("file1.txt", 6)]));
+
+        // The reader dropped before the end of the archive.
+        let (reader, handle) = crate::zip_pipe(16, |mut archive| async move {
+            archive
+                .tokio_append(
+                    "file1.txt".to_owned(),
+                    FileDateTime::Zero,
+                    &mut Cursor::new(vec![0; 1024]),
+                )
+                .await?;
+            archive.tokio_finalize().await?;
+            Ok(())
+        });
+        drop(reader);
+        let error = handle.await.unwrap().unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::BrokenPipe);
+    }
+
+    #[cfg(feature = "stream")]
+    #[tokio::test]
+    async fn append_stream() {
+        use bytes::Bytes;
+        use futures_util::stream;
+
+        let mut expected = Archive::new(Vec::new());
+        expected
+            .tokio_append(
                 "file1.txt".to_owned(),
-                FileDateTime::now(),
-                &mut Cursor::new(b"hello\n".to_vec()),
+                FileDateTime::Zero,
+                &mut Cursor::new(b"hello world\n"),
             )
             .await
             .unwrap();
+        let mut archive = Archive::new(Vec::new());
+        let chunks =
+            ["hello", "", " world\n"].map(|chunk| Ok::<_, std::io::Error>(Bytes::from(chunk)));
         archive
-            .tokio_append(
+            .tokio_append_stream(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                stream::iter(chunks),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            archive.tokio_finalize().await.unwrap(),
+            expected.tokio_finalize().await.unwrap(),
+        );
+
+        // An I/O error kept as is, any other error wrapped.
+        let chunks = [
+            Ok(Bytes::from("hello")),
+            Err(std::io::Error::from(std::io::ErrorKind::TimedOut)),
+        ];
+        let error = Archive::new(Vec::new())
+            .tokio_append_stream(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                stream::iter(chunks),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
+        let chunks = [Err::<Bytes, _>("connection reset")];
+        let error = Archive::new(Vec::new())
+            .tokio_append_stream(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                stream::iter(chunks),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "connection reset");
+    }
+
+    #[cfg(feature = "body")]
+    #[tokio::test]
+    async fn append_body() {
+        use futures_util::stream;
+
+        let mut archive = Archive::new(Vec::new());
+        let chunks = ["hello", " world\n"].map(Ok::<_, std::io::Error>);
+        archive
+            .tokio_append_body(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                hyper::Body::wrap_stream(stream::iter(chunks)),
+            )
+            .await
+            .unwrap();
+        archive
+            .tokio_append_body(
                 "file2.txt".to_owned(),
-                FileDateTime::now(),
-                &mut Cursor::new(b"world\n".to_vec()),
+                FileDateTime::Zero,
+                hyper::Body::empty(),
             )
             .await
             .unwrap();
         let data = archive.tokio_finalize().await.unwrap();
 
-        fn match_except_datetime(a1: &[u8], a2: &[u8]) -> bool {
-            let datetime_ranges = [
-                10..12,
-                12..14,
-                71..73,
-                73..75,
-                134..136,
-                136..138,
-                189..191,
-                191..193,
-            ];
-            let size_ranges = [18..22, 22..26, 79..83, 83..87];
-            a1.len() == a2.len()
-                && a1
-                    .into_iter()
-                    .zip(a2)
-                    .enumerate()
-                    .filter(|(i, _)| {
-                        datetime_ranges
+        let mut expected = Archive::new(Vec::new());
+        for (name, payload) in [("file1.txt", &b"hello world\n"[..]), ("file2.txt", b"")] {
+            expected
+                .tokio_append(
+                    name.to_owned(),
+                    FileDateTime::Zero,
+                    &mut Cursor::new(payload),
+                )
+                .await
+                .unwrap();
+        }
+        assert_eq!(data, expected.tokio_finalize().await.unwrap());
+
+        let chunks = [
+            Ok("hello"),
+            Err(std::io::Error::from(std::io::ErrorKind::ConnectionReset)),
+        ];
+        let error = Archive::new(Vec::new())
+            .tokio_append_body(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                hyper::Body::wrap_stream(stream::iter(chunks)),
+            )
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("connection reset"));
+    }
+
+    #[cfg(feature = "stream")]
+    #[tokio::test]
+    async fn append_from_channel() {
+        use bytes::Bytes;
+
+        let (sender, receiver) = tokio::sync::mpsc::channel(1);
+        let producer = tokio::spawn(async move {
+            for chunk in ["hello", " ", "world\n"] {
+                sender.send(Bytes::from(chunk)).await.unwrap();
+            }
+        });
+        let mut archive = Archive::new(Vec::new());
+        archive
+            .tokio_append_from_channel("file1.txt".to_owned(), FileDateTime::Zero, receiver)
+            .await
+            .unwrap();
+        producer.await.unwrap();
+        let data = archive.tokio_finalize().await.unwrap();
+
+        let mut expected = Archive::new(Vec::new());
+        expected
+            .tokio_append(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                &mut Cursor::new(b"hello world\n"),
+            )
+            .await
+            .unwrap();
+        assert_eq!(data, expected.tokio_finalize().await.unwrap());
+    }
+
+    #[cfg(feature = "stream")]
+    #[tokio::test]
+    async fn archive_plan_stream_from() {
+        use crate::ArchiveStream;
+        use futures_core::Stream;
+        use std::future::poll_fn;
+        use std::pin::Pin;
+        use std::sync::{Arc, Mutex};
+
+        async fn collect(mut stream: ArchiveStream) -> Result<Vec<u8>, std::io::Error> {
+            let mut data = Vec::new();
+            while let Some(chunk) = poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await {
+                data.extend_from_slice(&chunk?);
+            }
+            Ok(data)
+        }
+
+        let payloads = [&b"hello\n"[..], b"world\n", &[7; 5000]];
+        for options in [
+            ArchiveOptions::new(),
+            ArchiveOptions::new().data_descriptor(DataDescriptor::WhenNeeded),
+        ] {
+            let mut plan = ArchivePlan::with_options(options);
+            plan.add_file("file1.txt".to_owned(), 6, FileDateTime::Zero)
+                .crc(crc32fast::hash(payloads[0]));
+            plan.add_directory("dir".to_owned(), FileDateTime::Zero);
+            plan.add_file("dir/file2.txt".to_owned(), 6, FileDateTime::Zero);
+            plan.add_file("file3.bin".to_owned(), 5000, FileDateTime::Zero)
+                .crc(crc32fast::hash(payloads[2]));
+
+            let mut archive = plan.clone().into_archive(Vec::new());
+            archive
+                .tokio_append_all(|entry| {
+                    let index = ["file1.txt", "dir/file2.txt", "file3.bin"]
+                        .iter()
+                        .position(|name| *name == entry.name())
+                        .unwrap();
+                    async move { Ok(Cursor::new(payloads[index])) }
+                })
+                .await
+                .unwrap();
+            let expected = archive.tokio_finalize().await.unwrap();
+            assert_eq!(expected.len() as u64, plan.size());
+
+            for offset in 0..=expected.len() as u64 {
+                let opened = Arc::new(Mutex::new(Vec::new()));
+                let stream = plan.clone().stream_from(offset, {
+                    let opened = Arc::clone(&opened);
+                    move |entry, start| {
+                        opened.lock().unwrap().push(entry.name().to_owned());
+                        let index = ["file1.txt", "dir/file2.txt", "file3.bin"]
                             .iter()
-                            .chain(&size_ranges)
-                            .all(|range| !range.contains(i))
-                    })
-                    .all(|(_, (b1, b2))| b1 == b2)
+                            .position(|name| *name == entry.name())
+                            .unwrap();
+                        async move { Ok(Cursor::new(&payloads[index][start as usize..])) }
+                    }
+                });
+                assert_eq!(stream.size(), Some(plan.size() - offset));
+                assert_eq!(collect(stream).await.unwrap(), &expected[offset as usize..]);
+
+                // The file without a CRC32 is always read, the ones before the offset aren't.
+                let opened = opened.lock().unwrap();
+                assert!(opened.contains(&"dir/file2.txt".to_owned()));
+                if offset >= 100 {
+                    assert!(!opened.contains(&"file1.txt".to_owned()));
+                }
+                if offset as usize >= expected.len() - 200 {
+                    assert!(!opened.contains(&"file3.bin".to_owned()));
+                }
+            }
         }
-        assert!(match_except_datetime(
-            &data,
-            include_bytes!("timeless_test_archive.zip")
+
+        // Past the end of the archive, or encrypted.
+        let mut plan = ArchivePlan::with_options(ArchiveOptions::new().password("secret"));
+        plan.add_file("file1.txt".to_owned(), 6, FileDateTime::Zero);
+        for offset in [1, plan.size() + 1] {
+            let stream = plan
+                .clone()
+                .stream_from(offset, |_, _| async { Ok(Cursor::new(b"hello\n")) });
+            let error = collect(stream).await.unwrap_err();
+            assert!(matches!(
+                error.get_ref().unwrap().downcast_ref::<Error>(),
+                Some(Error::NotResumable { offset: o }) if *o == offset,
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn index() {
+        let mut archive = Archive::new(Cursor::new(Vec::new()));
+        archive
+            .tokio_append(
+                "file1.txt".to_owned(),
+                FileDateTime::Zero,
+                &mut Cursor::new(b"hello\n"),
+            )
+            .await
+            .unwrap();
+        archive
+            .tokio_append_directory("dir/".to_owned(), FileDateTime::Zero)
+            .await
+            .unwrap();
+        archive
+            .tokio_append_with_options(
+                "dir/\"quoted\".txt".to_owned(),
+                FileDateTime::Zero,
+                EntryOptions::new().large_file(true),
+                &mut Cursor::new(b"world!\n"),
+            )
+            .await
+            .unwrap();
+        let index = archive.index();
+        let data = archive.tokio_finalize().await.unwrap();
+
+        let data = data.into_inner();
+        for (entry, payload) in index
+            .entries()
+            .iter()
+            .zip([&b"hello\n"[..], b"", b"world!\n"])
+        {
+            let offset = entry.header_offset() as usize;
+            assert_eq!(&data[offset..offset + 4], &0x04034b50u32.to_le_bytes());
+            let range = entry.range().unwrap();
+            assert_eq!(&data[range.start as usize..range.end as usize], payload);
+            assert_eq!(entry.crc(), crc32fast::hash(payload));
+        }
+        assert_eq!(index.get("dir/").unwrap().header_offset(), 61);
+        assert_eq!(
+            index.to_json(),
+            concat!(
+                r#"[{"name":"file1.txt","header_offset":0,"data_offset":39,"compressed_size":6,"size":6,"crc":909783072,"method":0},"#,
+                r#"{"name":"dir/","header_offset":61,"data_offset":95,"compressed_size":0,"size":0,"crc":0,"method":0},"#,
+                r#"{"name":"dir/\"quoted\".txt","header_offset":111,"data_offset":177,"compressed_size":7,"size":7,"crc":2625103937,"method":0}]"#,
+            ),
+        );
+
+        // The payloads of the existing entries aren't located.
+        let archive = Archive::tokio_open_existing(Cursor::new(data), ArchiveOptions::new())
+            .await
+            .unwrap();
+        let index = archive.index();
+        assert_eq!(index.entries().len(), 3);
+        assert_eq!(index.get("file1.txt").unwrap().data_offset(), None);
+        assert_eq!(index.get("file1.txt").unwrap().header_offset(), 0);
+    }
+
+    #[tokio::test]
+    async fn finalize_with_directory() {
+        use crate::DetachedDirectory;
+
+        async fn generate(options: ArchiveOptions, files: &[&str]) -> Archive<Vec<u8>> {
+            let mut archive = Archive::with_options(Vec::new(), options);
+            for name in files {
+                archive
+                    .tokio_append(
+                        name.to_string(),
+                        FileDateTime::Zero,
+                        &mut Cursor::new(name.as_bytes()),
+                    )
+                    .await
+                    .unwrap();
+            }
+            archive
+        }
+
+        for options in [
+            ArchiveOptions::new(),
+            ArchiveOptions::new().zip64(Zip64Mode::Always),
+            ArchiveOptions::new()
+                .signer(Sha256::new)
+                .embed_signature(true),
+        ] {
+            let files = ["file1.txt", "dir/file2.txt"];
+            let expected = generate(options.clone(), &files)
+                .await
+                .tokio_finalize()
+                .await
+                .unwrap();
+            let (data, directory) = generate(options.clone(), &files)
+                .await
+                .tokio_finalize_detached()
+                .await
+                .unwrap();
+            assert_eq!(data, expected);
+            assert_eq!(directory.entry_count(), 2);
+            assert_eq!(directory.as_bytes(), &data[directory.offset() as usize..]);
+            let directory = DetachedDirectory::from_bytes(directory.into_bytes()).unwrap();
+            assert_eq!(directory.entry_count(), 2);
+            assert_eq!(directory.as_bytes(), &data[directory.offset() as usize..]);
+
+            let data = generate(options.clone(), &files)
+                .await
+                .tokio_finalize_with_directory(&directory)
+                .await
+                .unwrap();
+            assert_eq!(data, expected);
+
+            // Another archive.
+            let error = generate(options, &files[..1])
+                .await
+                .tokio_finalize_with_directory(&directory)
+                .await
+                .unwrap_err();
+            assert!(matches!(
+                error.get_ref().unwrap().downcast_ref::<Error>(),
+                Some(Error::DirectoryMismatch { entry_count: 1, .. }),
+            ));
+        }
+
+        // The embedded signature of another archive with the same layout.
+        let options = ArchiveOptions::new()
+            .signer(Sha256::new)
+            .embed_signature(true);
+        let (_, directory) = generate(options.clone(), &["file1.txt"])
+            .await
+            .tokio_finalize_detached()
+            .await
+            .unwrap();
+        let error = generate(options, &["file2.txt"])
+            .await
+            .tokio_finalize_with_directory(&directory)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.get_ref().unwrap().downcast_ref::<Error>(),
+            Some(Error::DirectoryMismatch { .. }),
         ));
-        assert!(match_except_datetime(
-            &data,
-            include_bytes!("zip_command_test_archive.zip")
+
+        assert!(DetachedDirectory::from_bytes(b"PK\x05\x06".to_vec()).is_err());
+    }
+
+    #[derive(Default)]
+    struct UploadLog {
+        parts: Vec<Vec<u8>>,
+        completed: bool,
+        aborted: bool,
+    }
+
+    // Records the parts in a shared log, failing the `failing`th part if any.
+    struct MemoryUpload(std::sync::Arc<std::sync::Mutex<UploadLog>>, Option<usize>);
+
+    impl crate::ChunkedUpload for MemoryUpload {
+        async fn put_part(&mut self, part: Vec<u8>) -> Result<(), std::io::Error> {
+            let mut log = self.0.lock().unwrap();
+            if self.1 == Some(log.parts.len()) {
+                return Err(std::io::Error::other("part rejected"));
+            }
+            log.parts.push(part);
+            Ok(())
+        }
+
+        async fn complete(&mut self) -> Result<(), std::io::Error> {
+            self.0.lock().unwrap().completed = true;
+            Ok(())
+        }
+
+        async fn abort(&mut self) -> Result<(), std::io::Error> {
+            self.0.lock().unwrap().aborted = true;
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "gcs")]
+    #[tokio::test]
+    async fn gcs_upload() {
+        use crate::{ChunkedUpload, ChunkedWriter, GcsUpload};
+        use hyper::service::{make_service_fn, service_fn};
+        use hyper::{Body, Request, Response, Server};
+        use std::convert::Infallible;
+        use std::sync::{Arc, Mutex};
+        use tokio::io::AsyncWriteExt;
+
+        // The object being uploaded, the Content-Range of the chunks received, and the chunk to fail after 100 bytes.
+        #[derive(Default)]
+        struct Session {
+            object: Vec<u8>,
+            ranges: Vec<String>,
+            fail: Option<usize>,
+            completed: bool,
+        }
+
+        async fn handle(
+            session: Arc<Mutex<Session>>,
+            address: String,
+            request: Request<Body>,
+        ) -> Response<Body> {
+            assert_eq!(request.headers()["authorization"], "Bearer token");
+            let response = Response::builder();
+            if request.method() == "POST" {
+                assert_eq!(
+                    request.uri().to_string(),
+                    "/upload/storage/v1/b/bucket/o?uploadType=resumable&name=exports%2Ffiles.zip",
+                );
+                let location = format!("http://{address}/session");
+                return response
+                    .header("Location", location)
+                    .body(Body::empty())
+                    .unwrap();
+            }
+            if request.method() == "DELETE" {
+                return response.status(499).body(Body::empty()).unwrap();
+            }
+            let range = request.headers()["content-range"]
+                .to_str()
+                .unwrap()
+                .to_owned();
+            let data = hyper::body::to_bytes(request.into_body()).await.unwrap();
+            let mut session = session.lock().unwrap();
+            if range != "bytes */*" {
+                let (start, total) = range["bytes ".len()..].split_once('/').unwrap();
+                if start != "*" {
+                    let start = start.split_once('-').unwrap().0.parse::<usize>().unwrap();
+                    assert_eq!(start, session.object.len());
+                }
+                session.ranges.push(range.clone());
+                if session.fail == Some(session.ranges.len()) {
+                    session.object.extend_from_slice(&data[..100]);
+                    return response.status(503).body(Body::empty()).unwrap();
+                }
+                session.object.extend_from_slice(&data);
+                if total != "*" {
+                    assert_eq!(total.parse::<usize>().unwrap(), session.object.len());
+                    session.completed = true;
+                    return response.body(Body::empty()).unwrap();
+                }
+            }
+            let response = response.status(308);
+            match session.object.len() {
+                0 => response,
+                len => response.header("Range", format!("bytes=0-{}", len - 1)),
+            }
+            .body(Body::empty())
+            .unwrap()
+        }
+
+        let session = Arc::new(Mutex::new(Session {
+            fail: Some(2),
+            ..Session::default()
+        }));
+        let state = Arc::clone(&session);
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service_fn(
+            move |_: &hyper::server::conn::AddrStream| {
+                let state = Arc::clone(&state);
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |request| {
+                        let state = Arc::clone(&state);
+                        async move {
+                            let address = request.headers()["host"].to_str().unwrap().to_owned();
+                            Ok::<_, Infallible>(handle(state, address, request).await)
+                        }
+                    }))
+                }
+            },
+        ));
+        let endpoint = format!("http://{}", server.local_addr());
+        tokio::spawn(server);
+
+        let payload = (0..700_000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let mut expected = Archive::new(Vec::new());
+        expected
+            .tokio_append(
+                "file.bin".to_owned(),
+                FileDateTime::Zero,
+                &mut payload.as_slice(),
+            )
+            .await
+            .unwrap();
+        let expected = expected.tokio_finalize().await.unwrap();
+
+        let client = reqwest::Client::new();
+        let upload = GcsUpload::create_with_endpoint(
+            &client,
+            &endpoint,
+            "bucket",
+            "exports/files.zip",
+            "token",
+        )
+        .await
+        .unwrap();
+        assert_eq!(upload.session(), format!("{endpoint}/session"));
+        // Parts of 100 KB, sent as chunks of 256 KiB.
+        let mut archive = Archive::new(ChunkedWriter::with_part_size(upload, 100_000));
+        archive
+            .tokio_append(
+                "file.bin".to_owned(),
+                FileDateTime::Zero,
+                &mut payload.as_slice(),
+            )
+            .await
+            .unwrap();
+        archive
+            .tokio_finalize()
+            .await
+            .unwrap()
+            .shutdown()
+            .await
+            .unwrap();
+
+        {
+            let mut session = session.lock().unwrap();
+            assert!(session.completed);
+            assert_eq!(session.object, expected);
+            let total = expected.len();
+            assert_eq!(
+                session.ranges,
+                [
+                    "bytes 0-262143/*".to_owned(),
+                    "bytes 262144-524287/*".to_owned(),
+                    // Resumed after the failure, from the 100 bytes persisted.
+                    "bytes 262244-524287/*".to_owned(),
+                    format!("bytes 524288-{}/{total}", total - 1),
+                ],
+            );
+            *session = Session::default();
+        }
+
+        // Resumed from the persisted offset, then aborted.
+        let mut upload = GcsUpload::resume(&client, format!("{endpoint}/session"), "token")
+            .await
+            .unwrap();
+        assert_eq!(upload.offset(), 0);
+        upload.put_part(vec![0; 300_000]).await.unwrap();
+        let upload = GcsUpload::resume(&client, upload.session().to_owned(), "token")
+            .await
+            .unwrap();
+        assert_eq!(upload.offset(), 262_144);
+        ChunkedWriter::new(upload).abort().await.unwrap();
+    }
+
+    #[cfg(feature = "azure")]
+    #[tokio::test]
+    async fn azure_upload() {
+        use crate::{AzureUpload, ChunkedWriter};
+        use hyper::service::{make_service_fn, service_fn};
+        use hyper::{Body, Request, Response, Server};
+        use std::collections::HashMap;
+        use std::convert::Infallible;
+        use std::sync::{Arc, Mutex};
+        use tokio::io::AsyncWriteExt;
+
+        // The staged blocks, by ID, and the committed blob.
+        #[derive(Default)]
+        struct Container {
+            blocks: HashMap<String, Vec<u8>>,
+            blob: Option<(String, Vec<u8>)>,
+        }
+
+        async fn handle(
+            container: Arc<Mutex<Container>>,
+            request: Request<Body>,
+        ) -> Response<Body> {
+            assert_eq!(request.method(), "PUT");
+            assert_eq!(request.uri().path(), "/container/files.zip");
+            assert_eq!(request.headers()["x-ms-version"], "2021-08-06");
+            let query = request.uri().query().unwrap().to_owned();
+            let content_type = request.headers().get("x-ms-blob-content-type").cloned();
+            let data = hyper::body::to_bytes(request.into_body()).await.unwrap();
+            let mut container = container.lock().unwrap();
+            if let Some(id) = query.strip_prefix("sig=secret&comp=block&blockid=") {
+                container.blocks.insert(id.to_owned(), data.to_vec());
+            } else {
+                assert_eq!(query, "sig=secret&comp=blocklist");
+                let list = String::from_utf8(data.to_vec()).unwrap();
+                let blob = list
+                    .split("<Latest>")
+                    .skip(1)
+                    .flat_map(|id| {
+                        // The IDs are percent-encoded in the query strings.
+                        let id = id.split_once('<').unwrap().0.replace('=', "%3D");
+                        container.blocks[&id].clone()
+                    })
+                    .collect();
+                let content_type = content_type.unwrap().to_str().unwrap().to_owned();
+                container.blob = Some((content_type, blob));
+            }
+            Response::builder().status(201).body(Body::empty()).unwrap()
+        }
+
+        let container = Arc::new(Mutex::new(Container::default()));
+        let state = Arc::clone(&container);
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service_fn(
+            move |_: &hyper::server::conn::AddrStream| {
+                let state = Arc::clone(&state);
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |request| {
+                        let state = Arc::clone(&state);
+                        async move { Ok::<_, Infallible>(handle(state, request).await) }
+                    }))
+                }
+            },
         ));
+        let url = format!(
+            "http://{}/container/files.zip?sig=secret",
+            server.local_addr()
+        );
+        tokio::spawn(server);
+
+        let payload = (0..10_000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let mut expected = Archive::new(Vec::new());
+        expected
+            .tokio_append(
+                "file.bin".to_owned(),
+                FileDateTime::Zero,
+                &mut payload.as_slice(),
+            )
+            .await
+            .unwrap();
+        let expected = expected.tokio_finalize().await.unwrap();
+
+        let upload = AzureUpload::with_sas(&reqwest::Client::new(), &url).unwrap();
+        let mut archive = Archive::new(ChunkedWriter::with_part_size(upload, 1024));
+        archive
+            .tokio_append(
+                "file.bin".to_owned(),
+                FileDateTime::Zero,
+                &mut payload.as_slice(),
+            )
+            .await
+            .unwrap();
+        archive
+            .tokio_finalize()
+            .await
+            .unwrap()
+            .shutdown()
+            .await
+            .unwrap();
+
+        let container = container.lock().unwrap();
+        assert_eq!(container.blocks.len(), expected.len().div_ceil(1024));
+        // Block IDs of the same length.
+        assert!(container.blocks.contains_key("MDAwMDAwMDA%3D"));
+        let (content_type, blob) = container.blob.as_ref().unwrap();
+        assert_eq!(content_type, "application/zip");
+        assert_eq!(blob, &expected);
+
+        assert_eq!(
+            AzureUpload::with_sas(&reqwest::Client::new(), "not a url")
+                .unwrap_err()
+                .kind(),
+            std::io::ErrorKind::InvalidInput,
+        );
+    }
+
+    #[tokio::test]
+    async fn chunked_writer() {
+        use crate::ChunkedWriter;
+        use tokio::io::AsyncWriteExt;
+
+        let payload = (0..10_000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let mut expected = Archive::new(Vec::new());
+        expected
+            .tokio_append(
+                "file.bin".to_owned(),
+                FileDateTime::Zero,
+                &mut payload.as_slice(),
+            )
+            .await
+            .unwrap();
+        let expected = expected.tokio_finalize().await.unwrap();
+
+        let log = std::sync::Arc::default();
+        let writer =
+            ChunkedWriter::with_part_size(MemoryUpload(std::sync::Arc::clone(&log), None), 1024);
+        let mut archive = Archive::new(writer);
+        archive
+            .tokio_append(
+                "file.bin".to_owned(),
+                FileDateTime::Zero,
+                &mut payload.as_slice(),
+            )
+            .await
+            .unwrap();
+        let mut writer = archive.tokio_finalize().await.unwrap();
+        assert!(!log.lock().unwrap().completed);
+        writer.shutdown().await.unwrap();
+
+        let log = log.lock().unwrap();
+        assert!(log.completed);
+        assert_eq!(log.parts.len(), expected.len().div_ceil(1024));
+        assert!(log.parts[..log.parts.len() - 1]
+            .iter()
+            .all(|part| part.len() == 1024));
+        assert_eq!(log.parts.concat(), expected);
+    }
+
+    #[tokio::test]
+    async fn chunked_writer_abort() {
+        use crate::ChunkedWriter;
+
+        let log = std::sync::Arc::default();
+        let mut writer =
+            ChunkedWriter::with_part_size(MemoryUpload(std::sync::Arc::clone(&log), Some(1)), 1024);
+        let mut archive = Archive::new(&mut writer);
+        let error = archive
+            .tokio_append(
+                "file.bin".to_owned(),
+                FileDateTime::Zero,
+                &mut [0; 10_000].as_slice(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "part rejected");
+        drop(archive);
+        writer.abort().await.unwrap();
+
+        let log = log.lock().unwrap();
+        assert_eq!(log.parts.len(), 1);
+        assert!(!log.completed);
+        assert!(log.aborted);
     }
 }
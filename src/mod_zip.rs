@@ -0,0 +1,46 @@
+use std::fmt::Write;
+
+/// Generate the manifest of an archive for nginx's [mod_zip](https://github.com/evanmiller/mod_zip) module,
+/// to be returned by an upstream response with the `X-Archive-Files: zip` header, from the CRC32, size, path and name
+/// of its files, one per line.
+///
+/// The paths are the locations the files are fetched from by nginx (they are percent-encoded by this function),
+/// and the names the ones of the entries (they can't contain line breaks). An unknown CRC32 is written as `-`,
+/// letting mod_zip compute it, at the cost of data descriptors and of the support of range requests.
+/// The same files can be zipped in process with `Archive`, and the CRC32 computed with `crc32fast` or declared
+/// with `PlannedEntry::crc` (see `ArchivePlan::mod_zip_manifest`).
+///
+/// ## Example
+///
+/// ```
+/// assert_eq!(
+///     zipit::mod_zip_manifest([
+///         (Some(0x363a3020), 6, "/files/hello.txt", "hello.txt"),
+///         (None, 6, "/files/my world.txt", "dir/world.txt"),
+///     ]),
+///     "363a3020 6 /files/hello.txt hello.txt\n- 6 /files/my%20world.txt dir/world.txt\n",
+/// );
+/// ```
+pub fn mod_zip_manifest<'a, I>(files: I) -> String
+where
+    I: IntoIterator<Item = (Option<u32>, u64, &'a str, &'a str)>,
+{
+    let mut manifest = String::new();
+    for (crc, size, path, name) in files {
+        match crc {
+            Some(crc) => write!(manifest, "{crc:08x}").unwrap(),
+            None => manifest.push('-'),
+        }
+        write!(manifest, " {size} ").unwrap();
+        for &byte in path.as_bytes() {
+            match byte {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                    manifest.push(byte as char)
+                }
+                byte => write!(manifest, "%{byte:02X}").unwrap(),
+            }
+        }
+        writeln!(manifest, " {name}").unwrap();
+    }
+    manifest
+}
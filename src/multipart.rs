@@ -0,0 +1,84 @@
+use std::io::{Error as IoError, ErrorKind};
+
+use multer::Multipart;
+use tokio::io::AsyncWrite;
+
+use crate::{Archive, EntryOptions, FileDateTime};
+
+// The name of the entry of a part, its file name (or field name) without the directories a client may have sent,
+// or `None` if there is none.
+fn entry_name(file_name: Option<&str>, name: Option<&str>) -> Option<String> {
+    let name = file_name.or(name)?.rsplit(['/', '\\']).next()?;
+    match name {
+        "" | "." | ".." => None,
+        name => Some(name.to_owned()),
+    }
+}
+
+macro_rules! impl_append_multipart {
+    (
+        $fam:tt, $famo:tt, $faso:tt,
+    ) => {
+        impl<W: AsyncWrite + Unpin> Archive<W> {
+            /// Append a new file to the archive for each part of a `multipart/form-data` body (e.g. the files of an
+            /// upload form, received with axum's `Multipart` extractor or built with `multer::Multipart::new`),
+            /// streaming its payload into the entry as it is received, like `append_stream`.
+            ///
+            /// Each entry is named after the file name of its part, or after its field name if it has none,
+            /// keeping only its last path component. The parts without any name are skipped.
+            /// Returns the names of the entries appended, in order.
+            ///
+            /// # Error
+            ///
+            /// This function will return an `std::io::Error` of kind `InvalidData` if the body isn't a valid multipart body,
+            /// and will forward any error found while receiving a part or writing to the underlying sink,
+            /// see `append_with_options`.
+            pub async fn $fam(
+                &mut self,
+                multipart: Multipart<'_>,
+                datetime: FileDateTime,
+            ) -> Result<Vec<String>, IoError> {
+                self.$famo(multipart, datetime, EntryOptions::default())
+                    .await
+            }
+
+            /// Append a new file to the archive for each part of a `multipart/form-data` body, using the provided options,
+            /// see `append_multipart` and `append_with_options`.
+            pub async fn $famo(
+                &mut self,
+                mut multipart: Multipart<'_>,
+                datetime: FileDateTime,
+                options: EntryOptions,
+            ) -> Result<Vec<String>, IoError> {
+                let mut names = Vec::new();
+                while let Some(field) = multipart
+                    .next_field()
+                    .await
+                    .map_err(|error| IoError::new(ErrorKind::InvalidData, error))?
+                {
+                    let Some(name) = entry_name(field.file_name(), field.name()) else {
+                        continue;
+                    };
+                    self.$faso(name.clone(), datetime, options.clone(), field)
+                        .await?;
+                    names.push(name);
+                }
+                Ok(names)
+            }
+        }
+    };
+}
+
+#[cfg(feature = "futures-async-io")]
+impl_append_multipart!(
+    tokio_append_multipart,
+    tokio_append_multipart_with_options,
+    tokio_append_stream_with_options,
+);
+
+#[cfg(not(feature = "futures-async-io"))]
+impl_append_multipart!(
+    append_multipart,
+    append_multipart_with_options,
+    append_stream_with_options,
+);
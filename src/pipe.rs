@@ -0,0 +1,63 @@
+use std::future::Future;
+use std::io::Error as IoError;
+
+use tokio::io::{duplex, DuplexStream};
+use tokio::task::JoinHandle;
+
+use crate::{Archive, ArchiveOptions};
+
+/// Write an archive in a spawned task, through an in-memory pipe of `capacity` bytes, and return the reading end
+/// of the pipe with the handle of the task.
+///
+/// The `write` function appends the entries to the archive and finalizes it. Its error, e.g. a `BrokenPipe` once the
+/// reader is dropped by a client disconnecting, is returned by the handle instead of being lost in a detached task.
+/// The reader reaches its end once the archive is written, or once the task stops with an error, so the handle
+/// should be awaited to know whether the archive is complete.
+///
+/// ## Example
+///
+/// ```
+/// use std::io::Cursor;
+/// use tokio::io::AsyncReadExt;
+/// use zipit::{archive_size, zip_pipe, FileDateTime};
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let (mut reader, handle) = zip_pipe(64 * 1024, |mut archive| async move {
+///     archive
+///         .append("file1.txt".to_owned(), FileDateTime::Zero, &mut Cursor::new(b"hello\n"))
+///         .await?;
+///     archive.finalize().await?;
+///     Ok(())
+/// });
+/// let mut data = Vec::new();
+/// reader.read_to_end(&mut data).await.unwrap();
+/// handle.await.unwrap().unwrap();
+/// assert_eq!(data.len(), archive_size([("file1.txt", 6)]));
+/// # }
+/// ```
+pub fn zip_pipe<F, Fut>(
+    capacity: usize,
+    write: F,
+) -> (DuplexStream, JoinHandle<Result<(), IoError>>)
+where
+    F: FnOnce(Archive<DuplexStream>) -> Fut,
+    Fut: Future<Output = Result<(), IoError>> + Send + 'static,
+{
+    zip_pipe_with_options(capacity, ArchiveOptions::default(), write)
+}
+
+/// Write an archive created with `options` in a spawned task, through an in-memory pipe (see `zip_pipe`).
+pub fn zip_pipe_with_options<F, Fut>(
+    capacity: usize,
+    options: ArchiveOptions,
+    write: F,
+) -> (DuplexStream, JoinHandle<Result<(), IoError>>)
+where
+    F: FnOnce(Archive<DuplexStream>) -> Fut,
+    Fut: Future<Output = Result<(), IoError>> + Send + 'static,
+{
+    let (reader, writer) = duplex(capacity);
+    let handle = tokio::spawn(write(Archive::with_options(writer, options)));
+    (reader, handle)
+}
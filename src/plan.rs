@@ -0,0 +1,448 @@
+use std::future::Future;
+use std::io::Error as IoError;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use crate::{
+    mod_zip_manifest, Archive, ArchiveOptions, ArchiveSizeEstimator, Compression, EntryOptions,
+    Error, FileDateTime, Sha256,
+};
+
+/// The entries of an archive declared up front, with the sizes of their payloads, to know the archive's exact size before writing it
+/// (e.g. for a `Content-Length` header), and to stream the archive against the plan with `into_archive`.
+///
+/// The payloads are stored (the compression of the entries is ignored), and not deduplicated (see `ArchiveOptions::deduplicate`).
+///
+/// ## Example
+///
+/// ```
+/// use zipit::{ArchivePlan, FileDateTime};
+///
+/// let mut plan = ArchivePlan::new();
+/// plan.add_file("file1.txt".to_owned(), 6, FileDateTime::Zero);
+/// plan.add_directory("dir".to_owned(), FileDateTime::Zero);
+/// assert_eq!(plan.size(), 238);
+/// ```
+#[derive(Clone, Debug)]
+pub struct ArchivePlan {
+    pub(crate) options: ArchiveOptions,
+    pub(crate) entries: Vec<PlannedEntry>,
+    estimator: ArchiveSizeEstimator,
+}
+
+/// An entry declared in an `ArchivePlan`.
+#[derive(Clone, Debug)]
+pub struct PlannedEntry {
+    pub(crate) name: String,
+    pub(crate) size: u64,
+    pub(crate) datetime: FileDateTime,
+    pub(crate) options: EntryOptions,
+    pub(crate) crc: Option<u32>,
+    content_hash: Option<Vec<u8>>,
+}
+
+impl PlannedEntry {
+    /// The name of the entry, ending with a slash if it is a directory.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The size of the entry's payload.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The date and time of the entry.
+    pub fn datetime(&self) -> FileDateTime {
+        self.datetime
+    }
+
+    /// Declare the CRC32 of the entry's payload, identifying its content for `ArchivePlan::etag`. It isn't checked while appending.
+    pub fn crc(&mut self, crc: u32) -> &mut Self {
+        self.crc = Some(crc);
+        self
+    }
+
+    /// Declare a hash of the entry's payload (e.g. its SHA-256, or the ETag of the object it is read from),
+    /// identifying its content for `ArchivePlan::etag`. It isn't checked while appending.
+    pub fn content_hash<H: AsRef<[u8]>>(&mut self, hash: H) -> &mut Self {
+        self.content_hash = Some(hash.as_ref().to_vec());
+        self
+    }
+
+    fn new(name: String, size: u64, datetime: FileDateTime, options: EntryOptions) -> Self {
+        Self {
+            name,
+            size,
+            datetime,
+            options,
+            crc: None,
+            content_hash: None,
+        }
+    }
+}
+
+impl Default for ArchivePlan {
+    fn default() -> Self {
+        Self::with_options(ArchiveOptions::default())
+    }
+}
+
+impl ArchivePlan {
+    /// Create an empty plan for an archive written with the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an empty plan for an archive written with `options`.
+    pub fn with_options(mut options: ArchiveOptions) -> Self {
+        options.dedup_size = 0;
+        Self {
+            estimator: ArchiveSizeEstimator::with_options(options.clone()),
+            options,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Declare a file whose payload is `size` bytes long, appended with the default entry options.
+    pub fn add_file(
+        &mut self,
+        name: String,
+        size: u64,
+        datetime: FileDateTime,
+    ) -> &mut PlannedEntry {
+        self.add_file_with_options(name, size, datetime, EntryOptions::default())
+    }
+
+    /// Declare a file whose payload is `size` bytes long, appended with `options` (whose compression is ignored).
+    pub fn add_file_with_options(
+        &mut self,
+        name: String,
+        size: u64,
+        datetime: FileDateTime,
+        options: EntryOptions,
+    ) -> &mut PlannedEntry {
+        let options = EntryOptions {
+            compression: Compression::Stored,
+            compressor: None,
+            ..options
+        };
+        self.estimator.add_file_with_options(&name, size, &options);
+        self.entries
+            .push(PlannedEntry::new(name, size, datetime, options));
+        self.entries.last_mut().unwrap()
+    }
+
+    /// Declare an empty directory, appended like `Archive::append_directory`. A trailing slash is added to the name if missing.
+    pub fn add_directory(&mut self, mut name: String, datetime: FileDateTime) -> &mut PlannedEntry {
+        if !name.ends_with('/') {
+            name.push('/');
+        }
+        self.estimator.add_directory(&name);
+        self.entries.push(PlannedEntry::new(
+            name,
+            0,
+            datetime,
+            EntryOptions::default(),
+        ));
+        self.entries.last_mut().unwrap()
+    }
+
+    /// The entries declared so far, in the order they are appended.
+    pub fn entries(&self) -> &[PlannedEntry] {
+        &self.entries
+    }
+
+    /// The exact size of the archive made of the entries declared so far.
+    pub fn size(&self) -> u64 {
+        self.estimator.total()
+    }
+
+    /// A strong HTTP entity tag (quoted) identifying the archive the plan describes, derived without writing it,
+    /// e.g. to answer `HEAD` or conditional requests. It changes with the options, the names, sizes, dates/times and contents
+    /// of the entries, and the version of the crate, which may write different bytes.
+    ///
+    /// `None` is returned if the content of a file isn't identified by its CRC32 or a hash (see `PlannedEntry::crc`),
+    /// or if the archive is encrypted, as its encryption headers are random.
+    /// It is also returned if a custom `Signer` (see `ArchiveOptions::signer`) or checksums `Digest` (see `ArchiveOptions::checksums_with`)
+    /// is set, as the bytes they produce aren't identified by the options (the entries' compressors are dropped, see `add_file_with_options`).
+    /// The dates/times are expected to be fixed (e.g. not `FileDateTime::now`).
+    pub fn etag(&self) -> Option<String> {
+        let custom_digest =
+            matches!(&self.options.checksums, Some((_, factory)) if factory.is_custom());
+        if self.options.password.is_some() || self.options.signer.is_some() || custom_digest {
+            return None;
+        }
+        let mut hasher = Sha256::new();
+        let mut field = |data: &[u8]| {
+            hasher.update(&(data.len() as u64).to_le_bytes());
+            hasher.update(data);
+        };
+        field(env!("CARGO_PKG_VERSION").as_bytes());
+        field(format!("{:?}", self.options).as_bytes());
+        for entry in &self.entries {
+            if entry.options.password.is_some() {
+                return None;
+            }
+            let content = match (entry.crc, &entry.content_hash) {
+                _ if entry.name.ends_with('/') => Vec::new(),
+                (_, Some(hash)) => [&[1], &hash[..]].concat(),
+                (Some(crc), None) => [&[2], &crc.to_le_bytes()[..]].concat(),
+                (None, None) => return None,
+            };
+            let (date, time) = entry.datetime.ms_dos();
+            field(entry.name.as_bytes());
+            field(&entry.size.to_le_bytes());
+            field(&[date.to_le_bytes(), time.to_le_bytes()].concat());
+            field(&content);
+            field(format!("{:?}", entry.options).as_bytes());
+        }
+
+        let digest: String = hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        Some(format!("\"{digest}\""))
+    }
+
+    /// The manifest of the archive for nginx's mod_zip module (see `mod_zip_manifest`), with the CRC32 declared
+    /// with `PlannedEntry::crc`, the location of each file returned by `location`, and the names and sizes of the plan.
+    /// Directories are left out, mod_zip only archiving files (the directories are implied by their names).
+    pub fn mod_zip_manifest<F>(&self, mut location: F) -> String
+    where
+        F: FnMut(&PlannedEntry) -> String,
+    {
+        let files: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|entry| !entry.name.ends_with('/'))
+            .map(|entry| (entry, location(entry)))
+            .collect();
+        mod_zip_manifest(
+            files
+                .iter()
+                .map(|(entry, path)| (entry.crc, entry.size, path.as_str(), entry.name.as_str())),
+        )
+    }
+
+    /// Start writing the archive to `sink`, its entries being appended one by one, in the order they were declared.
+    pub fn into_archive<W>(self, sink: W) -> PlannedArchive<W> {
+        PlannedArchive {
+            archive: Archive::with_options(sink, self.options),
+            planned: self.entries.len(),
+            entries: self.entries.into_iter(),
+        }
+    }
+}
+
+/// An archive being written against an `ArchivePlan`, failing if it doesn't match the plan
+/// (a payload isn't as long as declared, or the number of entries differs), so its size is the one of the plan.
+#[derive(Debug)]
+pub struct PlannedArchive<W> {
+    archive: Archive<W>,
+    planned: usize,
+    entries: std::vec::IntoIter<PlannedEntry>,
+}
+
+impl<W> PlannedArchive<W> {
+    /// The next entry of the plan to append, `None` if they were all appended.
+    pub fn next_entry(&self) -> Option<&PlannedEntry> {
+        self.entries.as_slice().first()
+    }
+
+    fn next(&mut self) -> Result<PlannedEntry, IoError> {
+        self.entries.next().ok_or_else(|| {
+            Error::PlanMismatch {
+                planned: self.planned,
+                appended: self.planned + 1,
+            }
+            .into()
+        })
+    }
+
+    fn check_complete(&self) -> Result<(), IoError> {
+        match self.entries.len() {
+            0 => Ok(()),
+            left => Err(Error::PlanMismatch {
+                planned: self.planned,
+                appended: self.planned - left,
+            }
+            .into()),
+        }
+    }
+}
+
+/// A reader failing with an `Error::SizeMismatch` if its payload isn't `size` bytes long,
+/// or stopping after `size` bytes if `truncate` is set.
+pub(crate) struct SizedReader<'a, R> {
+    reader: &'a mut R,
+    name: String,
+    size: u64,
+    remaining: u64,
+    truncate: bool,
+}
+
+impl<'a, R> SizedReader<'a, R> {
+    pub(crate) fn new(reader: &'a mut R, name: String, size: u64, truncate: bool) -> Self {
+        Self {
+            reader,
+            name,
+            size,
+            remaining: size,
+            truncate,
+        }
+    }
+
+    // The length to read at most into a buffer of `len` bytes, `None` if the payload is complete.
+    fn limit(&self, len: usize) -> Option<usize> {
+        match self.remaining {
+            0 if self.truncate => None,
+            // Read a single byte past the size, to check the payload ends there.
+            0 => Some(len.min(1)),
+            remaining => Some(len.min(remaining.min(usize::MAX as u64) as usize)),
+        }
+    }
+
+    fn consume(&mut self, read: usize) -> Result<(), IoError> {
+        if (read == 0 && self.remaining > 0) || read as u64 > self.remaining {
+            return Err(Error::SizeMismatch {
+                name: self.name.clone(),
+                expected: self.size,
+            }
+            .into());
+        }
+        self.remaining -= read as u64;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "tokio-async-io")]
+impl<R: tokio::io::AsyncRead + Unpin> tokio::io::AsyncRead for SizedReader<'_, R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<Result<(), IoError>> {
+        let this = self.get_mut();
+        let Some(limit) = this.limit(buf.remaining()) else {
+            return Poll::Ready(Ok(()));
+        };
+        let mut limited = tokio::io::ReadBuf::new(&mut buf.initialize_unfilled()[..limit]);
+        std::task::ready!(Pin::new(&mut *this.reader).poll_read(cx, &mut limited))?;
+        let read = limited.filled().len();
+        this.consume(read)?;
+        buf.advance(read);
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(feature = "futures-async-io")]
+impl<R: futures_util::AsyncRead + Unpin> futures_util::AsyncRead for SizedReader<'_, R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, IoError>> {
+        let this = self.get_mut();
+        let Some(limit) = this.limit(buf.len()) else {
+            return Poll::Ready(Ok(0));
+        };
+        let read = std::task::ready!(Pin::new(&mut *this.reader).poll_read(cx, &mut buf[..limit]))?;
+        this.consume(read)?;
+        Poll::Ready(Ok(read))
+    }
+}
+
+macro_rules! impl_plan_methods {
+    (
+        $(#[$($attrss:tt)*])*,
+        $w:path, $r:path,
+        $fao:tt, $fd:tt, $ff:tt, $fan:tt, $faa:tt,
+    ) => {
+        impl<W> PlannedArchive<W> {
+            /// Append the next entry of the plan (see `next_entry`), reading its payload from `reader`,
+            /// which is left untouched for a directory.
+            ///
+            /// # Error
+            ///
+            /// This function will return an `Error::SizeMismatch` if the payload read isn't as long as declared
+            /// (the reader isn't read further than one byte past the declared size), an `Error::PlanMismatch` if every entry was already appended,
+            /// and the same errors as `Archive::append_with_options`.
+            $(#[$($attrss)*])*
+            pub async fn $fan<R>(&mut self, reader: &mut R) -> Result<(), IoError> where W: $w + Unpin, R: $r + Unpin {
+                let entry = self.next()?;
+                if entry.name.ends_with('/') {
+                    return self.archive.$fd(entry.name, entry.datetime).await;
+                }
+                let mut reader = SizedReader::new(reader, entry.name.clone(), entry.size, false);
+                self.archive.$fao(entry.name, entry.datetime, entry.options, &mut reader).await
+            }
+
+            /// Append the remaining entries of the plan, opening the reader of each file with `open` only when its turn comes
+            /// (e.g. one request to an object storage at a time), and closing it once appended,
+            /// so the readers of all the entries aren't opened up front. `open` isn't called for the directories.
+            ///
+            /// # Error
+            ///
+            /// This function will forward any error returned by `open`, and the same errors as `append_next`.
+            $(#[$($attrss)*])*
+            pub async fn $faa<F, Fut, R>(&mut self, mut open: F) -> Result<(), IoError>
+            where
+                W: $w + Unpin,
+                F: FnMut(&PlannedEntry) -> Fut,
+                Fut: Future<Output = Result<R, IoError>>,
+                R: $r + Unpin,
+            {
+                while let Some(entry) = self.next_entry() {
+                    if entry.name.ends_with('/') {
+                        self.$fan(&mut &[][..]).await?;
+                    } else {
+                        let mut reader = open(entry).await?;
+                        self.$fan(&mut reader).await?;
+                    }
+                }
+                Ok(())
+            }
+
+            /// Finalize the archive (see `Archive::finalize`), once every entry of the plan was appended.
+            ///
+            /// # Error
+            ///
+            /// This function will return an `Error::PlanMismatch` if some entries weren't appended,
+            /// and forward any error found while writing to the underlying sink.
+            $(#[$($attrss)*])*
+            pub async fn $ff(self) -> Result<W, IoError> where W: $w + Unpin {
+                self.check_complete()?;
+                self.archive.$ff().await
+            }
+        }
+    };
+}
+
+#[cfg(all(feature = "futures-async-io", feature = "tokio-async-io"))]
+impl_plan_methods!(
+    #[cfg(all(feature = "futures-async-io", feature = "tokio-async-io"))],
+    futures_util::AsyncWrite, futures_util::AsyncRead,
+    futures_append_with_options, futures_append_directory, futures_finalize, futures_append_next, futures_append_all,
+);
+#[cfg(all(feature = "futures-async-io", feature = "tokio-async-io"))]
+impl_plan_methods!(
+    #[cfg(all(feature = "futures-async-io", feature = "tokio-async-io"))],
+    tokio::io::AsyncWrite, tokio::io::AsyncRead,
+    tokio_append_with_options, tokio_append_directory, tokio_finalize, tokio_append_next, tokio_append_all,
+);
+
+#[cfg(all(feature = "futures-async-io", not(feature = "tokio-async-io")))]
+impl_plan_methods!(
+    #[cfg(all(feature = "futures-async-io", not(feature = "tokio-async-io")))],
+    futures_util::AsyncWrite, futures_util::AsyncRead,
+    append_with_options, append_directory, finalize, append_next, append_all,
+);
+
+#[cfg(all(not(feature = "futures-async-io"), feature = "tokio-async-io"))]
+impl_plan_methods!(
+    #[cfg(all(not(feature = "futures-async-io"), feature = "tokio-async-io"))],
+    tokio::io::AsyncWrite, tokio::io::AsyncRead,
+    append_with_options, append_directory, finalize, append_next, append_all,
+);
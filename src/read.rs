@@ -0,0 +1,1641 @@
+//! Read archives from an `AsyncRead`, entry by entry, without seeking.
+//!
+//! The entries are described by their local headers, read while streaming (the central directory at the end of the archive isn't needed).
+//! Their payloads are decompressed and checked against their CRC32 and sizes, including the ones written in a data descriptor.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! # #[cfg(feature = "tokio-async-io")]
+//! # async fn run() {
+//! use zipit::read::ArchiveReader;
+//!
+//! let mut reader = ArchiveReader::new(tokio::fs::File::open("archive.zip").await.unwrap());
+//! while let Some(entry) = reader.next_entry().await.unwrap() {
+//!     let mut payload = Vec::new();
+//!     let mut buf = [0; 4096];
+//!     loop {
+//!         let read = reader.read(&mut buf).await.unwrap();
+//!         if read == 0 {
+//!             break;
+//!         }
+//!         payload.extend_from_slice(&buf[..read]);
+//!     }
+//!     println!("{}: {} bytes", entry.name(), payload.len());
+//! }
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::io::{Error as IoError, SeekFrom};
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+use std::task::{ready, Context, Poll};
+
+use crc32fast::Hasher;
+
+#[cfg(feature = "deflate-compression")]
+use crate::compression::inflate::Inflater;
+pub use crate::glob::Glob;
+use crate::{cp437, Error, FileDateTime, HostSystem};
+
+const LOCAL_HEADER_SIGNATURE: [u8; 4] = 0x04034b50u32.to_le_bytes();
+const DESCRIPTOR_SIGNATURE: [u8; 4] = 0x08074b50u32.to_le_bytes();
+const CENTRAL_DIRECTORY_SIGNATURE: [u8; 4] = 0x02014b50u32.to_le_bytes();
+const ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE: [u8; 4] = 0x06064b50u32.to_le_bytes();
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: [u8; 4] = 0x06054b50u32.to_le_bytes();
+const ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIGNATURE: [u8; 4] = 0x07064b50u32.to_le_bytes();
+const LOCAL_HEADER_SIZE: usize = 30;
+const CENTRAL_DIRECTORY_ENTRY_SIZE: usize = 46;
+const ZIP64_END_OF_CENTRAL_DIRECTORY_SIZE: usize = 56;
+const ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIZE: usize = 20;
+const END_OF_CENTRAL_DIRECTORY_SIZE: usize = 22;
+const READ_SIZE: usize = 8192;
+
+/// An entry of an archive being read, as described by its local header.
+#[derive(Clone, Debug)]
+pub struct Entry {
+    name: String,
+    flags: u16,
+    method: u16,
+    datetime: FileDateTime,
+    crc: Option<u32>,
+    compressed_size: Option<u64>,
+    size: Option<u64>,
+    extra_field: Vec<u8>,
+    offset: u64,
+}
+
+impl Entry {
+    /// The name of the entry, decoded from UTF-8 or code page 437 (or from its Unicode Path extra field).
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether the entry is a directory, its name ending with a slash.
+    pub fn is_directory(&self) -> bool {
+        self.name.ends_with('/')
+    }
+
+    /// The modification date and time of the entry.
+    pub fn datetime(&self) -> FileDateTime {
+        self.datetime
+    }
+
+    /// The compression method of the entry (e.g. 0 if stored, 8 if deflated).
+    pub fn method(&self) -> u16 {
+        self.method
+    }
+
+    /// The general purpose flag of the entry.
+    pub fn flags(&self) -> u16 {
+        self.flags
+    }
+
+    /// Whether the payload of the entry is encrypted.
+    pub fn is_encrypted(&self) -> bool {
+        self.flags & 1 != 0
+    }
+
+    /// The CRC32 of the payload, `None` until it is read if it is written in a data descriptor.
+    pub fn crc(&self) -> Option<u32> {
+        self.crc
+    }
+
+    /// The size of the compressed payload, `None` until it is read if it is written in a data descriptor.
+    pub fn compressed_size(&self) -> Option<u64> {
+        self.compressed_size
+    }
+
+    /// The size of the payload, `None` until it is read if it is written in a data descriptor.
+    pub fn size(&self) -> Option<u64> {
+        self.size
+    }
+
+    /// The extra fields of the local header.
+    pub fn extra_field(&self) -> &[u8] {
+        &self.extra_field
+    }
+
+    /// The offset of the local header in the archive.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Whether the entry's sizes are stored in a Zip64 extra field.
+    pub(crate) fn is_zip64(&self) -> bool {
+        extra_fields(&self.extra_field).any(|(id, _)| id == 0x0001)
+    }
+}
+
+/// The description of an entry listed by `ArchiveReader::list`, with the CRC32 and sizes of its data descriptor if any.
+///
+/// It is displayed as a line of `unzip -v`'s listing: size, method, compressed size, ratio, date, time, CRC32 and name.
+#[derive(Clone, Debug)]
+pub struct EntryInfo {
+    name: String,
+    method: u16,
+    datetime: FileDateTime,
+    crc: u32,
+    compressed_size: u64,
+    size: u64,
+    encrypted: bool,
+    offset: u64,
+}
+
+impl EntryInfo {
+    fn new(entry: &Entry) -> Self {
+        Self {
+            name: entry.name.clone(),
+            method: entry.method,
+            datetime: entry.datetime,
+            crc: entry.crc.unwrap_or_default(),
+            compressed_size: entry.compressed_size.unwrap_or_default(),
+            size: entry.size.unwrap_or_default(),
+            encrypted: entry.is_encrypted(),
+            offset: entry.offset,
+        }
+    }
+
+    /// The name of the entry.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether the entry is a directory, its name ending with a slash.
+    pub fn is_directory(&self) -> bool {
+        self.name.ends_with('/')
+    }
+
+    /// The compression method of the entry (e.g. 0 if stored, 8 if deflated).
+    pub fn method(&self) -> u16 {
+        self.method
+    }
+
+    /// The modification date and time of the entry.
+    pub fn datetime(&self) -> FileDateTime {
+        self.datetime
+    }
+
+    /// The CRC32 of the payload.
+    pub fn crc(&self) -> u32 {
+        self.crc
+    }
+
+    /// The size of the compressed payload.
+    pub fn compressed_size(&self) -> u64 {
+        self.compressed_size
+    }
+
+    /// The size of the payload.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Whether the payload of the entry is encrypted.
+    pub fn is_encrypted(&self) -> bool {
+        self.encrypted
+    }
+
+    /// The offset of the local header in the archive.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+impl Display for EntryInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let method = match self.method {
+            0 => "Stored".to_owned(),
+            8 => "Defl:N".to_owned(),
+            9 => "Def64".to_owned(),
+            12 => "BZip2".to_owned(),
+            14 => "LZMA".to_owned(),
+            code => format!("Unk:{code:03}"),
+        };
+        // The space saved, rounded like unzip does.
+        let ratio = match self.size {
+            0 => 0,
+            size => 100 - ((self.compressed_size as f64 / size as f64) * 100.0).round() as i64,
+        };
+        let (year, month, day, hour, minute, _) = self.datetime.tuple();
+        write!(
+            f,
+            "{:>8}  {method:<6}  {:>7} {ratio:>3}% {:04}-{month:02}-{day:02} {hour:02}:{minute:02} {:08x}  {}",
+            self.size,
+            self.compressed_size,
+            year.max(1980),
+            self.crc,
+            self.name,
+        )
+    }
+}
+
+/// The outcome of the verification of an entry by `ArchiveReader::verify`.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum EntryStatus {
+    /// The payload matches its CRC32 and sizes, and so does the entry's central directory record.
+    Valid,
+    /// The payload can't be decompressed, or doesn't match the CRC32 and sizes of its local header or data descriptor.
+    Corrupt,
+    /// The payload can't be checked: it is encrypted, or compressed with an unsupported method.
+    Unsupported,
+    /// The payload is valid, but the name, CRC32 or sizes of the entry's central directory record differ.
+    Mismatch,
+    /// The payload is valid, but the entry isn't listed in the central directory (most readers ignore it).
+    Unlisted,
+}
+
+/// The verification of an entry by `ArchiveReader::verify`.
+#[derive(Clone, Debug)]
+pub struct EntryReport {
+    info: EntryInfo,
+    status: EntryStatus,
+}
+
+impl EntryReport {
+    /// The entry, as described by its local header and data descriptor.
+    pub fn info(&self) -> &EntryInfo {
+        &self.info
+    }
+
+    /// The outcome of the verification of the entry.
+    pub fn status(&self) -> EntryStatus {
+        self.status
+    }
+}
+
+/// The report of the verification of an archive by `ArchiveReader::verify`.
+#[derive(Clone, Debug)]
+pub struct VerifyReport {
+    entries: Vec<EntryReport>,
+    missing: Vec<CentralEntry>,
+}
+
+impl VerifyReport {
+    /// The verification of the entries read, in the order of the archive.
+    pub fn entries(&self) -> &[EntryReport] {
+        &self.entries
+    }
+
+    /// The entries listed in the central directory whose local header wasn't found where the record locates it.
+    pub fn missing(&self) -> &[CentralEntry] {
+        &self.missing
+    }
+
+    /// Whether every entry is valid, and listed in the central directory.
+    pub fn is_valid(&self) -> bool {
+        self.missing.is_empty()
+            && self
+                .entries
+                .iter()
+                .all(|entry| entry.status == EntryStatus::Valid)
+    }
+}
+
+/// The compressed payload of an archive reader's current entry, read as is,
+/// its (uncompressed) size and CRC32 being set once it is read.
+pub(crate) struct RawPayload<'a, R> {
+    reader: &'a mut ArchiveReader<R>,
+    metadata: Arc<OnceLock<(u64, u32)>>,
+}
+
+impl<'a, R> RawPayload<'a, R> {
+    pub(crate) fn new(
+        reader: &'a mut ArchiveReader<R>,
+        metadata: Arc<OnceLock<(u64, u32)>>,
+    ) -> Self {
+        Self { reader, metadata }
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<Option<usize>, IoError> {
+        let read = self.reader.poll_read_raw(buf)?;
+        if read == Some(0) {
+            if let Some(Entry {
+                crc: Some(crc),
+                size: Some(size),
+                ..
+            }) = &self.reader.entry
+            {
+                let _ = self.metadata.set((*size, *crc));
+            }
+        }
+        Ok(read)
+    }
+}
+
+#[cfg(feature = "tokio-async-io")]
+impl<R: tokio::io::AsyncRead + Unpin> tokio::io::AsyncRead for RawPayload<'_, R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<Result<(), IoError>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(read) = this.read(buf.initialize_unfilled())? {
+                buf.advance(read);
+                return Poll::Ready(Ok(()));
+            }
+            ready!(this.reader.poll_fill_tokio(cx))?;
+        }
+    }
+}
+
+#[cfg(feature = "futures-async-io")]
+impl<R: futures_util::AsyncRead + Unpin> futures_util::AsyncRead for RawPayload<'_, R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, IoError>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(read) = this.read(buf)? {
+                return Poll::Ready(Ok(read));
+            }
+            ready!(this.reader.poll_fill_futures(cx))?;
+        }
+    }
+}
+
+/// The decompression of the current entry's payload.
+#[derive(Debug)]
+enum Decoder {
+    Stored,
+    #[cfg(feature = "deflate-compression")]
+    Deflate(Box<Inflater>),
+    Unsupported,
+}
+
+/// The state of the current entry's payload.
+#[derive(Debug)]
+struct Payload {
+    decoder: Decoder,
+    // The compressed size written in the local header, if any.
+    compressed_size: Option<u64>,
+    descriptor: bool,
+    zip64: bool,
+    hasher: Hasher,
+    size: u64,
+    compressed: u64,
+    // Whether part of the payload was skipped rather than read.
+    skipped: bool,
+    done: bool,
+}
+
+/// A reader of an archive streamed from an `AsyncRead`.
+///
+/// Use `next_entry` to move to the following entry, skipping what is left of the current one,
+/// and `read` to read its (decompressed) payload. Stored and deflated (`deflate-compression` feature required) entries can be read.
+#[derive(Debug)]
+pub struct ArchiveReader<R> {
+    source: R,
+    input: Vec<u8>,
+    pos: usize,
+    offset: u64,
+    eof: bool,
+    entry: Option<Entry>,
+    payload: Option<Payload>,
+    end: bool,
+}
+
+impl<R> ArchiveReader<R> {
+    /// Create a reader of the archive streamed from `source`.
+    pub fn new(source: R) -> Self {
+        Self {
+            source,
+            input: Vec::new(),
+            pos: 0,
+            offset: 0,
+            eof: false,
+            entry: None,
+            payload: None,
+            end: false,
+        }
+    }
+
+    /// The current entry, its CRC32 and sizes being updated from its data descriptor once its payload is read.
+    pub fn entry(&self) -> Option<&Entry> {
+        self.entry.as_ref()
+    }
+
+    fn available(&self) -> &[u8] {
+        &self.input[self.pos..]
+    }
+
+    fn advance(&mut self, n: usize) {
+        self.pos += n;
+        self.offset += n as u64;
+    }
+
+    // Make room for the next read from the source, returning where it starts.
+    fn reserve(&mut self) -> usize {
+        self.input.drain(..self.pos);
+        self.pos = 0;
+        let len = self.input.len();
+        self.input.resize(len + READ_SIZE, 0);
+        len
+    }
+
+    fn filled(&mut self, start: usize, read: usize) {
+        self.input.truncate(start + read);
+        self.eof = read == 0;
+    }
+
+    // Need more bytes than available: an error at the end of the source.
+    fn need_input<T>(&self) -> Result<Option<T>, IoError> {
+        if self.eof {
+            Err(Error::InvalidArchive {
+                offset: self.offset + self.available().len() as u64,
+            }
+            .into())
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn entry_error(&self, error: fn(String) -> Error) -> IoError {
+        error(
+            self.entry
+                .as_ref()
+                .map_or_else(String::new, |entry| entry.name.clone()),
+        )
+        .into()
+    }
+
+    // Move to the next entry, `None` if more input is needed.
+    fn poll_next_entry(&mut self) -> Result<Option<Option<Entry>>, IoError> {
+        if self.end {
+            return Ok(Some(None));
+        }
+        if self.payload.as_ref().is_some_and(|payload| !payload.done) && self.poll_skip()?.is_none()
+        {
+            return Ok(None);
+        }
+
+        let available = self.available();
+        if available.len() < 4 {
+            return self.need_input();
+        }
+        match available[..4].try_into().unwrap_or_default() {
+            LOCAL_HEADER_SIGNATURE => {}
+            // The end of the entries, the central directory is left unread.
+            CENTRAL_DIRECTORY_SIGNATURE
+            | ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE
+            | END_OF_CENTRAL_DIRECTORY_SIGNATURE => {
+                self.entry = None;
+                self.payload = None;
+                self.end = true;
+                return Ok(Some(None));
+            }
+            // The marker of an archive split in a single segment.
+            DESCRIPTOR_SIGNATURE if self.offset == 0 => {
+                self.advance(4);
+                return self.poll_next_entry();
+            }
+            _ => {
+                return Err(Error::InvalidArchive {
+                    offset: self.offset,
+                }
+                .into())
+            }
+        }
+        if available.len() < LOCAL_HEADER_SIZE {
+            return self.need_input();
+        }
+        let name_length = u16_at(available, 26) as usize;
+        let extra_field_length = u16_at(available, 28) as usize;
+        if available.len() < LOCAL_HEADER_SIZE + name_length + extra_field_length {
+            return self.need_input();
+        }
+
+        let flags = u16_at(available, 6);
+        let method = u16_at(available, 8);
+        let raw_name = &available[LOCAL_HEADER_SIZE..LOCAL_HEADER_SIZE + name_length];
+        let extra_field = &available[LOCAL_HEADER_SIZE + name_length..][..extra_field_length];
+        let name = decode_name(raw_name, flags, extra_field);
+        let mut sizes = [u32_at(available, 22) as u64, u32_at(available, 18) as u64];
+        let zip64 = zip64_values(extra_field, &mut sizes).ok_or(Error::InvalidArchive {
+            offset: self.offset,
+        })?;
+
+        let descriptor = flags & 1 << 3 != 0;
+        let entry = Entry {
+            name,
+            flags,
+            method,
+            datetime: FileDateTime::from_ms_dos(u16_at(available, 12), u16_at(available, 10)),
+            crc: (!descriptor).then(|| u32_at(available, 14)),
+            compressed_size: (!descriptor).then_some(sizes[1]),
+            size: (!descriptor).then_some(sizes[0]),
+            extra_field: extra_field.to_vec(),
+            offset: self.offset,
+        };
+        let decoder = match method {
+            _ if flags & 1 != 0 => Decoder::Unsupported,
+            0 => Decoder::Stored,
+            #[cfg(feature = "deflate-compression")]
+            8 => Decoder::Deflate(Box::new(Inflater::new())),
+            _ => Decoder::Unsupported,
+        };
+        self.payload = Some(Payload {
+            decoder,
+            // Some writers still set the compressed size with a data descriptor.
+            compressed_size: (!descriptor || sizes[1] != 0).then_some(sizes[1]),
+            descriptor,
+            zip64,
+            hasher: Hasher::new(),
+            size: 0,
+            compressed: 0,
+            skipped: false,
+            done: false,
+        });
+        self.advance(LOCAL_HEADER_SIZE + name_length + extra_field_length);
+        self.entry = Some(entry.clone());
+        Ok(Some(Some(entry)))
+    }
+
+    // Skip what is left of the current entry's payload, without decompressing it if its compressed size is known.
+    fn poll_skip(&mut self) -> Result<Option<()>, IoError> {
+        loop {
+            let Some(payload) = &mut self.payload else {
+                return Ok(Some(()));
+            };
+            if payload.done {
+                return Ok(Some(()));
+            }
+            match payload.compressed_size {
+                Some(compressed_size) if compressed_size > payload.compressed => {
+                    let left = compressed_size - payload.compressed;
+                    let n = (self.input.len() - self.pos).min(left.min(usize::MAX as u64) as usize);
+                    if n == 0 {
+                        return self.need_input();
+                    }
+                    payload.compressed += n as u64;
+                    payload.skipped = true;
+                    self.advance(n);
+                }
+                Some(_) if payload.descriptor => return self.poll_descriptor(),
+                Some(_) => {
+                    payload.done = true;
+                    return Ok(Some(()));
+                }
+                // The end of the payload is only found by decompressing it.
+                None => {
+                    let mut buf = [0; READ_SIZE];
+                    return loop {
+                        match self.poll_read(&mut buf)? {
+                            Some(0) => break Ok(Some(())),
+                            Some(_) => {}
+                            None => break Ok(None),
+                        }
+                    };
+                }
+            }
+        }
+    }
+
+    // Read the current entry's payload, `None` if more input is needed.
+    fn poll_read(&mut self, buf: &mut [u8]) -> Result<Option<usize>, IoError> {
+        let Some(payload) = &mut self.payload else {
+            return Ok(Some(0));
+        };
+        if payload.done || buf.is_empty() {
+            return Ok(Some(0));
+        }
+        match &mut payload.decoder {
+            Decoder::Unsupported => Err(self.entry_error(|name| Error::UnsupportedEntry { name })),
+            Decoder::Stored => match payload.compressed_size {
+                Some(compressed_size) => {
+                    let left = compressed_size - payload.compressed;
+                    if left == 0 {
+                        return self.poll_end();
+                    }
+                    let available = &self.input[self.pos..];
+                    let n = available
+                        .len()
+                        .min(buf.len())
+                        .min(left.min(usize::MAX as u64) as usize);
+                    if n == 0 {
+                        return self.need_input();
+                    }
+                    buf[..n].copy_from_slice(&available[..n]);
+                    payload.hasher.update(&buf[..n]);
+                    payload.size += n as u64;
+                    payload.compressed += n as u64;
+                    self.advance(n);
+                    Ok(Some(n))
+                }
+                None => self.poll_scan(buf),
+            },
+            #[cfg(feature = "deflate-compression")]
+            Decoder::Deflate(inflater) => loop {
+                let output = inflater.output();
+                if !output.is_empty() {
+                    let n = output.len().min(buf.len());
+                    buf[..n].copy_from_slice(&output[..n]);
+                    inflater.consume(n);
+                    payload.hasher.update(&buf[..n]);
+                    payload.size += n as u64;
+                    return Ok(Some(n));
+                }
+                if inflater.is_done() {
+                    return self.poll_end();
+                }
+
+                let available = &self.input[self.pos..];
+                let left = payload
+                    .compressed_size
+                    .map_or(u64::MAX, |size| size - payload.compressed);
+                let input = &available[..available.len().min(left.min(usize::MAX as u64) as usize)];
+                let consumed = match inflater.inflate(input) {
+                    Ok(consumed) => consumed,
+                    Err(_) => return Err(self.entry_error(|name| Error::CorruptEntry { name })),
+                };
+                payload.compressed += consumed as u64;
+                self.pos += consumed;
+                self.offset += consumed as u64;
+                if consumed == 0 && inflater.output().is_empty() && !inflater.is_done() {
+                    if left == 0 {
+                        return Err(self.entry_error(|name| Error::CorruptEntry { name }));
+                    }
+                    return self.need_input();
+                }
+            },
+        }
+    }
+
+    // Read the current entry's compressed payload as is, `None` if more input is needed.
+    // Only the payloads whose end can only be found by decompressing them are checked.
+    fn poll_read_raw(&mut self, buf: &mut [u8]) -> Result<Option<usize>, IoError> {
+        let Some(payload) = &mut self.payload else {
+            return Ok(Some(0));
+        };
+        if payload.done || buf.is_empty() {
+            return Ok(Some(0));
+        }
+        match (payload.compressed_size, &mut payload.decoder) {
+            (Some(compressed_size), _) => {
+                let left = compressed_size - payload.compressed;
+                if left == 0 {
+                    payload.skipped = true;
+                    if payload.descriptor {
+                        return Ok(self.poll_descriptor()?.map(|()| 0));
+                    }
+                    payload.done = true;
+                    return Ok(Some(0));
+                }
+                let available = &self.input[self.pos..];
+                let n = available
+                    .len()
+                    .min(buf.len())
+                    .min(left.min(usize::MAX as u64) as usize);
+                if n == 0 {
+                    return self.need_input();
+                }
+                buf[..n].copy_from_slice(&available[..n]);
+                payload.compressed += n as u64;
+                self.advance(n);
+                Ok(Some(n))
+            }
+            (None, Decoder::Stored) => self.poll_scan(buf),
+            // The end of the payload is only found by decompressing it.
+            #[cfg(feature = "deflate-compression")]
+            (None, Decoder::Deflate(inflater)) => loop {
+                let output = inflater.output();
+                let produced = output.len();
+                payload.hasher.update(output);
+                payload.size += produced as u64;
+                inflater.consume(produced);
+                if inflater.is_done() {
+                    return self.poll_end();
+                }
+
+                let available = &self.input[self.pos..];
+                let input = &available[..available.len().min(buf.len())];
+                let consumed = match inflater.inflate(input) {
+                    Ok(consumed) => consumed,
+                    Err(_) => return Err(self.entry_error(|name| Error::CorruptEntry { name })),
+                };
+                if consumed > 0 {
+                    buf[..consumed].copy_from_slice(&input[..consumed]);
+                    payload.compressed += consumed as u64;
+                    self.advance(consumed);
+                    return Ok(Some(consumed));
+                }
+                if inflater.output().is_empty() && !inflater.is_done() {
+                    return self.need_input();
+                }
+            },
+            (None, _) => Err(self.entry_error(|name| Error::UnsupportedEntry { name })),
+        }
+    }
+
+    // Read the next bytes of the source into the input buffer.
+    #[cfg(feature = "tokio-async-io")]
+    fn poll_fill_tokio(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), IoError>>
+    where
+        R: tokio::io::AsyncRead + Unpin,
+    {
+        let start = self.reserve();
+        let mut buf = tokio::io::ReadBuf::new(&mut self.input[start..]);
+        match Pin::new(&mut self.source).poll_read(cx, &mut buf) {
+            Poll::Ready(Ok(())) => {
+                let read = buf.filled().len();
+                self.filled(start, read);
+                Poll::Ready(Ok(()))
+            }
+            result => {
+                self.input.truncate(start);
+                result
+            }
+        }
+    }
+
+    // Read the next bytes of the source into the input buffer.
+    #[cfg(feature = "futures-async-io")]
+    fn poll_fill_futures(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), IoError>>
+    where
+        R: futures_util::AsyncRead + Unpin,
+    {
+        let start = self.reserve();
+        match Pin::new(&mut self.source).poll_read(cx, &mut self.input[start..]) {
+            Poll::Ready(Ok(read)) => {
+                self.filled(start, read);
+                Poll::Ready(Ok(()))
+            }
+            result => {
+                self.input.truncate(start);
+                result.map_ok(|_| ())
+            }
+        }
+    }
+
+    // Read a stored payload whose size is only written in the following data descriptor,
+    // looking for a descriptor matching the bytes read so far.
+    fn poll_scan(&mut self, buf: &mut [u8]) -> Result<Option<usize>, IoError> {
+        let Some(payload) = &mut self.payload else {
+            return Ok(Some(0));
+        };
+        let available = &self.input[self.pos..];
+        let width = if payload.zip64 { 8 } else { 4 };
+        let fields_size = 4 + 2 * width;
+        let mut end = available.len();
+        for start in 0..available.len() {
+            let rest = &available[start..];
+            // Wait for the whole descriptor and the following signature.
+            if !self.eof && rest.len() < 4 + fields_size + 4 {
+                end = start;
+                break;
+            }
+            let signed = rest.starts_with(&DESCRIPTOR_SIGNATURE);
+            let fields = &rest[if signed { 4 } else { 0 }..];
+            if fields.len() < fields_size {
+                continue;
+            }
+            let next = &fields[fields_size..];
+            // Without its optional signature, the descriptor must be followed by the next record.
+            let followed = [
+                LOCAL_HEADER_SIGNATURE,
+                CENTRAL_DIRECTORY_SIGNATURE,
+                ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE,
+                END_OF_CENTRAL_DIRECTORY_SIGNATURE,
+            ]
+            .iter()
+            .any(|signature| next.starts_with(signature));
+            let (crc, compressed_size, size) = descriptor_fields(fields, width);
+            let expected_size = payload.compressed + start as u64;
+            if !(signed || followed) || compressed_size != expected_size || size != expected_size {
+                continue;
+            }
+            // The sizes only match the descriptor of this payload, the payload is corrupted if the CRC32 doesn't.
+            let mut hasher = payload.hasher.clone();
+            hasher.update(&available[..start]);
+            let descriptor_size = if signed { 4 } else { 0 } + fields_size;
+            if hasher.finalize() != crc {
+                // Move past the descriptor, the following entries can still be read.
+                payload.done = true;
+                payload.compressed += start as u64;
+                self.advance(start + descriptor_size);
+                return Err(self.entry_error(|name| Error::CorruptEntry { name }));
+            }
+
+            if start == 0 {
+                payload.done = true;
+                self.advance(descriptor_size);
+                if let Some(entry) = &mut self.entry {
+                    (entry.crc, entry.compressed_size, entry.size) =
+                        (Some(crc), Some(compressed_size), Some(size));
+                }
+                return Ok(Some(0));
+            }
+            end = start;
+            break;
+        }
+
+        let n = end.min(buf.len());
+        if n == 0 {
+            return self.need_input();
+        }
+        buf[..n].copy_from_slice(&available[..n]);
+        payload.hasher.update(&buf[..n]);
+        payload.size += n as u64;
+        payload.compressed += n as u64;
+        self.advance(n);
+        Ok(Some(n))
+    }
+
+    // Check the payload once read, against its data descriptor if any.
+    fn poll_end(&mut self) -> Result<Option<usize>, IoError> {
+        if self
+            .payload
+            .as_ref()
+            .is_some_and(|payload| payload.descriptor)
+        {
+            if self.poll_descriptor()?.is_none() {
+                return Ok(None);
+            }
+        } else if let (Some(payload), Some(entry)) = (&mut self.payload, &self.entry) {
+            payload.done = true;
+            if entry.crc != Some(payload.hasher.clone().finalize())
+                || entry.size != Some(payload.size)
+                || entry.compressed_size != Some(payload.compressed)
+            {
+                return Err(self.entry_error(|name| Error::CorruptEntry { name }));
+            }
+        }
+        Ok(Some(0))
+    }
+
+    // Read the central directory once the entries are read (or skipped), `None` if more input is needed.
+    fn poll_central_directory(&mut self) -> Result<Option<CentralDirectory>, IoError> {
+        while !self.end {
+            if self.poll_next_entry()?.is_none() {
+                return Ok(None);
+            }
+        }
+        // The end records are found from the end of the archive, which must be read first.
+        if !self.eof {
+            return Ok(None);
+        }
+        Ok(Some(CentralDirectory::parse(
+            self.available(),
+            self.offset,
+        )?))
+    }
+
+    // Read the data descriptor following the payload, checking it if the payload was read.
+    fn poll_descriptor(&mut self) -> Result<Option<()>, IoError> {
+        let Some(payload) = &mut self.payload else {
+            return Ok(Some(()));
+        };
+        let available = &self.input[self.pos..];
+        let width = if payload.zip64 { 8 } else { 4 };
+        let signed = available.len() >= 4 && available.starts_with(&DESCRIPTOR_SIGNATURE);
+        let descriptor_size = if signed { 4 } else { 0 } + 4 + 2 * width;
+        if available.len() < descriptor_size || (!signed && available.len() < 4 && !self.eof) {
+            return self.need_input();
+        }
+        let (crc, compressed_size, size) =
+            descriptor_fields(&available[if signed { 4 } else { 0 }..], width);
+        payload.done = true;
+        let corrupt = compressed_size != payload.compressed
+            || (!payload.skipped
+                && (size != payload.size || crc != payload.hasher.clone().finalize()));
+        self.advance(descriptor_size);
+        if corrupt {
+            return Err(self.entry_error(|name| Error::CorruptEntry { name }));
+        }
+        if let Some(entry) = &mut self.entry {
+            (entry.crc, entry.compressed_size, entry.size) =
+                (Some(crc), Some(compressed_size), Some(size));
+        }
+        Ok(Some(()))
+    }
+}
+
+/// An entry of an existing archive, as described by its central directory record.
+#[derive(Clone, Debug)]
+pub struct CentralEntry {
+    name: String,
+    raw_name: Vec<u8>,
+    version_made_by: u16,
+    version_needed: u16,
+    flags: u16,
+    method: u16,
+    datetime: (u16, u16),
+    crc: u32,
+    compressed_size: u64,
+    size: u64,
+    internal_attributes: u16,
+    external_attributes: u32,
+    extra_field: Vec<u8>,
+    comment: String,
+    offset: u64,
+    record: Vec<u8>,
+}
+
+impl CentralEntry {
+    /// The name of the entry, decoded from UTF-8 or code page 437 (or from its Unicode Path extra field).
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The name of the entry, as written in the archive.
+    pub fn raw_name(&self) -> &[u8] {
+        &self.raw_name
+    }
+
+    /// Whether the entry is a directory, its name ending with a slash.
+    pub fn is_directory(&self) -> bool {
+        self.name.ends_with('/')
+    }
+
+    /// The system the archive claims the entry was made on, interpreting its external attributes.
+    pub fn host_system(&self) -> HostSystem {
+        HostSystem::from_code((self.version_made_by >> 8) as u8)
+    }
+
+    /// The "version made by" field of the entry.
+    pub fn version_made_by(&self) -> u16 {
+        self.version_made_by
+    }
+
+    /// The version of the specification needed to extract the entry (e.g. 45 for Zip64).
+    pub fn version_needed(&self) -> u16 {
+        self.version_needed
+    }
+
+    /// The general purpose flag of the entry.
+    pub fn flags(&self) -> u16 {
+        self.flags
+    }
+
+    /// Whether the payload of the entry is encrypted.
+    pub fn is_encrypted(&self) -> bool {
+        self.flags & 1 != 0
+    }
+
+    /// The compression method of the entry (e.g. 0 if stored, 8 if deflated).
+    pub fn method(&self) -> u16 {
+        self.method
+    }
+
+    /// The modification date and time of the entry.
+    pub fn datetime(&self) -> FileDateTime {
+        FileDateTime::from_ms_dos(self.datetime.0, self.datetime.1)
+    }
+
+    /// The CRC32 of the payload.
+    pub fn crc(&self) -> u32 {
+        self.crc
+    }
+
+    /// The size of the compressed payload.
+    pub fn compressed_size(&self) -> u64 {
+        self.compressed_size
+    }
+
+    /// The size of the payload.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Whether the entry is flagged as text in its internal attributes.
+    pub fn is_text(&self) -> bool {
+        self.internal_attributes & 1 != 0
+    }
+
+    /// The internal file attributes of the entry.
+    pub fn internal_attributes(&self) -> u16 {
+        self.internal_attributes
+    }
+
+    /// The external file attributes of the entry, their meaning depending on its host system.
+    pub fn external_attributes(&self) -> u32 {
+        self.external_attributes
+    }
+
+    /// The Unix mode (file type and permissions) of the entry, if it was made on Unix or macOS and has one.
+    pub fn unix_mode(&self) -> Option<u32> {
+        let mode = self.external_attributes >> 16;
+        (matches!(self.host_system(), HostSystem::Unix | HostSystem::Osx) && mode != 0)
+            .then_some(mode)
+    }
+
+    /// The extra fields of the central directory record (they might differ from the local header's).
+    pub fn extra_field(&self) -> &[u8] {
+        &self.extra_field
+    }
+
+    /// The comment of the entry, decoded like its name.
+    pub fn comment(&self) -> &str {
+        &self.comment
+    }
+
+    /// The offset of the local header in the archive.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    // The MS-DOS (date, time) of the entry, as written.
+    pub(crate) fn ms_dos_datetime(&self) -> (u16, u16) {
+        self.datetime
+    }
+
+    // The central directory record of the entry, as written.
+    pub(crate) fn record(&self) -> &[u8] {
+        &self.record
+    }
+}
+
+/// The central directory of an existing archive, read from its end.
+///
+/// Unlike `ArchiveReader`, it needs a seekable source, but describes every entry without reading their payloads,
+/// including the attributes only written in the central directory (e.g. the Unix permissions and the comments).
+#[derive(Clone, Debug)]
+pub struct CentralDirectory {
+    entries: Vec<CentralEntry>,
+    offset: u64,
+    size: u64,
+    comment: Vec<u8>,
+    zip64: bool,
+}
+
+impl CentralDirectory {
+    /// The entries of the archive, in the order of the central directory.
+    pub fn entries(&self) -> &[CentralEntry] {
+        &self.entries
+    }
+
+    /// The offset of the central directory, where the entries' local headers and payloads end.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// The size of the central directory.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The comment of the archive.
+    pub fn comment(&self) -> &[u8] {
+        &self.comment
+    }
+
+    /// Whether the central directory is located by Zip64 end of central directory records.
+    pub fn is_zip64(&self) -> bool {
+        self.zip64
+    }
+}
+
+/// The location of the central directory, read from the end of the archive.
+#[derive(Debug)]
+struct End {
+    // The offset of the end of central directory record.
+    offset: u64,
+    entries: u64,
+    size: u64,
+    directory_offset: u64,
+    comment: Vec<u8>,
+    // The offset of the Zip64 end of central directory record, if any.
+    zip64_offset: Option<u64>,
+}
+
+impl End {
+    // Find the end of central directory record in the `tail` of the archive, starting at `tail_offset`.
+    fn parse(tail: &[u8], tail_offset: u64) -> Result<Self, Error> {
+        let invalid = Error::InvalidArchive {
+            offset: tail_offset + tail.len() as u64,
+        };
+        let candidates = (0..=tail.len().saturating_sub(END_OF_CENTRAL_DIRECTORY_SIZE))
+            .rev()
+            .filter(|&start| tail[start..].starts_with(&END_OF_CENTRAL_DIRECTORY_SIGNATURE));
+        // Prefer a record whose comment ends the archive, one followed by garbage otherwise.
+        let comment_end = |start: usize| {
+            start + END_OF_CENTRAL_DIRECTORY_SIZE + u16_at(tail, start + 20) as usize
+        };
+        let start = candidates
+            .clone()
+            .find(|&start| comment_end(start) == tail.len())
+            .or_else(|| {
+                candidates
+                    .clone()
+                    .find(|&start| comment_end(start) <= tail.len())
+            })
+            .ok_or(invalid)?;
+
+        let record = &tail[start..];
+        let zip64_offset = start
+            .checked_sub(ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIZE)
+            .map(|locator| &tail[locator..])
+            .filter(|locator| {
+                locator.starts_with(&ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIGNATURE)
+            })
+            .map(|locator| u64_at(locator, 8));
+        Ok(Self {
+            offset: tail_offset + start as u64,
+            entries: u16_at(record, 10) as u64,
+            size: u32_at(record, 12) as u64,
+            directory_offset: u32_at(record, 16) as u64,
+            comment: record[END_OF_CENTRAL_DIRECTORY_SIZE..comment_end(start) - start].to_vec(),
+            zip64_offset,
+        })
+    }
+
+    // Use the values of the Zip64 end of central directory record.
+    fn parse_zip64(&mut self, record: &[u8], offset: u64) -> Result<(), Error> {
+        if !record.starts_with(&ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE) {
+            return Err(Error::InvalidArchive { offset });
+        }
+        self.entries = u64_at(record, 32);
+        self.size = u64_at(record, 40);
+        self.directory_offset = u64_at(record, 48);
+        Ok(())
+    }
+
+    // The offset of the first end record, which the central directory must precede.
+    fn records_offset(&self) -> u64 {
+        self.zip64_offset.unwrap_or(self.offset)
+    }
+}
+
+impl CentralDirectory {
+    // Parse the central directory located by `end`, its `data` being read.
+    fn from_end(end: End, data: &[u8]) -> Result<Self, Error> {
+        Ok(Self {
+            entries: central_entries(data, end.directory_offset, end.entries)?,
+            offset: end.directory_offset,
+            size: end.size,
+            comment: end.comment,
+            zip64: end.zip64_offset.is_some(),
+        })
+    }
+
+    // Parse the central directory and the end records at the end of an archive, `data` starting at `offset`.
+    fn parse(data: &[u8], offset: u64) -> Result<Self, Error> {
+        let mut end = End::parse(data, offset)?;
+        let invalid = Error::InvalidArchive { offset: end.offset };
+        if let Some(zip64_offset) = end.zip64_offset {
+            let record = zip64_offset
+                .checked_sub(offset)
+                .and_then(|start| {
+                    data.get(start as usize..)?
+                        .get(..ZIP64_END_OF_CENTRAL_DIRECTORY_SIZE)
+                })
+                .ok_or(invalid)?;
+            end.parse_zip64(record, zip64_offset)?;
+        }
+        let directory = end
+            .directory_offset
+            .checked_sub(offset)
+            .filter(|_| end.directory_offset.saturating_add(end.size) <= end.records_offset())
+            .and_then(|start| data.get(start as usize..)?.get(..end.size as usize))
+            .ok_or(Error::InvalidArchive { offset: end.offset })?
+            .to_vec();
+        Self::from_end(end, &directory)
+    }
+}
+
+// Parse the records of the central directory, starting at `offset` in the archive.
+fn central_entries(data: &[u8], offset: u64, count: u64) -> Result<Vec<CentralEntry>, Error> {
+    let mut entries = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let invalid = Error::InvalidArchive {
+            offset: offset + pos as u64,
+        };
+        let record = &data[pos..];
+        if record.len() < CENTRAL_DIRECTORY_ENTRY_SIZE
+            || !record.starts_with(&CENTRAL_DIRECTORY_SIGNATURE)
+        {
+            return Err(invalid);
+        }
+        let name_length = u16_at(record, 28) as usize;
+        let extra_field_length = u16_at(record, 30) as usize;
+        let comment_length = u16_at(record, 32) as usize;
+        let record_size =
+            CENTRAL_DIRECTORY_ENTRY_SIZE + name_length + extra_field_length + comment_length;
+        if record.len() < record_size {
+            return Err(invalid);
+        }
+
+        let flags = u16_at(record, 8);
+        let raw_name = &record[CENTRAL_DIRECTORY_ENTRY_SIZE..][..name_length];
+        let extra_field =
+            &record[CENTRAL_DIRECTORY_ENTRY_SIZE + name_length..][..extra_field_length];
+        let comment =
+            &record[CENTRAL_DIRECTORY_ENTRY_SIZE + name_length + extra_field_length..record_size];
+        let mut values = [
+            u32_at(record, 24) as u64,
+            u32_at(record, 20) as u64,
+            u32_at(record, 42) as u64,
+        ];
+        zip64_values(extra_field, &mut values).ok_or(invalid)?;
+        entries.push(CentralEntry {
+            name: decode_name(raw_name, flags, extra_field),
+            raw_name: raw_name.to_vec(),
+            version_made_by: u16_at(record, 4),
+            version_needed: u16_at(record, 6),
+            flags,
+            method: u16_at(record, 10),
+            datetime: (u16_at(record, 14), u16_at(record, 12)),
+            crc: u32_at(record, 16),
+            compressed_size: values[1],
+            size: values[0],
+            internal_attributes: u16_at(record, 36),
+            external_attributes: u32_at(record, 38),
+            extra_field: extra_field.to_vec(),
+            comment: decode_text(comment, flags),
+            offset: values[2],
+            record: record[..record_size].to_vec(),
+        });
+        pos += record_size;
+    }
+    // The count of a Zip64-less archive with more entries is truncated.
+    if entries.len() as u64 != count && (count != u16::MAX as u64 || entries.len() < count as usize)
+    {
+        return Err(Error::InvalidArchive {
+            offset: offset + data.len() as u64,
+        });
+    }
+    Ok(entries)
+}
+
+// The CRC32, compressed size and size of a data descriptor (without its signature).
+fn descriptor_fields(fields: &[u8], width: usize) -> (u32, u64, u64) {
+    let size_at = |offset| {
+        if width == 8 {
+            u64_at(fields, offset)
+        } else {
+            u32_at(fields, offset) as u64
+        }
+    };
+    (u32_at(fields, 0), size_at(4), size_at(4 + width))
+}
+
+// Decode a name from UTF-8 if flagged, from code page 437 otherwise,
+// preferring its Unicode Path extra field if it was written for the same name.
+pub(crate) fn decode_name(raw_name: &[u8], flags: u16, extra_field: &[u8]) -> String {
+    for (id, data) in extra_fields(extra_field) {
+        if id == 0x7075 && data.len() >= 5 && data[0] == 1 && u32_at(data, 1) == crc32(raw_name) {
+            return String::from_utf8_lossy(&data[5..]).into_owned();
+        }
+    }
+    decode_text(raw_name, flags)
+}
+
+fn decode_text(raw: &[u8], flags: u16) -> String {
+    if flags & 1 << 11 != 0 {
+        String::from_utf8_lossy(raw).into_owned()
+    } else {
+        cp437::decode(raw)
+    }
+}
+
+// Replace the values that don't fit in their field with the ones of the Zip64 extra field, in this order.
+// Returns whether the extra field is present, `None` if it is missing a value.
+fn zip64_values(extra_field: &[u8], fields: &mut [u64]) -> Option<bool> {
+    let Some((_, data)) = extra_fields(extra_field).find(|&(id, _)| id == 0x0001) else {
+        return Some(false);
+    };
+    let mut values = data.chunks_exact(8).map(|value| u64_at(value, 0));
+    for field in fields {
+        if *field == u32::MAX as u64 {
+            *field = values.next()?;
+        }
+    }
+    Some(true)
+}
+
+// Iterate over the (id, data) of the extra fields.
+fn extra_fields(mut extra_field: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    std::iter::from_fn(move || {
+        if extra_field.len() < 4 {
+            return None;
+        }
+        let id = u16_at(extra_field, 0);
+        let size = (u16_at(extra_field, 2) as usize).min(extra_field.len() - 4);
+        let data = &extra_field[4..4 + size];
+        extra_field = &extra_field[4 + size..];
+        Some((id, data))
+    })
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut hasher = Hasher::new();
+    hasher.update(data);
+    hasher.finalize()
+}
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+fn u64_at(data: &[u8], offset: usize) -> u64 {
+    u32_at(data, offset) as u64 | (u32_at(data, offset + 4) as u64) << 32
+}
+
+macro_rules! impl_methods {
+    (
+        $(#[$($attrss:tt)*])*,
+        $r:path, $re:path,
+        $s:path, $se:path,
+        $fn:tt, $fm:tt, $fr:tt, $frr:tt, $fs:tt, $fl:tt, $fv:tt, $fcd:tt, $fc:tt,
+    ) => {
+        impl<R> ArchiveReader<R> {
+            /// Move to the next entry, skipping what is left of the current one's payload.
+            /// Returns `None` once every entry was read, when reaching the central directory.
+            ///
+            /// # Error
+            ///
+            /// This function will forward any error found while reading from the source.
+            /// An `Error::InvalidArchive` is returned if the source isn't a valid archive or is truncated,
+            /// and the same errors as `read` if the end of the skipped payload can only be found by decompressing it.
+            $(#[$($attrss)*])*
+            pub async fn $fn(&mut self) -> Result<Option<Entry>, IoError> where R: $r + Unpin {
+                use $re;
+
+                loop {
+                    if let Some(entry) = self.poll_next_entry()? {
+                        return Ok(entry);
+                    }
+                    let start = self.reserve();
+                    let read = self.source.read(&mut self.input[start..]).await?;
+                    self.filled(start, read);
+                }
+            }
+
+            /// Move to the next entry matching `predicate` (e.g. a `Glob`), skipping the payloads of the other ones.
+            /// A skipped payload isn't decompressed if its compressed size is written in its local header,
+            /// otherwise its end can only be found by decompressing (or scanning) it.
+            ///
+            /// # Error
+            ///
+            /// The same errors as `next_entry` are returned.
+            $(#[$($attrss)*])*
+            pub async fn $fm<F>(&mut self, mut predicate: F) -> Result<Option<Entry>, IoError>
+            where
+                R: $r + Unpin,
+                F: FnMut(&Entry) -> bool,
+            {
+                while let Some(entry) = self.$fn().await? {
+                    if predicate(&entry) {
+                        return Ok(Some(entry));
+                    }
+                }
+                Ok(None)
+            }
+
+            /// Read the compressed payload of the current entry as is into `buf`, returning the number of bytes read, 0 at its end,
+            /// e.g. to copy it to another archive without recompressing it (see `Archive::append_raw`).
+            /// Its CRC32 isn't checked, unless its end can only be found by decompressing it (deflated with a data descriptor).
+            ///
+            /// # Error
+            ///
+            /// The same errors as `read` are returned, an `Error::UnsupportedEntry` only being returned
+            /// if the end of the payload can't be found without decompressing it.
+            $(#[$($attrss)*])*
+            pub async fn $frr(&mut self, buf: &mut [u8]) -> Result<usize, IoError> where R: $r + Unpin {
+                use $re;
+
+                loop {
+                    if let Some(read) = self.poll_read_raw(buf)? {
+                        return Ok(read);
+                    }
+                    let start = self.reserve();
+                    let read = self.source.read(&mut self.input[start..]).await?;
+                    self.filled(start, read);
+                }
+            }
+
+            /// Read the (decompressed) payload of the current entry into `buf`, returning the number of bytes read, 0 at its end.
+            ///
+            /// # Error
+            ///
+            /// This function will forward any error found while reading from the source.
+            /// An `Error::UnsupportedEntry` is returned if the entry is encrypted or its compression method isn't supported,
+            /// an `Error::CorruptEntry` if the payload doesn't match its CRC32 or sizes,
+            /// and an `Error::InvalidArchive` if the source is truncated.
+            $(#[$($attrss)*])*
+            pub async fn $fr(&mut self, buf: &mut [u8]) -> Result<usize, IoError> where R: $r + Unpin {
+                use $re;
+
+                loop {
+                    if let Some(read) = self.poll_read(buf)? {
+                        return Ok(read);
+                    }
+                    let start = self.reserve();
+                    let read = self.source.read(&mut self.input[start..]).await?;
+                    self.filled(start, read);
+                }
+            }
+        }
+
+        impl<R> ArchiveReader<R> {
+            /// Skip what is left of the current entry's payload, its CRC32 and sizes being then known (see `entry`).
+            ///
+            /// # Error
+            ///
+            /// The same errors as `next_entry` are returned.
+            $(#[$($attrss)*])*
+            pub async fn $fs(&mut self) -> Result<(), IoError> where R: $r + Unpin {
+                use $re;
+
+                loop {
+                    if self.poll_skip()?.is_some() {
+                        return Ok(());
+                    }
+                    let start = self.reserve();
+                    let read = self.source.read(&mut self.input[start..]).await?;
+                    self.filled(start, read);
+                }
+            }
+
+            /// List the entries left to read, skipping their payloads, e.g. to show the content of an archive without extracting it.
+            ///
+            /// # Error
+            ///
+            /// The same errors as `next_entry` are returned.
+            $(#[$($attrss)*])*
+            pub async fn $fl(&mut self) -> Result<Vec<EntryInfo>, IoError> where R: $r + Unpin {
+                let mut entries = Vec::new();
+                while self.$fn().await?.is_some() {
+                    self.$fs().await?;
+                    entries.extend(self.entry.as_ref().map(EntryInfo::new));
+                }
+                Ok(entries)
+            }
+
+            /// Verify the entries left to read, decompressing their payloads to check their CRC32 and sizes
+            /// against their local headers and data descriptors, then against the central directory.
+            /// The entries that can't be checked (e.g. encrypted) are reported as `EntryStatus::Unsupported`.
+            ///
+            /// # Error
+            ///
+            /// This function will forward any error found while reading from the source.
+            /// An `Error::InvalidArchive` is returned if the archive or its central directory is invalid or truncated,
+            /// and the errors of `read` if the end of an invalid entry can't be found (its compressed size only being written in a data descriptor).
+            $(#[$($attrss)*])*
+            pub async fn $fv(&mut self) -> Result<VerifyReport, IoError> where R: $r + Unpin {
+                let mut entries = Vec::new();
+                let mut buf = vec![0; READ_SIZE];
+                while self.$fn().await?.is_some() {
+                    let status = loop {
+                        let error = match self.$fr(&mut buf).await {
+                            Ok(0) => break EntryStatus::Valid,
+                            Ok(_) => continue,
+                            Err(error) => error,
+                        };
+                        match error.get_ref().and_then(|error| error.downcast_ref::<Error>()) {
+                            Some(Error::CorruptEntry { .. }) => break EntryStatus::Corrupt,
+                            Some(Error::UnsupportedEntry { .. }) => break EntryStatus::Unsupported,
+                            _ => return Err(error),
+                        }
+                    };
+                    self.$fs().await?;
+                    if let Some(entry) = &self.entry {
+                        entries.push(EntryReport { info: EntryInfo::new(entry), status });
+                    }
+                }
+
+                let mut listed: HashMap<u64, CentralEntry> = self
+                    .$fcd()
+                    .await?
+                    .entries
+                    .into_iter()
+                    .map(|entry| (entry.offset, entry))
+                    .collect();
+                for report in &mut entries {
+                    let record = listed.remove(&report.info.offset);
+                    if report.status != EntryStatus::Valid {
+                        continue;
+                    }
+                    report.status = match record {
+                        None => EntryStatus::Unlisted,
+                        Some(record)
+                            if record.name != report.info.name
+                                || record.crc != report.info.crc
+                                || record.compressed_size != report.info.compressed_size
+                                || record.size != report.info.size =>
+                        {
+                            EntryStatus::Mismatch
+                        }
+                        Some(_) => EntryStatus::Valid,
+                    };
+                }
+                let mut missing: Vec<_> = listed.into_values().collect();
+                missing.sort_by_key(|entry| entry.offset);
+                Ok(VerifyReport { entries, missing })
+            }
+
+            /// Read the central directory following the entries, skipping the ones left,
+            /// e.g. for the attributes only written in it (like the Unix permissions).
+            /// The rest of the source is buffered, to find the end of central directory record at its end.
+            ///
+            /// # Error
+            ///
+            /// The same errors as `next_entry` are returned while skipping the entries left,
+            /// and an `Error::InvalidArchive` if the central directory is invalid or truncated.
+            $(#[$($attrss)*])*
+            pub async fn $fcd(&mut self) -> Result<CentralDirectory, IoError> where R: $r + Unpin {
+                use $re;
+
+                loop {
+                    if let Some(central_directory) = self.poll_central_directory()? {
+                        return Ok(central_directory);
+                    }
+                    let start = self.reserve();
+                    let read = self.source.read(&mut self.input[start..]).await?;
+                    self.filled(start, read);
+                }
+            }
+        }
+
+        impl CentralDirectory {
+            /// Read the central directory of the archive in `source`, from the end of central directory record at its end.
+            ///
+            /// # Error
+            ///
+            /// This function will forward any error found while reading from or seeking the source.
+            /// An `Error::InvalidArchive` is returned if the end of central directory record isn't found,
+            /// or if the central directory it locates is truncated or invalid.
+            $(#[$($attrss)*])*
+            pub async fn $fc<R>(source: &mut R) -> Result<Self, IoError> where R: $r + $s + Unpin {
+                use $re;
+                use $se;
+
+                // The record might be followed by a comment of up to 65,535 bytes, and preceded by the Zip64 locator.
+                let len = source.seek(SeekFrom::End(0)).await?;
+                let tail_offset = len.saturating_sub(
+                    (ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIZE + END_OF_CENTRAL_DIRECTORY_SIZE + u16::MAX as usize) as u64,
+                );
+                let mut tail = vec![0; (len - tail_offset) as usize];
+                source.seek(SeekFrom::Start(tail_offset)).await?;
+                source.read_exact(&mut tail).await?;
+                let mut end = End::parse(&tail, tail_offset)?;
+
+                if let Some(offset) = end.zip64_offset {
+                    if offset.saturating_add(ZIP64_END_OF_CENTRAL_DIRECTORY_SIZE as u64) > end.offset {
+                        return Err(Error::InvalidArchive { offset: end.offset }.into());
+                    }
+                    let mut record = [0; ZIP64_END_OF_CENTRAL_DIRECTORY_SIZE];
+                    source.seek(SeekFrom::Start(offset)).await?;
+                    source.read_exact(&mut record).await?;
+                    end.parse_zip64(&record, offset)?;
+                }
+
+                if end.directory_offset.saturating_add(end.size) > end.records_offset() {
+                    return Err(Error::InvalidArchive { offset: end.records_offset() }.into());
+                }
+                let mut data = vec![0; end.size as usize];
+                source.seek(SeekFrom::Start(end.directory_offset)).await?;
+                source.read_exact(&mut data).await?;
+                Ok(Self::from_end(end, &data)?)
+            }
+        }
+    };
+}
+
+#[cfg(all(feature = "futures-async-io", feature = "tokio-async-io"))]
+impl_methods!(
+    #[cfg(all(feature = "futures-async-io", feature = "tokio-async-io"))],
+    futures_util::AsyncRead, futures_util::AsyncReadExt,
+    futures_util::AsyncSeek, futures_util::AsyncSeekExt,
+    futures_next_entry, futures_next_entry_matching, futures_read, futures_read_raw, futures_skip, futures_list, futures_verify, futures_central_directory, futures_read,
+);
+
+#[cfg(all(feature = "futures-async-io", feature = "tokio-async-io"))]
+impl_methods!(
+    #[cfg(all(feature = "futures-async-io", feature = "tokio-async-io"))],
+    tokio::io::AsyncRead, tokio::io::AsyncReadExt,
+    tokio::io::AsyncSeek, tokio::io::AsyncSeekExt,
+    tokio_next_entry, tokio_next_entry_matching, tokio_read, tokio_read_raw, tokio_skip, tokio_list, tokio_verify, tokio_central_directory, tokio_read,
+);
+
+#[cfg(all(feature = "futures-async-io", not(feature = "tokio-async-io")))]
+impl_methods!(
+    #[cfg(all(feature = "futures-async-io", not(feature = "tokio-async-io")))],
+    futures_util::AsyncRead, futures_util::AsyncReadExt,
+    futures_util::AsyncSeek, futures_util::AsyncSeekExt,
+    next_entry, next_entry_matching, read, read_raw, skip, list, verify, central_directory, read,
+);
+
+#[cfg(all(feature = "tokio-async-io", not(feature = "futures-async-io")))]
+impl_methods!(
+    #[cfg(all(feature = "tokio-async-io", not(feature = "futures-async-io")))],
+    tokio::io::AsyncRead, tokio::io::AsyncReadExt,
+    tokio::io::AsyncSeek, tokio::io::AsyncSeekExt,
+    next_entry, next_entry_matching, read, read_raw, skip, list, verify, central_directory, read,
+);
@@ -0,0 +1,190 @@
+use std::fmt::Write;
+
+#[cfg(feature = "actix")]
+use actix_web::body::{BodyStream, BoxBody, MessageBody, SizedStream};
+#[cfg(feature = "actix")]
+use actix_web::{HttpRequest, HttpResponse, Responder as ActixResponder};
+#[cfg(feature = "axum")]
+use axum::body::Body as AxumBody;
+#[cfg(feature = "axum")]
+use axum::response::{IntoResponse, Response as AxumResponse};
+#[cfg(feature = "rocket")]
+use rocket::response::{
+    Responder as RocketResponder, Response as RocketResponse, Result as RocketResult,
+};
+#[cfg(feature = "rocket")]
+use rocket::Request as RocketRequest;
+#[cfg(feature = "warp")]
+use warp::hyper::Body as WarpBody;
+#[cfg(feature = "warp")]
+use warp::reply::{Reply, Response as WarpResponse};
+
+#[cfg(feature = "rocket")]
+use crate::stream::StreamReader;
+#[cfg(feature = "axum")]
+use crate::ZipBody;
+use crate::{ArchiveOptions, ArchiveStream, EntrySource};
+
+/// A response streaming a zip archive, for the web frameworks enabled by their feature
+/// (`IntoResponse` with `axum`, `Responder` with `actix` and `rocket`, `Reply` with `warp`).
+///
+/// The response has a `Content-Type` of `application/zip`, a `Content-Disposition` of `attachment`
+/// (with the name of the archive if set with `filename`), and a `Content-Length` if the size of the archive is known
+/// (see `ArchiveStream::size`, e.g. for a plan or sources whose files all have a declared size).
+/// An error while streaming the archive (e.g. while reading a payload) aborts the body.
+///
+/// ## Example
+///
+/// ```
+/// use std::io::Cursor;
+/// use zipit::{EntrySource, FileDateTime, ZipResponse};
+///
+/// async fn download() -> ZipResponse {
+///     ZipResponse::new([
+///         EntrySource::file("file1.txt".to_owned(), FileDateTime::Zero, Cursor::new(b"hello\n")).size(6),
+///     ])
+///     .filename("files.zip")
+/// }
+/// // e.g. axum::Router::new().route("/download", axum::routing::get(download)),
+/// // or actix_web::App::new().route("/download", actix_web::web::get().to(download)),
+/// // or warp::path("download").then(download), or returned by a rocket route.
+/// ```
+#[derive(Debug)]
+pub struct ZipResponse {
+    stream: ArchiveStream,
+    filename: Option<String>,
+}
+
+impl ZipResponse {
+    /// Create the response of an archive made of the entries of `sources`, in order, written with the default options.
+    pub fn new<I>(sources: I) -> Self
+    where
+        I: IntoIterator<Item = EntrySource>,
+    {
+        Self::from(ArchiveStream::new(sources))
+    }
+
+    /// Create the response of an archive made of the entries of `sources`, in order, written with `options`.
+    pub fn with_options<I>(sources: I, options: ArchiveOptions) -> Self
+    where
+        I: IntoIterator<Item = EntrySource>,
+    {
+        Self::from(ArchiveStream::with_options(sources, options))
+    }
+
+    /// Set the name the archive is downloaded as, in the `Content-Disposition` header.
+    pub fn filename<N: Into<String>>(mut self, filename: N) -> Self {
+        self.filename = Some(filename.into());
+        self
+    }
+
+    // The headers of the response, by lowercase name.
+    fn headers(&self) -> Vec<(&'static str, String)> {
+        let mut headers = vec![
+            ("content-type", "application/zip".to_owned()),
+            (
+                "content-disposition",
+                content_disposition(self.filename.as_deref()),
+            ),
+        ];
+        if let Some(size) = self.stream.size() {
+            headers.push(("content-length", size.to_string()));
+        }
+        headers
+    }
+}
+
+impl From<ArchiveStream> for ZipResponse {
+    fn from(stream: ArchiveStream) -> Self {
+        Self {
+            stream,
+            filename: None,
+        }
+    }
+}
+
+// An attachment named `filename`, quoted with its non-ASCII characters replaced (and escaped in `filename*`, as in RFC 6266).
+fn content_disposition(filename: Option<&str>) -> String {
+    let mut disposition = "attachment".to_owned();
+    let Some(filename) = filename else {
+        return disposition;
+    };
+    let fallback: String = filename
+        .chars()
+        .map(|c| match c {
+            ' '..='~' if c != '"' && c != '\\' => c,
+            _ => '_',
+        })
+        .collect();
+    write!(disposition, "; filename=\"{fallback}\"").unwrap();
+    if fallback != filename {
+        disposition.push_str("; filename*=UTF-8''");
+        for &byte in filename.as_bytes() {
+            match byte {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                    disposition.push(byte as char)
+                }
+                byte => write!(disposition, "%{byte:02X}").unwrap(),
+            }
+        }
+    }
+    disposition
+}
+
+#[cfg(feature = "axum")]
+impl IntoResponse for ZipResponse {
+    fn into_response(self) -> AxumResponse {
+        let headers = self.headers();
+        let mut response = AxumResponse::new(AxumBody::new(ZipBody::new(self.stream)));
+        for (name, value) in headers {
+            response
+                .headers_mut()
+                .insert(name, value.try_into().unwrap());
+        }
+        response
+    }
+}
+
+#[cfg(feature = "actix")]
+impl ActixResponder for ZipResponse {
+    type Body = BoxBody;
+
+    fn respond_to(self, _request: &HttpRequest) -> HttpResponse {
+        let mut response = HttpResponse::Ok();
+        // The Content-Length is the size of the body, which actix writes itself.
+        for (name, value) in self.headers() {
+            if name != "content-length" {
+                response.insert_header((name, value));
+            }
+        }
+        match self.stream.size() {
+            Some(size) => response.body(SizedStream::new(size, self.stream).boxed()),
+            None => response.body(BodyStream::new(self.stream).boxed()),
+        }
+    }
+}
+
+#[cfg(feature = "warp")]
+impl Reply for ZipResponse {
+    fn into_response(self) -> WarpResponse {
+        let headers = self.headers();
+        let mut response = WarpResponse::new(WarpBody::wrap_stream(self.stream));
+        for (name, value) in headers {
+            response
+                .headers_mut()
+                .insert(name, value.try_into().unwrap());
+        }
+        response
+    }
+}
+
+#[cfg(feature = "rocket")]
+impl<'r> RocketResponder<'r, 'static> for ZipResponse {
+    fn respond_to(self, _request: &'r RocketRequest<'_>) -> RocketResult<'static> {
+        let mut response = RocketResponse::build();
+        for (name, value) in self.headers() {
+            response.raw_header(name, value);
+        }
+        response.streamed_body(StreamReader::new(self.stream)).ok()
+    }
+}
@@ -0,0 +1,155 @@
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::future::Future;
+use std::io::Error as IoError;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use futures_core::Stream;
+use http::header::{HeaderName, HeaderValue, CONTENT_LENGTH, CONTENT_TYPE, TRAILER};
+use http::{HeaderMap, Request, Response};
+use http_body::{Body, SizeHint};
+use tower_service::Service;
+
+use crate::{ArchiveOptions, ArchiveStream, EntrySource};
+
+/// A `tower::Service` answering every request with a zip archive streamed from the entry sources
+/// the `sources` function returns for it, so the same zipping logic can be mounted in any tower-based stack
+/// (e.g. as a hyper service, or an axum route).
+///
+/// The response has a `Content-Type` of `application/zip`, and a `Content-Length` if the size of every file is declared
+/// (see `EntrySource::size`). Its body is the `ArchiveStream` of the archive, which implements `http_body::Body`.
+/// With `trailers`, the body ends with the digest of the archive and the CRC32 of its entries as HTTP trailers.
+///
+/// ## Example
+///
+/// ```
+/// use hyper::{Body, Request};
+/// use std::io::Cursor;
+/// use zipit::{EntrySource, FileDateTime, ZipService};
+///
+/// let service = ZipService::new(|request: Request<Body>| {
+///     let name = format!("{}.txt", request.uri().path().trim_start_matches('/'));
+///     async move {
+///         Ok::<_, std::io::Error>(vec![
+///             EntrySource::file(name, FileDateTime::Zero, Cursor::new(b"hello\n")).size(6),
+///         ])
+///     }
+/// });
+/// // e.g. hyper::Server::bind(&address).serve(tower::make::Shared::new(service)).
+/// ```
+#[derive(Clone)]
+pub struct ZipService<F> {
+    sources: F,
+    options: ArchiveOptions,
+    trailers: bool,
+}
+
+impl<F> ZipService<F> {
+    /// Create a service writing the archives with the default options.
+    pub fn new(sources: F) -> Self {
+        Self::with_options(sources, ArchiveOptions::default())
+    }
+
+    /// Create a service writing the archives with `options`.
+    pub fn with_options(sources: F, options: ArchiveOptions) -> Self {
+        Self {
+            sources,
+            options,
+            trailers: false,
+        }
+    }
+
+    /// Announce with a `Trailer` header and send at the end of the bodies the `content-digest` (the SHA-256 digest
+    /// of the archive, as in RFC 9530) and `x-zip-crc32` (the comma-separated hexadecimal CRC32 of the entries)
+    /// trailers (see `ArchiveStream::trailers`), so clients can verify chunked downloads without a `Content-Length`.
+    ///
+    /// Trailers are only sent by protocols supporting them, e.g. HTTP/2 (hyper doesn't send them over HTTP/1.1).
+    pub fn trailers(mut self, trailers: bool) -> Self {
+        self.trailers = trailers;
+        self
+    }
+}
+
+impl<F> Debug for ZipService<F> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("ZipService")
+            .field("options", &self.options)
+            .field("trailers", &self.trailers)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<B, F, Fut> Service<Request<B>> for ZipService<F>
+where
+    F: FnMut(Request<B>) -> Fut,
+    Fut: Future<Output = Result<Vec<EntrySource>, IoError>> + Send + 'static,
+{
+    type Response = Response<ArchiveStream>;
+    type Error = IoError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let sources = (self.sources)(request);
+        let options = self.options.clone();
+        let trailers = self.trailers;
+        Box::pin(async move {
+            let stream = ArchiveStream::with_options(sources.await?, options).trailers(trailers);
+            let mut headers = HeaderMap::new();
+            headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/zip"));
+            if let Some(size) = stream.size() {
+                headers.insert(CONTENT_LENGTH, HeaderValue::from(size));
+            }
+            if trailers {
+                headers.insert(
+                    TRAILER,
+                    HeaderValue::from_static("content-digest, x-zip-crc32"),
+                );
+            }
+
+            let mut response = Response::new(stream);
+            *response.headers_mut() = headers;
+            Ok(response)
+        })
+    }
+}
+
+impl Body for ArchiveStream {
+    type Data = Bytes;
+    type Error = IoError;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        self.poll_next(cx)
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        let Some(values) = self.trailer_values() else {
+            return Poll::Ready(Ok(None));
+        };
+        let mut trailers = HeaderMap::new();
+        for (name, value) in values {
+            trailers.insert(
+                HeaderName::from_static(name),
+                HeaderValue::try_from(value).unwrap(),
+            );
+        }
+        Poll::Ready(Ok(Some(trailers)))
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match self.size() {
+            Some(size) => SizeHint::with_exact(size),
+            None => SizeHint::default(),
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::sync::Arc;
+
+/// A signer computing a detached signature over the bytes of the archive, fed chunk by chunk while they are written.
+///
+/// Implement this trait with the signature scheme of your choice (e.g. an HMAC or an Ed25519 key over a SHA-256 digest),
+/// and pass it to `ArchiveOptions::signer`. The signature is returned by `finalize_signed`,
+/// and can be embedded in the archive's comment with `ArchiveOptions::embed_signature`.
+///
+/// ## Example
+///
+/// ```
+/// use zipit::Signer;
+///
+/// // A (very) insecure checksum, standing for a real signature.
+/// struct Sum(u32);
+///
+/// impl Signer for Sum {
+///     fn update(&mut self, data: &[u8]) {
+///         for &byte in data {
+///             self.0 = self.0.wrapping_add(byte as u32);
+///         }
+///     }
+///
+///     fn sign(&mut self) -> Vec<u8> {
+///         format!("sum:{:08x}", self.0).into_bytes()
+///     }
+/// }
+/// ```
+pub trait Signer {
+    /// Feed the next bytes written to the archive.
+    fn update(&mut self, data: &[u8]);
+
+    /// Sign the bytes fed so far, once the archive is finalized.
+    fn sign(&mut self) -> Vec<u8>;
+}
+
+/// A shareable function creating a new signer for each archive.
+#[derive(Clone)]
+pub(crate) struct SignerFactory(Arc<dyn Fn() -> Box<dyn Signer + Send> + Send + Sync>);
+
+impl SignerFactory {
+    pub(crate) fn new<F, S>(factory: F) -> Self
+    where
+        F: Fn() -> S + Send + Sync + 'static,
+        S: Signer + Send + 'static,
+    {
+        Self(Arc::new(move || Box::new(factory())))
+    }
+
+    pub(crate) fn build(&self) -> Box<dyn Signer + Send> {
+        (self.0)()
+    }
+}
+
+impl Debug for SignerFactory {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str("SignerFactory")
+    }
+}
+
+/// The signer of an archive being written.
+pub(crate) struct Signature(Box<dyn Signer + Send>);
+
+impl Signature {
+    pub(crate) fn new(factory: &SignerFactory) -> Self {
+        Self(factory.build())
+    }
+
+    pub(crate) fn update(signature: &mut Option<Self>, data: &[u8]) {
+        if let Some(signature) = signature {
+            signature.0.update(data);
+        }
+    }
+
+    pub(crate) fn sign(&mut self) -> Vec<u8> {
+        self.0.sign()
+    }
+}
+
+impl Debug for Signature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str("Signature")
+    }
+}
@@ -0,0 +1,213 @@
+use std::future::Future;
+use std::io::Error as IoError;
+
+use crate::{Archive, ArchiveOptions, ArchiveSizeEstimator, EntryOptions, FileDateTime};
+
+/// A source of an entry (e.g. a file, an object of a storage service, or a generated report),
+/// to model heterogeneous sources uniformly, append them with `Archive::append_source`,
+/// and count the size of their archive with `archive_size_of_sources`.
+///
+/// ## Example
+///
+/// ```
+/// use std::io::{Cursor, Error};
+/// use zipit::{FileDateTime, ZipEntrySource};
+///
+/// struct Report {
+///     title: String,
+/// }
+///
+/// impl ZipEntrySource for Report {
+///     type Reader = Cursor<Vec<u8>>;
+///
+///     fn name(&self) -> String {
+///         format!("{}.txt", self.title)
+///     }
+///
+///     fn datetime(&self) -> FileDateTime {
+///         FileDateTime::Zero
+///     }
+///
+///     fn size_hint(&self) -> Option<u64> {
+///         Some(self.title.len() as u64 + 1)
+///     }
+///
+///     async fn open(&mut self) -> Result<Self::Reader, Error> {
+///         Ok(Cursor::new(format!("{}\n", self.title).into_bytes()))
+///     }
+/// }
+/// ```
+pub trait ZipEntrySource {
+    /// The reader of the payload, an `AsyncRead` object of the backend the archive is written with.
+    type Reader;
+
+    /// The name of the entry, ending with a slash if it is a directory (whose source isn't opened).
+    fn name(&self) -> String;
+
+    /// The date and time of the entry.
+    fn datetime(&self) -> FileDateTime;
+
+    /// The exact size of the payload if known beforehand, checked while appending, `None` by default.
+    fn size_hint(&self) -> Option<u64> {
+        None
+    }
+
+    /// The options of the entry, the default ones by default.
+    fn options(&self) -> EntryOptions {
+        EntryOptions::default()
+    }
+
+    /// Open the payload, once the entry is appended.
+    fn open(&mut self) -> impl Future<Output = Result<Self::Reader, IoError>> + Send;
+}
+
+/// What `Archive::append_all` does when a source can't be opened.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SourcePolicy {
+    /// Stop appending the sources, returning the error.
+    #[default]
+    Abort,
+    /// Skip the source, its error being returned in the summary.
+    Skip,
+}
+
+/// The names of the sources appended by `Archive::append_all`, in order, with the errors of the skipped ones.
+pub type SourceSummary = Vec<(String, Result<(), IoError>)>;
+
+/// Calculate the exact size of an archive written with `options` from `sources`, see `archive_size_with_options`,
+/// or `None` if the size of a file isn't known beforehand (see `ZipEntrySource::size_hint`).
+pub fn archive_size_of_sources<'a, S, I>(sources: I, options: &ArchiveOptions) -> Option<u64>
+where
+    S: ZipEntrySource + 'a,
+    I: IntoIterator<Item = &'a S>,
+{
+    let mut estimator = ArchiveSizeEstimator::with_options(options.clone());
+    for source in sources {
+        let name = source.name();
+        match source.size_hint() {
+            _ if name.ends_with('/') => estimator.add_directory(&name),
+            Some(size) => estimator.add_file_with_options(&name, size, &source.options()),
+            None => return None,
+        }
+    }
+    Some(estimator.total())
+}
+
+macro_rules! impl_source_methods {
+    (
+        $(#[$($attrss:tt)*])*,
+        $w:path, $r:path,
+        $fao:tt, $fd:tt, $fsh:tt, $fas:tt, $faa:tt, $fan:tt,
+    ) => {
+        impl<W: $w + Unpin> Archive<W> {
+            /// Append the entries of `sources` one after the other, see `append_source`.
+            /// A source that can't be opened stops the appending or is skipped, according to `policy`.
+            ///
+            /// # Error
+            ///
+            /// This function will forward any error found while appending an opened source, interrupting the archive,
+            /// whose last entry may be incomplete, or while opening one with `SourcePolicy::Abort`.
+            $(#[$($attrss)*])*
+            pub async fn $faa<I>(&mut self, sources: I, policy: SourcePolicy) -> Result<SourceSummary, IoError>
+            where
+                I: IntoIterator,
+                I::Item: ZipEntrySource,
+                <I::Item as ZipEntrySource>::Reader: $r + Unpin,
+            {
+                let mut summary = Vec::new();
+                for source in sources {
+                    self.$fan(source, policy, &mut summary).await?;
+                }
+                Ok(summary)
+            }
+
+            // Append the entry of a source of `append_all`, adding it to the summary.
+            pub(crate) async fn $fan<S>(
+                &mut self,
+                mut source: S,
+                policy: SourcePolicy,
+                summary: &mut SourceSummary,
+            ) -> Result<(), IoError>
+            where
+                S: ZipEntrySource,
+                S::Reader: $r + Unpin,
+            {
+                let name = source.name();
+                if name.ends_with('/') {
+                    self.$fd(name.clone(), source.datetime()).await?;
+                    summary.push((name, Ok(())));
+                    return Ok(());
+                }
+                let mut reader = match source.open().await {
+                    Ok(reader) => reader,
+                    Err(error) if policy == SourcePolicy::Skip => {
+                        summary.push((name, Err(error)));
+                        return Ok(());
+                    }
+                    Err(error) => return Err(error),
+                };
+                match source.size_hint() {
+                    Some(size) => {
+                        self.$fsh(name.clone(), source.datetime(), source.options(), size, &mut reader).await?
+                    }
+                    None => self.$fao(name.clone(), source.datetime(), source.options(), &mut reader).await?,
+                }
+                summary.push((name, Ok(())));
+                Ok(())
+            }
+
+            /// Append the entry of `source`, opened once its header is about to be written,
+            /// with `append_with_size_hint` if its size is known beforehand, with `append_with_options` otherwise,
+            /// or with `append_directory` if it is a directory.
+            ///
+            /// # Error
+            ///
+            /// This function will forward any error found while opening the source, or the errors of the method it is appended with.
+            $(#[$($attrss)*])*
+            pub async fn $fas<S>(&mut self, mut source: S) -> Result<(), IoError>
+            where
+                S: ZipEntrySource,
+                S::Reader: $r + Unpin,
+            {
+                let name = source.name();
+                if name.ends_with('/') {
+                    return self.$fd(name, source.datetime()).await;
+                }
+                let mut reader = source.open().await?;
+                match source.size_hint() {
+                    Some(size) => {
+                        self.$fsh(name, source.datetime(), source.options(), size, &mut reader).await
+                    }
+                    None => self.$fao(name, source.datetime(), source.options(), &mut reader).await,
+                }
+            }
+        }
+    };
+}
+
+#[cfg(all(feature = "futures-async-io", feature = "tokio-async-io"))]
+impl_source_methods!(
+    #[cfg(all(feature = "futures-async-io", feature = "tokio-async-io"))],
+    futures_util::AsyncWrite, futures_util::AsyncRead,
+    futures_append_with_options, futures_append_directory, futures_append_with_size_hint, futures_append_source, futures_append_all, futures_append_next_source,
+);
+#[cfg(all(feature = "futures-async-io", feature = "tokio-async-io"))]
+impl_source_methods!(
+    #[cfg(all(feature = "futures-async-io", feature = "tokio-async-io"))],
+    tokio::io::AsyncWrite, tokio::io::AsyncRead,
+    tokio_append_with_options, tokio_append_directory, tokio_append_with_size_hint, tokio_append_source, tokio_append_all, tokio_append_next_source,
+);
+
+#[cfg(all(feature = "futures-async-io", not(feature = "tokio-async-io")))]
+impl_source_methods!(
+    #[cfg(all(feature = "futures-async-io", not(feature = "tokio-async-io")))],
+    futures_util::AsyncWrite, futures_util::AsyncRead,
+    append_with_options, append_directory, append_with_size_hint, append_source, append_all, append_next_source,
+);
+
+#[cfg(all(not(feature = "futures-async-io"), feature = "tokio-async-io"))]
+impl_source_methods!(
+    #[cfg(all(not(feature = "futures-async-io"), feature = "tokio-async-io"))],
+    tokio::io::AsyncWrite, tokio::io::AsyncRead,
+    append_with_options, append_directory, append_with_size_hint, append_source, append_all, append_next_source,
+);
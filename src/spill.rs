@@ -0,0 +1,51 @@
+use std::fs::File;
+use std::io::{Error as IoError, Seek, SeekFrom, Write};
+
+/// A temporary file, rewound, with the number of bytes spilled to it.
+pub(crate) type Spilled = (File, u64);
+
+/// The buffered payload of an entry, kept in memory up to a threshold,
+/// then spilled to a temporary file (`spill-to-disk` feature).
+pub(crate) struct SpillBuffer {
+    memory: Vec<u8>,
+    #[cfg_attr(not(feature = "spill-to-disk"), allow(dead_code))]
+    threshold: usize,
+    file: Option<File>,
+    spilled: u64,
+}
+
+impl SpillBuffer {
+    pub(crate) fn new(memory: Vec<u8>, threshold: usize) -> Self {
+        Self {
+            memory,
+            threshold,
+            file: None,
+            spilled: 0,
+        }
+    }
+
+    pub(crate) fn write(&mut self, chunk: &[u8]) -> Result<(), IoError> {
+        #[cfg(feature = "spill-to-disk")]
+        if self.file.is_some() || self.memory.len() + chunk.len() > self.threshold {
+            let file = match &mut self.file {
+                Some(file) => file,
+                None => self.file.insert(tempfile::tempfile()?),
+            };
+            file.write_all(chunk)?;
+            self.spilled += chunk.len() as u64;
+            return Ok(());
+        }
+        self.memory.write_all(chunk)
+    }
+
+    /// The bytes kept in memory, and the temporary file if any bytes were spilled.
+    pub(crate) fn into_parts(self) -> Result<(Vec<u8>, Option<Spilled>), IoError> {
+        match self.file {
+            Some(mut file) => {
+                file.seek(SeekFrom::Start(0))?;
+                Ok((self.memory, Some((file, self.spilled))))
+            }
+            None => Ok((self.memory, None)),
+        }
+    }
+}
@@ -0,0 +1,719 @@
+use std::convert::Infallible;
+use std::error::Error as StdError;
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::future::{poll_fn, Future};
+use std::io::Error as IoError;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll, Waker};
+
+use bytes::Bytes;
+use futures_core::Stream;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf};
+use tokio::sync::mpsc::Receiver;
+
+#[cfg(any(feature = "tower", feature = "http-body1"))]
+use crate::base64;
+use crate::sha256::SHA256_SIZE;
+use crate::{
+    Archive, ArchiveOptions, ArchivePlan, ArchiveSizeEstimator, EntryOptions, Error, FileDateTime,
+    PlannedEntry, Sha256, SourcePolicy, SourceSummary, ZipEntrySource,
+};
+
+// The size of the archive's bytes buffered before the archive waits for them to be consumed.
+const STREAM_BUFFER_SIZE: usize = 64 * 1024;
+
+type BoxedReader = Box<dyn AsyncRead + Send + Unpin>;
+type BoxedOpen =
+    Box<dyn FnOnce() -> Pin<Box<dyn Future<Output = Result<BoxedReader, IoError>> + Send>> + Send>;
+type BoxedArchive = Pin<Box<dyn Future<Output = Result<(), IoError>> + Send>>;
+
+/// An entry of an `ArchiveStream`, with the source of its payload.
+pub struct EntrySource {
+    name: String,
+    datetime: FileDateTime,
+    options: EntryOptions,
+    size: Option<u64>,
+    payload: Payload,
+}
+
+enum Payload {
+    Reader(BoxedReader),
+    Open(BoxedOpen),
+    Directory,
+}
+
+impl EntrySource {
+    /// A file whose payload is read from `reader`.
+    pub fn file<R>(name: String, datetime: FileDateTime, reader: R) -> Self
+    where
+        R: AsyncRead + Send + Unpin + 'static,
+    {
+        Self::new(name, datetime, Payload::Reader(Box::new(reader)))
+    }
+
+    /// A file whose payload is read from the reader returned by `open`, which is only called when its turn comes
+    /// (e.g. to open one file or one request at a time).
+    pub fn lazy<F, Fut, R>(name: String, datetime: FileDateTime, open: F) -> Self
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<R, IoError>> + Send + 'static,
+        R: AsyncRead + Send + Unpin + 'static,
+    {
+        let open: BoxedOpen = Box::new(move || {
+            Box::pin(async move { open().await.map(|reader| Box::new(reader) as BoxedReader) })
+        });
+        Self::new(name, datetime, Payload::Open(open))
+    }
+
+    /// An empty directory, appended like `Archive::append_directory`.
+    pub fn directory(name: String, datetime: FileDateTime) -> Self {
+        Self::new(name, datetime, Payload::Directory)
+    }
+
+    /// Set the options of the entry, the default ones if not set. They are ignored for a directory.
+    pub fn options(mut self, options: EntryOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Declare the size of the file's payload, appended like `Archive::append_with_size_hint`,
+    /// so that the size of the archive is known before it is generated (see `ArchiveStream::size`).
+    /// The stream fails with an `Error::SizeMismatch` if the payload isn't `size` bytes long.
+    pub fn size(mut self, size: u64) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    fn new(name: String, datetime: FileDateTime, payload: Payload) -> Self {
+        Self {
+            name,
+            datetime,
+            options: EntryOptions::default(),
+            size: None,
+            payload,
+        }
+    }
+}
+
+impl Debug for EntrySource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("EntrySource")
+            .field("name", &self.name)
+            .field("datetime", &self.datetime)
+            .field("options", &self.options)
+            .field("size", &self.size)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A zip archive generated on demand as a stream of chunks, its entries being appended (and their payloads read)
+/// as the stream is polled, without a background task, e.g. for the body of an HTTP response.
+/// The stream ends after its first error (e.g. while reading a payload).
+///
+/// ## Example
+///
+/// ```
+/// use std::io::Cursor;
+/// use zipit::{archive_size, ArchiveStream, EntrySource, FileDateTime};
+///
+/// # async fn example() {
+/// let size = archive_size([("file1.txt", 6), ("file2.txt", 6)]);
+/// let stream = ArchiveStream::new([
+///     EntrySource::file("file1.txt".to_owned(), FileDateTime::Zero, Cursor::new(b"hello\n")),
+///     EntrySource::lazy("file2.txt".to_owned(), FileDateTime::Zero, || async {
+///         Ok(Cursor::new(b"world\n"))
+///     }),
+/// ]);
+/// // e.g. hyper::Body::wrap_stream(stream), with a Content-Length of `size`.
+/// # }
+/// ```
+pub struct ArchiveStream {
+    buffer: Arc<Mutex<SharedBuffer>>,
+    archive: Option<BoxedArchive>,
+    error: Option<IoError>,
+    size: Option<u64>,
+    // The digest of the bytes streamed so far, then of all of them, if computed for the trailers.
+    hasher: Option<Sha256>,
+    digest: Option<[u8; SHA256_SIZE]>,
+}
+
+impl ArchiveStream {
+    /// Create the stream of an archive made of the entries of `sources`, in order, written with the default options.
+    pub fn new<I>(sources: I) -> Self
+    where
+        I: IntoIterator<Item = EntrySource>,
+    {
+        Self::with_options(sources, ArchiveOptions::default())
+    }
+
+    /// Create the stream of an archive made of the entries of `sources`, in order, written with `options`.
+    pub fn with_options<I>(sources: I, options: ArchiveOptions) -> Self
+    where
+        I: IntoIterator<Item = EntrySource>,
+    {
+        let sources = sources.into_iter().collect::<Vec<_>>();
+        let size = archive_size(&sources, &options);
+        Self {
+            size,
+            ..Self::from_stream(Iter(sources.into_iter()), options)
+        }
+    }
+
+    /// Create the stream of an archive made of the entries produced by `sources`, in order, written with `options`,
+    /// e.g. while they are discovered.
+    pub fn from_stream<S>(sources: S, options: ArchiveOptions) -> Self
+    where
+        S: Stream<Item = EntrySource> + Send + 'static,
+    {
+        Self::from_writer(0, |sink| write(sink, Box::pin(sources), options))
+    }
+
+    // Create the stream of the archive written to the sink by `write`, without its first `skip` bytes.
+    fn from_writer<F, Fut>(skip: u64, write: F) -> Self
+    where
+        F: FnOnce(SharedSink) -> Fut,
+        Fut: Future<Output = Result<(), IoError>> + Send + 'static,
+    {
+        let buffer = Arc::new(Mutex::new(SharedBuffer::default()));
+        let sink = SharedSink {
+            buffer: Arc::clone(&buffer),
+            skip,
+        };
+        Self {
+            buffer,
+            archive: Some(Box::pin(write(sink))),
+            error: None,
+            size: None,
+            hasher: None,
+            digest: None,
+        }
+    }
+
+    /// Compute the SHA-256 digest of the bytes streamed and keep the CRC32 of the entries (see `digest` and `entry_crcs`),
+    /// sent as HTTP trailers by the `http_body::Body` implementation (`tower` feature, see `ZipService::trailers`),
+    /// so clients can verify a download without a `Content-Length`. Disabled by default.
+    pub fn trailers(mut self, trailers: bool) -> Self {
+        self.hasher = trailers.then(Sha256::new);
+        self.buffer.lock().unwrap().detach = trailers;
+        self
+    }
+
+    /// The SHA-256 digest of all the bytes streamed, once the stream ended without error, if enabled by `trailers`.
+    pub fn digest(&self) -> Option<[u8; SHA256_SIZE]> {
+        self.digest
+    }
+
+    /// The CRC32 of the entries of the archive, in the order of its central directory,
+    /// once the stream ended without error, if enabled by `trailers`.
+    pub fn entry_crcs(&self) -> Option<Vec<u32>> {
+        self.digest?;
+        self.buffer.lock().unwrap().crcs.clone()
+    }
+
+    /// The size of the whole archive, known if the stream was created from a list of sources
+    /// whose files all have a declared size (see `EntrySource::size`), e.g. for a `Content-Length` header.
+    pub fn size(&self) -> Option<u64> {
+        self.size
+    }
+
+    // The `content-digest` and `x-zip-crc32` trailers, once the stream ended without error, if enabled by `trailers`.
+    #[cfg(any(feature = "tower", feature = "http-body1"))]
+    pub(crate) fn trailer_values(&self) -> Option<[(&'static str, String); 2]> {
+        let (digest, crcs) = (self.digest()?, self.entry_crcs()?);
+        let crcs = crcs
+            .iter()
+            .map(|crc| format!("{crc:08x}"))
+            .collect::<Vec<_>>()
+            .join(",");
+        Some([
+            (
+                "content-digest",
+                format!("sha-256=:{}:", base64::encode(&digest)),
+            ),
+            ("x-zip-crc32", crcs),
+        ])
+    }
+}
+
+// The size of the archive made of the sources, if all their sizes are known.
+fn archive_size(sources: &[EntrySource], options: &ArchiveOptions) -> Option<u64> {
+    let mut estimator = ArchiveSizeEstimator::with_options(options.clone());
+    for source in sources {
+        match (&source.payload, source.size) {
+            (Payload::Directory, _) => estimator.add_directory(&source.name),
+            (_, Some(size)) => estimator.add_file_with_options(&source.name, size, &source.options),
+            (_, None) => return None,
+        }
+    }
+    Some(estimator.total())
+}
+
+impl Debug for ArchiveStream {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("ArchiveStream")
+            .field("done", &self.archive.is_none())
+            .field("size", &self.size)
+            .field("trailers", &self.hasher.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Stream for ArchiveStream {
+    type Item = Result<Bytes, IoError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        // Write the archive until it is done, or waits for its payloads or for its buffer to be consumed.
+        if let Some(archive) = &mut self.archive {
+            if let Poll::Ready(result) = archive.as_mut().poll(cx) {
+                self.archive = None;
+                self.error = result.err();
+            }
+        }
+
+        let mut buffer = self.buffer.lock().unwrap();
+        if !buffer.data.is_empty() {
+            let chunk = Bytes::from(std::mem::take(&mut buffer.data));
+            if let Some(waker) = buffer.waker.take() {
+                waker.wake();
+            }
+            drop(buffer);
+            if let Some(hasher) = &mut self.hasher {
+                hasher.update(&chunk);
+            }
+            return Poll::Ready(Some(Ok(chunk)));
+        }
+        drop(buffer);
+        match self.error.take() {
+            Some(error) => {
+                self.hasher = None;
+                Poll::Ready(Some(Err(error)))
+            }
+            None if self.archive.is_none() => {
+                if let Some(hasher) = self.hasher.take() {
+                    self.digest = Some(hasher.finalize());
+                }
+                Poll::Ready(None)
+            }
+            None => Poll::Pending,
+        }
+    }
+}
+
+#[derive(Default)]
+struct SharedBuffer {
+    data: Vec<u8>,
+    waker: Option<Waker>,
+    // Whether the central directory is detached to keep the CRC32 of the entries.
+    detach: bool,
+    crcs: Option<Vec<u32>>,
+}
+
+// A sink buffering the bytes written until they are consumed by the stream, waiting for it once full,
+// after discarding the first `skip` bytes.
+struct SharedSink {
+    buffer: Arc<Mutex<SharedBuffer>>,
+    skip: u64,
+}
+
+impl AsyncWrite for SharedSink {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        data: &[u8],
+    ) -> Poll<Result<usize, IoError>> {
+        if self.skip > 0 {
+            let skipped = self.skip.min(data.len() as u64);
+            self.skip -= skipped;
+            return Poll::Ready(Ok(skipped as usize));
+        }
+        let mut buffer = self.buffer.lock().unwrap();
+        let available = STREAM_BUFFER_SIZE.saturating_sub(buffer.data.len());
+        if available == 0 {
+            buffer.waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        let written = data.len().min(available);
+        buffer.data.extend_from_slice(&data[..written]);
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), IoError>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), IoError>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl ArchivePlan {
+    /// Stream the archive the plan describes from its byte at `offset`, the ones before it being skipped,
+    /// e.g. to answer a `Range: bytes=offset-` request resuming an interrupted download (see `size`).
+    /// The reader of each file is opened with `open` only when its turn comes (see `PlannedArchive::append_all`),
+    /// given the position in the payload to read from. `open` isn't called for the directories.
+    ///
+    /// The files ending before `offset` whose CRC32 is declared (see `PlannedEntry::crc`) aren't read,
+    /// the file `offset` falls in is read from that position, the following ones from their beginning.
+    /// The files whose CRC32 isn't declared are read entirely, as is every file if the archive has a checksums entry
+    /// or a signature (see `ArchiveOptions::checksums`), since the bytes after `offset` depend on their payloads.
+    /// The plan, its CRC32s and its dates/times, must be the ones of the archive being resumed.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use std::io::Cursor;
+    /// use zipit::{ArchivePlan, FileDateTime};
+    ///
+    /// let mut plan = ArchivePlan::new();
+    /// plan.add_file("file1.txt".to_owned(), 6, FileDateTime::Zero).crc(0x363a3020);
+    /// let stream = plan.stream_from(100, |entry, start| async move {
+    ///     // e.g. a ranged request to an object storage, or a seek in a file.
+    ///     Ok(Cursor::new(b"hello\n"[start as usize..].to_vec()))
+    /// });
+    /// assert_eq!(stream.size(), Some(138 - 100));
+    /// ```
+    ///
+    /// # Error
+    ///
+    /// The stream fails with an `Error::NotResumable` if `offset` is past the end of the archive,
+    /// or if it isn't 0 and the archive is encrypted, and with the same errors as `PlannedArchive::append_all`.
+    pub fn stream_from<F, Fut, R>(self, offset: u64, open: F) -> ArchiveStream
+    where
+        F: FnMut(&PlannedEntry, u64) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<R, IoError>> + Send + 'static,
+        R: AsyncRead + Send + Unpin + 'static,
+    {
+        let size = self.size();
+        let encrypted = self.options.password.is_some()
+            || self
+                .entries
+                .iter()
+                .any(|entry| entry.options.password.is_some());
+        if offset > size || (offset > 0 && encrypted) {
+            return ArchiveStream::from_writer(0, move |_| async move {
+                Err(Error::NotResumable { offset }.into())
+            });
+        }
+        ArchiveStream {
+            size: Some(size - offset),
+            ..ArchiveStream::from_writer(offset, move |sink| write_from(sink, self, offset, open))
+        }
+    }
+}
+
+// An iterator of sources, as a stream.
+struct Iter<I>(I);
+
+impl<I: Iterator + Unpin> Stream for Iter<I> {
+    type Item = I::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Poll::Ready(self.0.next())
+    }
+}
+
+// A stream of chunks, as a reader.
+pub(crate) struct StreamReader<S> {
+    stream: Pin<Box<S>>,
+    chunk: Bytes,
+}
+
+impl<S> StreamReader<S> {
+    pub(crate) fn new(stream: S) -> Self {
+        Self {
+            stream: Box::pin(stream),
+            chunk: Bytes::new(),
+        }
+    }
+}
+
+impl<S, E> AsyncRead for StreamReader<S>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+    E: Into<Box<dyn StdError + Send + Sync>>,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<(), IoError>> {
+        while self.chunk.is_empty() && buf.remaining() > 0 {
+            match ready!(self.stream.as_mut().poll_next(cx)) {
+                Some(Ok(chunk)) => self.chunk = chunk,
+                // Keep the kind of I/O errors.
+                Some(Err(error)) => {
+                    return Poll::Ready(Err(match error.into().downcast::<IoError>() {
+                        Ok(error) => *error,
+                        Err(error) => IoError::other(error),
+                    }))
+                }
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+        let len = buf.remaining().min(self.chunk.len());
+        let chunk = self.chunk.split_to(len);
+        buf.put_slice(&chunk);
+        Poll::Ready(Ok(()))
+    }
+}
+
+// The chunks received from a channel, as a stream.
+struct Channel(Receiver<Bytes>);
+
+impl Stream for Channel {
+    type Item = Result<Bytes, Infallible>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0.poll_recv(cx).map(|chunk| chunk.map(Ok))
+    }
+}
+
+macro_rules! impl_append_stream {
+    (
+        $fas:tt, $faso:tt, $fac:tt, $faco:tt, $fao:tt, $faas:tt, $fan:tt,
+    ) => {
+        impl<W: AsyncWrite + Unpin> Archive<W> {
+            /// Append the entries of the sources of a `Stream` as they are received (e.g. listed page by page
+            /// from a storage service), see `append_all`.
+            ///
+            /// # Error
+            ///
+            /// This function will return the same errors as `append_all`.
+            pub async fn $faas<St>(
+                &mut self,
+                sources: St,
+                policy: SourcePolicy,
+            ) -> Result<SourceSummary, IoError>
+            where
+                St: Stream,
+                St::Item: ZipEntrySource,
+                <St::Item as ZipEntrySource>::Reader: AsyncRead + Unpin,
+            {
+                let mut sources = Box::pin(sources);
+                let mut summary = Vec::new();
+                while let Some(source) = poll_fn(|cx| sources.as_mut().poll_next(cx)).await {
+                    self.$fan(source, policy, &mut summary).await?;
+                }
+                Ok(summary)
+            }
+
+            /// Append a new file to the archive using the provided name, date/time and `Stream` of chunks
+            /// (e.g. the body of an HTTP client's response, or an object of a storage SDK), as if appended with `append`,
+            /// without adapting the stream into an `AsyncRead` object.
+            ///
+            /// # Error
+            ///
+            /// This function will forward any error found while polling the stream (as is if it is an `std::io::Error`)
+            /// or while writing to the underlying sink, see `append_with_options`.
+            pub async fn $fas<S, E>(
+                &mut self,
+                name: String,
+                datetime: FileDateTime,
+                stream: S,
+            ) -> Result<(), IoError>
+            where
+                S: Stream<Item = Result<Bytes, E>>,
+                E: Into<Box<dyn StdError + Send + Sync>>,
+            {
+                self.$faso(name, datetime, EntryOptions::default(), stream)
+                    .await
+            }
+
+            /// Append a new file to the archive using the provided name, date/time, options and `Stream` of chunks,
+            /// see `append_stream` and `append_with_options`.
+            pub async fn $faso<S, E>(
+                &mut self,
+                name: String,
+                datetime: FileDateTime,
+                options: EntryOptions,
+                stream: S,
+            ) -> Result<(), IoError>
+            where
+                S: Stream<Item = Result<Bytes, E>>,
+                E: Into<Box<dyn StdError + Send + Sync>>,
+            {
+                let mut reader = StreamReader::new(stream);
+                self.$fao(name, datetime, options, &mut reader).await
+            }
+
+            /// Append a new file to the archive using the provided name, date/time and the chunks received from `receiver`,
+            /// e.g. pushed by a producer task while the archive is written. The payload ends once every sender is dropped.
+            ///
+            /// # Error
+            ///
+            /// This function will forward any error found while writing to the underlying sink, see `append_with_options`.
+            pub async fn $fac(
+                &mut self,
+                name: String,
+                datetime: FileDateTime,
+                receiver: Receiver<Bytes>,
+            ) -> Result<(), IoError> {
+                self.$faco(name, datetime, EntryOptions::default(), receiver)
+                    .await
+            }
+
+            /// Append a new file to the archive using the provided name, date/time, options and the chunks received from `receiver`,
+            /// see `append_from_channel` and `append_with_options`.
+            pub async fn $faco(
+                &mut self,
+                name: String,
+                datetime: FileDateTime,
+                options: EntryOptions,
+                receiver: Receiver<Bytes>,
+            ) -> Result<(), IoError> {
+                self.$faso(name, datetime, options, Channel(receiver)).await
+            }
+        }
+    };
+}
+
+#[cfg(feature = "futures-async-io")]
+impl_append_stream!(
+    tokio_append_stream,
+    tokio_append_stream_with_options,
+    tokio_append_from_channel,
+    tokio_append_from_channel_with_options,
+    tokio_append_with_options,
+    tokio_append_all_stream,
+    tokio_append_next_source,
+);
+
+#[cfg(not(feature = "futures-async-io"))]
+impl_append_stream!(
+    append_stream,
+    append_stream_with_options,
+    append_from_channel,
+    append_from_channel_with_options,
+    append_with_options,
+    append_all_stream,
+    append_next_source,
+);
+
+macro_rules! impl_write {
+    (
+        $fao:tt, $fd:tt, $fsh:tt, $ff:tt, $ffdt:tt,
+    ) => {
+        // Finalize the archive, keeping the CRC32 of its entries if needed for the trailers.
+        async fn finish(archive: Archive<SharedSink>) -> Result<(), IoError> {
+            let buffer = Arc::clone(&archive.sink.buffer);
+            if !buffer.lock().unwrap().detach {
+                return archive.$ff().await.map(|_| ());
+            }
+            let (_, directory) = archive.$ffdt().await?;
+            buffer.lock().unwrap().crcs = Some(directory.crcs());
+            Ok(())
+        }
+
+        // Write the archive of the plan to the sink, without reading the payloads ending before `offset` if possible,
+        // as the sink discards the bytes before it.
+        async fn write_from<F, Fut, R>(
+            sink: SharedSink,
+            plan: ArchivePlan,
+            offset: u64,
+            mut open: F,
+        ) -> Result<(), IoError>
+        where
+            F: FnMut(&PlannedEntry, u64) -> Fut,
+            Fut: Future<Output = Result<R, IoError>>,
+            R: AsyncRead + Send + Unpin + 'static,
+        {
+            // The payloads don't change the bytes written after them, except for their checksums and the signature.
+            let skips = plan.options.checksums.is_none() && plan.options.signer.is_none();
+            let mut estimator = ArchiveSizeEstimator::with_options(plan.options.clone());
+            // The CRC32 of the payloads replaced by zeros can't be verified.
+            let options = ArchiveOptions {
+                verify_crc: false,
+                ..plan.options
+            };
+            let mut archive = Archive::with_options(sink, options);
+            for entry in plan.entries {
+                let payload_offset = estimator.payload_offset(&entry.name, &entry.options);
+                estimator.add_file_with_options(&entry.name, entry.size, &entry.options);
+                if entry.name.ends_with('/') {
+                    archive.$fd(entry.name, entry.datetime).await?;
+                    continue;
+                }
+
+                // The skipped part of the payload is replaced by zeros, written with the declared CRC32.
+                let (start, crc) = match entry.crc {
+                    Some(crc) if skips => (
+                        offset.saturating_sub(payload_offset).min(entry.size),
+                        Some(crc),
+                    ),
+                    _ => (0, None),
+                };
+                let rest: BoxedReader = if start < entry.size {
+                    Box::new(open(&entry, start).await?)
+                } else {
+                    Box::new(tokio::io::empty())
+                };
+                let mut reader = tokio::io::repeat(0).take(start).chain(rest);
+                let options = EntryOptions {
+                    crc: crc.or(entry.options.crc),
+                    ..entry.options
+                };
+                archive
+                    .$fsh(entry.name, entry.datetime, options, entry.size, &mut reader)
+                    .await?;
+            }
+            finish(archive).await
+        }
+
+        // Write the archive made of the sources to the sink.
+        async fn write(
+            sink: SharedSink,
+            mut sources: Pin<Box<dyn Stream<Item = EntrySource> + Send>>,
+            options: ArchiveOptions,
+        ) -> Result<(), IoError> {
+            let mut archive = Archive::with_options(sink, options);
+            while let Some(source) = poll_fn(|cx| sources.as_mut().poll_next(cx)).await {
+                let mut reader = match source.payload {
+                    Payload::Reader(reader) => reader,
+                    Payload::Open(open) => open().await?,
+                    Payload::Directory => {
+                        archive.$fd(source.name, source.datetime).await?;
+                        continue;
+                    }
+                };
+                match source.size {
+                    Some(size) => {
+                        archive
+                            .$fsh(
+                                source.name,
+                                source.datetime,
+                                source.options,
+                                size,
+                                &mut reader,
+                            )
+                            .await?
+                    }
+                    None => {
+                        archive
+                            .$fao(source.name, source.datetime, source.options, &mut reader)
+                            .await?
+                    }
+                }
+            }
+            finish(archive).await
+        }
+    };
+}
+
+#[cfg(feature = "futures-async-io")]
+impl_write!(
+    tokio_append_with_options,
+    tokio_append_directory,
+    tokio_append_with_size_hint,
+    tokio_finalize,
+    tokio_finalize_detached,
+);
+
+#[cfg(not(feature = "futures-async-io"))]
+impl_write!(
+    append_with_options,
+    append_directory,
+    append_with_size_hint,
+    finalize,
+    finalize_detached,
+);
@@ -0,0 +1,216 @@
+use std::future::Future;
+use std::io::{Error as IoError, ErrorKind};
+use std::mem;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+// A multiple of 256 KiB (required by Google Cloud Storage) above 5 MiB (the minimum of Amazon S3).
+const DEFAULT_PART_SIZE: usize = 8 << 20;
+
+/// A multipart (or resumable) upload to a storage backend, receiving a streamed archive through a `ChunkedWriter`.
+///
+/// ## Example
+///
+/// ```
+/// use std::io::Error;
+/// use tokio::io::AsyncWriteExt;
+/// use zipit::{Archive, ChunkedUpload, ChunkedWriter, FileDateTime};
+///
+/// #[derive(Default)]
+/// struct MemoryUpload {
+///     parts: Vec<Vec<u8>>,
+/// }
+///
+/// impl ChunkedUpload for MemoryUpload {
+///     async fn put_part(&mut self, part: Vec<u8>) -> Result<(), Error> {
+///         self.parts.push(part);
+///         Ok(())
+///     }
+///
+///     async fn complete(&mut self) -> Result<(), Error> {
+///         println!("uploaded {} parts", self.parts.len());
+///         Ok(())
+///     }
+///
+///     async fn abort(&mut self) -> Result<(), Error> {
+///         self.parts.clear();
+///         Ok(())
+///     }
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Error> {
+/// let mut archive = Archive::new(ChunkedWriter::new(MemoryUpload::default()));
+/// archive.append("file.txt".to_owned(), FileDateTime::now(), &mut b"hello\n".as_ref()).await?;
+/// let mut writer = archive.finalize().await?;
+/// writer.shutdown().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub trait ChunkedUpload {
+    /// Upload the next part of the archive, all of them being of the part size of the writer but the last one.
+    fn put_part(&mut self, part: Vec<u8>) -> impl Future<Output = Result<(), IoError>> + Send;
+
+    /// Complete the upload once its last part is uploaded, when the writer is shut down (or closed).
+    fn complete(&mut self) -> impl Future<Output = Result<(), IoError>> + Send;
+
+    /// Abort the upload, discarding its uploaded parts, see `ChunkedWriter::abort`.
+    fn abort(&mut self) -> impl Future<Output = Result<(), IoError>> + Send;
+}
+
+type PendingUpload<U> = Pin<Box<dyn Future<Output = (U, Result<(), IoError>)> + Send>>;
+
+/// An `AsyncWrite` sink buffering the written bytes into parts of a fixed size, uploaded one at a time
+/// (while the next one is buffered) with a `ChunkedUpload`, completed once the writer is shut down (or closed).
+///
+/// Flushing the writer waits for the part being uploaded, but doesn't upload the partial one being buffered,
+/// to keep the parts aligned on the part size.
+pub struct ChunkedWriter<U> {
+    upload: Option<U>,
+    pending: Option<PendingUpload<U>>,
+    buffer: Vec<u8>,
+    part_size: usize,
+    completed: bool,
+    failed: bool,
+}
+
+// The upload is moved in and out of the pending futures, but never pinned.
+impl<U> Unpin for ChunkedWriter<U> {}
+
+impl<U: ChunkedUpload + Send + 'static> ChunkedWriter<U> {
+    /// Create a writer uploading parts of 8 MiB.
+    pub fn new(upload: U) -> Self {
+        Self::with_part_size(upload, DEFAULT_PART_SIZE)
+    }
+
+    /// Create a writer uploading parts of `part_size` bytes (e.g. a multiple of 256 KiB for Google Cloud Storage),
+    /// holding up to twice this size in memory.
+    pub fn with_part_size(upload: U, part_size: usize) -> Self {
+        let part_size = part_size.max(1);
+        Self {
+            upload: Some(upload),
+            pending: None,
+            buffer: Vec::with_capacity(part_size),
+            part_size,
+            completed: false,
+            failed: false,
+        }
+    }
+
+    /// Abort the upload, once its part being uploaded (if any) is done, e.g. after an error interrupted the archive,
+    /// written to `&mut writer` to keep the writer once the archive is dropped.
+    ///
+    /// # Error
+    ///
+    /// This function will forward any error found while aborting the upload.
+    pub async fn abort(mut self) -> Result<(), IoError> {
+        if let Some(pending) = self.pending.take() {
+            let (upload, _) = pending.await;
+            self.upload = Some(upload);
+        }
+        let mut upload = self.upload.take().expect("upload not pending");
+        upload.abort().await
+    }
+
+    // Start uploading a part, or completing the upload if `part` is `None`.
+    fn start(&mut self, part: Option<Vec<u8>>) {
+        let mut upload = self.upload.take().expect("upload not pending");
+        self.pending = Some(Box::pin(async move {
+            let result = match part {
+                Some(part) => upload.put_part(part).await,
+                None => upload.complete().await,
+            };
+            (upload, result)
+        }));
+    }
+
+    // Wait for the part being uploaded (or the completion), failing if any failed.
+    fn poll_pending(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), IoError>> {
+        if let Some(pending) = &mut self.pending {
+            let (upload, result) = ready!(pending.as_mut().poll(cx));
+            self.pending = None;
+            self.upload = Some(upload);
+            self.failed = result.is_err();
+            return Poll::Ready(result);
+        }
+        if self.failed {
+            return Poll::Ready(Err(IoError::other("chunked upload failed")));
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_write_part(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, IoError>> {
+        ready!(self.poll_pending(cx))?;
+        if self.completed {
+            return Poll::Ready(Err(IoError::new(
+                ErrorKind::InvalidInput,
+                "chunked upload is completed",
+            )));
+        }
+        let written = buf.len().min(self.part_size - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..written]);
+        if self.buffer.len() == self.part_size {
+            let part = mem::replace(&mut self.buffer, Vec::with_capacity(self.part_size));
+            self.start(Some(part));
+        }
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_complete(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), IoError>> {
+        loop {
+            ready!(self.poll_pending(cx))?;
+            if self.completed {
+                return Poll::Ready(Ok(()));
+            }
+            if self.buffer.is_empty() {
+                self.completed = true;
+                self.start(None);
+            } else {
+                let part = mem::take(&mut self.buffer);
+                self.start(Some(part));
+            }
+        }
+    }
+}
+
+#[cfg(feature = "tokio-async-io")]
+impl<U: ChunkedUpload + Send + 'static> tokio::io::AsyncWrite for ChunkedWriter<U> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, IoError>> {
+        self.get_mut().poll_write_part(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), IoError>> {
+        self.get_mut().poll_pending(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), IoError>> {
+        self.get_mut().poll_complete(cx)
+    }
+}
+
+#[cfg(feature = "futures-async-io")]
+impl<U: ChunkedUpload + Send + 'static> futures_util::AsyncWrite for ChunkedWriter<U> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, IoError>> {
+        self.get_mut().poll_write_part(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), IoError>> {
+        self.get_mut().poll_pending(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), IoError>> {
+        self.get_mut().poll_complete(cx)
+    }
+}
@@ -0,0 +1,177 @@
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::future::Future;
+use std::io::{Error as IoError, ErrorKind};
+use std::mem;
+use std::path::Path;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{ready, Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_uring::fs::File;
+
+// The size of the buffers submitted to io_uring.
+const BUFFER_SIZE: usize = 256 << 10;
+
+// The result of a read or write, with its buffer given back by io_uring.
+type Completion = (Result<usize, IoError>, Vec<u8>);
+type Operation = Pin<Box<dyn Future<Output = Completion>>>;
+
+/// A file read or written with [tokio-uring](https://docs.rs/tokio-uring), e.g. a file appended to the archive
+/// (as an `AsyncRead` object) or the file the archive is written to (as an `AsyncWrite` sink), on Linux.
+///
+/// Its reads and writes are submitted to io_uring by blocks of 256 KiB, without the thread pool of `tokio::fs`.
+/// A block is written while the next one is buffered, its error being returned by the next write, flush or shutdown.
+/// The file must be used within the tokio-uring runtime (see `tokio_uring::start`), either to be read or written.
+///
+/// ## Example
+///
+/// ```no_run
+/// use tokio::io::AsyncWriteExt;
+/// use zipit::{Archive, FileDateTime, UringFile};
+///
+/// tokio_uring::start(async {
+///     let mut archive = Archive::new(UringFile::create("archive.zip").await?);
+///     let mut file = UringFile::open("data.bin").await?;
+///     archive.append("data.bin".to_owned(), FileDateTime::Zero, &mut file).await?;
+///     archive.finalize().await?.shutdown().await
+/// })
+/// .unwrap();
+/// ```
+pub struct UringFile {
+    file: Rc<File>,
+    // The position of the next read or write in the file.
+    position: u64,
+    operation: Option<Operation>,
+    // The bytes read not consumed yet (from `start`), or the bytes to write.
+    buffer: Vec<u8>,
+    start: usize,
+}
+
+impl UringFile {
+    /// Read or write `file` from its start.
+    pub fn new(file: File) -> Self {
+        Self {
+            file: Rc::new(file),
+            position: 0,
+            operation: None,
+            buffer: Vec::new(),
+            start: 0,
+        }
+    }
+
+    /// Open the file at `path` in read-only mode, see `tokio_uring::fs::File::open`.
+    pub async fn open<P: AsRef<Path>>(path: P) -> Result<Self, IoError> {
+        File::open(path).await.map(Self::new)
+    }
+
+    /// Create (or truncate) the file at `path` in write-only mode, see `tokio_uring::fs::File::create`.
+    pub async fn create<P: AsRef<Path>>(path: P) -> Result<Self, IoError> {
+        File::create(path).await.map(Self::new)
+    }
+
+    // Wait for the operation in progress, if any.
+    fn poll_operation(&mut self, cx: &mut Context<'_>) -> Poll<Option<Completion>> {
+        let Some(operation) = &mut self.operation else {
+            return Poll::Ready(None);
+        };
+        let result = ready!(operation.as_mut().poll(cx));
+        self.operation = None;
+        Poll::Ready(Some(result))
+    }
+
+    // Wait for the write in progress, if any, submitting the rest of the partial ones.
+    fn poll_written(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), IoError>> {
+        while let Some((result, mut buffer)) = ready!(self.poll_operation(cx)) {
+            let written = result?;
+            if written == 0 {
+                return Poll::Ready(Err(ErrorKind::WriteZero.into()));
+            }
+            self.position += written as u64;
+            if written < buffer.len() {
+                buffer.drain(..written);
+                self.submit_write(buffer);
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn submit_write(&mut self, buffer: Vec<u8>) {
+        let (file, position) = (Rc::clone(&self.file), self.position);
+        self.operation = Some(Box::pin(
+            async move { file.write_at(buffer, position).await },
+        ));
+    }
+}
+
+impl Debug for UringFile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("UringFile")
+            .field("position", &self.position)
+            .field("pending", &self.operation.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl AsyncRead for UringFile {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<(), IoError>> {
+        let this = self.get_mut();
+        if this.start == this.buffer.len() {
+            if this.operation.is_none() {
+                let mut buffer = mem::take(&mut this.buffer);
+                buffer.clear();
+                this.start = 0;
+                buffer.reserve(BUFFER_SIZE);
+                let (file, position) = (Rc::clone(&this.file), this.position);
+                this.operation = Some(Box::pin(
+                    async move { file.read_at(buffer, position).await },
+                ));
+            }
+            let (result, buffer) = ready!(this.poll_operation(cx)).unwrap();
+            this.buffer = buffer;
+            this.position += result? as u64;
+        }
+        let read = buf.remaining().min(this.buffer.len() - this.start);
+        buf.put_slice(&this.buffer[this.start..this.start + read]);
+        this.start += read;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for UringFile {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        data: &[u8],
+    ) -> Poll<Result<usize, IoError>> {
+        let this = self.get_mut();
+        if this.buffer.len() == BUFFER_SIZE {
+            ready!(this.poll_written(cx))?;
+            let buffer = mem::replace(&mut this.buffer, Vec::with_capacity(BUFFER_SIZE));
+            this.submit_write(buffer);
+        }
+        let written = data.len().min(BUFFER_SIZE - this.buffer.len());
+        this.buffer.extend_from_slice(&data[..written]);
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), IoError>> {
+        let this = self.get_mut();
+        loop {
+            ready!(this.poll_written(cx))?;
+            if this.buffer.is_empty() {
+                return Poll::Ready(Ok(()));
+            }
+            let buffer = mem::take(&mut this.buffer);
+            this.submit_write(buffer);
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), IoError>> {
+        self.poll_flush(cx)
+    }
+}
@@ -0,0 +1,108 @@
+use std::io::{Error as IoError, ErrorKind};
+use std::sync::OnceLock;
+
+use reqwest::header::LAST_MODIFIED;
+use reqwest::{Client, IntoUrl, StatusCode};
+use tokio::io::AsyncWrite;
+
+use crate::stream::StreamReader;
+use crate::{Archive, EntryOptions, FileDateTime};
+
+// The client of `append_url`, shared by every archive.
+fn client() -> &'static Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    CLIENT.get_or_init(Client::new)
+}
+
+// An error of the client as an I/O error, keeping the kind of timeouts and of missing or forbidden resources.
+pub(crate) fn io_error(error: reqwest::Error) -> IoError {
+    let kind = match error.status() {
+        _ if error.is_timeout() => ErrorKind::TimedOut,
+        Some(StatusCode::NOT_FOUND | StatusCode::GONE) => ErrorKind::NotFound,
+        Some(StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) => ErrorKind::PermissionDenied,
+        _ => ErrorKind::Other,
+    };
+    IoError::new(kind, error)
+}
+
+macro_rules! impl_append_url {
+    (
+        $fau:tt, $fauo:tt, $faso:tt, $fsh:tt,
+    ) => {
+        impl<W: AsyncWrite + Unpin> Archive<W> {
+            /// Append a new file to the archive using the provided name, date/time and the resource at `url`
+            /// (fetched with a `GET` request, following redirects), streaming the response's body into the entry.
+            ///
+            /// The entry is dated with the response's `Last-Modified` header if any, with `datetime` otherwise,
+            /// and its size is checked against the `Content-Length` header if any (see `append_with_size_hint`).
+            /// HTTPS requires one of reqwest's TLS features (e.g. `rustls-tls`) to be enabled.
+            ///
+            /// # Error
+            ///
+            /// This function will return an `std::io::Error` if the request fails or its response has an error status
+            /// (of kind `NotFound` for a 404 or 410, `PermissionDenied` for a 401 or 403, `TimedOut` on timeouts),
+            /// and will forward any error found while receiving the body or writing to the underlying sink,
+            /// see `append_with_options`.
+            pub async fn $fau<U: IntoUrl>(
+                &mut self,
+                name: String,
+                datetime: FileDateTime,
+                url: U,
+            ) -> Result<(), IoError> {
+                self.$fauo(name, datetime, EntryOptions::default(), client(), url)
+                    .await
+            }
+
+            /// Append a new file to the archive using the provided name, date/time, options and the resource at `url`,
+            /// fetched with `client` (e.g. configured with a timeout, or the redirect policy), see `append_url`.
+            pub async fn $fauo<U: IntoUrl>(
+                &mut self,
+                name: String,
+                datetime: FileDateTime,
+                options: EntryOptions,
+                client: &Client,
+                url: U,
+            ) -> Result<(), IoError> {
+                let response = client
+                    .get(url)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status())
+                    .map_err(io_error)?;
+                let datetime = response
+                    .headers()
+                    .get(LAST_MODIFIED)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| httpdate::parse_http_date(value).ok())
+                    .map_or(datetime, FileDateTime::from_system_time);
+                // Unknown if the body is decompressed by the client.
+                match response.content_length() {
+                    Some(size) => {
+                        let mut reader = StreamReader::new(response.bytes_stream());
+                        self.$fsh(name, datetime, options, size, &mut reader).await
+                    }
+                    None => {
+                        self.$faso(name, datetime, options, response.bytes_stream())
+                            .await
+                    }
+                }
+            }
+        }
+    };
+}
+
+#[cfg(feature = "futures-async-io")]
+impl_append_url!(
+    tokio_append_url,
+    tokio_append_url_with_options,
+    tokio_append_stream_with_options,
+    tokio_append_with_size_hint,
+);
+
+#[cfg(not(feature = "futures-async-io"))]
+impl_append_url!(
+    append_url,
+    append_url_with_options,
+    append_stream_with_options,
+    append_with_size_hint,
+);
@@ -0,0 +1,101 @@
+use std::io::Error as IoError;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use bytes::Bytes;
+use futures_core::Stream;
+use http1::header::{HeaderName, HeaderValue};
+use http1::HeaderMap;
+use http_body1::{Body, Frame, SizeHint};
+
+use crate::ArchiveStream;
+
+/// An `http_body::Body` of http-body 1.0 (e.g. for hyper 1.x) streaming an `ArchiveStream`,
+/// each of its chunks being sent as a data frame.
+///
+/// The body's size hint is exact if the size of the archive is known (see `ArchiveStream::size`),
+/// and it ends with the trailers of the stream if enabled (see `ArchiveStream::trailers`).
+///
+/// ## Example
+///
+/// ```
+/// use std::io::Cursor;
+/// use zipit::{ArchiveStream, EntrySource, FileDateTime, ZipBody};
+///
+/// let body = ZipBody::new(ArchiveStream::new([
+///     EntrySource::file("file1.txt".to_owned(), FileDateTime::Zero, Cursor::new(b"hello\n")).size(6),
+/// ]));
+/// // e.g. hyper::Response::new(body).
+/// ```
+#[derive(Debug)]
+pub struct ZipBody {
+    stream: ArchiveStream,
+    done: bool,
+}
+
+impl ZipBody {
+    /// Create a body streaming `stream`.
+    pub fn new(stream: ArchiveStream) -> Self {
+        Self {
+            stream,
+            done: false,
+        }
+    }
+
+    /// The stream of the body, e.g. to get its digest once it ended (see `ArchiveStream::digest`).
+    pub fn stream(&self) -> &ArchiveStream {
+        &self.stream
+    }
+}
+
+impl From<ArchiveStream> for ZipBody {
+    fn from(stream: ArchiveStream) -> Self {
+        Self::new(stream)
+    }
+}
+
+impl Body for ZipBody {
+    type Data = Bytes;
+    type Error = IoError;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+        match ready!(Pin::new(&mut self.stream).poll_next(cx)) {
+            Some(Ok(chunk)) => Poll::Ready(Some(Ok(Frame::data(chunk)))),
+            Some(Err(error)) => {
+                self.done = true;
+                Poll::Ready(Some(Err(error)))
+            }
+            None => {
+                self.done = true;
+                let Some(values) = self.stream.trailer_values() else {
+                    return Poll::Ready(None);
+                };
+                let mut trailers = HeaderMap::new();
+                for (name, value) in values {
+                    trailers.insert(
+                        HeaderName::from_static(name),
+                        HeaderValue::try_from(value).unwrap(),
+                    );
+                }
+                Poll::Ready(Some(Ok(Frame::trailers(trailers))))
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.done
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match self.stream.size() {
+            Some(size) => SizeHint::with_exact(size),
+            None => SizeHint::default(),
+        }
+    }
+}